@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use std::path::PathBuf;
 
+use crate::config::{AuthConfig, AuthPreference, AuthSource};
+
 #[cfg(target_os = "macos")]
 use std::process::Command;
 
@@ -69,48 +71,85 @@ struct AuthJsonFile {
     refresh_token: Option<String>,
 }
 
-/// Discover Claude credentials from various sources
+/// Source order used when `auth.sources` is empty
+const DEFAULT_SOURCE_ORDER: [AuthSource; 4] = [
+    AuthSource::Env,
+    AuthSource::ClaudeDir,
+    AuthSource::Keychain,
+    AuthSource::ConfigDir,
+];
+
+/// Discover Claude credentials from the configured sources
 ///
-/// Checks in order:
+/// By default checks in order:
 /// 1. ANTHROPIC_API_KEY environment variable
 /// 2. ~/.claude/.credentials.json (OAuth tokens)
 /// 3. macOS Keychain (claude-auth)
 /// 4. ~/.config/claude/auth.json
 ///
-/// Returns credentials if found, with source information
-pub fn discover_credentials() -> ClaudeCredentials {
-    // 1. Check environment variable first
-    if let Ok(api_key) = std::env::var("ANTHROPIC_API_KEY") {
-        if !api_key.is_empty() {
-            return ClaudeCredentials {
-                source: CredentialSource::EnvApiKey,
-                oauth_token: None,
-                api_key: Some(api_key),
-            };
-        }
-    }
+/// `auth.sources` replaces this list and `auth.prefer` moves OAuth or API key
+/// sources to the front. Returns credentials if found, with source information
+pub fn discover_credentials_with(auth: &AuthConfig) -> ClaudeCredentials {
+    discover_from(&source_order(auth), check_source)
+}
 
-    // 2. Check ~/.claude/.credentials.json
-    if let Some(creds) = check_claude_dir() {
-        return creds;
+/// Sources to check, in order, for the given auth settings
+fn source_order(auth: &AuthConfig) -> Vec<AuthSource> {
+    let mut order = if auth.sources.is_empty() {
+        DEFAULT_SOURCE_ORDER.to_vec()
+    } else {
+        auth.sources.clone()
+    };
+
+    if let Some(prefer) = auth.prefer {
+        // Stable sort: preferred sources first, otherwise in configured order
+        let wants_api_key = prefer == AuthPreference::ApiKey;
+        order.sort_by_key(|&source| (source == AuthSource::Env) != wants_api_key);
     }
 
-    // 3. Check macOS Keychain
-    #[cfg(target_os = "macos")]
-    if let Some(creds) = check_macos_keychain() {
-        return creds;
+    order
+}
+
+/// Return the credentials from the first source that has any
+fn discover_from<F>(order: &[AuthSource], check: F) -> ClaudeCredentials
+where
+    F: Fn(AuthSource) -> Option<ClaudeCredentials>,
+{
+    order
+        .iter()
+        .find_map(|&source| check(source))
+        .unwrap_or(ClaudeCredentials {
+            source: CredentialSource::None,
+            oauth_token: None,
+            api_key: None,
+        })
+}
+
+/// Check a single credential source
+fn check_source(source: AuthSource) -> Option<ClaudeCredentials> {
+    match source {
+        AuthSource::Env => check_env_api_key(),
+        AuthSource::ClaudeDir => check_claude_dir(),
+        #[cfg(target_os = "macos")]
+        AuthSource::Keychain => check_macos_keychain(),
+        #[cfg(not(target_os = "macos"))]
+        AuthSource::Keychain => None,
+        AuthSource::ConfigDir => check_config_dir(),
     }
+}
 
-    // 4. Check ~/.config/claude/auth.json
-    if let Some(creds) = check_config_dir() {
-        return creds;
+/// Check the ANTHROPIC_API_KEY environment variable
+fn check_env_api_key() -> Option<ClaudeCredentials> {
+    let api_key = std::env::var("ANTHROPIC_API_KEY").ok()?;
+    if api_key.is_empty() {
+        return None;
     }
 
-    ClaudeCredentials {
-        source: CredentialSource::None,
+    Some(ClaudeCredentials {
+        source: CredentialSource::EnvApiKey,
         oauth_token: None,
-        api_key: None,
-    }
+        api_key: Some(api_key),
+    })
 }
 
 /// Check ~/.claude/.credentials.json for OAuth tokens
@@ -240,6 +279,80 @@ pub fn get_credential_env_vars(creds: &ClaudeCredentials) -> Vec<(String, String
 mod tests {
     use super::*;
 
+    /// Fake sources: an API key in the environment and an OAuth credentials file
+    fn fake_check(source: AuthSource) -> Option<ClaudeCredentials> {
+        match source {
+            AuthSource::Env => Some(ClaudeCredentials {
+                source: CredentialSource::EnvApiKey,
+                oauth_token: None,
+                api_key: Some("sk-test".to_string()),
+            }),
+            AuthSource::ClaudeDir => Some(ClaudeCredentials {
+                source: CredentialSource::ClaudeDir,
+                oauth_token: Some("oauth-token".to_string()),
+                api_key: None,
+            }),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_default_order_unchanged() {
+        let order = source_order(&AuthConfig::default());
+        assert_eq!(order, DEFAULT_SOURCE_ORDER.to_vec());
+        assert_eq!(
+            discover_from(&order, fake_check).source,
+            CredentialSource::EnvApiKey
+        );
+    }
+
+    #[test]
+    fn test_prefer_api_key_over_oauth_file() {
+        let auth = AuthConfig {
+            prefer: Some(AuthPreference::ApiKey),
+            sources: vec![AuthSource::ClaudeDir, AuthSource::Env],
+            ..Default::default()
+        };
+        let creds = discover_from(&source_order(&auth), fake_check);
+        assert_eq!(creds.source, CredentialSource::EnvApiKey);
+        assert_eq!(creds.api_key.as_deref(), Some("sk-test"));
+    }
+
+    #[test]
+    fn test_prefer_oauth() {
+        let auth = AuthConfig {
+            prefer: Some(AuthPreference::Oauth),
+            sources: vec![],
+            ..Default::default()
+        };
+        assert_eq!(
+            source_order(&auth),
+            vec![
+                AuthSource::ClaudeDir,
+                AuthSource::Keychain,
+                AuthSource::ConfigDir,
+                AuthSource::Env
+            ]
+        );
+        assert_eq!(
+            discover_from(&source_order(&auth), fake_check).source,
+            CredentialSource::ClaudeDir
+        );
+    }
+
+    #[test]
+    fn test_sources_restrict_checks() {
+        let auth = AuthConfig {
+            prefer: None,
+            sources: vec![AuthSource::ConfigDir],
+            ..Default::default()
+        };
+        assert_eq!(
+            discover_from(&source_order(&auth), fake_check).source,
+            CredentialSource::None
+        );
+    }
+
     #[test]
     fn test_credential_source_display() {
         assert_eq!(
@@ -1,12 +1,15 @@
 //! Lazy worktree cleanup
 //!
-//! Automatically cleans up orphaned worktrees on ccs startup.
+//! Automatically cleans up orphaned worktrees on ccs startup, and removes
+//! single worktrees on request.
 
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use crate::config::Config;
-use crate::docker::ContainerRuntime;
+use crate::docker::{self, ContainerRuntime};
+use crate::git;
+use crate::prompt;
 
 /// Result of cleanup operation
 #[derive(Debug, Default)]
@@ -44,72 +47,54 @@ impl CleanupResult {
 }
 
 /// Perform lazy cleanup of orphaned ccs worktrees
-pub fn lazy_cleanup(config: &Config) -> CleanupResult {
+///
+/// Candidates come from the ccs data dir (the default `base_path`) and from
+/// `git worktree list` in the current project, which finds ccs worktrees
+/// under a custom `base_path` such as `{repo_root}/.worktrees`.
+pub fn lazy_cleanup(config: &Config, project_path: &Path) -> CleanupResult {
     let mut result = CleanupResult::default();
 
-    // Get the worktree base directory
-    let data_dir = match dirs::data_dir() {
-        Some(d) => d.join("ccs"),
-        None => return result,
-    };
-
-    if !data_dir.exists() {
-        return result;
-    }
-
     // Get list of running ccs containers
-    let running_containers = get_running_container_worktrees();
-
-    // Iterate through repo directories in the ccs data dir
-    let entries = match std::fs::read_dir(&data_dir) {
-        Ok(e) => e,
-        Err(_) => return result,
-    };
-
-    for entry in entries.filter_map(|e| e.ok()) {
-        let repo_dir = entry.path();
-        if !repo_dir.is_dir() {
-            continue;
+    let running_containers = get_running_container_worktrees(&docker::container_prefix(config));
+
+    let repo_dirs = dirs::data_dir()
+        .map(|d| data_dir_repo_dirs(&d.join("ccs")))
+        .unwrap_or_default();
+    let mut candidates: Vec<PathBuf> = repo_dirs.iter().flat_map(|d| subdirs(d)).collect();
+    for path in listed_ccs_worktrees(project_path) {
+        if !candidates.iter().any(|c| same_path(c, &path)) {
+            candidates.push(path);
         }
+    }
 
-        // Each repo_dir contains worktree directories
-        let worktrees = match std::fs::read_dir(&repo_dir) {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-
-        for wt_entry in worktrees.filter_map(|e| e.ok()) {
-            let worktree_path = wt_entry.path();
-            if !worktree_path.is_dir() {
-                continue;
-            }
-
-            // Check if this worktree should be cleaned up
-            match should_cleanup_worktree(&worktree_path, &running_containers) {
-                CleanupDecision::Remove(reason) => match remove_worktree(&worktree_path, config) {
-                    Ok(()) => {
-                        result.removed.push(worktree_path);
-                    }
-                    Err(e) => {
-                        result.errors.push(format!(
-                            "{}: {} (reason: {})",
-                            worktree_path.display(),
-                            e,
-                            reason
-                        ));
-                    }
-                },
-                CleanupDecision::Keep(reason) => {
-                    // Only track kept worktrees for verbose output
-                    if std::env::var("CCS_VERBOSE").is_ok() {
-                        result.kept.push(worktree_path);
-                        result.errors.push(format!("Kept: {}", reason));
-                    }
+    for worktree_path in candidates {
+        // Check if this worktree should be cleaned up
+        match should_cleanup_worktree(&worktree_path, &running_containers) {
+            CleanupDecision::Remove(reason) => match remove_worktree(&worktree_path, config) {
+                Ok(()) => {
+                    result.removed.push(worktree_path);
+                }
+                Err(e) => {
+                    result.errors.push(format!(
+                        "{}: {} (reason: {})",
+                        worktree_path.display(),
+                        e,
+                        reason
+                    ));
+                }
+            },
+            CleanupDecision::Keep(reason) => {
+                // Only track kept worktrees for verbose output
+                if std::env::var("CCS_VERBOSE").is_ok() {
+                    result.kept.push(worktree_path);
+                    result.errors.push(format!("Kept: {}", reason));
                 }
             }
         }
+    }
 
-        // Remove empty repo directories
+    // Remove empty repo directories
+    for repo_dir in repo_dirs {
         if repo_dir
             .read_dir()
             .map(|mut d| d.next().is_none())
@@ -122,6 +107,43 @@ pub fn lazy_cleanup(config: &Config) -> CleanupResult {
     result
 }
 
+/// Per-repo worktree directories in the ccs data dir
+fn data_dir_repo_dirs(data_dir: &Path) -> Vec<PathBuf> {
+    let logs_dir = crate::docker::session_logs_dir();
+    subdirs(data_dir)
+        .into_iter()
+        .filter(|dir| Some(dir) != logs_dir.as_ref())
+        .collect()
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect()
+}
+
+/// Worktrees of the project's repository that ccs created, wherever they are
+fn listed_ccs_worktrees(project_path: &Path) -> Vec<PathBuf> {
+    git::list_worktrees(project_path)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| entry.path.is_dir() && entry.is_ccs_created())
+        .map(|entry| entry.path)
+        .collect()
+}
+
+/// Whether two paths name the same directory, resolving symlinks when possible
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
 enum CleanupDecision {
     Remove(String),
     Keep(String),
@@ -136,6 +158,11 @@ fn should_cleanup_worktree(
         return CleanupDecision::Keep("container is running".to_string());
     }
 
+    // Respect `git worktree lock`
+    if is_worktree_locked(worktree_path) {
+        return CleanupDecision::Keep("locked".to_string());
+    }
+
     // Check if this is a valid git worktree
     let git_file = worktree_path.join(".git");
     if !git_file.exists() {
@@ -166,6 +193,22 @@ fn should_cleanup_worktree(
     CleanupDecision::Remove("no changes, no running container".to_string())
 }
 
+/// Check whether a worktree was locked with `git worktree lock`
+///
+/// Git marks a locked worktree with a `locked` file in its admin directory
+/// (`.git/worktrees/<name>/`), which the worktree's `.git` file points to.
+fn is_worktree_locked(worktree_path: &Path) -> bool {
+    let Ok(content) = std::fs::read_to_string(worktree_path.join(".git")) else {
+        return false;
+    };
+    let Some(gitdir) = content.strip_prefix("gitdir: ") else {
+        return false;
+    };
+
+    // Relative gitdir paths are relative to the worktree
+    worktree_path.join(gitdir.trim()).join("locked").exists()
+}
+
 fn has_uncommitted_changes(worktree_path: &Path) -> bool {
     let output = Command::new("git")
         .args(["status", "--porcelain"])
@@ -179,49 +222,150 @@ fn has_uncommitted_changes(worktree_path: &Path) -> bool {
 }
 
 fn has_unmerged_commits(worktree_path: &Path) -> bool {
-    // Get the current branch
-    let branch_output = Command::new("git")
+    // If we can't determine, assume there are unmerged commits
+    unmerged_commit_count(worktree_path).is_none_or(|count| count > 0)
+}
+
+/// Get the branch checked out in a worktree, or None if detached or unknown
+fn current_branch(worktree_path: &Path) -> Option<String> {
+    let output = Command::new("git")
         .args(["branch", "--show-current"])
         .current_dir(worktree_path)
-        .output();
+        .output()
+        .ok()?;
 
-    let branch = match branch_output {
-        Ok(o) => String::from_utf8_lossy(&o.stdout).trim().to_string(),
-        Err(_) => return true, // Assume unmerged if we can't check
-    };
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !branch.is_empty()).then_some(branch)
+}
+
+/// Count commits on the worktree's branch that are not in main/master
+///
+/// Returns None if the count can't be determined.
+fn unmerged_commit_count(worktree_path: &Path) -> Option<usize> {
+    let branch = current_branch(worktree_path)?;
 
     // Skip if this is main/master
     if branch == "main" || branch == "master" {
-        return false;
+        return Some(0);
     }
 
     // Check if branch has commits not in main/master
     // Try main first, then master
     for base in ["main", "master", "origin/main", "origin/master"] {
         let output = Command::new("git")
-            .args(["log", &format!("{}..HEAD", base), "--oneline"])
+            .args(["rev-list", "--count", &format!("{}..HEAD", base)])
             .current_dir(worktree_path)
             .output();
 
         if let Ok(o) = output {
             if o.status.success() {
-                return !o.stdout.is_empty();
+                return String::from_utf8_lossy(&o.stdout).trim().parse().ok();
             }
         }
     }
 
-    // If we can't determine, assume there are unmerged commits
-    true
+    None
+}
+
+/// Describe the work that would be lost by removing a worktree, if any
+fn describe_unsaved_work(
+    branch: Option<&str>,
+    unmerged: Option<usize>,
+    dirty: bool,
+) -> Option<String> {
+    let mut problems = Vec::new();
+    match unmerged {
+        Some(0) => {}
+        Some(1) => problems.push("1 unmerged commit".to_string()),
+        Some(n) => problems.push(format!("{} unmerged commits", n)),
+        None => problems.push("an unknown number of unmerged commits".to_string()),
+    }
+    if dirty {
+        problems.push("uncommitted changes".to_string());
+    }
+
+    if problems.is_empty() {
+        return None;
+    }
+
+    let branch = match branch {
+        Some(b) => format!("branch '{}'", b),
+        None => "detached HEAD".to_string(),
+    };
+    Some(format!("{} has {}", branch, problems.join(" and ")))
+}
+
+/// Remove a single worktree on request
+///
+/// Worktrees with unmerged commits or uncommitted changes are only removed
+/// with `force`, after an interactive confirmation or with `assume_yes`.
+pub fn remove_worktree_checked(
+    worktree_path: &Path,
+    config: &Config,
+    force: bool,
+    assume_yes: bool,
+) -> anyhow::Result<()> {
+    let worktree_path = worktree_path.canonicalize().map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to resolve worktree path '{}': {}",
+            worktree_path.display(),
+            e
+        )
+    })?;
+
+    if !worktree_path.join(".git").is_file() {
+        anyhow::bail!("'{}' is not a git worktree", worktree_path.display());
+    }
+
+    if is_worktree_locked(&worktree_path) {
+        anyhow::bail!(
+            "'{}' is locked; run 'git worktree unlock {}' first",
+            worktree_path.display(),
+            worktree_path.display()
+        );
+    }
+
+    let branch = current_branch(&worktree_path);
+    let unsaved = describe_unsaved_work(
+        branch.as_deref(),
+        unmerged_commit_count(&worktree_path),
+        has_uncommitted_changes(&worktree_path),
+    );
+
+    if let Some(unsaved) = unsaved {
+        if !force {
+            anyhow::bail!("{}; use --force to remove it anyway", unsaved);
+        }
+
+        let question = format!("{}. Remove {} anyway?", unsaved, worktree_path.display());
+        if !prompt::confirm(&question, assume_yes, prompt::stdin_is_tty())? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    remove_worktree(&worktree_path, config).map_err(anyhow::Error::msg)?;
+    println!("Removed worktree: {}", worktree_path.display());
+    Ok(())
 }
 
-fn get_running_container_worktrees() -> Vec<PathBuf> {
+fn get_running_container_worktrees(prefix: &str) -> Vec<PathBuf> {
     let runtime = match ContainerRuntime::detect() {
         Ok(r) => r,
         Err(_) => return vec![],
     };
 
-    let output = Command::new(runtime.command())
-        .args(["ps", "--filter", "name=ccs-", "--format", "{{.Mounts}}"])
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "--filter",
+            &docker::container_name_filter(prefix),
+            "--filter",
+            docker::SESSION_LABEL_FILTER,
+            "--format",
+            "{{.Mounts}}",
+        ])
         .output();
 
     let output = match output {
@@ -342,6 +486,138 @@ mod tests {
         assert!(!has_uncommitted_changes(dir.path()));
     }
 
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn commit_file(dir: &Path, name: &str) {
+        fs::write(dir.join(name), name).unwrap();
+        git(dir, &["add", "."]);
+        git(dir, &["commit", "-q", "-m", name]);
+    }
+
+    #[test]
+    fn test_locked_worktree_is_kept() {
+        let dir = TempDir::new().unwrap();
+        let repo = dir.path().join("repo");
+        let worktree = dir.path().join("feature");
+        fs::create_dir(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["config", "user.email", "test@test.com"]);
+        git(&repo, &["config", "user.name", "Test"]);
+        commit_file(&repo, "a.txt");
+        git(
+            &repo,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "feature",
+                worktree.to_str().unwrap(),
+            ],
+        );
+        assert!(!is_worktree_locked(&worktree));
+
+        git(&repo, &["worktree", "lock", worktree.to_str().unwrap()]);
+        assert!(is_worktree_locked(&worktree));
+        assert!(matches!(
+            should_cleanup_worktree(&worktree, &[]),
+            CleanupDecision::Keep(reason) if reason == "locked"
+        ));
+        assert!(remove_worktree_checked(&worktree, &Config::default(), true, true).is_err());
+        assert!(worktree.exists());
+    }
+
+    #[test]
+    fn test_listed_ccs_worktrees() {
+        let dir = TempDir::new().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["config", "user.email", "test@test.com"]);
+        git(&repo, &["config", "user.name", "Test"]);
+        commit_file(&repo, "a.txt");
+        fs::create_dir(repo.join(".worktrees")).unwrap();
+
+        let ccs = repo.join(".worktrees/fix-auth");
+        let other = dir.path().join("topic");
+        for (branch, path) in [("ccs/fix-auth", &ccs), ("topic", &other)] {
+            git(
+                &repo,
+                &[
+                    "worktree",
+                    "add",
+                    "-q",
+                    "-b",
+                    branch,
+                    path.to_str().unwrap(),
+                ],
+            );
+        }
+
+        // Only the marked worktree is picked up, not the user's own, even on a
+        // branch named like ccs's
+        git::mark_ccs_worktree(&ccs).unwrap();
+        let user_ccs = dir.path().join("user-ccs");
+        git(
+            &repo,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "ccs/mine",
+                user_ccs.to_str().unwrap(),
+            ],
+        );
+        let listed = listed_ccs_worktrees(&repo);
+        assert_eq!(listed.len(), 1);
+        assert!(same_path(&listed[0], &ccs));
+        assert!(listed_ccs_worktrees(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_unmerged_commit_count() {
+        let dir = TempDir::new().unwrap();
+        git(dir.path(), &["init", "-q"]);
+        git(dir.path(), &["checkout", "-q", "-b", "main"]);
+        git(dir.path(), &["config", "user.email", "test@test.com"]);
+        git(dir.path(), &["config", "user.name", "Test"]);
+        commit_file(dir.path(), "base.txt");
+
+        assert_eq!(unmerged_commit_count(dir.path()), Some(0));
+
+        git(dir.path(), &["checkout", "-q", "-b", "feature"]);
+        assert_eq!(unmerged_commit_count(dir.path()), Some(0));
+        assert!(!has_unmerged_commits(dir.path()));
+
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            commit_file(dir.path(), name);
+        }
+        assert_eq!(unmerged_commit_count(dir.path()), Some(3));
+        assert!(has_unmerged_commits(dir.path()));
+    }
+
+    #[test]
+    fn test_describe_unsaved_work() {
+        assert_eq!(describe_unsaved_work(Some("feature"), Some(0), false), None);
+        assert_eq!(
+            describe_unsaved_work(Some("feature"), Some(3), false).as_deref(),
+            Some("branch 'feature' has 3 unmerged commits")
+        );
+        assert_eq!(
+            describe_unsaved_work(None, Some(1), true).as_deref(),
+            Some("detached HEAD has 1 unmerged commit and uncommitted changes")
+        );
+    }
+
     #[test]
     fn test_has_uncommitted_changes_dirty() {
         let dir = TempDir::new().unwrap();
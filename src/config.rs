@@ -1,13 +1,20 @@
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+/// Name of the project-local config file, merged over the global config
+pub const PROJECT_CONFIG_FILE: &str = ".ccs.toml";
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Failed to determine config directory")]
     NoConfigDir,
 
+    #[error("Config file not found: {0}")]
+    NotFound(PathBuf),
+
     #[error("Failed to read config file: {0}")]
     ReadError(#[from] std::io::Error),
 
@@ -16,10 +23,16 @@ pub enum ConfigError {
 
     #[error("Failed to serialize config: {0}")]
     SerializeError(#[from] toml::ser::Error),
+
+    #[error("MCP server '{0}' already exists; remove it first with --mcp-remove {0}")]
+    ServerExists(String),
+
+    #[error("No MCP server named '{0}'")]
+    ServerNotFound(String),
 }
 
 /// Main configuration structure
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Config {
     /// Docker-related settings
@@ -31,11 +44,35 @@ pub struct Config {
     /// Secrets backend configuration
     pub secrets: SecretsConfig,
 
+    /// Git identity settings for commits made in the container
+    pub git: GitConfig,
+
+    /// Generated MCP config settings
+    pub mcp: McpConfig,
+
+    /// Editor used by `--open`
+    pub editor: EditorConfig,
+
+    /// Directories ccs may or may not run in
+    pub safety: SafetyConfig,
+
+    /// Which Claude credential sources to use, and in what order
+    pub auth: AuthConfig,
+
+    /// Sidecar service containers started alongside each session, keyed by name
+    pub services: HashMap<String, ServiceConfig>,
+
+    /// Timing spans exported over OTLP (requires the `telemetry` build feature)
+    pub telemetry: TelemetryConfig,
+
+    /// Claude Code options
+    pub claude: ClaudeConfig,
+
     /// Path to the MCP servers configuration file
     pub mcp_config_path: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct DockerConfig {
     /// Docker image name
@@ -44,6 +81,17 @@ pub struct DockerConfig {
     /// Path to the Dockerfile (for building)
     pub dockerfile_path: Option<PathBuf>,
 
+    /// Build context directory (defaults to the Dockerfile's directory)
+    pub build_context: Option<PathBuf>,
+
+    /// Warn when a build context without a .dockerignore exceeds this many
+    /// megabytes (0 disables the check)
+    pub build_context_warn_mb: u64,
+
+    /// Stage of a multi-stage Dockerfile to build (`--target`); the final
+    /// stage when unset
+    pub build_target: Option<String>,
+
     /// Additional volumes to mount (host_path: container_path)
     pub extra_volumes: HashMap<String, String>,
 
@@ -67,21 +115,320 @@ pub struct DockerConfig {
 
     /// Custom .env file path (relative to project, defaults to ".env")
     pub env_file_path: String,
+
+    /// Forward the host SSH agent socket into the container
+    pub forward_ssh_agent: bool,
+
+    /// Stop detached sessions idle for longer than this many seconds
+    pub max_idle_secs: Option<u64>,
+
+    /// Build arguments passed to the image build (values may be secret references)
+    pub build_args: HashMap<String, String>,
+
+    /// Claude Code version installed by the image build (`CLAUDE_VERSION`
+    /// build arg); the Dockerfile defaults to "latest"
+    pub claude_version: Option<String>,
+
+    /// Pass the host timezone (TZ) and locale (LANG, LC_ALL) into the container
+    pub inherit_timezone: bool,
+
+    /// Copy detached session output to ~/.local/share/ccs/logs/<container>.log
+    pub log_to_file: bool,
+
+    /// After `-d`, wait up to this many seconds for the session to be running
+    /// (or healthy, if the image defines a HEALTHCHECK)
+    pub wait_for_ready_secs: Option<u64>,
+
+    /// Run the container as the host UID:GID so files created in the
+    /// workspace are owned by the host user (Unix only)
+    pub match_host_uid: bool,
+
+    /// Mount ~/.claude/settings.json read-only, plus the main repo's CLAUDE.md
+    /// when a worktree doesn't have one (e.g. because it is untracked)
+    pub mount_claude_settings: bool,
+
+    /// Build the image before running if it doesn't exist locally
+    pub auto_build_if_missing: bool,
+
+    /// When to pull registry images (names with a `/`); local images are built
+    pub pull_policy: PullPolicy,
+
+    /// Prefix for session container names (`<prefix>-<repo>-<id>`)
+    pub container_prefix: String,
+
+    /// Arguments passed to Claude on every run, before any given on the command line
+    pub default_claude_args: Vec<String>,
+
+    /// Mount the workspace at its host path instead of /workspace, so
+    /// absolute paths match between host and container
+    pub mirror_host_path: bool,
+
+    /// Keep the container user's ~/.claude (conversation history, caches) in a
+    /// per-repo named volume so it survives container recreation
+    pub persist_claude_state: bool,
+
+    /// Shell started by `--shell`; falls back to /bin/sh if the image lacks it
+    pub shell: String,
+
+    /// Run a minimal init (`--init`) as PID 1 to reap zombie processes;
+    /// disable if the image brings its own init
+    pub use_init: bool,
+
+    /// Extra flags passed verbatim to `docker run` before the image name
+    /// (not validated; they may conflict with flags ccs sets). Global config only
+    pub extra_run_args: Vec<String>,
+
+    /// Podman connection (from `podman system connection list`) passed as
+    /// `--connection` to every podman command; ignored with Docker
+    pub connection: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct WorktreeConfig {
     /// Base path for creating new worktrees
     /// Supports {repo_name} placeholder
     pub base_path: String,
+
+    /// Only check out these directories in new worktrees (git sparse-checkout,
+    /// cone mode); files at the repository root are always included
+    pub sparse_paths: Vec<String>,
+
+    /// Fetch the base ref's remote (or origin) before creating a branch from it
+    pub fetch_before_create: bool,
+
+    /// Name for generated branches; supports {user}, {slug} (current branch),
+    /// {date}, {timestamp} and {uuid}
+    pub branch_template: String,
+
+    /// What `--new` does when the worktree directory already exists
+    pub on_exists: WorktreeOnExists,
+
+    /// Glob patterns, relative to the main repo, of untracked or ignored files
+    /// (e.g. `.env`) to copy into new worktrees
+    pub copy_patterns: Vec<String>,
+
+    /// After a session in a worktree exits cleanly, offer to push its branch
+    /// to `git.default_remote` (`--yes` pushes without asking)
+    pub push_on_exit: bool,
+}
+
+/// When to pull a registry image (`docker.pull_policy`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PullPolicy {
+    /// Only use the local copy
+    Never,
+    /// Pull if there is no local copy
+    #[default]
+    Missing,
+    /// Pull before every run to pick up updates
+    Always,
+}
+
+impl PullPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PullPolicy::Never => "never",
+            PullPolicy::Missing => "missing",
+            PullPolicy::Always => "always",
+        }
+    }
+}
+
+/// Strategy for `--new` when the worktree directory already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WorktreeOnExists {
+    /// Fail with an error
+    #[default]
+    Error,
+    /// Use the existing worktree
+    Reuse,
+    /// Append -2, -3, ... to the name until it is free
+    Suffix,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct SecretsConfig {
-    /// Secrets backend: "1password", "bitwarden", "pass", or "env"
+    /// Secrets backend: "1password", "bitwarden", "pass", "gcp", "azure", "doppler",
+    /// "custom", or "env"
+    #[schemars(schema_with = "backend_schema")]
     pub backend: String,
+
+    /// Cache resolved secrets on disk (encrypted) for this many seconds;
+    /// 0 disables the cache
+    pub cache_ttl_secs: u64,
+
+    /// External resolver for `cmd://` references
+    pub custom: CustomSecretsConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct CustomSecretsConfig {
+    /// Shell command printing the secret to stdout; `{ref}` is replaced with
+    /// the reference after `cmd://`. Only read from the global config
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct GitConfig {
+    /// Commit author/committer name (defaults to host `git config user.name`)
+    pub user_name: Option<String>,
+
+    /// Commit author/committer email (defaults to host `git config user.email`)
+    pub user_email: Option<String>,
+
+    /// Let git in the container push over HTTPS with the host's credentials: a
+    /// token from `gh auth token`/`git credential fill`, else ~/.git-credentials
+    pub forward_credentials: bool,
+
+    /// Remote that `worktree.push_on_exit` pushes to
+    pub default_remote: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct McpConfig {
+    /// Write the generated Claude MCP config here (overwritten each run)
+    /// instead of a temp file, e.g. for inspection. All sessions share the
+    /// file, so starting one changes it under those already running
+    pub output_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct EditorConfig {
+    /// Command to open worktrees with (e.g. "code"); defaults to $VISUAL, then $EDITOR
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SafetyConfig {
+    /// Directories ccs refuses to run in (exact match, e.g. "~")
+    pub blocked_paths: Vec<String>,
+
+    /// If non-empty, ccs only runs in projects under one of these directories
+    pub allowed_roots: Vec<String>,
+
+    /// `--doctor` warns when the worktree directory has less free space (GB)
+    pub min_free_gb: f64,
+
+    /// Projects (and directories of projects) whose `.ccs.toml` may set more
+    /// than the sandbox-only settings; host-side settings stay global-only
+    pub trusted_projects: Vec<String>,
+}
+
+/// A place Claude credentials can be discovered
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthSource {
+    /// ANTHROPIC_API_KEY environment variable
+    Env,
+    /// ~/.claude/.credentials.json (OAuth)
+    ClaudeDir,
+    /// macOS Keychain (OAuth; ignored elsewhere)
+    Keychain,
+    /// ~/.config/claude/auth.json (OAuth)
+    ConfigDir,
+}
+
+/// Kind of credential to try first
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthPreference {
+    Oauth,
+    ApiKey,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Try OAuth or API key sources first, keeping their relative order
+    pub prefer: Option<AuthPreference>,
+
+    /// Sources to check, in order (default: env, claude_dir, keychain, config_dir)
+    pub sources: Vec<AuthSource>,
+
+    /// Refuse to start a session when no credentials are found, instead of
+    /// warning and letting Claude fail inside the container
+    pub require_credentials: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ClaudeConfig {
+    /// Permission mode passed to Claude as `--permission-mode`
+    pub permission_mode: Option<PermissionMode>,
+}
+
+/// Claude Code permission mode
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionMode {
+    Default,
+    AcceptEdits,
+    Plan,
+    BypassPermissions,
+}
+
+impl PermissionMode {
+    pub const ALL: [PermissionMode; 4] = [
+        PermissionMode::Default,
+        PermissionMode::AcceptEdits,
+        PermissionMode::Plan,
+        PermissionMode::BypassPermissions,
+    ];
+
+    /// Name as Claude Code spells it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PermissionMode::Default => "default",
+            PermissionMode::AcceptEdits => "acceptEdits",
+            PermissionMode::Plan => "plan",
+            PermissionMode::BypassPermissions => "bypassPermissions",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP collector URL, e.g. "http://localhost:4318"; unset disables telemetry
+    pub endpoint: Option<String>,
+}
+
+/// A sidecar container reachable from the session by its service name
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ServiceConfig {
+    /// Image to run
+    pub image: String,
+
+    /// Environment variables (values may be secret references, except in
+    /// a project's `.ccs.toml`)
+    pub env: HashMap<String, String>,
+
+    /// Ports published to the host (`-p` syntax, e.g. "5432:5432")
+    pub ports: Vec<String>,
+}
+
+/// Schema for `secrets.backend`: one of the supported backend names
+fn backend_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "string",
+        "enum": crate::secrets::backend_names(),
+        "default": SecretsConfig::default().backend,
+    })
+}
+
+/// JSON Schema for `config.toml`, for editor validation and completion
+pub fn config_schema() -> Schema {
+    schemars::schema_for!(Config)
 }
 
 impl Default for DockerConfig {
@@ -89,6 +436,9 @@ impl Default for DockerConfig {
         Self {
             image: "ccs:latest".to_string(),
             dockerfile_path: None,
+            build_context: None,
+            build_context_warn_mb: 100,
+            build_target: None,
             extra_volumes: HashMap::new(),
             extra_env: HashMap::new(),
             user: "claude".to_string(),
@@ -97,6 +447,25 @@ impl Default for DockerConfig {
             cpu_limit: None,
             load_env_file: true,
             env_file_path: ".env".to_string(),
+            forward_ssh_agent: false,
+            max_idle_secs: None,
+            build_args: HashMap::new(),
+            claude_version: None,
+            inherit_timezone: true,
+            log_to_file: false,
+            wait_for_ready_secs: None,
+            match_host_uid: false,
+            mount_claude_settings: false,
+            auto_build_if_missing: true,
+            pull_policy: PullPolicy::Missing,
+            container_prefix: "ccs".to_string(),
+            default_claude_args: Vec::new(),
+            mirror_host_path: false,
+            persist_claude_state: false,
+            shell: "/bin/bash".to_string(),
+            use_init: true,
+            extra_run_args: Vec::new(),
+            connection: None,
         }
     }
 }
@@ -105,6 +474,34 @@ impl Default for WorktreeConfig {
     fn default() -> Self {
         Self {
             base_path: "{data_dir}/ccs/{repo_name}".to_string(),
+            sparse_paths: Vec::new(),
+            fetch_before_create: false,
+            branch_template: "ccs-{timestamp}".to_string(),
+            on_exists: WorktreeOnExists::Error,
+            copy_patterns: Vec::new(),
+            push_on_exit: false,
+        }
+    }
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            user_name: None,
+            user_email: None,
+            forward_credentials: false,
+            default_remote: "origin".to_string(),
+        }
+    }
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            blocked_paths: Vec::new(),
+            allowed_roots: Vec::new(),
+            min_free_gb: 5.0,
+            trusted_projects: Vec::new(),
         }
     }
 }
@@ -113,6 +510,8 @@ impl Default for SecretsConfig {
     fn default() -> Self {
         Self {
             backend: "env".to_string(),
+            cache_ttl_secs: 0,
+            custom: CustomSecretsConfig::default(),
         }
     }
 }
@@ -125,22 +524,65 @@ impl Config {
     }
 
     /// Returns the path to the MCP servers config file
-    pub fn mcp_servers_path() -> Result<PathBuf, ConfigError> {
+    /// Uses `mcp_config_path` if set, otherwise `~/.config/ccs/mcp.toml`
+    pub fn mcp_servers_path(&self) -> Result<PathBuf, ConfigError> {
+        if let Some(ref path) = self.mcp_config_path {
+            return Ok(expand_path(&path.to_string_lossy()));
+        }
+
         let config_dir = dirs::config_dir().ok_or(ConfigError::NoConfigDir)?;
         Ok(config_dir.join("ccs").join("mcp.toml"))
     }
 
-    /// Load configuration from file, falling back to defaults
-    pub fn load() -> Result<Self, ConfigError> {
-        let config_path = Self::config_path()?;
+    /// Load the global config, falling back to defaults, and merge a
+    /// project-local `.ccs.toml` on top
+    pub fn load_with_project(project_path: &Path) -> Result<Self, ConfigError> {
+        Self::load_layered(&Self::config_path()?, project_path)
+    }
 
-        if config_path.exists() {
-            let contents = std::fs::read_to_string(&config_path)?;
-            let config: Config = toml::from_str(&contents)?;
-            Ok(config)
-        } else {
-            Ok(Config::default())
+    /// Load the config from an explicit file instead of the default location,
+    /// merging a project-local `.ccs.toml` on top
+    ///
+    /// Unlike the default path, a missing explicit file is an error.
+    pub fn load_from(path: &Path, project_path: &Path) -> Result<Self, ConfigError> {
+        if !path.is_file() {
+            return Err(ConfigError::NotFound(path.to_path_buf()));
         }
+        Self::load_layered(path, project_path)
+    }
+
+    /// Load only the global config, from `path` or the default location
+    ///
+    /// For settings a project must not influence, such as `[safety]`.
+    pub fn load_global(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => Self::config_path()?,
+        };
+        Ok(read_toml_table(&path)?.try_into()?)
+    }
+
+    /// Load `global_path` and deep-merge the project config found from `project_path`
+    ///
+    /// Only the settings [`project_overlay`] lets through are taken from the
+    /// project; the rest are ignored with a warning.
+    fn load_layered(global_path: &Path, project_path: &Path) -> Result<Self, ConfigError> {
+        let mut value = read_toml_table(global_path)?;
+
+        if let Some(project_file) = find_project_config(project_path) {
+            let global: Config = value.clone().try_into()?;
+            let trusted = is_trusted_project(&project_file, &global.safety.trusted_projects);
+            let mut project = read_toml_table(&project_file)?;
+            // MCP servers are read by McpServersConfig
+            if let toml::Value::Table(ref mut table) = project {
+                table.remove("servers");
+            }
+            let (overlay, ignored) = project_overlay(project, trusted);
+            warn_ignored(&project_file, &ignored);
+            merge_toml(&mut value, overlay);
+        }
+
+        Ok(value.try_into()?)
     }
 
     /// Serialize config to TOML string
@@ -148,10 +590,55 @@ impl Config {
         Ok(toml::to_string_pretty(self)?)
     }
 
+    /// Rewrite the config file at `path` with every current setting filled in
+    ///
+    /// Values set in the file are kept and settings it doesn't mention get
+    /// their defaults, each commented with its description. The original is
+    /// copied to `<path>.bak` first (`<path>.bak.1`, `.bak.2`, ... if earlier
+    /// backups exist); comments in it are not carried over.
+    pub fn upgrade_on_disk(path: &Path) -> Result<ConfigUpgrade, ConfigError> {
+        if !path.is_file() {
+            return Err(ConfigError::NotFound(path.to_path_buf()));
+        }
+        let old = read_toml_table(path)?;
+        let config: Config = old.clone().try_into()?;
+        let new = toml::Value::try_from(&config)?;
+
+        let mut dropped_keys = Vec::new();
+        unknown_keys(&old, &new, "", &mut dropped_keys);
+
+        let schema = serde_json::to_value(config_schema()).unwrap_or_default();
+        let contents = format!(
+            "# ccs configuration, regenerated by `ccs --reconfigure`\n\n{}",
+            annotate_toml(&config.to_toml()?, &schema)
+        );
+
+        let backup = free_backup_path(path);
+        std::fs::copy(path, &backup)?;
+        std::fs::write(path, contents)?;
+
+        Ok(ConfigUpgrade {
+            backup,
+            dropped_keys,
+        })
+    }
+
     /// Resolve worktree base path with placeholders
-    /// Supports: {repo_name}, {data_dir} (XDG_DATA_HOME, defaults to ~/.local/share)
-    pub fn resolve_worktree_path(&self, repo_name: &str, repo_parent: &std::path::Path) -> PathBuf {
-        let mut path_str = self.worktree.base_path.replace("{repo_name}", repo_name);
+    /// Supports: {repo_name}, {repo_root} (the main repo's working directory),
+    /// {data_dir} (XDG_DATA_HOME, defaults to ~/.local/share)
+    pub fn resolve_worktree_path(&self, repo_name: &str, repo_root: &std::path::Path) -> PathBuf {
+        let mut path_str = self
+            .worktree
+            .base_path
+            .replace("{repo_name}", repo_name)
+            .replace(
+                "{repo_root}",
+                // Drops a trailing separator, as in git2's workdir()
+                &repo_root
+                    .components()
+                    .collect::<PathBuf>()
+                    .to_string_lossy(),
+            );
 
         // Replace {data_dir} with XDG_DATA_HOME
         if path_str.contains("{data_dir}") {
@@ -160,30 +647,412 @@ impl Config {
             path_str = path_str.replace("{data_dir}", &data_dir.to_string_lossy());
         }
 
-        let path = PathBuf::from(&path_str);
+        let path = expand_path(&path_str);
 
         if path.is_absolute() {
-            // Handle ~ expansion for absolute paths
-            if let Some(stripped) = path_str.strip_prefix("~/") {
-                if let Some(home) = dirs::home_dir() {
-                    return home.join(stripped);
-                }
-            }
             path
         } else {
             // Relative path is relative to repo's parent directory
-            repo_parent.join(path)
+            repo_root.parent().unwrap_or(repo_root).join(path)
+        }
+    }
+}
+
+/// First of `<path>.bak`, `<path>.bak.1`, `<path>.bak.2`, ... that doesn't exist
+fn free_backup_path(path: &Path) -> PathBuf {
+    let candidate = |suffix: String| {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(suffix);
+        PathBuf::from(backup)
+    };
+    std::iter::once(".bak".to_string())
+        .chain((1..).map(|n| format!(".bak.{}", n)))
+        .map(candidate)
+        .find(|backup| !backup.exists())
+        .expect("unbounded candidates")
+}
+
+/// Result of [`Config::upgrade_on_disk`]
+#[derive(Debug)]
+pub struct ConfigUpgrade {
+    /// Copy of the file as it was before the rewrite
+    pub backup: PathBuf,
+    /// Dotted paths of keys ccs no longer knows, which were left out
+    pub dropped_keys: Vec<String>,
+}
+
+/// Collect the dotted paths of keys in `old` that are missing from `new`
+fn unknown_keys(old: &toml::Value, new: &toml::Value, prefix: &str, out: &mut Vec<String>) {
+    let (toml::Value::Table(old), toml::Value::Table(new)) = (old, new) else {
+        return;
+    };
+    for (key, old_value) in old {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match new.get(key) {
+            Some(new_value) => unknown_keys(old_value, new_value, &path, out),
+            None => out.push(path),
+        }
+    }
+}
+
+/// Prefix tables and keys in serialized config with their schema descriptions
+fn annotate_toml(toml: &str, schema: &serde_json::Value) -> String {
+    let mut out = String::new();
+    let mut table = object_schema(schema, schema);
+    let mut in_multiline_string = false;
+
+    for line in toml.lines() {
+        let description = if in_multiline_string || line.starts_with(char::is_whitespace) {
+            None
+        } else if let Some(header) = line.strip_prefix('[') {
+            let header = header.trim_start_matches('[').trim_end_matches(']');
+            let mut node = Some(schema);
+            let mut property = None;
+            for segment in header.split('.') {
+                property = node
+                    .and_then(|n| object_schema(schema, n))
+                    .and_then(|n| n.get("properties"))
+                    .and_then(|p| p.get(segment.trim_matches('"')));
+                node = property;
+            }
+            table = node.and_then(|n| object_schema(schema, n));
+            property.and_then(|p| p.get("description"))
+        } else if let Some((key, _)) = line.split_once(" = ") {
+            table
+                .and_then(|t| t.get("properties"))
+                .and_then(|p| p.get(key.trim_matches('"')))
+                .and_then(|p| p.get("description"))
+        } else {
+            None
+        };
+
+        if let Some(description) = description.and_then(|d| d.as_str()) {
+            for comment in description.lines() {
+                out.push_str(format!("# {}", comment).trim_end());
+                out.push('\n');
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+
+        if (line.matches("\"\"\"").count() + line.matches("'''").count()) % 2 == 1 {
+            in_multiline_string = !in_multiline_string;
+        }
+    }
+    out
+}
+
+/// The object schema `node` describes, following `$ref`s, `anyOf` and array items
+fn object_schema<'a>(
+    root: &'a serde_json::Value,
+    node: &'a serde_json::Value,
+) -> Option<&'a serde_json::Value> {
+    if node.get("properties").is_some() {
+        return Some(node);
+    }
+    if let Some(reference) = node.get("$ref").and_then(|r| r.as_str()) {
+        let target = reference
+            .strip_prefix("#/")?
+            .split('/')
+            .try_fold(root, |n, segment| n.get(segment))?;
+        return object_schema(root, target);
+    }
+    if let Some(variants) = node.get("anyOf").and_then(|v| v.as_array()) {
+        return variants.iter().find_map(|v| object_schema(root, v));
+    }
+    node.get("items")
+        .and_then(|items| object_schema(root, items))
+}
+
+/// Expand `~`, `~user` and `$VAR`/`${VAR}` in a configured path
+///
+/// References to unset environment variables are left in place literally,
+/// so a typo shows up in the resulting path rather than silently vanishing.
+pub fn expand_path(path: &str) -> PathBuf {
+    PathBuf::from(expand_env_vars(&expand_tilde(path)))
+}
+
+/// Expand a leading `~` or `~user`
+fn expand_tilde(path: &str) -> String {
+    let Some(rest) = path.strip_prefix('~') else {
+        return path.to_string();
+    };
+
+    let (user, remainder) = match rest.find('/') {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, ""),
+    };
+
+    let home = if user.is_empty() {
+        dirs::home_dir()
+    } else {
+        user_home_dir(user)
+    };
+
+    match home {
+        Some(home) => format!("{}{}", home.display(), remainder),
+        None => path.to_string(),
+    }
+}
+
+/// Look up another user's home directory (best-effort, Unix only)
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    if !cfg!(unix) {
+        return None;
+    }
+
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() >= 6 && fields[0] == user).then(|| PathBuf::from(fields[5]))
+    })
+}
+
+/// Expand `$VAR` and `${VAR}`, leaving unset variables untouched
+fn expand_env_vars(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut rest = path;
+
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        let (name, consumed) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => result.push_str(&value),
+            _ => result.push_str(&rest[pos..pos + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Find the nearest `.ccs.toml` between `project_path` and its repository root
+///
+/// Outside a repository only `project_path` itself is checked.
+pub fn find_project_config(project_path: &Path) -> Option<PathBuf> {
+    let root = repo_root(project_path);
+
+    for dir in project_path.ancestors() {
+        let candidate = dir.join(PROJECT_CONFIG_FILE);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+
+        // Don't look past the repository root
+        if dir == root {
+            break;
+        }
+    }
+
+    None
+}
+
+/// The nearest directory at or above `path` with a `.git`, or `path` itself
+/// outside a repository
+pub fn repo_root(path: &Path) -> &Path {
+    path.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(path)
+}
+
+/// Settings a project `.ccs.toml` may set without being trusted
+///
+/// They only shape the sandbox; nothing here runs commands or reads files on
+/// the host, or hands credentials to the container.
+const PROJECT_SAFE_KEYS: &[&str] = &[
+    "docker.extra_env",
+    "docker.build_args",
+    "docker.build_target",
+    "docker.claude_version",
+    "docker.memory_limit",
+    "docker.cpu_limit",
+    "docker.workdir",
+    "docker.shell",
+    "docker.inherit_timezone",
+    "docker.persist_claude_state",
+    "docker.wait_for_ready_secs",
+    "docker.use_init",
+    "docker.max_idle_secs",
+    "worktree.sparse_paths",
+    "worktree.copy_patterns",
+    "worktree.fetch_before_create",
+    "worktree.branch_template",
+    "worktree.on_exists",
+    "git.user_name",
+    "git.user_email",
+];
+
+/// Settings only the global config may set, even for a trusted project
+const GLOBAL_ONLY_KEYS: &[&str] = &[
+    "secrets",
+    "safety",
+    "editor",
+    "telemetry",
+    "auth",
+    "mcp_config_path",
+    "mcp.output_path",
+    "git.forward_credentials",
+    "docker.extra_volumes",
+    "docker.extra_run_args",
+    "docker.dockerfile_path",
+    "docker.build_context",
+    "docker.env_file_path",
+    "docker.forward_ssh_agent",
+    "docker.mount_claude_settings",
+    "docker.connection",
+];
+
+/// Whether the dotted `key` is one of `keys` or inside one of them
+fn key_is_under(key: &str, keys: &[&str]) -> bool {
+    keys.iter().any(|k| {
+        key == *k
+            || key
+                .strip_prefix(k)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Whether `project_file` lies in one of the `safety.trusted_projects` directories
+fn is_trusted_project(project_file: &Path, trusted: &[String]) -> bool {
+    let Some(dir) = project_file.parent() else {
+        return false;
+    };
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    trusted.iter().any(|entry| {
+        let entry = expand_path(entry);
+        dir.starts_with(entry.canonicalize().unwrap_or(entry))
+    })
+}
+
+/// Keep the parts of a project config it is allowed to set
+///
+/// Untrusted projects only get [`PROJECT_SAFE_KEYS`]; trusted ones get
+/// everything but [`GLOBAL_ONLY_KEYS`]. Values mentioning a secret reference
+/// are always dropped, so a repository can't make ccs resolve the user's
+/// secrets (or run a `cmd://` resolver) for it. Returns the overlay and the
+/// dotted keys that were dropped.
+pub fn project_overlay(value: toml::Value, trusted: bool) -> (toml::Value, Vec<String>) {
+    let mut ignored = Vec::new();
+    let overlay = filter_project_value(value, "", trusted, &mut ignored)
+        .unwrap_or_else(|| toml::Value::Table(toml::map::Map::new()));
+    (overlay, ignored)
+}
+
+fn filter_project_value(
+    value: toml::Value,
+    key: &str,
+    trusted: bool,
+    ignored: &mut Vec<String>,
+) -> Option<toml::Value> {
+    match value {
+        toml::Value::Table(table) => {
+            let mut kept = toml::map::Map::new();
+            for (name, child) in table {
+                let child_key = if key.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", key, name)
+                };
+                let allowed = trusted
+                    || key_is_under(&child_key, PROJECT_SAFE_KEYS)
+                    || PROJECT_SAFE_KEYS
+                        .iter()
+                        .any(|k| k.starts_with(&format!("{}.", child_key)));
+                if !allowed || key_is_under(&child_key, GLOBAL_ONLY_KEYS) {
+                    ignored.push(child_key);
+                    continue;
+                }
+                if let Some(child) = filter_project_value(child, &child_key, trusted, ignored) {
+                    kept.insert(name, child);
+                }
+            }
+            Some(toml::Value::Table(kept))
+        }
+        toml::Value::String(ref s) if crate::secrets::mentions_secret_reference(s) => {
+            ignored.push(key.to_string());
+            None
         }
+        toml::Value::Array(ref items)
+            if items.iter().any(|item| {
+                item.as_str()
+                    .is_some_and(crate::secrets::mentions_secret_reference)
+            }) =>
+        {
+            ignored.push(key.to_string());
+            None
+        }
+        value => Some(value),
+    }
+}
+
+/// Warn about project settings that were not applied
+fn warn_ignored(project_file: &Path, ignored: &[String]) {
+    if ignored.is_empty() {
+        return;
+    }
+    eprintln!(
+        "Warning: ignoring {} from {}: secret references and host settings are only read from the global config (see safety.trusted_projects)",
+        ignored.join(", "),
+        project_file.display()
+    );
+}
+
+/// Read a TOML file as a table, or an empty table if it doesn't exist
+fn read_toml_table(path: &Path) -> Result<toml::Value, ConfigError> {
+    if path.exists() {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    } else {
+        Ok(toml::Value::Table(toml::map::Map::new()))
+    }
+}
+
+/// Recursively merge `overlay` into `base`
+///
+/// Tables are merged key by key, so maps like `extra_env` are additive;
+/// any other value in `overlay` replaces the one in `base`.
+pub fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base_table), toml::Value::Table(overlay_table)) => {
+            for (key, overlay_value) in overlay_table {
+                match base_table.get_mut(&key) {
+                    Some(base_value) => merge_toml(base_value, overlay_value),
+                    None => {
+                        base_table.insert(key, overlay_value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
     }
 }
 
 /// MCP Server configuration (loaded from separate file)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct McpServersConfig {
+    #[serde(default)]
     pub servers: HashMap<String, McpServer>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct McpServer {
     pub command: String,
 
@@ -192,20 +1061,80 @@ pub struct McpServer {
 
     #[serde(default)]
     pub env: HashMap<String, String>,
+
+    /// Directory to start the server in; `{workspace}` expands to the
+    /// container workspace path
+    #[serde(default)]
+    pub cwd: Option<String>,
 }
 
 impl McpServersConfig {
-    /// Load MCP servers configuration from file
-    pub fn load() -> Result<Option<Self>, ConfigError> {
-        let mcp_path = Config::mcp_servers_path()?;
-
-        if mcp_path.exists() {
-            let contents = std::fs::read_to_string(&mcp_path)?;
-            let config: McpServersConfig = toml::from_str(&contents)?;
-            Ok(Some(config))
-        } else {
-            Ok(None)
+    /// Load MCP servers configuration from file, adding any `[servers.*]` from a project `.ccs.toml`
+    pub fn load_with_project(
+        config: &Config,
+        project_path: &Path,
+    ) -> Result<Option<Self>, ConfigError> {
+        Self::load_layered(&config.mcp_servers_path()?, project_path)
+    }
+
+    /// Load just the MCP servers file at `path`, empty if it doesn't exist
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        Ok(read_toml_table(path)?.try_into()?)
+    }
+
+    /// Write the servers to `path`, sorted by name
+    ///
+    /// The file is regenerated, so comments in it are not kept.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        // Going through toml::Value sorts the HashMap keys
+        let contents = toml::to_string_pretty(&toml::Value::try_from(self)?)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Add a server, refusing to replace an existing one
+    pub fn add_server(&mut self, name: &str, server: McpServer) -> Result<(), ConfigError> {
+        if self.servers.contains_key(name) {
+            return Err(ConfigError::ServerExists(name.to_string()));
         }
+        self.servers.insert(name.to_string(), server);
+        Ok(())
+    }
+
+    /// Remove a server, returning its config
+    pub fn remove_server(&mut self, name: &str) -> Result<McpServer, ConfigError> {
+        self.servers
+            .remove(name)
+            .ok_or_else(|| ConfigError::ServerNotFound(name.to_string()))
+    }
+
+    fn load_layered(global_path: &Path, project_path: &Path) -> Result<Option<Self>, ConfigError> {
+        let mut value = read_toml_table(global_path)?;
+        let mut found = global_path.exists();
+
+        if let Some(project_file) = find_project_config(project_path) {
+            if let Some(servers) = read_toml_table(&project_file)?.get("servers") {
+                // Servers run in the sandbox, but their env must not pull in secrets
+                let mut ignored = Vec::new();
+                let servers = filter_project_value(servers.clone(), "servers", true, &mut ignored);
+                warn_ignored(&project_file, &ignored);
+                let mut overlay = toml::map::Map::new();
+                if let Some(servers) = servers {
+                    overlay.insert("servers".to_string(), servers);
+                }
+                merge_toml(&mut value, toml::Value::Table(overlay));
+                found = true;
+            }
+        }
+
+        if !found {
+            return Ok(None);
+        }
+
+        Ok(Some(value.try_into()?))
     }
 }
 
@@ -224,9 +1153,9 @@ mod tests {
     #[test]
     fn test_worktree_path_resolution_with_data_dir() {
         let config = Config::default();
-        let repo_parent = PathBuf::from("/home/user/projects");
+        let repo_root = PathBuf::from("/home/user/projects/myrepo");
 
-        let resolved = config.resolve_worktree_path("myrepo", &repo_parent);
+        let resolved = config.resolve_worktree_path("myrepo", &repo_root);
         // Default uses {data_dir}/ccs/{repo_name}, which resolves to XDG_DATA_HOME
         let data_dir = dirs::data_dir()
             .unwrap_or_else(|| dirs::home_dir().unwrap_or_default().join(".local/share"));
@@ -237,15 +1166,447 @@ mod tests {
     fn test_worktree_path_resolution_relative() {
         let mut config = Config::default();
         config.worktree.base_path = "../{repo_name}-worktrees".to_string();
-        let repo_parent = PathBuf::from("/home/user/projects");
+        let repo_root = PathBuf::from("/home/user/projects/myrepo");
 
-        let resolved = config.resolve_worktree_path("myrepo", &repo_parent);
+        let resolved = config.resolve_worktree_path("myrepo", &repo_root);
         assert_eq!(
             resolved,
             PathBuf::from("/home/user/projects/../myrepo-worktrees")
         );
     }
 
+    #[test]
+    fn test_worktree_path_resolution_repo_root() {
+        let mut config = Config::default();
+        config.worktree.base_path = "{repo_root}/.worktrees".to_string();
+        let repo_root = PathBuf::from("/home/user/projects/myrepo");
+
+        let resolved = config.resolve_worktree_path("myrepo", &repo_root);
+        assert_eq!(
+            resolved,
+            PathBuf::from("/home/user/projects/myrepo/.worktrees")
+        );
+    }
+
+    #[test]
+    fn test_merge_toml_semantics() {
+        let mut base: toml::Value = toml::from_str(
+            r#"
+            [docker]
+            image = "ccs:latest"
+            memory_limit = "4g"
+            [docker.extra_env]
+            EDITOR = "vim"
+            TERM = "xterm"
+            "#,
+        )
+        .unwrap();
+        let overlay: toml::Value = toml::from_str(
+            r#"
+            [docker]
+            image = "ccs:project"
+            [docker.extra_env]
+            TERM = "xterm-256color"
+            RUST_LOG = "debug"
+            "#,
+        )
+        .unwrap();
+
+        merge_toml(&mut base, overlay);
+
+        let docker = &base["docker"];
+        assert_eq!(docker["image"].as_str(), Some("ccs:project"));
+        assert_eq!(docker["memory_limit"].as_str(), Some("4g"));
+        let env = docker["extra_env"].as_table().unwrap();
+        assert_eq!(env.len(), 3);
+        assert_eq!(env["EDITOR"].as_str(), Some("vim"));
+        assert_eq!(env["TERM"].as_str(), Some("xterm-256color"));
+        assert_eq!(env["RUST_LOG"].as_str(), Some("debug"));
+    }
+
+    #[test]
+    fn test_load_with_project_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let global = dir.path().join("config.toml");
+        std::fs::write(
+            &global,
+            "[docker]\ncpu_limit = 2.0\n[docker.extra_volumes]\n\"~/.ssh\" = \"/home/claude/.ssh:ro\"\n",
+        )
+        .unwrap();
+
+        let repo = dir.path().join("repo");
+        let subdir = repo.join("packages").join("app");
+        std::fs::create_dir_all(&subdir).unwrap();
+        std::fs::create_dir(repo.join(".git")).unwrap();
+        std::fs::write(
+            repo.join(PROJECT_CONFIG_FILE),
+            "[docker]\nmemory_limit = \"8g\"\n[docker.extra_volumes]\n\"/data\" = \"/data\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load_layered(&global, &subdir).unwrap();
+        assert_eq!(config.docker.cpu_limit, Some(2.0));
+        assert_eq!(config.docker.memory_limit.as_deref(), Some("8g"));
+        // Mounting host paths is global-only
+        assert_eq!(config.docker.extra_volumes.len(), 1);
+        assert_eq!(config.docker.image, "ccs:latest");
+    }
+
+    #[test]
+    fn test_project_config_cannot_add_cmd_resolver() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::write(
+            repo.join(PROJECT_CONFIG_FILE),
+            "[secrets]\nbackend = \"custom\"\n[secrets.custom]\ncommand = \"touch /tmp/PWNED\"\n[docker.extra_env]\nX = \"cmd://x\"\n",
+        )
+        .unwrap();
+
+        // Not even a trusted project may configure the resolver or use it
+        let global = dir.path().join("config.toml");
+        std::fs::write(
+            &global,
+            format!(
+                "[safety]\ntrusted_projects = [{:?}]\n",
+                repo.display().to_string()
+            ),
+        )
+        .unwrap();
+
+        let config = Config::load_layered(&global, &repo).unwrap();
+        assert!(config.secrets.custom.command.is_none());
+        assert_eq!(config.secrets.backend, SecretsConfig::default().backend);
+        assert!(config.docker.extra_env.is_empty());
+    }
+
+    #[test]
+    fn test_project_overlay_allowlist() {
+        let project: toml::Value = toml::from_str(
+            r#"
+            mcp_config_path = "/tmp/mcp.toml"
+
+            [docker]
+            image = "evil:latest"
+            memory_limit = "8g"
+            extra_run_args = ["--privileged"]
+
+            [docker.extra_env]
+            RUST_LOG = "debug"
+            TOKEN = "op://Private/bank/password"
+            WRAPPED = "prefix pass://x"
+
+            [secrets.custom]
+            command = "touch /tmp/PWNED"
+
+            [editor]
+            command = "sh -c evil"
+
+            [services.db]
+            image = "postgres:16"
+            env = { POSTGRES_USER = "dev", POSTGRES_PASSWORD = "op://Private/db/password" }
+            "#,
+        )
+        .unwrap();
+
+        let (overlay, mut ignored) = project_overlay(project.clone(), false);
+        let config: Config = overlay.try_into().unwrap();
+        assert_eq!(config.docker.memory_limit.as_deref(), Some("8g"));
+        assert_eq!(
+            config.docker.extra_env,
+            HashMap::from([("RUST_LOG".to_string(), "debug".to_string())])
+        );
+        assert_eq!(config.docker.image, "ccs:latest");
+        assert!(config.docker.extra_run_args.is_empty());
+        assert!(config.secrets.custom.command.is_none());
+        assert!(config.services.is_empty());
+        ignored.sort();
+        assert_eq!(
+            ignored,
+            vec![
+                "docker.extra_env.TOKEN",
+                "docker.extra_env.WRAPPED",
+                "docker.extra_run_args",
+                "docker.image",
+                "editor",
+                "mcp_config_path",
+                "secrets",
+                "services",
+            ]
+        );
+
+        // Trust unlocks the rest, but not host settings or secret references
+        let (overlay, _) = project_overlay(project, true);
+        let config: Config = overlay.try_into().unwrap();
+        assert_eq!(config.docker.image, "evil:latest");
+        assert_eq!(config.services.len(), 1);
+        assert_eq!(
+            config.services["db"].env,
+            HashMap::from([("POSTGRES_USER".to_string(), "dev".to_string())])
+        );
+        assert!(config.docker.extra_run_args.is_empty());
+        assert!(config.secrets.custom.command.is_none());
+        assert!(config.editor.command.is_none());
+        assert_eq!(config.mcp_config_path, None);
+        assert!(!config.docker.extra_env.contains_key("TOKEN"));
+    }
+
+    #[test]
+    fn test_project_config_cannot_relax_safety() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::write(
+            repo.join(PROJECT_CONFIG_FILE),
+            "[safety]\nblocked_paths = []\nallowed_roots = [\"/\"]\n",
+        )
+        .unwrap();
+        let global = dir.path().join("config.toml");
+        std::fs::write(
+            &global,
+            format!(
+                "[safety]\nallowed_roots = [\"~/code\"]\ntrusted_projects = [{:?}]\n",
+                repo.display().to_string()
+            ),
+        )
+        .unwrap();
+
+        let merged = Config::load_layered(&global, &repo).unwrap();
+        let global = Config::load_global(Some(&global)).unwrap();
+        assert_eq!(global.safety.allowed_roots, vec!["~/code".to_string()]);
+        assert_eq!(merged.safety.allowed_roots, global.safety.allowed_roots);
+        assert_eq!(merged.safety.blocked_paths, global.safety.blocked_paths);
+    }
+
+    #[test]
+    fn test_trusted_project() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let project = dir.path().join("work").join("repo");
+        std::fs::create_dir_all(&project).unwrap();
+        let file = project.join(PROJECT_CONFIG_FILE);
+
+        assert!(!is_trusted_project(&file, &[]));
+        let parent = dir.path().join("work").display().to_string();
+        assert!(is_trusted_project(&file, &[parent]));
+        let other = dir.path().join("other").display().to_string();
+        assert!(!is_trusted_project(&file, &[other]));
+    }
+
+    #[test]
+    fn test_load_from_explicit_path() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("custom.toml");
+        std::fs::write(&path, "[docker]\nimage = \"ccs:custom\"\n").unwrap();
+
+        let config = Config::load_from(&path, dir.path()).unwrap();
+        assert_eq!(config.docker.image, "ccs:custom");
+
+        let missing = dir.path().join("missing.toml");
+        assert!(matches!(
+            Config::load_from(&missing, dir.path()),
+            Err(ConfigError::NotFound(p)) if p == missing
+        ));
+    }
+
+    #[test]
+    fn test_project_config_stops_at_repo_root() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(PROJECT_CONFIG_FILE), "").unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+
+        assert_eq!(find_project_config(&repo), None);
+
+        // Outside a repository, parent directories aren't searched
+        let plain = dir.path().join("plain");
+        std::fs::create_dir(&plain).unwrap();
+        assert_eq!(find_project_config(&plain), None);
+        std::fs::write(plain.join(PROJECT_CONFIG_FILE), "").unwrap();
+        assert_eq!(
+            find_project_config(&plain),
+            Some(plain.join(PROJECT_CONFIG_FILE))
+        );
+    }
+
+    #[test]
+    fn test_mcp_servers_additive() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let global = dir.path().join("mcp.toml");
+        std::fs::write(&global, "[servers.github]\ncommand = \"npx github\"\n").unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(
+            dir.path().join(PROJECT_CONFIG_FILE),
+            "[docker]\nimage = \"x\"\n[servers.postgres]\ncommand = \"npx postgres\"\nenv = { PGPASSWORD = \"op://Private/db/password\", PGUSER = \"dev\" }\n",
+        )
+        .unwrap();
+
+        let servers = McpServersConfig::load_layered(&global, dir.path())
+            .unwrap()
+            .unwrap();
+        assert_eq!(servers.servers.len(), 2);
+        assert!(servers.servers.contains_key("github"));
+        // Project servers can't pull in the user's secrets
+        assert_eq!(
+            servers.servers["postgres"].env,
+            HashMap::from([("PGUSER".to_string(), "dev".to_string())])
+        );
+    }
+
+    fn test_server(command: &str) -> McpServer {
+        McpServer {
+            command: command.to_string(),
+            args: vec!["-y".to_string(), "server-github".to_string()],
+            env: HashMap::from([(
+                "GITHUB_TOKEN".to_string(),
+                "op://vault/github/token".to_string(),
+            )]),
+            cwd: None,
+        }
+    }
+
+    #[test]
+    fn test_mcp_servers_add_remove() {
+        let mut servers = McpServersConfig::default();
+        servers.add_server("github", test_server("npx")).unwrap();
+        assert!(matches!(
+            servers.add_server("github", test_server("other")),
+            Err(ConfigError::ServerExists(name)) if name == "github"
+        ));
+        // The original is kept when adding a duplicate fails
+        assert_eq!(servers.servers["github"].command, "npx");
+
+        assert_eq!(servers.remove_server("github").unwrap(), test_server("npx"));
+        assert!(servers.servers.is_empty());
+        assert!(matches!(
+            servers.remove_server("github"),
+            Err(ConfigError::ServerNotFound(name)) if name == "github"
+        ));
+    }
+
+    #[test]
+    fn test_mcp_servers_save_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("ccs").join("mcp.toml");
+        assert!(McpServersConfig::load(&path).unwrap().servers.is_empty());
+
+        let mut servers = McpServersConfig::default();
+        servers.add_server("github", test_server("npx")).unwrap();
+        let mut postgres = test_server("postgres-mcp");
+        postgres.cwd = Some("{workspace}".to_string());
+        servers.add_server("postgres", postgres.clone()).unwrap();
+        servers.save(&path).unwrap();
+
+        let loaded = McpServersConfig::load(&path).unwrap();
+        assert_eq!(loaded.servers.len(), 2);
+        assert_eq!(loaded.servers["github"], test_server("npx"));
+        assert_eq!(loaded.servers["postgres"], postgres);
+
+        // Servers are written in name order
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let github = contents.find("[servers.github]").unwrap();
+        let postgres = contents.find("[servers.postgres]").unwrap();
+        assert!(github < postgres);
+    }
+
+    #[test]
+    fn test_expand_path_tilde() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_path("~/.ssh"), home.join(".ssh"));
+        assert_eq!(expand_path("~"), home);
+    }
+
+    #[test]
+    fn test_expand_path_env_vars() {
+        std::env::set_var("CCS_TEST_EXPAND_DIR", "/opt/data");
+        assert_eq!(
+            expand_path("$CCS_TEST_EXPAND_DIR/cache"),
+            PathBuf::from("/opt/data/cache")
+        );
+        assert_eq!(
+            expand_path("${CCS_TEST_EXPAND_DIR}-backup"),
+            PathBuf::from("/opt/data-backup")
+        );
+        std::env::remove_var("CCS_TEST_EXPAND_DIR");
+    }
+
+    #[test]
+    fn test_expand_path_literal_and_unset() {
+        assert_eq!(expand_path("/plain/path"), PathBuf::from("/plain/path"));
+        assert_eq!(expand_path("relative/dir"), PathBuf::from("relative/dir"));
+        assert_eq!(
+            expand_path("/data/$CCS_TEST_UNSET_VAR/x"),
+            PathBuf::from("/data/$CCS_TEST_UNSET_VAR/x")
+        );
+        assert_eq!(
+            expand_path("/data/${CCS_TEST_UNSET_VAR}"),
+            PathBuf::from("/data/${CCS_TEST_UNSET_VAR}")
+        );
+        assert_eq!(expand_path("/cost$"), PathBuf::from("/cost$"));
+    }
+
+    #[test]
+    fn test_worktree_path_resolution_tilde() {
+        let mut config = Config::default();
+        config.worktree.base_path = "~/worktrees/{repo_name}".to_string();
+
+        let resolved =
+            config.resolve_worktree_path("myrepo", Path::new("/home/user/projects/myrepo"));
+        assert_eq!(
+            resolved,
+            dirs::home_dir().unwrap().join("worktrees").join("myrepo")
+        );
+    }
+
+    #[test]
+    fn test_config_schema() {
+        let schema = serde_json::to_value(config_schema()).unwrap();
+        let defs = &schema["$defs"];
+
+        let backend = &defs["SecretsConfig"]["properties"]["backend"];
+        assert_eq!(backend["default"], "env");
+        let backends = backend["enum"].as_array().unwrap();
+        assert!(backends.contains(&"1password".into()));
+        assert!(backends.contains(&"doppler".into()));
+
+        let image = &defs["DockerConfig"]["properties"]["image"];
+        assert_eq!(image["default"], "ccs:latest");
+        assert!(image["description"].as_str().is_some());
+    }
+
+    #[test]
+    fn test_upgrade_on_disk_keeps_values() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "[docker]\nimage = \"custom:1\"\nold_setting = true\n\n[worktree]\nfetch_before_create = true\n";
+        std::fs::write(&path, original).unwrap();
+
+        let upgrade = Config::upgrade_on_disk(&path).unwrap();
+        assert_eq!(upgrade.dropped_keys, vec!["docker.old_setting"]);
+        assert_eq!(std::fs::read_to_string(&upgrade.backup).unwrap(), original);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let config: Config = toml::from_str(&contents).unwrap();
+        assert_eq!(config.docker.image, "custom:1");
+        assert!(config.worktree.fetch_before_create);
+
+        // Settings the old file didn't mention appear with their defaults
+        assert!(contents.contains("pull_policy = \"missing\""));
+        assert!(contents.contains("[safety]"));
+        assert!(contents.contains("# Docker image name\nimage = \"custom:1\""));
+        assert!(contents.contains("# Docker-related settings\n[docker]"));
+
+        // Running it again keeps the first backup
+        let again = Config::upgrade_on_disk(&path).unwrap();
+        assert_eq!(again.backup, dir.path().join("config.toml.bak.1"));
+        assert_eq!(std::fs::read_to_string(&upgrade.backup).unwrap(), original);
+        assert_eq!(std::fs::read_to_string(&again.backup).unwrap(), contents);
+
+        assert!(matches!(
+            Config::upgrade_on_disk(&dir.path().join("missing.toml")),
+            Err(ConfigError::NotFound(_))
+        ));
+    }
+
     #[test]
     fn test_config_serialization() {
         let config = Config::default();
@@ -1,12 +1,22 @@
+use std::collections::HashMap;
 use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output, Stdio};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 use crate::auth::{self, ClaudeCredentials, CredentialSource};
-use crate::config::Config;
-use crate::git::GitContext;
+use crate::config::{
+    expand_path, Config, PermissionMode, PullPolicy, SecretsConfig, ServiceConfig,
+};
+use crate::git::{self, GitContext, GitCredentials, GitIdentity, CONTAINER_WORKSPACE};
+use crate::mcp;
+use crate::output::{self, Style};
+use crate::reaper;
+use crate::secrets::{self, SecretsError};
+use crate::telemetry;
 use crate::toolchain::Toolchain;
 
 #[derive(Error, Debug)]
@@ -22,6 +32,56 @@ pub enum DockerError {
 
     #[error("Dockerfile not found at: {0}")]
     DockerfileNotFound(PathBuf),
+
+    #[error("Image '{0}' not found. Build it with: ccs --build")]
+    ImageNotFound(String),
+
+    #[error("A container named '{0}' already exists. Stop it with: ccs --stop {0}")]
+    ContainerExists(String),
+
+    #[error("Invalid workdir '{0}': {1}")]
+    InvalidWorkdir(String, String),
+
+    #[error("No session metadata found for '{0}' (was it started by an older ccs?)")]
+    NoSessionMetadata(String),
+
+    #[error("{0} is connected to a remote daemon ({1}); workspace bind mounts refer to paths on that host and won't work. Unset DOCKER_HOST (CONTAINER_HOST for Podman) to use a local daemon")]
+    RemoteDaemon(&'static str, String),
+
+    #[error("Session '{0}' did not become ready: {1}. Check its output with: ccs --logs {0}")]
+    NotReady(String, String),
+
+    #[error("Secrets error: {0}")]
+    Secrets(#[from] SecretsError),
+
+    #[error("Service '{0}' failed to start: {1}")]
+    ServiceFailed(String, String),
+
+    #[error("No Claude credentials found (auth.require_credentials is set)")]
+    NoCredentials,
+
+    #[error("Invalid build target '{0}': expected a Dockerfile stage name")]
+    InvalidBuildTarget(String),
+}
+
+/// `docker.connection`, applied to every Podman invocation once set
+static CONNECTION: OnceLock<String> = OnceLock::new();
+
+/// Use `docker.connection` for all later runtime commands
+pub fn init_connection(config: &Config) {
+    if let Some(name) = configured_connection(config) {
+        let _ = CONNECTION.set(name.to_string());
+    }
+}
+
+/// `docker.connection`, ignoring a blank value
+fn configured_connection(config: &Config) -> Option<&str> {
+    config
+        .docker
+        .connection
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
 }
 
 /// Container runtime (Docker or Podman)
@@ -58,734 +118,6104 @@ impl ContainerRuntime {
             ContainerRuntime::Podman => "Podman",
         }
     }
-}
 
-pub struct DockerRunner {
-    runtime: ContainerRuntime,
-    config: Config,
-    git_context: GitContext,
-    mcp_config_path: Option<PathBuf>,
-    container_name: String,
-    credentials: ClaudeCredentials,
-    toolchain: Toolchain,
-}
+    /// Flags placed before every subcommand (`--connection` for Podman)
+    pub fn global_args(&self) -> Vec<String> {
+        connection_args(*self, CONNECTION.get().map(String::as_str))
+    }
 
-impl DockerRunner {
-    /// Create a new Docker/Podman runner
-    pub fn new(
-        config: &Config,
-        git_context: &GitContext,
-        mcp_config_path: Option<PathBuf>,
-        toolchain: Toolchain,
-    ) -> Result<Self, DockerError> {
-        let runtime = ContainerRuntime::detect()?;
-        let container_name = generate_container_name(&git_context.repo_name);
-        let credentials = auth::discover_credentials();
+    /// A command running the runtime binary with its global flags
+    pub fn new_command(&self) -> Command {
+        let mut cmd = Command::new(self.command());
+        cmd.args(self.global_args());
+        cmd
+    }
 
-        Ok(DockerRunner {
-            runtime,
-            config: config.clone(),
-            git_context: git_context.clone(),
-            mcp_config_path,
-            container_name,
-            credentials,
-            toolchain,
-        })
+    /// The binary and global flags, as printed before a command's arguments
+    pub fn command_prefix(&self) -> Vec<String> {
+        std::iter::once(self.command().to_string())
+            .chain(self.global_args())
+            .collect()
     }
 
-    /// Build the container image
-    pub fn build_image(config: &Config) -> anyhow::Result<()> {
-        let runtime = ContainerRuntime::detect()?;
+    /// Check whether the runtime runs rootless (without a root daemon)
+    pub fn is_rootless(&self) -> bool {
+        let format = match self {
+            ContainerRuntime::Podman => "{{.Host.Security.Rootless}}",
+            ContainerRuntime::Docker => "{{.SecurityOptions}}",
+        };
 
-        // Find Dockerfile
-        let dockerfile_path = config
-            .docker
-            .dockerfile_path
-            .clone()
-            .or_else(|| {
-                // Look in common locations
-                let candidates = [
-                    PathBuf::from("docker/Dockerfile"),
-                    PathBuf::from("Dockerfile"),
-                ];
-                candidates.into_iter().find(|p| p.exists())
-            })
-            .ok_or_else(|| DockerError::DockerfileNotFound(PathBuf::from("docker/Dockerfile")))?;
+        self.new_command()
+            .args(["info", "--format", format])
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| parse_rootless_output(*self, &String::from_utf8_lossy(&o.stdout)))
+            .unwrap_or(false)
+    }
+}
 
-        if !dockerfile_path.exists() {
-            return Err(DockerError::DockerfileNotFound(dockerfile_path).into());
+/// `--connection <name>` for Podman; Docker has no equivalent flag
+fn connection_args(runtime: ContainerRuntime, connection: Option<&str>) -> Vec<String> {
+    match (runtime, connection) {
+        (ContainerRuntime::Podman, Some(name)) => {
+            vec!["--connection".to_string(), name.to_string()]
         }
+        _ => Vec::new(),
+    }
+}
 
-        let default_dir = PathBuf::from(".");
-        let dockerfile_dir = dockerfile_path.parent().unwrap_or(&default_dir);
+/// Where the container runtime's daemon runs, from `DOCKER_HOST`/`CONTAINER_HOST`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DaemonHost {
+    /// No host configured, the runtime's default local socket
+    Default,
+    /// A local socket or loopback address
+    Local(String),
+    /// Another machine, where host bind-mount paths don't exist
+    Remote(String),
+}
 
-        println!(
-            "Building image {} using {} from {}...",
-            config.docker.image,
-            runtime.name(),
-            dockerfile_path.display()
-        );
+impl DaemonHost {
+    /// Detect the daemon host the runtime will connect to
+    pub fn detect(runtime: ContainerRuntime) -> Self {
+        // Podman reads CONTAINER_HOST and ignores DOCKER_HOST
+        let var = match runtime {
+            ContainerRuntime::Podman => "CONTAINER_HOST",
+            ContainerRuntime::Docker => "DOCKER_HOST",
+        };
+        parse_daemon_host(std::env::var(var).ok().as_deref())
+    }
 
-        let status = Command::new(runtime.command())
-            .arg("build")
-            .arg("-t")
-            .arg(&config.docker.image)
-            .arg("-f")
-            .arg(&dockerfile_path)
-            .arg(dockerfile_dir)
-            .status()?;
+    /// Whether bind mounts of host paths won't reach the daemon
+    pub fn is_remote(&self) -> bool {
+        matches!(self, DaemonHost::Remote(_))
+    }
+}
 
-        if !status.success() {
-            return Err(
-                DockerError::CommandFailed(format!("{} build failed", runtime.name())).into(),
-            );
+impl std::fmt::Display for DaemonHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonHost::Default => write!(f, "local (default socket)"),
+            DaemonHost::Local(host) => write!(f, "local ({})", host),
+            DaemonHost::Remote(host) => write!(f, "remote ({})", host),
         }
+    }
+}
 
-        println!("Successfully built image: {}", config.docker.image);
-        Ok(())
+/// Classify a `DOCKER_HOST`-style value as local or remote
+///
+/// `ssh://` hosts are always remote; `tcp://` hosts are remote unless they
+/// point at a loopback address.
+pub fn parse_daemon_host(value: Option<&str>) -> DaemonHost {
+    let value = match value.map(str::trim) {
+        Some(v) if !v.is_empty() => v,
+        _ => return DaemonHost::Default,
+    };
+
+    let Some((scheme, rest)) = value.split_once("://") else {
+        return DaemonHost::Local(value.to_string());
+    };
+
+    let remote = match scheme {
+        "ssh" => true,
+        "tcp" | "http" | "https" => !is_loopback_host(rest),
+        _ => false,
+    };
+
+    if remote {
+        DaemonHost::Remote(value.to_string())
+    } else {
+        DaemonHost::Local(value.to_string())
     }
+}
 
-    /// Run the container with Claude Code
-    pub fn run(&self, extra_args: &[String], detach: bool, dry_run: bool) -> anyhow::Result<()> {
-        // Build up argument list for the command
-        let mut args: Vec<String> = vec![
-            "run".to_string(),
-            "--name".to_string(),
-            self.container_name.clone(),
-        ];
+/// Check whether the host in `[user@]host[:port][/path]` is a loopback address
+fn is_loopback_host(authority: &str) -> bool {
+    let authority = authority.split('/').next().unwrap_or_default();
+    let host_port = authority.rsplit('@').next().unwrap_or_default();
 
-        if detach {
-            // Detached mode - run in background, don't remove on exit
-            args.push("-d".to_string());
-        } else {
-            // Interactive mode - remove on exit
-            args.push("--rm".to_string());
-            // Only use -it flags when we have a TTY
-            if std::io::stdin().is_terminal() {
-                args.push("-it".to_string());
-            } else {
-                // Non-interactive mode - still need -i for stdin
-                args.push("-i".to_string());
-            }
+    let host = match host_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+
+    host == "localhost" || host == "0.0.0.0" || host == "::1" || host.starts_with("127.")
+}
+
+/// Interpret `info --format` output from `ContainerRuntime::is_rootless`
+fn parse_rootless_output(runtime: ContainerRuntime, stdout: &str) -> bool {
+    match runtime {
+        ContainerRuntime::Podman => stdout.trim() == "true",
+        ContainerRuntime::Docker => stdout.contains("name=rootless"),
+    }
+}
+
+/// Extra run flags needed so files written to bind mounts keep sane host ownership
+fn rootless_flags(runtime: ContainerRuntime, rootless: bool) -> Vec<String> {
+    if runtime == ContainerRuntime::Podman && rootless {
+        // Map the host user to the same UID in the container instead of a subuid
+        vec!["--userns=keep-id".to_string()]
+    } else {
+        vec![]
+    }
+}
+
+/// Validate a `--user` value (`user`, `uid`, `user:group` or `uid:gid`)
+pub fn parse_user_spec(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    let valid_part = |p: &str| {
+        !p.is_empty()
+            && p.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    };
+
+    let mut parts = s.splitn(2, ':');
+    let user = parts.next().unwrap_or_default();
+    if !valid_part(user) || !parts.next().is_none_or(valid_part) {
+        return Err(format!(
+            "invalid user '{}' (expected user, uid, user:group or uid:gid)",
+            s
+        ));
+    }
+    Ok(s.to_string())
+}
+
+/// The host user's `uid:gid`, or None where it can't be determined
+#[cfg(unix)]
+fn host_uid_gid() -> Option<String> {
+    let id = |flag: &str| {
+        let output = Command::new("id").arg(flag).output().ok()?;
+        if !output.status.success() {
+            return None;
         }
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        value.parse::<u32>().ok().map(|_| value)
+    };
+    Some(format!("{}:{}", id("-u")?, id("-g")?))
+}
 
-        // Add resource limits
-        if let Some(ref mem) = self.config.docker.memory_limit {
-            args.push("--memory".to_string());
-            args.push(mem.clone());
+#[cfg(not(unix))]
+fn host_uid_gid() -> Option<String> {
+    None
+}
+
+/// The main repository's CLAUDE.md, if a worktree lacks its own
+///
+/// An untracked CLAUDE.md in the main checkout isn't copied into new
+/// worktrees, so the session would otherwise run without it.
+fn missing_project_claude_md(workspace: &Path, shared_git_dir: Option<&Path>) -> Option<PathBuf> {
+    if workspace.join("CLAUDE.md").exists() {
+        return None;
+    }
+    let main_repo = shared_git_dir?.parent()?;
+    Some(main_repo.join("CLAUDE.md")).filter(|p| p.is_file())
+}
+
+/// Per-run options from the command line
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Run the container in the background
+    pub detach: bool,
+    /// Print the command instead of running it
+    pub dry_run: bool,
+    /// Ad-hoc bind mounts, added after `docker.extra_volumes`
+    pub mounts: Vec<Mount>,
+    /// Host file to append detached session output to
+    pub log_file: Option<PathBuf>,
+    /// Additional project directories, mounted at `/workspace-<name>`
+    pub also: Vec<PathBuf>,
+    /// Container user to run as, overriding `docker.match_host_uid`
+    pub user: Option<String>,
+    /// Extra env files, loaded regardless of `docker.load_env_file`
+    pub env_files: Vec<PathBuf>,
+    /// Start `docker.shell` instead of Claude
+    pub shell: bool,
+    /// Leave secret references unresolved (for `--export-command`)
+    pub keep_secret_refs: bool,
+    /// Flags passed verbatim to `docker run`, after `docker.extra_run_args`
+    pub docker_args: Vec<String>,
+    /// Config overrides given on the command line, recorded for `--restart`
+    pub overrides: RunOverrides,
+}
+
+/// Per-run config overrides from the command line
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RunOverrides {
+    /// `--memory`, overriding `docker.memory_limit`
+    pub memory: Option<String>,
+    /// `--cpus`, overriding `docker.cpu_limit`
+    pub cpus: Option<f32>,
+    /// `--permission-mode`, overriding `claude.permission_mode`
+    pub permission_mode: Option<PermissionMode>,
+    /// `--workdir` as given, resolved against the workspace
+    pub workdir: Option<String>,
+    /// `--sparse`, overriding `worktree.sparse_paths`
+    pub sparse_paths: Vec<String>,
+    /// `--name`, the session name in place of a timestamp
+    pub name: Option<String>,
+}
+
+impl RunOverrides {
+    /// Apply to `config` for a session on `host_workspace`
+    pub fn apply(&self, config: &mut Config, host_workspace: &Path) -> Result<(), DockerError> {
+        if let Some(ref memory) = self.memory {
+            config.docker.memory_limit = Some(memory.clone());
         }
-        if let Some(cpu) = self.config.docker.cpu_limit {
-            args.push("--cpus".to_string());
-            args.push(cpu.to_string());
+        if let Some(cpus) = self.cpus {
+            config.docker.cpu_limit = Some(cpus);
+        }
+        if let Some(mode) = self.permission_mode {
+            config.claude.permission_mode = Some(mode);
+        }
+        if let Some(ref workdir) = self.workdir {
+            config.docker.workdir = resolve_workdir(workdir, host_workspace)?;
         }
+        if !self.sparse_paths.is_empty() {
+            config.worktree.sparse_paths = self.sparse_paths.clone();
+        }
+        Ok(())
+    }
+}
 
-        // Load .env file from project if configured and exists
-        let env_file_loaded = if self.config.docker.load_env_file {
-            let env_path = self
-                .git_context
-                .workspace_path
-                .join(&self.config.docker.env_file_path);
-            if env_path.exists() {
-                args.push("--env-file".to_string());
-                args.push(env_path.display().to_string());
-                true
-            } else {
-                false
-            }
-        } else {
-            false
-        };
+/// Parse an `--env-file` path, which must exist
+///
+/// `~` is expanded and relative paths resolve against the current directory.
+pub fn parse_env_file_arg(s: &str) -> Result<PathBuf, String> {
+    let path = expand_path(s);
+    if !path.is_file() {
+        return Err(format!("env file not found: {}", s));
+    }
+    path.canonicalize()
+        .map_err(|e| format!("cannot resolve {}: {}", s, e))
+}
 
-        // Add volume mounts for git context
-        for (host_path, container_path) in self.git_context.docker_mounts() {
-            args.push("-v".to_string());
-            args.push(format!("{}:{}", host_path.display(), container_path));
-        }
+/// A bind mount given on the command line as `host:container[:ro]`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Mount {
+    pub host: String,
+    pub container: String,
+    pub read_only: bool,
+}
 
-        // Pass Claude credentials via environment variables (not mount)
-        // This is more secure - the container gets the token but can't
-        // access or modify host credential files
-        let credential_env_vars = auth::get_credential_env_vars(&self.credentials);
-        for (key, value) in &credential_env_vars {
-            args.push("-e".to_string());
-            args.push(format!("{}={}", key, value));
+impl Mount {
+    /// Format as a `-v` argument, expanding the host path
+    fn volume_arg(&self) -> String {
+        let mut arg = format!("{}:{}", expand_path(&self.host).display(), self.container);
+        if self.read_only {
+            arg.push_str(":ro");
         }
+        arg
+    }
+}
 
-        // Mount MCP config if available
-        if let Some(ref mcp_path) = self.mcp_config_path {
-            args.push("-v".to_string());
-            args.push(format!(
-                "{}:/home/{}/.claude.json:ro",
-                mcp_path.display(),
-                self.config.docker.user
-            ));
-        }
+/// Parse a `host:container[:ro]` mount spec
+///
+/// A leading Windows drive letter (`C:\...`) is kept as part of the host path.
+pub fn parse_mount(s: &str) -> Result<Mount, String> {
+    let bytes = s.as_bytes();
+    let drive_len = if bytes.len() > 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/')
+    {
+        2
+    } else {
+        0
+    };
 
-        // Add extra volumes from config
-        for (host, container) in &self.config.docker.extra_volumes {
-            let expanded_host = shellexpand::tilde(host);
-            args.push("-v".to_string());
-            args.push(format!("{}:{}", expanded_host, container));
+    let (drive, rest) = s.split_at(drive_len);
+    let parts: Vec<&str> = rest.split(':').collect();
+    let (host, container, read_only) = match parts.as_slice() {
+        [host, container] => (*host, *container, false),
+        [host, container, "ro"] => (*host, *container, true),
+        [host, container, "rw"] => (*host, *container, false),
+        [_, _, mode] => {
+            return Err(format!(
+                "invalid mount mode '{}' in '{}': expected 'ro' or 'rw'",
+                mode, s
+            ))
         }
-
-        // Add environment variables from config
-        for (key, value) in &self.config.docker.extra_env {
-            args.push("-e".to_string());
-            args.push(format!("{}={}", key, value));
+        _ => {
+            return Err(format!(
+                "invalid mount '{}': expected host:container[:ro]",
+                s
+            ))
         }
+    };
 
-        // Add toolchain install commands as environment variable
-        // This allows the container to install required tools on startup
-        if !self.toolchain.is_empty() {
-            let install_cmds = self.toolchain.install_commands().join(" && ");
-            args.push("-e".to_string());
-            args.push(format!("CCS_TOOLCHAIN_INSTALL={}", install_cmds));
+    if host.is_empty() || container.is_empty() {
+        return Err(format!(
+            "invalid mount '{}': expected host:container[:ro]",
+            s
+        ));
+    }
 
-            // Also add individual tool names for reference
-            args.push("-e".to_string());
-            args.push(format!(
-                "CCS_TOOLCHAIN={}",
-                self.toolchain.tool_names().join(",")
-            ));
-        }
+    Ok(Mount {
+        host: format!("{}{}", drive, host),
+        container: container.to_string(),
+        read_only,
+    })
+}
 
-        // Set working directory
-        args.push("-w".to_string());
-        args.push(self.config.docker.workdir.clone());
+/// Options for building the container image
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Build without using the layer cache
+    pub no_cache: bool,
+    /// Build args from the command line (override `docker.build_args`)
+    pub build_args: Vec<(String, String)>,
+    /// Capture the build output and print only step headers (full tail on failure)
+    pub quiet: bool,
+    /// Reinstall Claude Code even if its layer is cached (`CLAUDE_CACHE_BUST`)
+    pub update_claude: bool,
+    /// Dockerfile stage to build (overrides `docker.build_target`)
+    pub target: Option<String>,
+}
 
-        // Use the configured image
-        args.push(self.config.docker.image.clone());
+/// Build arg pinning the Claude Code version installed in the image
+const CLAUDE_VERSION_BUILD_ARG: &str = "CLAUDE_VERSION";
 
-        // Add any extra arguments for Claude
-        for arg in extra_args {
-            args.push(arg.clone());
-        }
+/// Build arg that invalidates the cached Claude Code install layer when changed
+const CLAUDE_CACHE_BUST_BUILD_ARG: &str = "CLAUDE_CACHE_BUST";
 
-        // Handle dry-run mode: print command and exit
-        if dry_run {
-            // Build the command string with proper quoting, redacting credentials
-            let cmd_parts: Vec<String> = std::iter::once(self.runtime.command().to_string())
-                .chain(args.iter().map(|arg| shell_quote(&redact_credentials(arg))))
-                .collect();
-            println!("{}", cmd_parts.join(" \\\n  "));
-            return Ok(());
+/// Lines of captured build output shown when a quiet build fails
+const BUILD_FAILURE_TAIL_LINES: usize = 100;
+
+/// Extract a step header from a line of build output
+///
+/// Recognizes the classic builder (`Step 2/8 : RUN ...`), Podman/Buildah
+/// (`STEP 2/8: RUN ...`) and BuildKit plain progress (`#5 [2/8] RUN ...`).
+fn parse_build_step(line: &str) -> Option<String> {
+    let line = line.trim();
+
+    for prefix in ["Step ", "STEP "] {
+        if let Some(rest) = line.strip_prefix(prefix) {
+            let (counter, instruction) = rest.split_once(':')?;
+            let counter = counter.trim();
+            if counter.contains('/') && counter.split('/').all(|n| n.parse::<u32>().is_ok()) {
+                return Some(format!("[{}] {}", counter, instruction.trim()));
+            }
+            return None;
         }
+    }
 
-        // Build the actual Command
-        let mut cmd = Command::new(self.runtime.command());
-        for arg in &args {
-            cmd.arg(arg);
+    // BuildKit: "#<id> [<stage> <n>/<m>] <instruction>"
+    let rest = line.strip_prefix('#')?;
+    let (id, rest) = rest.split_once(' ')?;
+    if id.parse::<u32>().is_err() || !rest.starts_with('[') {
+        return None;
+    }
+    let (stage, instruction) = rest[1..].split_once(']')?;
+    let counter = stage.rsplit(' ').next()?;
+    if !counter.contains('/') {
+        // Internal steps like "[internal] load build definition"
+        return None;
+    }
+    Some(format!("[{}] {}", stage, instruction.trim()))
+}
+
+/// Run a build command, printing only step headers and the output tail on failure
+fn run_quiet_build(mut cmd: Command) -> anyhow::Result<std::process::ExitStatus> {
+    use std::io::BufRead;
+
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    // Read both streams concurrently so neither pipe fills up
+    let (tx, rx) = std::sync::mpsc::channel();
+    let readers: Vec<_> = [
+        child
+            .stdout
+            .take()
+            .map(|s| Box::new(s) as Box<dyn std::io::Read + Send>),
+        child
+            .stderr
+            .take()
+            .map(|s| Box::new(s) as Box<dyn std::io::Read + Send>),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|stream| {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            for line in std::io::BufReader::new(stream)
+                .lines()
+                .map_while(Result::ok)
+            {
+                let _ = tx.send(line);
+            }
+        })
+    })
+    .collect();
+    drop(tx);
+
+    let mut output = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for line in rx {
+        if let Some(step) = parse_build_step(&line) {
+            if seen.insert(step.clone()) {
+                println!("  {}", step);
+            }
         }
+        output.push(line);
+    }
+    for reader in readers {
+        let _ = reader.join();
+    }
 
-        if detach {
-            println!("Starting Claude Code sandbox (detached)...");
-        } else {
-            // Set up proper TTY handling for interactive mode
-            cmd.stdin(Stdio::inherit())
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit());
-            println!("Starting Claude Code sandbox...");
+    let status = child.wait()?;
+    if !status.success() {
+        let start = output.len().saturating_sub(BUILD_FAILURE_TAIL_LINES);
+        eprintln!(
+            "Build output (last {} of {} lines):",
+            output.len() - start,
+            output.len()
+        );
+        for line in &output[start..] {
+            eprintln!("{}", line);
         }
-        println!("Runtime: {}", self.runtime.name());
-        println!("Container: {}", self.container_name);
-        println!("Workspace: {}", self.git_context.workspace_path.display());
-        if self.git_context.is_worktree {
-            println!("(Running in git worktree)");
-        }
-        // Show credential source
-        match self.credentials.source {
-            CredentialSource::None => {
-                eprintln!("Warning: No Claude credentials found");
-                eprintln!("  Run 'claude login' on host, or set ANTHROPIC_API_KEY");
-            }
-            ref source => {
-                println!("Auth: {}", source);
-            }
-        }
-        if env_file_loaded {
-            println!("Loaded .env: {}", self.config.docker.env_file_path);
-        }
-        if let Some(ref mem) = self.config.docker.memory_limit {
-            println!("Memory limit: {}", mem);
-        }
-        if let Some(cpu) = self.config.docker.cpu_limit {
-            println!("CPU limit: {}", cpu);
-        }
-        println!();
+    }
+    Ok(status)
+}
 
-        if detach {
-            let output = cmd.output()?;
-            if output.status.success() {
-                let container_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                println!("Container started: {}", self.container_name);
-                println!(
-                    "Container ID: {}",
-                    &container_id[..12.min(container_id.len())]
-                );
-                println!();
-                println!("Commands:");
-                println!("  ccs --list              # List running sessions");
-                println!(
-                    "  ccs --attach {}   # Attach to session",
-                    self.container_name
-                );
-                println!("  ccs --logs {}     # View logs", self.container_name);
-                println!("  ccs --stop {}     # Stop session", self.container_name);
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(DockerError::CommandFailed(stderr.to_string()).into());
-            }
-        } else {
-            let status = cmd.status()?;
-            if !status.success() {
-                if let Some(code) = status.code() {
-                    std::process::exit(code);
+/// Assemble the image build command
+fn build_command(
+    runtime: ContainerRuntime,
+    config: &Config,
+    dockerfile_path: &Path,
+    context_dir: &Path,
+    options: &BuildOptions,
+) -> Result<Command, DockerError> {
+    let mut cmd = runtime.new_command();
+    cmd.arg("build")
+        .arg("-t")
+        .arg(&config.docker.image)
+        .arg("-f")
+        .arg(dockerfile_path);
+
+    if options.no_cache {
+        cmd.arg("--no-cache");
+    }
+
+    if let Some(target) = options
+        .target
+        .as_ref()
+        .or(config.docker.build_target.as_ref())
+    {
+        cmd.arg("--target").arg(
+            parse_build_target(target)
+                .map_err(|_| DockerError::InvalidBuildTarget(target.clone()))?,
+        );
+    }
+
+    // CLI build args override those from config, which override claude_version
+    let mut build_args = HashMap::new();
+    if let Some(ref version) = config.docker.claude_version {
+        build_args.insert(CLAUDE_VERSION_BUILD_ARG.to_string(), version.clone());
+    }
+    if options.update_claude {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        build_args.insert(CLAUDE_CACHE_BUST_BUILD_ARG.to_string(), now.to_string());
+    }
+    build_args.extend(config.docker.build_args.clone());
+    build_args.extend(options.build_args.iter().cloned());
+    let resolved = secrets::resolve_secrets(&build_args, &config.secrets)?;
+
+    let mut keys: Vec<&String> = resolved.keys().collect();
+    keys.sort();
+    for key in keys {
+        cmd.arg("--build-arg")
+            .arg(format!("{}={}", key, resolved[key]));
+    }
+
+    cmd.arg(context_dir);
+    Ok(cmd)
+}
+
+/// Build context: `docker.build_context`, else the Dockerfile's directory
+fn build_context_dir(config: &Config, dockerfile_path: &Path) -> PathBuf {
+    if let Some(ref context) = config.docker.build_context {
+        return expand_path(&context.to_string_lossy());
+    }
+    match dockerfile_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Files and bytes found under a build context
+#[derive(Debug, Default, PartialEq)]
+struct ContextSize {
+    files: u64,
+    bytes: u64,
+}
+
+/// Measure `dir`, stopping early once it exceeds `limit_bytes`
+///
+/// Symlinks aren't followed, matching what the build context upload sends.
+fn measure_context(dir: &Path, limit_bytes: u64) -> ContextSize {
+    let mut size = ContextSize::default();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                size.files += 1;
+                size.bytes += metadata.len();
+                if size.bytes > limit_bytes {
+                    return size;
                 }
-                return Err(
-                    DockerError::CommandFailed("Container exited with error".to_string()).into(),
-                );
             }
         }
+    }
+    size
+}
 
-        Ok(())
+/// Whether `dir` has no .dockerignore and holds more than `threshold_mb` megabytes
+fn is_large_unignored_context(dir: &Path, threshold_mb: u64) -> bool {
+    if threshold_mb == 0 || dir.join(".dockerignore").exists() {
+        return false;
     }
+    let limit = threshold_mb.saturating_mul(1024 * 1024);
+    measure_context(dir, limit).bytes > limit
 }
 
-/// Quote a string for shell usage if it contains special characters
-fn shell_quote(s: &str) -> String {
-    // Check if string needs quoting
-    let needs_quoting = s.is_empty()
-        || s.contains(|c: char| {
-            c.is_whitespace()
-                || c == '"'
-                || c == '\''
-                || c == '\\'
-                || c == '$'
-                || c == '`'
-                || c == '!'
-                || c == '*'
-                || c == '?'
-                || c == '['
-                || c == ']'
-                || c == '{'
-                || c == '}'
-                || c == '('
-                || c == ')'
-                || c == '<'
-                || c == '>'
-                || c == '|'
-                || c == '&'
-                || c == ';'
-                || c == '#'
-        });
+fn warn_if_large_context(dir: &Path, threshold_mb: u64) {
+    if is_large_unignored_context(dir, threshold_mb) {
+        eprintln!(
+            "Warning: build context {} is over {} MB and has no .dockerignore; the whole directory is sent to the daemon. Add a .dockerignore or set docker.build_context",
+            dir.display(),
+            threshold_mb
+        );
+    }
+}
 
-    if !needs_quoting {
-        return s.to_string();
+/// Resolve a per-run workdir override to a container path under the workspace
+///
+/// Relative paths are resolved against the workspace mount; the directory
+/// must exist in the host workspace.
+pub fn resolve_workdir(workdir: &str, host_workspace: &Path) -> Result<String, DockerError> {
+    let invalid =
+        |reason: &str| DockerError::InvalidWorkdir(workdir.to_string(), reason.to_string());
+
+    let relative = if Path::new(workdir).is_absolute() {
+        Path::new(workdir)
+            .strip_prefix(CONTAINER_WORKSPACE)
+            .map_err(|_| invalid(&format!("must be under {}", CONTAINER_WORKSPACE)))?
+    } else {
+        Path::new(workdir)
+    };
+
+    if relative
+        .components()
+        .any(|c| matches!(c, std::path::Component::ParentDir))
+    {
+        return Err(invalid("must not contain '..'"));
     }
 
-    // Use single quotes, escaping any single quotes in the string
-    format!("'{}'", s.replace('\'', "'\\''"))
+    if !host_workspace.join(relative).is_dir() {
+        return Err(invalid("directory does not exist in the workspace"));
+    }
+
+    let relative = relative.to_string_lossy();
+    let relative = relative.trim_start_matches("./").trim_end_matches('/');
+    if relative.is_empty() || relative == "." {
+        Ok(CONTAINER_WORKSPACE.to_string())
+    } else {
+        Ok(format!("{}/{}", CONTAINER_WORKSPACE, relative))
+    }
 }
 
-/// Redact credential values in environment variable arguments for dry-run output
-fn redact_credentials(s: &str) -> String {
-    // Check for credential environment variable patterns
-    const SENSITIVE_PREFIXES: &[&str] = &[
-        "ANTHROPIC_API_KEY=",
-        "CLAUDE_CODE_OAUTH_TOKEN=",
-        "CCS_TOOLCHAIN_INSTALL=", // Long install commands, truncate for readability
-    ];
+/// Parse a KEY=VALUE build argument
+pub fn parse_build_arg(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid build arg '{}': expected KEY=VALUE", s)),
+    }
+}
 
-    for prefix in SENSITIVE_PREFIXES {
-        if s.starts_with(prefix) {
-            return format!("{}[...]", prefix);
-        }
+/// Validate a `--target` build stage name
+pub fn parse_build_target(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("expected a Dockerfile stage name".to_string());
     }
+    Ok(s.to_string())
+}
 
-    s.to_string()
+/// Validate a `--since` value, leaving the format itself for the runtime to check
+pub fn parse_since(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("expected a duration (e.g. 10m) or timestamp".to_string());
+    }
+    Ok(s.to_string())
 }
 
-/// List all running ccs sessions
-pub fn list_sessions() -> anyhow::Result<()> {
-    let runtime = ContainerRuntime::detect()?;
+/// Validate a `--memory` value in the runtime's size format (`512m`, `2g`)
+///
+/// A bare number is bytes; suffixes `b`, `k`, `m` and `g` are case-insensitive.
+pub fn parse_memory_limit(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &s[digits.len()..];
 
-    let output = Command::new(runtime.command())
-        .args([
-            "ps",
-            "-a",
-            "--filter",
-            "name=ccs-",
-            "--format",
-            "table {{.Names}}\t{{.Status}}\t{{.CreatedAt}}",
-        ])
-        .output()?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid memory size '{}' (e.g. 512m, 2g)", s));
+    }
+    if !matches!(
+        unit.to_ascii_lowercase().as_str(),
+        "" | "b" | "k" | "m" | "g"
+    ) {
+        return Err(format!(
+            "invalid memory unit '{}' in '{}' (use b, k, m or g)",
+            unit, s
+        ));
+    }
+    if digits.parse::<u64>().map_or(true, |n| n == 0) {
+        return Err(format!("memory size must be positive: '{}'", s));
+    }
 
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        if stdout.trim().is_empty() || stdout.lines().count() <= 1 {
-            println!("No ccs sessions found.");
-        } else {
-            println!("{}", stdout);
-        }
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(DockerError::CommandFailed(stderr.to_string()).into());
+    Ok(s.to_lowercase())
+}
+
+/// Validate a `--cpus` value: a positive number of cores
+pub fn parse_cpus(s: &str) -> Result<f32, String> {
+    let cpus: f32 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid CPU count '{}' (e.g. 2 or 1.5)", s))?;
+    if !cpus.is_finite() || cpus <= 0.0 {
+        return Err(format!("CPU count must be positive: '{}'", s));
     }
+    Ok(cpus)
+}
 
-    Ok(())
+/// Validate a `--permission-mode` value against the modes Claude Code knows
+pub fn parse_permission_mode(s: &str) -> Result<PermissionMode, String> {
+    PermissionMode::ALL
+        .into_iter()
+        .find(|mode| mode.as_str() == s)
+        .ok_or_else(|| {
+            let known: Vec<&str> = PermissionMode::ALL.iter().map(|m| m.as_str()).collect();
+            format!(
+                "unknown permission mode '{}' (expected one of: {})",
+                s,
+                known.join(", ")
+            )
+        })
 }
 
-/// Attach to a running ccs session
-pub fn attach_session(container: &str) -> anyhow::Result<()> {
-    let runtime = ContainerRuntime::detect()?;
+/// Options for showing session logs
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// Only show output since this duration (e.g. `10m`) or timestamp
+    pub since: Option<String>,
+    /// Keep printing new output until the container stops
+    pub follow: bool,
+}
 
-    // Resolve partial container name
-    let container_name = resolve_container_name(runtime, container)?;
+/// Assemble the `logs` arguments for a container
+fn logs_args(container: &str, options: &LogOptions) -> Vec<String> {
+    let mut args = vec!["logs".to_string()];
+    if options.follow {
+        args.push("-f".to_string());
+    }
+
+    if let Some(ref since) = options.since {
+        args.push("--since".to_string());
+        args.push(since.clone());
+    }
 
-    println!("Attaching to {}...", container_name);
-    println!("(Use Ctrl+P, Ctrl+Q to detach without stopping)\n");
+    args.push(container.to_string());
+    args
+}
 
-    let status = Command::new(runtime.command())
-        .args(["attach", &container_name])
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?;
+/// Lines of output replayed by `--attach --replay` unless `--tail` is given
+pub const DEFAULT_REPLAY_LINES: usize = 50;
 
-    if !status.success() {
-        if let Some(code) = status.code() {
-            std::process::exit(code);
-        }
+/// Options for attaching to a session
+#[derive(Debug, Clone, Default)]
+pub struct AttachOptions {
+    /// Print this many lines of recent output before attaching
+    pub replay_lines: Option<usize>,
+}
+
+/// Assemble the `logs` arguments that replay a session's recent output
+fn replay_args(container: &str, lines: usize) -> Vec<String> {
+    vec![
+        "logs".to_string(),
+        "--tail".to_string(),
+        lines.to_string(),
+        container.to_string(),
+    ]
+}
+
+/// How `--attach` and `--logs` relay a session, depending on what ccs is connected to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Passthrough {
+    /// Forward stdin to the container; attaching it from a pipe fails
+    stdin: bool,
+    /// Strip terminal escape sequences because stdout is piped or redirected
+    strip_ansi: bool,
+}
+
+impl Passthrough {
+    fn detect() -> Self {
+        Self::for_terminals(
+            std::io::stdin().is_terminal(),
+            std::io::stdout().is_terminal(),
+        )
     }
 
-    Ok(())
+    fn for_terminals(stdin_is_terminal: bool, stdout_is_terminal: bool) -> Self {
+        Self {
+            stdin: stdin_is_terminal,
+            strip_ansi: !stdout_is_terminal,
+        }
+    }
 }
 
-/// Show logs from a ccs session
-pub fn show_logs(container: &str) -> anyhow::Result<()> {
-    let runtime = ContainerRuntime::detect()?;
+/// Assemble the `attach` arguments for a container
+fn attach_args(container: &str, passthrough: Passthrough) -> Vec<String> {
+    let mut args = vec!["attach".to_string()];
+    if !passthrough.stdin {
+        args.push("--no-stdin".to_string());
+    }
+    args.push(container.to_string());
+    args
+}
 
-    // Resolve partial container name
-    let container_name = resolve_container_name(runtime, container)?;
+/// Run `command`, copying its stdout through an [`AnsiStripper`] if asked to
+fn run_relayed(
+    command: &mut Command,
+    passthrough: Passthrough,
+) -> std::io::Result<std::process::ExitStatus> {
+    if !passthrough.strip_ansi {
+        return command.stdout(Stdio::inherit()).status();
+    }
 
-    let status = Command::new(runtime.command())
-        .args(["logs", "-f", &container_name])
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?;
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    if let Some(stdout) = child.stdout.take() {
+        if !relay_output(stdout, true)? {
+            let _ = child.kill();
+        }
+    }
+    child.wait()
+}
 
-    if !status.success() {
-        if let Some(code) = status.code() {
-            std::process::exit(code);
+/// Copy `stdout` to ours, through an [`AnsiStripper`] if `strip_ansi` is set
+///
+/// Returns false if our stdout was closed (e.g. `| head`) before the stream ended.
+fn relay_output(mut stdout: impl std::io::Read, strip_ansi: bool) -> std::io::Result<bool> {
+    use std::io::Write;
+    let mut stripper = AnsiStripper::default();
+    let mut out = std::io::stdout().lock();
+    let mut buf = [0u8; 8192];
+    let mut plain = Vec::new();
+    loop {
+        let n = stdout.read(&mut buf)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        let chunk = if strip_ansi {
+            plain.clear();
+            stripper.strip(&buf[..n], &mut plain);
+            &plain[..]
+        } else {
+            &buf[..n]
+        };
+        if out.write_all(chunk).and_then(|_| out.flush()).is_err() {
+            return Ok(false);
         }
     }
+}
 
-    Ok(())
+/// Reader counting the bytes read through it, so a watcher can tell when the
+/// stream goes quiet
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicUsize>,
 }
 
-/// Stop a running ccs session
-pub fn stop_session(container: &str) -> anyhow::Result<()> {
-    let runtime = ContainerRuntime::detect()?;
+impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+}
 
-    // Resolve partial container name
-    let container_name = resolve_container_name(runtime, container)?;
+/// How often `--logs` checks whether the followed container is still running
+const LOGS_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-    println!("Stopping {}...", container_name);
+/// How long a follower of a stopped container may print nothing before it's ended
+const LOGS_DRAIN_GRACE: Duration = Duration::from_secs(2);
 
-    let status = Command::new(runtime.command())
-        .args(["stop", &container_name])
-        .status()?;
+/// Run a `logs -f` command like [`run_relayed`], ending it once the container stops
+///
+/// Some runtime setups keep `logs -f` open after the container exits, so
+/// `inspect` is polled for the container's `{{.State.Running}}` in the
+/// meantime. Once the container isn't running and the follower has printed
+/// nothing for [`LOGS_DRAIN_GRACE`], the follower is killed.
+fn follow_relayed(
+    command: &mut Command,
+    passthrough: Passthrough,
+    inspect: impl FnMut() -> Option<String>,
+) -> std::io::Result<std::process::ExitStatus> {
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    let relayed = Arc::new(AtomicUsize::new(0));
+    let relay = child.stdout.take().map(|stdout| {
+        let stdout = CountingReader {
+            inner: stdout,
+            count: Arc::clone(&relayed),
+        };
+        std::thread::spawn(move || relay_output(stdout, passthrough.strip_ansi))
+    });
 
-    if status.success() {
-        println!("Stopped.");
+    let stopped = watch_until_stopped(
+        inspect,
+        || !matches!(child.try_wait(), Ok(None)),
+        || relayed.load(Ordering::Relaxed),
+        LOGS_POLL_INTERVAL,
+        LOGS_DRAIN_GRACE,
+    );
+    if stopped {
+        let _ = child.kill();
+    }
 
-        // Also remove the container
-        let _ = Command::new(runtime.command())
-            .args(["rm", &container_name])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status();
-    } else {
-        return Err(DockerError::CommandFailed("Failed to stop container".to_string()).into());
+    let status = child.wait()?;
+    if let Some(relay) = relay {
+        let _ = relay.join();
     }
+    Ok(status)
+}
 
-    Ok(())
+/// Poll until the container stops and its follower goes quiet, or `finished`
+/// reports the follower is done
+///
+/// `inspect` returns the container's `{{.State.Running}}`, or None once it
+/// can't be inspected (e.g. a `--rm` container that's gone), and `relayed`
+/// the amount of output the follower has produced. Returns true once the
+/// container isn't running and the follower, still running, has produced
+/// nothing for `grace` (counted from when the container was last seen running).
+fn watch_until_stopped(
+    mut inspect: impl FnMut() -> Option<String>,
+    mut finished: impl FnMut() -> bool,
+    mut relayed: impl FnMut() -> usize,
+    interval: Duration,
+    grace: Duration,
+) -> bool {
+    let mut last_relayed = relayed();
+    let mut quiet_since = Instant::now();
+    loop {
+        if finished() {
+            return false;
+        }
+        let now_relayed = relayed();
+        if inspect().as_deref().map(str::trim) == Some("true") || now_relayed != last_relayed {
+            last_relayed = now_relayed;
+            quiet_since = Instant::now();
+        } else if quiet_since.elapsed() >= grace {
+            return true;
+        }
+        std::thread::sleep(interval);
+    }
 }
 
-/// Resolve a partial container name to full name
-fn resolve_container_name(runtime: ContainerRuntime, partial: &str) -> anyhow::Result<String> {
-    // If it already starts with ccs-, use as-is
-    let search_name = if partial.starts_with("ccs-") {
-        partial.to_string()
-    } else {
-        format!("ccs-{}", partial)
-    };
+/// Removes terminal escape sequences from a byte stream split into chunks
+#[derive(Debug, Default)]
+struct AnsiStripper {
+    state: AnsiState,
+}
 
-    // Try to find matching container
-    let output = Command::new(runtime.command())
-        .args([
-            "ps",
-            "-a",
-            "--filter",
-            &format!("name={}", search_name),
-            "--format",
-            "{{.Names}}",
-        ])
-        .output()?;
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    #[default]
+    Text,
+    /// After ESC
+    Escape,
+    /// After `ESC (` or `ESC )`, which take one more byte
+    Charset,
+    /// Inside a control sequence (`ESC [`), ended by a byte in `@`..=`~`
+    Csi,
+    /// Inside an operating system command (`ESC ]`), ended by BEL or `ESC \`
+    Osc,
+    /// After ESC inside an operating system command
+    OscEscape,
+}
 
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let names: Vec<String> = stdout
-            .lines()
-            .filter(|s| !s.is_empty())
-            .map(|s| s.trim().to_string())
-            .collect();
+impl AnsiStripper {
+    /// Append the text of `input` to `out`, dropping escape sequences
+    fn strip(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        use AnsiState::*;
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (Text, 0x1b) => Escape,
+                (Text, _) => {
+                    out.push(byte);
+                    Text
+                }
+                (Escape, b'[') => Csi,
+                (Escape, b']') => Osc,
+                (Escape, b'(' | b')') => Charset,
+                (Escape | Charset, _) => Text,
+                (Csi, 0x40..=0x7e) => Text,
+                (Csi, _) => Csi,
+                (Osc, 0x07) => Text,
+                (Osc, 0x1b) => OscEscape,
+                (Osc, _) => Osc,
+                (OscEscape, _) => Text,
+            };
+        }
+    }
+}
 
-        select_container_match(names, partial, &search_name)
-    } else {
-        Ok(search_name)
+/// Container label keys holding session metadata
+const LABEL_WORKSPACE: &str = "ccs.workspace";
+const LABEL_DETACH: &str = "ccs.detach";
+const LABEL_ARGS: &str = "ccs.args";
+const LABEL_LOG_FILE: &str = "ccs.log_file";
+const LABEL_NETWORK: &str = "ccs.network";
+const LABEL_OPTIONS: &str = "ccs.options";
+
+/// Label naming the repository a session was started for
+const LABEL_REPO: &str = "ccs.repo";
+
+/// Label naming the branch checked out in a session's workspace
+const LABEL_BRANCH: &str = "ccs.branch";
+
+/// Label on service containers and networks naming the session they belong to
+const LABEL_SERVICE_OF: &str = "ccs.service_of";
+
+/// Session parameters persisted as container labels so a session can be recreated
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionMetadata {
+    /// Host path of the mounted workspace
+    pub workspace: PathBuf,
+    /// Whether the session was started detached
+    pub detach: bool,
+    /// Extra arguments passed to Claude Code
+    pub claude_args: Vec<String>,
+    /// Host file the session output is copied to
+    pub log_file: Option<PathBuf>,
+    /// Network shared with the session's `[services]` containers
+    pub network: Option<String>,
+    /// The rest of the command line's run options
+    pub options: SessionOptions,
+}
+
+/// Run options of a session beyond those [`SessionMetadata`] keeps in labels
+/// of their own, stored together as JSON
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SessionOptions {
+    pub mounts: Vec<Mount>,
+    pub also: Vec<PathBuf>,
+    pub user: Option<String>,
+    pub env_files: Vec<PathBuf>,
+    pub docker_args: Vec<String>,
+    pub overrides: RunOverrides,
+}
+
+impl SessionOptions {
+    /// The options of `options` that are recorded
+    fn from_run_options(options: &RunOptions) -> Self {
+        SessionOptions {
+            mounts: options.mounts.clone(),
+            also: options.also.clone(),
+            user: options.user.clone(),
+            env_files: options.env_files.clone(),
+            docker_args: options.docker_args.clone(),
+            overrides: options.overrides.clone(),
+        }
     }
 }
 
-/// Select the best container match from a list of names
-fn select_container_match(
-    mut names: Vec<String>,
-    partial: &str,
-    search_name: &str,
-) -> anyhow::Result<String> {
-    match names.len() {
-        0 => Err(anyhow::anyhow!("No container found matching '{}'", partial)),
-        1 => Ok(names.pop().unwrap()),
-        _ => {
-            // Check for exact match
-            if let Some(pos) = names.iter().position(|n| n == search_name) {
-                Ok(names.swap_remove(pos))
+impl SessionMetadata {
+    /// Encode as `--label` values
+    fn to_labels(&self) -> Vec<String> {
+        let mut labels = vec![
+            format!("{}={}", LABEL_WORKSPACE, self.workspace.display()),
+            format!("{}={}", LABEL_DETACH, self.detach),
+            format!(
+                "{}={}",
+                LABEL_ARGS,
+                serde_json::to_string(&self.claude_args).unwrap_or_default()
+            ),
+        ];
+        if let Some(ref log_file) = self.log_file {
+            labels.push(format!("{}={}", LABEL_LOG_FILE, log_file.display()));
+        }
+        if let Some(ref network) = self.network {
+            labels.push(format!("{}={}", LABEL_NETWORK, network));
+        }
+        if self.options != SessionOptions::default() {
+            labels.push(format!(
+                "{}={}",
+                LABEL_OPTIONS,
+                serde_json::to_string(&self.options).unwrap_or_default()
+            ));
+        }
+        labels
+    }
+
+    /// Options recreating the session's `run`
+    fn run_options(&self) -> RunOptions {
+        RunOptions {
+            detach: self.detach,
+            log_file: self.log_file.clone(),
+            mounts: self.options.mounts.clone(),
+            also: self.options.also.clone(),
+            user: self.options.user.clone(),
+            env_files: self.options.env_files.clone(),
+            docker_args: self.options.docker_args.clone(),
+            overrides: self.options.overrides.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Decode from a container's labels, if it was started by ccs
+    fn from_labels(labels: &HashMap<String, String>) -> Option<Self> {
+        Some(SessionMetadata {
+            workspace: PathBuf::from(labels.get(LABEL_WORKSPACE)?),
+            detach: labels.get(LABEL_DETACH)? == "true",
+            claude_args: serde_json::from_str(labels.get(LABEL_ARGS)?).ok()?,
+            log_file: labels.get(LABEL_LOG_FILE).map(PathBuf::from),
+            network: labels.get(LABEL_NETWORK).cloned(),
+            // Sessions started before these were recorded have no label
+            options: labels
+                .get(LABEL_OPTIONS)
+                .and_then(|options| serde_json::from_str(options).ok())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Read the metadata of an existing container
+    fn read(runtime: ContainerRuntime, container: &str) -> Result<Self, DockerError> {
+        let output = runtime
+            .new_command()
+            .args(["inspect", "--format", "{{json .Config.Labels}}", container])
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(DockerError::CommandFailed(stderr.to_string()));
+        }
+
+        serde_json::from_slice::<Option<HashMap<String, String>>>(&output.stdout)
+            .ok()
+            .flatten()
+            .and_then(|labels| Self::from_labels(&labels))
+            .ok_or_else(|| DockerError::NoSessionMetadata(container.to_string()))
+    }
+}
+
+/// Sidecar containers from `[services]`, started on a network shared with a session
+///
+/// Each service is reachable from the session by its service name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceGroup {
+    /// Session container the services belong to
+    pub session: String,
+    /// Network the session and its services are attached to
+    pub network: String,
+    /// Service name and `run` arguments for each service container
+    services: Vec<(String, Vec<String>)>,
+    /// Service environment variables whose values were resolved from secret references
+    secret_env_keys: Vec<String>,
+}
+
+impl ServiceGroup {
+    /// Plan the services for `session`, or `None` when none are configured
+    fn new(
+        session: &str,
+        services: &HashMap<String, ServiceConfig>,
+        secrets_config: &SecretsConfig,
+        keep_secret_refs: bool,
+    ) -> Result<Option<Self>, DockerError> {
+        if services.is_empty() {
+            return Ok(None);
+        }
+
+        let network = format!("{}-net", session);
+        let mut names: Vec<&String> = services.keys().collect();
+        names.sort();
+
+        let mut planned = Vec::new();
+        let mut secret_env_keys = Vec::new();
+        for name in names {
+            let service = &services[name];
+            let mut args: Vec<String> = vec![
+                "run".to_string(),
+                "-d".to_string(),
+                "--name".to_string(),
+                format!("{}-{}", session, name),
+                "--network".to_string(),
+                network.clone(),
+                "--network-alias".to_string(),
+                name.clone(),
+                "--label".to_string(),
+                format!("{}={}", LABEL_SERVICE_OF, session),
+            ];
+
+            for (key, value, secret) in resolve_env(&service.env, secrets_config, keep_secret_refs)?
+            {
+                if secret {
+                    secret_env_keys.push(key.clone());
+                }
+                args.push("-e".to_string());
+                args.push(format!("{}={}", key, value));
+            }
+
+            for port in &service.ports {
+                args.push("-p".to_string());
+                args.push(port.clone());
+            }
+
+            args.push(service.image.clone());
+            planned.push((name.clone(), args));
+        }
+
+        Ok(Some(ServiceGroup {
+            session: session.to_string(),
+            network,
+            services: planned,
+            secret_env_keys,
+        }))
+    }
+
+    /// Arguments creating the shared network
+    fn network_create_args(&self) -> Vec<String> {
+        vec![
+            "network".to_string(),
+            "create".to_string(),
+            "--label".to_string(),
+            format!("{}={}", LABEL_SERVICE_OF, self.session),
+            self.network.clone(),
+        ]
+    }
+
+    /// Create the network and start every service, tearing the group down on failure
+    fn start(&self, runtime: ContainerRuntime) -> Result<(), DockerError> {
+        let result = self.try_start(runtime);
+        if result.is_err() {
+            remove_service_group(runtime, &self.session, &self.network);
+        }
+        result
+    }
+
+    fn try_start(&self, runtime: ContainerRuntime) -> Result<(), DockerError> {
+        let output = runtime
+            .new_command()
+            .args(self.network_create_args())
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(DockerError::CommandFailed(stderr.trim().to_string()));
+        }
+
+        for (name, args) in &self.services {
+            let output = runtime.new_command().args(args).output()?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(DockerError::ServiceFailed(
+                    name.clone(),
+                    stderr.trim().to_string(),
+                ));
+            }
+            println!("Service: {} ({}-{})", name, self.session, name);
+        }
+        Ok(())
+    }
+
+    /// Print the commands that would start the group, redacting secrets
+    fn print_dry_run(&self, runtime: ContainerRuntime) {
+        let commands = std::iter::once(self.network_create_args())
+            .chain(self.services.iter().map(|(_, args)| args.clone()));
+        for args in commands {
+            let cmd_parts: Vec<String> = runtime
+                .command_prefix()
+                .into_iter()
+                .chain(
+                    args.iter()
+                        .map(|arg| shell_quote(&redact_arg(arg, &self.secret_env_keys))),
+                )
+                .collect();
+            println!("{}", cmd_parts.join(" \\\n  "));
+        }
+    }
+}
+
+/// Remove a session's service containers and network, ignoring failures
+pub fn remove_service_group(runtime: ContainerRuntime, session: &str, network: &str) {
+    let filter = format!("label={}={}", LABEL_SERVICE_OF, session);
+    let output = runtime
+        .new_command()
+        .args(["ps", "-aq", "--filter", &filter])
+        .stderr(Stdio::null())
+        .output();
+    if let Ok(output) = output {
+        for id in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+            let _ = runtime
+                .new_command()
+                .args(["rm", "-f", id])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+
+    let _ = runtime
+        .new_command()
+        .args(["network", "rm", network])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Network of the `[services]` recorded in a session's metadata, if any
+pub fn session_network(runtime: ContainerRuntime, session: &str) -> Option<String> {
+    SessionMetadata::read(runtime, session)
+        .ok()
+        .and_then(|metadata| metadata.network)
+}
+
+/// Assembled arguments for a container run
+struct RunArgs {
+    args: Vec<String>,
+    /// Environment variables whose values were resolved from secret references
+    secret_env_keys: Vec<String>,
+    env_file_loaded: bool,
+    /// Sidecar services to start before the session
+    services: Option<ServiceGroup>,
+    /// Index of the `--entrypoint` shell in `args`, for `--shell`
+    shell_index: Option<usize>,
+}
+
+pub struct DockerRunner {
+    runtime: ContainerRuntime,
+    config: Config,
+    git_context: GitContext,
+    mcp_config_path: Option<PathBuf>,
+    container_name: String,
+    credentials: ClaudeCredentials,
+    toolchain: Toolchain,
+    git_identity: GitIdentity,
+    /// Host credentials for `git.forward_credentials`
+    git_credentials: Option<GitCredentials>,
+    rootless: bool,
+}
+
+impl DockerRunner {
+    /// Create a new Docker/Podman runner
+    pub fn new(
+        config: &Config,
+        git_context: &GitContext,
+        toolchain: Toolchain,
+        session_name: Option<&str>,
+    ) -> Result<Self, DockerError> {
+        let runtime = ContainerRuntime::detect()?;
+        let rootless = runtime.is_rootless();
+        let container_name = generate_container_name(
+            &container_prefix(config),
+            &git_context.repo_name,
+            session_name,
+        );
+        let credentials = auth::discover_credentials_with(&config.auth);
+
+        // Config overrides take precedence over the host's git identity
+        let host_identity = git::host_identity();
+        let git_identity = GitIdentity {
+            name: config.git.user_name.clone().or(host_identity.name),
+            email: config.git.user_email.clone().or(host_identity.email),
+        };
+
+        let git_credentials = if config.git.forward_credentials {
+            let found = GitCredentials::discover();
+            if found.is_none() {
+                eprintln!(
+                    "Warning: git.forward_credentials is enabled but no token (gh auth token, git credential fill) or ~/.git-credentials was found"
+                );
+            }
+            found
+        } else {
+            None
+        };
+
+        Ok(DockerRunner {
+            runtime,
+            config: config.clone(),
+            git_context: git_context.clone(),
+            mcp_config_path: None,
+            container_name,
+            credentials,
+            toolchain,
+            git_identity,
+            git_credentials,
+            rootless,
+        })
+    }
+
+    /// Generate this session's MCP config (with resolved secrets) to be mounted
+    pub fn generate_mcp_config(&mut self) -> Result<(), mcp::McpError> {
+        self.mcp_config_path = mcp::generate_mcp_config(
+            &self.config,
+            &self.git_context.workspace_path,
+            &self.container_name,
+        )?;
+        Ok(())
+    }
+
+    /// Build the container image
+    pub fn build_image(config: &Config, options: &BuildOptions) -> anyhow::Result<()> {
+        let runtime = ContainerRuntime::detect()?;
+
+        // Find Dockerfile
+        let dockerfile_path = config
+            .docker
+            .dockerfile_path
+            .as_ref()
+            .map(|p| expand_path(&p.to_string_lossy()))
+            .or_else(|| {
+                // Look in common locations
+                let candidates = [
+                    PathBuf::from("docker/Dockerfile"),
+                    PathBuf::from("Dockerfile"),
+                ];
+                candidates.into_iter().find(|p| p.exists())
+            })
+            .ok_or_else(|| DockerError::DockerfileNotFound(PathBuf::from("docker/Dockerfile")))?;
+
+        if !dockerfile_path.exists() {
+            return Err(DockerError::DockerfileNotFound(dockerfile_path).into());
+        }
+
+        let context_dir = build_context_dir(config, &dockerfile_path);
+        warn_if_large_context(&context_dir, config.docker.build_context_warn_mb);
+
+        println!(
+            "Building image {} using {} from {}...",
+            config.docker.image,
+            runtime.name(),
+            dockerfile_path.display()
+        );
+
+        let started = Instant::now();
+        let mut cmd = build_command(runtime, config, &dockerfile_path, &context_dir, options)?;
+        let status = telemetry::timed("build", || {
+            if options.quiet {
+                run_quiet_build(cmd)
             } else {
-                Err(anyhow::anyhow!(
-                    "Multiple containers match '{}': {}",
-                    partial,
-                    names.join(", ")
-                ))
+                Ok(cmd.status()?)
+            }
+        })?;
+        let elapsed = started.elapsed().as_secs_f32();
+
+        if !status.success() {
+            return Err(DockerError::CommandFailed(format!(
+                "{} build failed after {:.1}s",
+                runtime.name(),
+                elapsed
+            ))
+            .into());
+        }
+
+        println!(
+            "Successfully built image: {} ({:.1}s)",
+            config.docker.image, elapsed
+        );
+        Ok(())
+    }
+
+    /// User to run the container as: `--user`, else the host UID:GID with `match_host_uid`
+    fn user_spec(&self, options: &RunOptions) -> Option<String> {
+        options.user.clone().or_else(|| {
+            if self.config.docker.match_host_uid {
+                host_uid_gid()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Claude settings files to mount read-only, as (host path, container path)
+    fn claude_settings_mounts(&self) -> Vec<(PathBuf, String)> {
+        if !self.config.docker.mount_claude_settings {
+            return vec![];
+        }
+
+        let claude_dir = format!("/home/{}/.claude", self.config.docker.user);
+        let mut mounts = Vec::new();
+
+        if let Some(settings) = dirs::home_dir()
+            .map(|home| home.join(".claude").join("settings.json"))
+            .filter(|p| p.is_file())
+        {
+            mounts.push((settings, format!("{}/settings.json", claude_dir)));
+        }
+
+        // Mounted as user memory rather than into /workspace, which would
+        // leave an empty placeholder file in the worktree on the host
+        if let Some(claude_md) = missing_project_claude_md(
+            &self.git_context.workspace_path,
+            self.git_context.shared_git_dir.as_deref(),
+        ) {
+            mounts.push((claude_md, format!("{}/CLAUDE.md", claude_dir)));
+        }
+
+        mounts
+    }
+
+    /// Named volume and container path for `docker.persist_claude_state`
+    ///
+    /// The volume covers ~/.claude only; the generated MCP config is mounted
+    /// at ~/.claude.json, outside it.
+    fn claude_state_mount(&self) -> Option<(String, String)> {
+        self.config.docker.persist_claude_state.then(|| {
+            (
+                claude_state_volume(&self.config, &self.git_context.repo_name),
+                format!("/home/{}/.claude", self.config.docker.user),
+            )
+        })
+    }
+
+    /// Assemble the arguments for the container run command
+    fn build_run_args(
+        &self,
+        extra_args: &[String],
+        options: &RunOptions,
+    ) -> Result<RunArgs, DockerError> {
+        // Build up argument list for the command
+        let mut args: Vec<String> = vec![
+            "run".to_string(),
+            "--name".to_string(),
+            self.container_name.clone(),
+        ];
+        let mut secret_env_keys = Vec::new();
+
+        let services = ServiceGroup::new(
+            &self.container_name,
+            &self.config.services,
+            &self.config.secrets,
+            options.keep_secret_refs,
+        )?;
+        if let Some(ref group) = services {
+            args.push("--network".to_string());
+            args.push(group.network.clone());
+        }
+
+        if options.detach {
+            // Detached mode - run in background, don't remove on exit
+            args.push("-d".to_string());
+        } else {
+            // Interactive mode - remove on exit
+            args.push("--rm".to_string());
+            // Only use -it flags when we have a TTY
+            if std::io::stdin().is_terminal() {
+                args.push("-it".to_string());
+            } else {
+                // Non-interactive mode - still need -i for stdin
+                args.push("-i".to_string());
+            }
+        }
+
+        // Reap zombies left behind by tools Claude runs
+        if self.config.docker.use_init {
+            args.push("--init".to_string());
+        }
+
+        // Keep bind-mounted files owned by the host user under rootless Podman
+        args.extend(rootless_flags(self.runtime, self.rootless));
+
+        // Run as another user, keeping HOME where credentials and config are mounted
+        if let Some(user) = self.user_spec(options) {
+            args.push("--user".to_string());
+            args.push(user);
+            args.push("-e".to_string());
+            args.push(format!("HOME=/home/{}", self.config.docker.user));
+        }
+
+        // Add resource limits
+        if let Some(ref mem) = self.config.docker.memory_limit {
+            args.push("--memory".to_string());
+            args.push(mem.clone());
+        }
+        if let Some(cpu) = self.config.docker.cpu_limit {
+            args.push("--cpus".to_string());
+            args.push(cpu.to_string());
+        }
+
+        // Load .env file from project if configured and exists
+        let env_file_loaded = if self.config.docker.load_env_file {
+            let env_path = self
+                .git_context
+                .workspace_path
+                .join(expand_path(&self.config.docker.env_file_path));
+            if env_path.exists() {
+                // The file comes with the repository, so it can't ask for the user's secrets
+                self.push_env_file(&env_path, false, options, &mut args, &mut secret_env_keys)?;
+                true
+            } else {
+                false
+            }
+        } else {
+            false
+        };
+
+        // Env files from --env-file come after the project .env so they win
+        for env_path in &options.env_files {
+            self.push_env_file(env_path, true, options, &mut args, &mut secret_env_keys)?;
+        }
+
+        // Add volume mounts for git context
+        let mirror_host_path = self.config.docker.mirror_host_path;
+        for (host_path, container_path) in self.git_context.docker_mounts(mirror_host_path) {
+            args.push("-v".to_string());
+            args.push(format!("{}:{}", host_path.display(), container_path));
+        }
+
+        // Mount additional project directories next to the main workspace
+        for (host_path, container_path) in git::extra_workspace_mounts(&options.also) {
+            args.push("-v".to_string());
+            args.push(format!("{}:{}", host_path.display(), container_path));
+        }
+
+        // Forward the host SSH agent so git/MCP servers can reach private repos
+        if self.config.docker.forward_ssh_agent {
+            let auth_sock = std::env::var("SSH_AUTH_SOCK").ok();
+            match ssh_agent_socket(self.runtime, auth_sock.as_deref()) {
+                Some((host_sock, container_sock)) => {
+                    args.push("-v".to_string());
+                    args.push(format!("{}:{}", host_sock, container_sock));
+                    args.push("-e".to_string());
+                    args.push(format!("SSH_AUTH_SOCK={}", container_sock));
+                }
+                None => {
+                    eprintln!("Warning: forward_ssh_agent is enabled but SSH_AUTH_SOCK is not set");
+                }
             }
         }
+
+        // Pass Claude credentials via environment variables (not mount)
+        // This is more secure - the container gets the token but can't
+        // access or modify host credential files
+        let credential_env_vars = auth::get_credential_env_vars(&self.credentials);
+        for (key, value) in &credential_env_vars {
+            args.push("-e".to_string());
+            args.push(format!("{}={}", key, value));
+            secret_env_keys.push(key.clone());
+        }
+
+        // Attribute commits made in the container to the configured identity
+        for (key, value) in self.git_identity.env_vars() {
+            args.push("-e".to_string());
+            args.push(format!("{}={}", key, value));
+        }
+
+        // Let git push with the host's credentials, preferring a token over the store file
+        if let Some(ref credentials) = self.git_credentials {
+            match credentials {
+                GitCredentials::Token(_) => secret_env_keys.push(git::GIT_TOKEN_ENV.to_string()),
+                GitCredentials::StoreFile(path) => {
+                    args.push("-v".to_string());
+                    args.push(format!(
+                        "{}:/home/{}/.git-credentials:ro",
+                        path.display(),
+                        self.config.docker.user
+                    ));
+                }
+            }
+            for (key, value) in credentials.env_vars() {
+                args.push("-e".to_string());
+                args.push(format!("{}={}", key, value));
+            }
+        }
+
+        // Match the host clock and locale so timestamps and tools behave as on the host
+        if self.config.docker.inherit_timezone {
+            if let Some(tz) = host_timezone() {
+                args.push("-e".to_string());
+                args.push(format!("TZ={}", tz));
+            }
+            for var in LOCALE_ENV_VARS {
+                if let Some(value) = std::env::var(var).ok().filter(|v| !v.is_empty()) {
+                    args.push("-e".to_string());
+                    args.push(format!("{}={}", var, value));
+                }
+            }
+        }
+
+        // Mount MCP config if available
+        if let Some(ref mcp_path) = self.mcp_config_path {
+            args.push("-v".to_string());
+            args.push(format!(
+                "{}:/home/{}/.claude.json:ro",
+                mcp_path.display(),
+                self.config.docker.user
+            ));
+        }
+
+        // Persist Claude state across sessions; the settings mounts below
+        // are nested inside it and take precedence
+        if let Some((volume, container_path)) = self.claude_state_mount() {
+            args.push("-v".to_string());
+            args.push(format!("{}:{}", volume, container_path));
+        }
+
+        // Mount Claude settings and a missing project CLAUDE.md
+        for (host_path, container_path) in self.claude_settings_mounts() {
+            args.push("-v".to_string());
+            args.push(format!("{}:{}:ro", host_path.display(), container_path));
+        }
+
+        // Add extra volumes from config
+        for (host, container) in &self.config.docker.extra_volumes {
+            args.push("-v".to_string());
+            args.push(format!("{}:{}", expand_path(host).display(), container));
+        }
+
+        // Add ad-hoc mounts from the command line
+        for mount in &options.mounts {
+            if !expand_path(&mount.host).exists() {
+                eprintln!("Warning: mount source does not exist: {}", mount.host);
+            }
+            args.push("-v".to_string());
+            args.push(mount.volume_arg());
+        }
+
+        // Add environment variables from config, resolving secret references
+        let resolved_env = telemetry::timed("secrets", || {
+            resolve_env(
+                &self.config.docker.extra_env,
+                &self.config.secrets,
+                options.keep_secret_refs,
+            )
+        })?;
+        for (key, value, secret) in resolved_env {
+            args.push("-e".to_string());
+            args.push(format!("{}={}", key, value));
+            if secret {
+                secret_env_keys.push(key);
+            }
+        }
+
+        // Add toolchain install commands as environment variable
+        // This allows the container to install required tools on startup
+        if !self.toolchain.is_empty() {
+            let install_cmds = self.toolchain.install_commands().join(" && ");
+            args.push("-e".to_string());
+            args.push(format!("CCS_TOOLCHAIN_INSTALL={}", install_cmds));
+
+            // Also add individual tool names for reference
+            args.push("-e".to_string());
+            args.push(format!(
+                "CCS_TOOLCHAIN={}",
+                self.toolchain.tool_names().join(",")
+            ));
+        }
+
+        // Set working directory
+        args.push("-w".to_string());
+        args.push(self.container_workdir());
+
+        // Record session parameters so the session can be restarted
+        let metadata = SessionMetadata {
+            workspace: self.git_context.workspace_path.clone(),
+            detach: options.detach,
+            claude_args: extra_args.to_vec(),
+            log_file: self.log_file_path(options),
+            network: services.as_ref().map(|group| group.network.clone()),
+            options: SessionOptions::from_run_options(options),
+        };
+        for label in metadata.to_labels() {
+            args.push("--label".to_string());
+            args.push(label);
+        }
+        args.push("--label".to_string());
+        args.push(format!("{}={}", LABEL_REPO, self.git_context.repo_name));
+        if let Some(branch) = self.git_context.current_branch() {
+            args.push("--label".to_string());
+            args.push(format!("{}={}", LABEL_BRANCH, branch));
+        }
+
+        // Replace Claude with a shell for --shell
+        let shell_index = if options.shell {
+            args.push("--entrypoint".to_string());
+            args.push(self.config.docker.shell.clone());
+            Some(args.len() - 1)
+        } else {
+            None
+        };
+
+        // Stop the runtime from pulling implicitly when pulls are disabled
+        if self.config.docker.pull_policy == PullPolicy::Never
+            && self.config.docker.image.contains('/')
+        {
+            args.push("--pull=never".to_string());
+        }
+
+        // Unvalidated escape hatch: config flags first, then --docker-arg
+        args.extend(self.config.docker.extra_run_args.iter().cloned());
+        args.extend(options.docker_args.iter().cloned());
+
+        // Use the configured image
+        args.push(self.config.docker.image.clone());
+
+        // Add any extra arguments for Claude
+        if !options.shell {
+            args.extend(self.claude_args(extra_args));
+        }
+
+        Ok(RunArgs {
+            args,
+            secret_env_keys,
+            env_file_loaded,
+            services,
+            shell_index,
+        })
+    }
+
+    /// Add `--env-file` for `env_path`, plus `-e` for its resolved secret references
+    ///
+    /// Without `resolve_secrets` (the workspace `.env`) references are passed
+    /// through as written, with a warning.
+    fn push_env_file(
+        &self,
+        env_path: &Path,
+        resolve_secrets: bool,
+        options: &RunOptions,
+        args: &mut Vec<String>,
+        secret_env_keys: &mut Vec<String>,
+    ) -> Result<(), DockerError> {
+        args.push("--env-file".to_string());
+        args.push(env_path.display().to_string());
+
+        let contents = std::fs::read_to_string(env_path)?;
+        let file_env: HashMap<String, String> = parse_env_file(&contents).into_iter().collect();
+        if !resolve_secrets {
+            let mut references: Vec<&str> = file_env
+                .iter()
+                .filter(|(_, value)| secrets::mentions_secret_reference(value))
+                .map(|(key, _)| key.as_str())
+                .collect();
+            if !references.is_empty() && !options.keep_secret_refs {
+                references.sort();
+                eprintln!(
+                    "Warning: not resolving secret references in {} ({}); put them in docker.extra_env or pass the file with --env-file",
+                    env_path.display(),
+                    references.join(", ")
+                );
+            }
+            return Ok(());
+        }
+
+        // Secret references in the env file are resolved and passed
+        // with -e, which takes precedence over --env-file
+        let resolved = resolve_env(&file_env, &self.config.secrets, options.keep_secret_refs)?;
+        for (key, value, secret) in resolved {
+            if secret {
+                args.push("-e".to_string());
+                args.push(format!("{}={}", key, value));
+                secret_env_keys.push(key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Working directory in the container, following the workspace to its
+    /// host path with `docker.mirror_host_path`
+    fn container_workdir(&self) -> String {
+        let workdir = &self.config.docker.workdir;
+        if !self.config.docker.mirror_host_path {
+            return workdir.clone();
+        }
+
+        match Path::new(workdir).strip_prefix(CONTAINER_WORKSPACE) {
+            Ok(relative) => self
+                .git_context
+                .workspace_path
+                .join(relative)
+                .display()
+                .to_string(),
+            Err(_) => workdir.clone(),
+        }
+    }
+
+    /// Arguments for Claude: `--permission-mode` from `claude.permission_mode`,
+    /// `docker.default_claude_args`, then `extra_args`
+    ///
+    /// Command-line arguments come last so they can override the defaults.
+    fn claude_args(&self, extra_args: &[String]) -> Vec<String> {
+        let permission_mode = self
+            .config
+            .claude
+            .permission_mode
+            .map(|mode| ["--permission-mode".to_string(), mode.as_str().to_string()]);
+
+        permission_mode
+            .into_iter()
+            .flatten()
+            .chain(self.config.docker.default_claude_args.iter().cloned())
+            .chain(extra_args.iter().cloned())
+            .collect()
+    }
+
+    /// Host file to copy this run's output to, for detached runs only
+    fn log_file_path(&self, options: &RunOptions) -> Option<PathBuf> {
+        if !options.detach {
+            return None;
+        }
+
+        options.log_file.clone().or_else(|| {
+            self.config
+                .docker
+                .log_to_file
+                .then(|| session_log_path(&self.container_name))
+                .flatten()
+        })
+    }
+
+    /// Run the container with Claude Code
+    pub fn run(&self, extra_args: &[String], options: &RunOptions) -> anyhow::Result<()> {
+        let run_args = self.build_run_args(extra_args, options)?;
+        self.run_with_args(extra_args, options, run_args)
+    }
+
+    /// Run the container with arguments already built by `build_run_args`
+    fn run_with_args(
+        &self,
+        extra_args: &[String],
+        options: &RunOptions,
+        run_args: RunArgs,
+    ) -> anyhow::Result<()> {
+        // Handle dry-run mode: print command and exit
+        if options.dry_run {
+            let claude_args = self.claude_args(extra_args);
+            if !claude_args.is_empty() && !options.shell {
+                let quoted: Vec<String> = claude_args.iter().map(|a| shell_quote(a)).collect();
+                println!("# Claude args: {}", quoted.join(" "));
+            }
+
+            if let Some(ref group) = run_args.services {
+                group.print_dry_run(self.runtime);
+            }
+
+            // Build the command string with proper quoting, redacting credentials
+            let cmd_parts: Vec<String> = self
+                .runtime
+                .command_prefix()
+                .into_iter()
+                .chain(
+                    run_args
+                        .args
+                        .iter()
+                        .map(|arg| shell_quote(&redact_arg(arg, &run_args.secret_env_keys))),
+                )
+                .collect();
+            println!("{}", cmd_parts.join(" \\\n  "));
+            return Ok(());
+        }
+
+        self.preflight()?;
+        self.start(options, run_args)
+    }
+
+    /// Checks that don't depend on the container name being free: credentials,
+    /// a local daemon and the image (pulled or built as configured)
+    fn preflight(&self) -> anyhow::Result<()> {
+        check_credentials(
+            &self.credentials.source,
+            self.config.auth.require_credentials,
+        )?;
+
+        // Workspace bind mounts only work against a daemon on this machine
+        if let DaemonHost::Remote(host) = DaemonHost::detect(self.runtime) {
+            return Err(DockerError::RemoteDaemon(self.runtime.name(), host).into());
+        }
+
+        let image = &self.config.docker.image;
+        if should_pull(image, self.config.docker.pull_policy, || {
+            check_image_exists(self.runtime, image)
+        }) {
+            pull_image(self.runtime, image)?;
+        }
+        match missing_image_action(image, self.config.docker.auto_build_if_missing, || {
+            check_image_exists(self.runtime, image)
+        }) {
+            ImageAction::Ready => {}
+            ImageAction::Build => {
+                println!("Image not found, building {}...", image);
+                Self::build_image(&self.config, &BuildOptions::default())?;
+            }
+            ImageAction::Missing => {
+                return Err(DockerError::ImageNotFound(image.clone()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Start the container (and its services) once `preflight` has passed
+    fn start(&self, options: &RunOptions, mut run_args: RunArgs) -> anyhow::Result<()> {
+        // Fail clearly rather than letting the runtime reject a duplicate name
+        if container_exists(self.runtime, &self.container_name) {
+            return Err(DockerError::ContainerExists(self.container_name.clone()).into());
+        }
+
+        // The image is known to exist now, so check it has the shell
+        if let Some(index) = run_args.shell_index {
+            let image = &self.config.docker.image;
+            run_args.args[index] = shell_with_fallback(&self.config.docker.shell, |shell| {
+                image_has_shell(self.runtime, image, shell)
+            });
+        }
+
+        // Start sidecar services first so they resolve by name once Claude starts
+        if let Some(ref group) = run_args.services {
+            group.start(self.runtime)?;
+        }
+
+        // Build the actual Command
+        let mut cmd = self.runtime.new_command();
+        for arg in &run_args.args {
+            cmd.arg(arg);
+        }
+
+        if options.detach {
+            println!("Starting Claude Code sandbox (detached)...");
+        } else {
+            // Set up proper TTY handling for interactive mode
+            cmd.stdin(Stdio::inherit())
+                .stdout(Stdio::inherit())
+                .stderr(Stdio::inherit());
+            println!("Starting Claude Code sandbox...");
+        }
+        if self.rootless {
+            println!("Runtime: {} (rootless)", self.runtime.name());
+        } else {
+            println!("Runtime: {}", self.runtime.name());
+        }
+        println!("Container: {}", self.container_name);
+        println!("Workspace: {}", self.git_context.workspace_path.display());
+        println!("Workdir: {}", self.container_workdir());
+        if let Some(user) = self.user_spec(options) {
+            println!("User: {}", user);
+        }
+        let style = output::style();
+        if self.git_context.is_worktree {
+            println!("{}", style.note("Running in git worktree"));
+        }
+        // Show credential source
+        match self.credentials.source {
+            CredentialSource::None => {
+                eprintln!("Warning: No Claude credentials found");
+                eprintln!("  Run 'claude login' on host, or set ANTHROPIC_API_KEY");
+            }
+            ref source => {
+                println!("Auth: {}", source);
+            }
+        }
+        if run_args.env_file_loaded {
+            println!("Loaded .env: {}", self.config.docker.env_file_path);
+        }
+        for env_path in &options.env_files {
+            println!("Env file: {}", env_path.display());
+        }
+        for (host_path, _) in self.claude_settings_mounts() {
+            println!("Claude settings: {}", host_path.display());
+        }
+        if let Some((volume, _)) = self.claude_state_mount() {
+            println!("Claude state: {}", volume);
+        }
+        if let Some(ref mem) = self.config.docker.memory_limit {
+            println!("Memory limit: {}", mem);
+        }
+        if let Some(cpu) = self.config.docker.cpu_limit {
+            println!("CPU limit: {}", cpu);
+        }
+        if !options.detach {
+            println!(
+                "{}",
+                style.note("Ctrl+C or SIGTERM stops and removes the session")
+            );
+        }
+        print!("{}", style.gap());
+
+        if options.detach {
+            let output = telemetry::timed("run", || cmd.output())?;
+            if output.status.success() {
+                let container_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if let Some(secs) = self.config.docker.wait_for_ready_secs {
+                    println!("Waiting up to {}s for the session to be ready...", secs);
+                    wait_until_ready(
+                        || inspect_state(self.runtime, &self.container_name, READINESS_FORMAT),
+                        Duration::from_secs(secs),
+                        READINESS_POLL_INTERVAL,
+                    )
+                    .map_err(|reason| DockerError::NotReady(self.container_name.clone(), reason))?;
+                }
+                println!("Container started: {}", self.container_name);
+                println!(
+                    "Container ID: {}",
+                    &container_id[..12.min(container_id.len())]
+                );
+                if let Some(log_file) = self.log_file_path(options) {
+                    match spawn_log_follower(self.runtime, &self.container_name, &log_file) {
+                        Ok(()) => println!("Log file: {}", log_file.display()),
+                        Err(e) => eprintln!(
+                            "Warning: failed to copy logs to {}: {}",
+                            log_file.display(),
+                            e
+                        ),
+                    }
+                }
+                println!();
+                println!("Commands:");
+                println!("  ccs --list              # List running sessions");
+                println!(
+                    "  ccs --attach {}   # Attach to session",
+                    self.container_name
+                );
+                println!("  ccs --logs {}     # View logs", self.container_name);
+                println!("  ccs --stop {}     # Stop session", self.container_name);
+            } else {
+                if let Some(ref group) = run_args.services {
+                    remove_service_group(self.runtime, &group.session, &group.network);
+                }
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(DockerError::CommandFailed(stderr.to_string()).into());
+            }
+        } else {
+            let network = run_args.services.as_ref().map(|g| g.network.clone());
+            install_stop_handler(self.runtime, &self.container_name, network.clone());
+            let status = telemetry::timed("run", || cmd.status());
+            if let Some(ref network) = network {
+                remove_service_group(self.runtime, &self.container_name, network);
+            }
+            let status = status?;
+            if !status.success() {
+                if let Some(code) = status.code() {
+                    telemetry::flush();
+                    std::process::exit(code);
+                }
+                return Err(
+                    DockerError::CommandFailed("Container exited with error".to_string()).into(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Exit code used when ccs is interrupted by a signal (128 + SIGINT)
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Stop and remove the session container (and its services on `network`) when
+/// ccs receives SIGINT/SIGTERM, so an interrupted run never leaves a
+/// half-attached container behind
+fn install_stop_handler(runtime: ContainerRuntime, container_name: &str, network: Option<String>) {
+    let container_name = container_name.to_string();
+    let result = ctrlc::set_handler(move || {
+        eprintln!("\nStopping {}...", container_name);
+        remove_container(runtime, &container_name);
+        if let Some(ref network) = network {
+            remove_service_group(runtime, &container_name, network);
+        }
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    });
+
+    if let Err(e) = result {
+        eprintln!("Warning: failed to install signal handler: {}", e);
+    }
+}
+
+/// Stop and remove a container, ignoring failures (it may already be gone)
+fn remove_container(runtime: ContainerRuntime, container_name: &str) {
+    for args in [["stop", container_name], ["rm", container_name]] {
+        let _ = runtime
+            .new_command()
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+/// Quote a string for shell usage if it contains special characters
+fn shell_quote(s: &str) -> String {
+    // Check if string needs quoting
+    let needs_quoting = s.is_empty()
+        || s.contains(|c: char| {
+            c.is_whitespace()
+                || c == '"'
+                || c == '\''
+                || c == '\\'
+                || c == '$'
+                || c == '`'
+                || c == '!'
+                || c == '*'
+                || c == '?'
+                || c == '['
+                || c == ']'
+                || c == '{'
+                || c == '}'
+                || c == '('
+                || c == ')'
+                || c == '<'
+                || c == '>'
+                || c == '|'
+                || c == '&'
+                || c == ';'
+                || c == '#'
+        });
+
+    if !needs_quoting {
+        return s.to_string();
+    }
+
+    // Use single quotes, escaping any single quotes in the string
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Redact credential values in environment variable arguments for dry-run output
+fn redact_credentials(s: &str) -> String {
+    // Check for credential environment variable patterns
+    const SENSITIVE_PREFIXES: &[&str] = &[
+        "ANTHROPIC_API_KEY=",
+        "CLAUDE_CODE_OAUTH_TOKEN=",
+        "CCS_TOOLCHAIN_INSTALL=", // Long install commands, truncate for readability
+    ];
+
+    for prefix in SENSITIVE_PREFIXES {
+        if s.starts_with(prefix) {
+            return format!("{}[...]", prefix);
+        }
+    }
+
+    s.to_string()
+}
+
+/// Container path the forwarded SSH agent socket is mounted at
+const CONTAINER_SSH_AUTH_SOCK: &str = "/run/ssh-agent.sock";
+
+/// Docker Desktop for macOS exposes the host agent at this fixed path in its VM
+const DOCKER_DESKTOP_SSH_AUTH_SOCK: &str = "/run/host-services/ssh-auth.sock";
+
+/// Determine the (host, container) socket paths for SSH agent forwarding
+fn ssh_agent_socket(
+    runtime: ContainerRuntime,
+    auth_sock: Option<&str>,
+) -> Option<(String, String)> {
+    // The host socket can't be bind-mounted through the Docker Desktop VM
+    if cfg!(target_os = "macos") && runtime == ContainerRuntime::Docker {
+        return Some((
+            DOCKER_DESKTOP_SSH_AUTH_SOCK.to_string(),
+            DOCKER_DESKTOP_SSH_AUTH_SOCK.to_string(),
+        ));
+    }
+
+    auth_sock
+        .filter(|s| !s.is_empty())
+        .map(|s| (s.to_string(), CONTAINER_SSH_AUTH_SOCK.to_string()))
+}
+
+/// Locale variables copied from the host with `docker.inherit_timezone`
+const LOCALE_ENV_VARS: &[&str] = &["LANG", "LC_ALL"];
+
+/// Determine the host's IANA timezone name (e.g. "Europe/Berlin")
+fn host_timezone() -> Option<String> {
+    timezone_from_sources(
+        std::env::var("TZ").ok().as_deref(),
+        std::fs::read_to_string("/etc/timezone").ok().as_deref(),
+        std::fs::read_link("/etc/localtime").ok().as_deref(),
+    )
+}
+
+/// Pick a timezone from `$TZ`, then `/etc/timezone`, then the `/etc/localtime` symlink target
+fn timezone_from_sources(
+    tz_env: Option<&str>,
+    etc_timezone: Option<&str>,
+    localtime_target: Option<&Path>,
+) -> Option<String> {
+    let non_empty = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+
+    // A leading ':' in TZ means "implementation-defined", usually a zoneinfo path
+    tz_env
+        .and_then(|tz| non_empty(tz.trim_start_matches(':')))
+        .or_else(|| {
+            etc_timezone
+                .and_then(|s| s.lines().next())
+                .and_then(non_empty)
+        })
+        .or_else(|| {
+            let target = localtime_target?.to_string_lossy().into_owned();
+            let (_, zone) = target.split_once("zoneinfo/")?;
+            non_empty(zone)
+        })
+}
+
+/// Whether a session may start with credentials from `source`
+///
+/// Missing credentials only warn (later, with the run summary) unless
+/// `auth.require_credentials` is set.
+fn check_credentials(source: &CredentialSource, require: bool) -> Result<(), DockerError> {
+    if require && *source == CredentialSource::None {
+        return Err(DockerError::NoCredentials);
+    }
+    Ok(())
+}
+
+/// Resolve the secret references in `env`, sorted by key, noting which values were references
+///
+/// With `keep_refs` the references are returned as they are.
+fn resolve_env(
+    env: &HashMap<String, String>,
+    secrets_config: &SecretsConfig,
+    keep_refs: bool,
+) -> Result<Vec<(String, String, bool)>, SecretsError> {
+    let mut resolved: Vec<(String, String, bool)> = if keep_refs {
+        env.iter()
+            .map(|(key, value)| {
+                (
+                    key.clone(),
+                    value.clone(),
+                    secrets::is_secret_reference(value),
+                )
+            })
+            .collect()
+    } else {
+        secrets::resolve_secrets(env, secrets_config)?
+            .into_iter()
+            .map(|(key, value)| {
+                let secret = env.get(&key) != Some(&value);
+                (key, value, secret)
+            })
+            .collect()
+    };
+    resolved.sort();
+    Ok(resolved)
+}
+
+/// Redact an argument for dry-run output, including env vars resolved from secrets
+fn redact_arg(s: &str, secret_env_keys: &[String]) -> String {
+    if let Some((key, _)) = s.split_once('=') {
+        if secret_env_keys.iter().any(|k| k == key) {
+            return format!("{}=[...]", key);
+        }
+    }
+
+    redact_credentials(s)
+}
+
+/// Parse KEY=VALUE lines from a .env file, skipping blank lines and comments
+fn parse_env_file(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .collect()
+}
+
+/// Directory holding copies of detached session output
+pub fn session_logs_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("ccs").join("logs"))
+}
+
+/// Default log file for a session
+fn session_log_path(container_name: &str) -> Option<PathBuf> {
+    session_logs_dir().map(|d| d.join(format!("{}.log", container_name)))
+}
+
+/// Follow a container's output into a host file in the background
+///
+/// The follower outlives ccs and exits when the container stops.
+fn spawn_log_follower(
+    runtime: ContainerRuntime,
+    container_name: &str,
+    log_file: &Path,
+) -> std::io::Result<()> {
+    if let Some(parent) = log_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)?;
+
+    let mut cmd = runtime.new_command();
+    cmd.args(["logs", "-f", container_name])
+        .stdin(Stdio::null())
+        .stdout(file.try_clone()?)
+        .stderr(file);
+
+    // Keep terminal signals (Ctrl+C) aimed at ccs from reaching the follower
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    cmd.spawn()?;
+    Ok(())
+}
+
+/// Find the saved log of a removed session by (partial) container name
+fn find_session_log(logs_dir: &Path, partial: &str) -> Option<PathBuf> {
+    let names: Vec<String> = std::fs::read_dir(logs_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            e.file_name()
+                .to_str()
+                .and_then(|n| n.strip_suffix(".log"))
+                .map(str::to_string)
+        })
+        .collect();
+
+    let name = if names.iter().any(|n| n == partial) {
+        partial.to_string()
+    } else {
+        let mut matches = names.into_iter().filter(|n| n.contains(partial));
+        let name = matches.next()?;
+        if matches.next().is_some() {
+            return None;
+        }
+        name
+    };
+
+    Some(logs_dir.join(format!("{}.log", name)))
+}
+
+/// Parse `name<TAB>log file` lines, skipping sessions without a log file
+fn parse_session_log_files(stdout: &str) -> Vec<(String, PathBuf)> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let (name, path) = line.split_once('\t')?;
+            let path = path.trim();
+            (!path.is_empty() && path != "<no value>")
+                .then(|| (name.trim().to_string(), PathBuf::from(path)))
+        })
+        .collect()
+}
+
+/// A ccs session container as listed by `ps -a`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub name: String,
+    /// Runtime state, e.g. "running" or "exited"
+    pub state: String,
+    /// Human-readable status, e.g. "Up 2 hours"
+    pub status: String,
+    pub created_at: String,
+    /// Repository from the `ccs.repo` label (unset for sessions from older ccs)
+    pub repo: Option<String>,
+}
+
+impl SessionInfo {
+    pub fn is_running(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+/// Which sessions `--list` shows by state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionStateFilter {
+    Running,
+    Stopped,
+}
+
+/// Constraints on the sessions shown by `--list`
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    /// Only sessions for this repository
+    pub repo: Option<String>,
+    /// Only running or only stopped sessions
+    pub state: Option<SessionStateFilter>,
+}
+
+impl SessionFilter {
+    /// Whether `session` passes the filter
+    ///
+    /// The repo is matched against the `ccs.repo` label, or the container
+    /// name (`<prefix>-<repo>-<id>`) for sessions without it.
+    pub fn matches(&self, session: &SessionInfo, prefix: &str) -> bool {
+        match self.state {
+            Some(SessionStateFilter::Running) if !session.is_running() => return false,
+            Some(SessionStateFilter::Stopped) if session.is_running() => return false,
+            _ => {}
+        }
+
+        let Some(ref repo) = self.repo else {
+            return true;
+        };
+        let repo = sanitize_container_name_component(repo);
+        match session.repo {
+            Some(ref label) => sanitize_container_name_component(label) == repo,
+            None => session.name.starts_with(&format!("{}-{}-", prefix, repo)),
+        }
+    }
+}
+
+/// `ps --format` template parsed by `parse_session_infos`
+fn session_info_format() -> String {
+    format!(
+        "{{{{.Names}}}}\t{{{{.State}}}}\t{{{{.Status}}}}\t{{{{.CreatedAt}}}}\t{{{{.Label \"{}\"}}}}",
+        LABEL_REPO
+    )
+}
+
+/// Parse `name<TAB>state<TAB>status<TAB>created<TAB>repo` lines
+fn parse_session_infos(stdout: &str) -> Vec<SessionInfo> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t').map(str::trim);
+            let name = fields.next().filter(|n| !n.is_empty())?.to_string();
+            let state = fields.next()?.to_lowercase();
+            let status = fields.next()?.to_string();
+            let created_at = fields.next()?.to_string();
+            let repo = fields
+                .next()
+                .filter(|r| !r.is_empty() && *r != "<no value>")
+                .map(str::to_string);
+            Some(SessionInfo {
+                name,
+                state,
+                status,
+                created_at,
+                repo,
+            })
+        })
+        .collect()
+}
+
+/// Format sessions as a NAMES/STATUS/CREATED AT table
+fn format_session_table(sessions: &[SessionInfo], style: Style) -> String {
+    let rows: Vec<Vec<&str>> = std::iter::once(vec!["NAMES", "STATUS", "CREATED AT"])
+        .chain(
+            sessions
+                .iter()
+                .map(|s| vec![s.name.as_str(), s.status.as_str(), s.created_at.as_str()]),
+        )
+        .collect();
+    style.table(&rows)
+}
+
+/// List ccs sessions matching `filter`
+pub fn list_sessions(prefix: &str, filter: &SessionFilter) -> anyhow::Result<()> {
+    let runtime = ContainerRuntime::detect()?;
+
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "-a",
+            "--filter",
+            &container_name_filter(prefix),
+            "--filter",
+            SESSION_LABEL_FILTER,
+            "--format",
+            &session_info_format(),
+        ])
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DockerError::CommandFailed(stderr.to_string()).into());
+    }
+
+    let sessions: Vec<SessionInfo> = parse_session_infos(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .filter(|session| filter.matches(session, prefix))
+        .collect();
+
+    if sessions.is_empty() {
+        println!("No ccs sessions found.");
+    } else {
+        let style = output::style();
+        println!("{}{}", format_session_table(&sessions, style), style.gap());
+        let names: Vec<&str> = sessions.iter().map(|s| s.name.as_str()).collect();
+        print_session_log_files(runtime, prefix, &names);
+    }
+
+    Ok(())
+}
+
+/// Print the host log files of the `listed` sessions started with log copying
+fn print_session_log_files(runtime: ContainerRuntime, prefix: &str, listed: &[&str]) {
+    let format = format!("{{{{.Names}}}}\t{{{{.Label \"{}\"}}}}", LABEL_LOG_FILE);
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "-a",
+            "--filter",
+            &container_name_filter(prefix),
+            "--filter",
+            SESSION_LABEL_FILTER,
+            "--format",
+            &format,
+        ])
+        .stderr(Stdio::null())
+        .output();
+
+    let log_files: Vec<(String, PathBuf)> = match output {
+        Ok(o) if o.status.success() => parse_session_log_files(&String::from_utf8_lossy(&o.stdout))
+            .into_iter()
+            .filter(|(name, _)| listed.contains(&name.as_str()))
+            .collect(),
+        _ => return,
+    };
+
+    if !log_files.is_empty() {
+        println!("Log files:");
+        for (name, path) in log_files {
+            println!("  {}: {}", name, path.display());
+        }
+    }
+}
+
+/// Attach to a running ccs session
+pub fn attach_session(
+    prefix: &str,
+    target: &SessionTarget,
+    options: &AttachOptions,
+) -> anyhow::Result<()> {
+    let runtime = ContainerRuntime::detect()?;
+
+    let container_name = resolve_session(runtime, prefix, target)?;
+    let passthrough = Passthrough::detect();
+
+    // Replay recent output for context; attach only shows new output
+    if let Some(lines) = options.replay_lines {
+        let status = run_relayed(
+            runtime
+                .new_command()
+                .args(replay_args(&container_name, lines))
+                .stderr(Stdio::inherit()),
+            passthrough,
+        )?;
+        if !status.success() {
+            eprintln!("Warning: could not replay logs for {}", container_name);
+        }
+    }
+
+    // Keep piped output to the session's own
+    eprintln!("Attaching to {}...", container_name);
+    if passthrough.stdin {
+        eprintln!("(Use Ctrl+P, Ctrl+Q to detach without stopping)\n");
+    }
+
+    let stdin = if passthrough.stdin {
+        Stdio::inherit()
+    } else {
+        Stdio::null()
+    };
+    let status = run_relayed(
+        runtime
+            .new_command()
+            .args(attach_args(&container_name, passthrough))
+            .stdin(stdin)
+            .stderr(Stdio::inherit()),
+        passthrough,
+    )?;
+
+    if !status.success() {
+        if let Some(code) = status.code() {
+            std::process::exit(code);
+        }
+    }
+
+    Ok(())
+}
+
+/// Show logs from a ccs session
+pub fn show_logs(prefix: &str, target: &SessionTarget, options: &LogOptions) -> anyhow::Result<()> {
+    let runtime = ContainerRuntime::detect()?;
+
+    // Resolve the session, falling back to the saved log of a removed session
+    let container_name = match (resolve_session(runtime, prefix, target), target) {
+        (Ok(name), _) => name,
+        (Err(e), SessionTarget::Last) => return Err(e),
+        (Err(e), SessionTarget::Named(container)) => {
+            let log_file = session_logs_dir().and_then(|dir| find_session_log(&dir, container));
+            return match log_file {
+                Some(path) => {
+                    eprintln!("Session is gone; showing saved log {}", path.display());
+                    std::io::copy(
+                        &mut std::fs::File::open(&path)?,
+                        &mut std::io::stdout().lock(),
+                    )?;
+                    Ok(())
+                }
+                None => Err(e),
+            };
+        }
+    };
+
+    let mut command = runtime.new_command();
+    command
+        .args(logs_args(&container_name, options))
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    let status = if options.follow {
+        follow_relayed(&mut command, Passthrough::detect(), || {
+            inspect_state(runtime, &container_name, "{{.State.Running}}")
+        })?
+    } else {
+        run_relayed(&mut command, Passthrough::detect())?
+    };
+
+    if !status.success() {
+        if let Some(code) = status.code() {
+            std::process::exit(code);
+        }
+    }
+
+    Ok(())
+}
+
+/// Show the logs of every running ccs session as one stream
+///
+/// Each line is prefixed with `[container-name]`. When following, each
+/// follower ends once its container stops, like with [`follow_relayed`], and
+/// Ctrl+C or SIGTERM stops all of them.
+pub fn follow_all_logs(prefix: &str, options: &LogOptions) -> anyhow::Result<()> {
+    let runtime = ContainerRuntime::detect()?;
+
+    let names = list_ccs_containers(runtime, prefix);
+    if names.is_empty() {
+        println!("No running ccs sessions.");
+        return Ok(());
+    }
+
+    let children = Arc::new(Mutex::new(Vec::new()));
+    let mut sources: Vec<(String, Box<dyn std::io::Read + Send>)> = Vec::new();
+    let mut watchers = Vec::new();
+    for (index, name) in names.into_iter().enumerate() {
+        let mut child = runtime
+            .new_command()
+            .args(logs_args(&name, options))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let relayed = Arc::new(AtomicUsize::new(0));
+        if let Some(stdout) = child.stdout.take() {
+            let stdout = CountingReader {
+                inner: stdout,
+                count: Arc::clone(&relayed),
+            };
+            sources.push((name.clone(), Box::new(stdout)));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let stderr = CountingReader {
+                inner: stderr,
+                count: Arc::clone(&relayed),
+            };
+            sources.push((name.clone(), Box::new(stderr)));
+        }
+        children.lock().unwrap().push(child);
+
+        if options.follow {
+            let children = Arc::clone(&children);
+            watchers.push(std::thread::spawn(move || {
+                let stopped = watch_until_stopped(
+                    || inspect_state(runtime, &name, "{{.State.Running}}"),
+                    || {
+                        let mut children = children.lock().unwrap();
+                        !matches!(children[index].try_wait(), Ok(None))
+                    },
+                    || relayed.load(Ordering::Relaxed),
+                    LOGS_POLL_INTERVAL,
+                    LOGS_DRAIN_GRACE,
+                );
+                if stopped {
+                    let _ = children.lock().unwrap()[index].kill();
+                }
+            }));
+        }
+    }
+
+    // Killing the followers ends their streams, so the multiplexer returns
+    let handler_children = Arc::clone(&children);
+    if let Err(e) = ctrlc::set_handler(move || {
+        for child in handler_children.lock().unwrap().iter_mut() {
+            let _ = child.kill();
+        }
+    }) {
+        eprintln!("Warning: failed to install signal handler: {}", e);
+    }
+
+    multiplex_lines(sources, &mut std::io::stdout().lock())?;
+
+    for child in children.lock().unwrap().iter_mut() {
+        let _ = child.wait();
+    }
+    for watcher in watchers {
+        let _ = watcher.join();
+    }
+    Ok(())
+}
+
+/// Copy lines from named streams to `out` as they arrive, prefixed with `[name]`
+///
+/// Each stream is read on its own thread; lines from one stream keep their
+/// order, while lines from different streams interleave.
+fn multiplex_lines(
+    sources: Vec<(String, Box<dyn std::io::Read + Send>)>,
+    out: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    use std::io::BufRead;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let readers: Vec<_> = sources
+        .into_iter()
+        .map(|(name, stream)| {
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                for line in std::io::BufReader::new(stream)
+                    .lines()
+                    .map_while(Result::ok)
+                {
+                    if tx.send(format!("[{}] {}", name, line)).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    for line in rx {
+        writeln!(out, "{}", line)?;
+        out.flush()?;
+    }
+    for reader in readers {
+        let _ = reader.join();
+    }
+    Ok(())
+}
+
+/// Stop a running ccs session
+pub fn stop_session(prefix: &str, target: &SessionTarget) -> anyhow::Result<()> {
+    let runtime = ContainerRuntime::detect()?;
+
+    let container_name = resolve_session(runtime, prefix, target)?;
+
+    println!("Stopping {}...", container_name);
+    stop_and_remove_session(runtime, &container_name)?;
+    println!("Stopped.");
+
+    Ok(())
+}
+
+/// Stop a session, then remove its container and any `[services]` started with it
+fn stop_and_remove_session(
+    runtime: ContainerRuntime,
+    container_name: &str,
+) -> Result<(), DockerError> {
+    let network = session_network(runtime, container_name);
+
+    let output = runtime
+        .new_command()
+        .args(["stop", container_name])
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DockerError::CommandFailed(format!(
+            "Failed to stop container: {}",
+            stderr.trim()
+        )));
+    }
+
+    // Also remove the container
+    let _ = runtime
+        .new_command()
+        .args(["rm", container_name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    // And the services that were started with it
+    if let Some(network) = network {
+        remove_service_group(runtime, container_name, &network);
+    }
+    mcp::remove_session_config(container_name);
+    Ok(())
+}
+
+/// Result of `--stop --all`
+#[derive(Debug, Default)]
+pub struct StopAllResult {
+    /// Sessions that were stopped and removed
+    pub stopped: Vec<String>,
+    /// Sessions that could not be stopped, with the reason
+    pub errors: Vec<String>,
+}
+
+impl StopAllResult {
+    /// Print a summary of the batch
+    pub fn print_summary(&self) {
+        println!(
+            "Stopped {} session(s), {} failed",
+            self.stopped.len(),
+            self.errors.len()
+        );
+        if !self.errors.is_empty() {
+            eprintln!("Failures:");
+            for err in &self.errors {
+                eprintln!("  - {}", err);
+            }
+        }
+    }
+
+    /// Check if any session failed to stop
+    pub fn had_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+/// Stop and remove every running ccs session, continuing past failures
+pub fn stop_all_sessions(prefix: &str) -> anyhow::Result<StopAllResult> {
+    let runtime = ContainerRuntime::detect()?;
+
+    let names = list_ccs_containers(runtime, prefix);
+    if names.is_empty() {
+        println!("No running ccs sessions.");
+        return Ok(StopAllResult::default());
+    }
+
+    let result = stop_each(&names, |name| {
+        stop_and_remove_session(runtime, name).map_err(|e| e.to_string())
+    });
+    result.print_summary();
+    Ok(result)
+}
+
+/// Run `stop` for each session, printing and collecting each outcome
+fn stop_each<F>(names: &[String], mut stop: F) -> StopAllResult
+where
+    F: FnMut(&str) -> Result<(), String>,
+{
+    let mut result = StopAllResult::default();
+    for name in names {
+        match stop(name) {
+            Ok(()) => {
+                println!("Stopped {}", name);
+                result.stopped.push(name.clone());
+            }
+            Err(e) => {
+                eprintln!("Failed to stop {}: {}", name, e);
+                result.errors.push(format!("{}: {}", name, e));
+            }
+        }
+    }
+    result
+}
+
+/// Host workspace of a session, from its labels
+pub fn session_workspace(prefix: &str, container: &str) -> anyhow::Result<PathBuf> {
+    let runtime = ContainerRuntime::detect()?;
+    let container_name = resolve_container_name(runtime, prefix, container)?;
+    Ok(SessionMetadata::read(runtime, &container_name)?.workspace)
+}
+
+/// Stop a ccs session and recreate it with the parameters it was started with
+pub fn restart_session(
+    prefix: &str,
+    container: &str,
+    config_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    let runtime = ContainerRuntime::detect()?;
+
+    // Resolve partial container name
+    let container_name = resolve_container_name(runtime, prefix, container)?;
+    let metadata = SessionMetadata::read(runtime, &container_name)?;
+
+    println!("Restarting {}...", container_name);
+
+    // Set everything up and check it first, so a failure leaves the old session in place
+    let runner = session_runner(&container_name, &metadata, config_path, true)?;
+    let options = metadata.run_options();
+    let run_args = runner.build_run_args(&metadata.claude_args, &options)?;
+    runner.preflight()?;
+
+    // Stop and remove the old container and its services so their names can be reused
+    remove_container(runtime, &container_name);
+    if let Some(ref network) = metadata.network {
+        remove_service_group(runtime, &container_name, network);
+    }
+
+    runner.start(&options, run_args)
+}
+
+/// Print a shell script that recreates a session's `run` command
+///
+/// Secret references are not resolved; the script reads those variables
+/// from the environment of whoever runs it instead. The MCP config, which
+/// would hold resolved secrets, is left out, and nothing on the host changes.
+pub fn export_session_command(
+    prefix: &str,
+    container: &str,
+    config_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    let runtime = ContainerRuntime::detect()?;
+    let container_name = resolve_container_name(runtime, prefix, container)?;
+    let metadata = SessionMetadata::read(runtime, &container_name)?;
+
+    let runner = session_runner(&container_name, &metadata, config_path, false)?;
+    let options = RunOptions {
+        keep_secret_refs: true,
+        ..metadata.run_options()
+    };
+    let run_args = runner.build_run_args(&metadata.claude_args, &options)?;
+    let mcp_omitted = mcp::has_servers(&runner.config, &runner.git_context.workspace_path)?;
+    print!(
+        "{}",
+        export_script(runtime, &container_name, &run_args, mcp_omitted)
+    );
+    Ok(())
+}
+
+/// Runner recreating the session `container_name` from its metadata and the current config
+///
+/// With `with_mcp` the MCP config is generated (resolving its secrets) and mounted.
+fn session_runner(
+    container_name: &str,
+    metadata: &SessionMetadata,
+    config_path: Option<&Path>,
+    with_mcp: bool,
+) -> anyhow::Result<DockerRunner> {
+    let mut config = match config_path {
+        Some(path) => Config::load_from(path, &metadata.workspace)?,
+        None => Config::load_with_project(&metadata.workspace)?,
+    };
+    let git_context = GitContext::detect(&metadata.workspace)?;
+    let overrides = &metadata.options.overrides;
+    overrides.apply(&mut config, &git_context.workspace_path)?;
+    let mut toolchain = Toolchain::detect(&git_context.workspace_path);
+    for path in &metadata.options.also {
+        toolchain.merge(Toolchain::detect(path));
+    }
+
+    let mut runner =
+        DockerRunner::new(&config, &git_context, toolchain, overrides.name.as_deref())?;
+    runner.container_name = container_name.to_string();
+    if with_mcp {
+        runner.generate_mcp_config()?;
+    }
+    Ok(runner)
+}
+
+/// Shell script running the commands of `run_args` (services first), with
+/// secret variables passed through from the caller's environment
+///
+/// `mcp_omitted` notes that the session's MCP servers were left out.
+fn export_script(
+    runtime: ContainerRuntime,
+    session: &str,
+    run_args: &RunArgs,
+    mcp_omitted: bool,
+) -> String {
+    let mut commands = Vec::new();
+    let mut secret_keys = run_args.secret_env_keys.clone();
+    if let Some(ref group) = run_args.services {
+        commands.push(group.network_create_args());
+        commands.extend(group.services.iter().map(|(_, args)| args.clone()));
+        secret_keys.extend(group.secret_env_keys.iter().cloned());
+    }
+    commands.push(run_args.args.clone());
+    secret_keys.sort();
+    secret_keys.dedup();
+
+    let mut script = format!(
+        "#!/bin/sh\n\
+         # Recreates ccs session {} (from `ccs --export-command`)\n\
+         # Host paths refer to the machine it was exported on\n\
+         set -e\n",
+        session
+    );
+    if mcp_omitted {
+        script.push_str(
+            "# MCP servers were not exported, since their config holds resolved secrets\n",
+        );
+    }
+    if !secret_keys.is_empty() {
+        script.push_str("\n# Secrets were not exported; set these before running\n");
+        for key in &secret_keys {
+            script.push_str(&format!(
+                ": \"${{{}:?is a secret; set it in the environment}}\"\n",
+                key
+            ));
+        }
+    }
+    for args in commands {
+        let parts: Vec<String> = runtime
+            .command_prefix()
+            .into_iter()
+            .chain(
+                args.iter()
+                    .map(|arg| shell_quote(&export_arg(arg, &secret_keys))),
+            )
+            .collect();
+        script.push('\n');
+        script.push_str(&parts.join(" \\\n  "));
+        script.push('\n');
+    }
+    script
+}
+
+/// Turn `KEY=value` for a secret variable into `KEY`, which the runtime
+/// fills in from its own environment
+fn export_arg(arg: &str, secret_keys: &[String]) -> String {
+    match arg.split_once('=') {
+        Some((key, _)) if secret_keys.iter().any(|k| k == key) => key.to_string(),
+        _ => arg.to_string(),
+    }
+}
+
+/// Resource usage of a container, from one `stats` sample
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ContainerStats {
+    pub name: String,
+    /// None while the runtime reports no value (`--`), e.g. for a stopped container
+    pub cpu_percent: Option<f64>,
+    pub mem_usage: String,
+    pub mem_limit: String,
+    pub mem_percent: Option<f64>,
+    /// Network received / sent
+    pub net_io: String,
+    /// Block device read / written
+    pub block_io: String,
+    pub pids: Option<u32>,
+}
+
+/// A `stats --format '{{json .}}'` line; Podman uses lowercase keys
+#[derive(Debug, serde::Deserialize)]
+struct RawStats {
+    #[serde(rename = "Name", alias = "name")]
+    name: String,
+    #[serde(rename = "CPUPerc", alias = "cpu_percent", default)]
+    cpu_percent: String,
+    #[serde(rename = "MemUsage", alias = "mem_usage", default)]
+    mem_usage: String,
+    #[serde(rename = "MemPerc", alias = "mem_percent", default)]
+    mem_percent: String,
+    #[serde(rename = "NetIO", alias = "net_io", default)]
+    net_io: String,
+    #[serde(rename = "BlockIO", alias = "block_io", default)]
+    block_io: String,
+    #[serde(rename = "PIDs", alias = "PIDS", alias = "pids", default)]
+    pids: String,
+}
+
+/// Assemble the `stats` arguments for one snapshot, or a stream with `watch`
+fn stats_args(container: &str, watch: bool) -> Vec<String> {
+    let mut args = vec!["stats".to_string()];
+    if !watch {
+        args.push("--no-stream".to_string());
+    }
+    args.extend(["--format".to_string(), "{{json .}}".to_string()]);
+    args.push(container.to_string());
+    args
+}
+
+/// Parse one line of `stats --format '{{json .}}'` output
+///
+/// Streaming output may start with screen-clearing escape sequences, so the
+/// JSON is taken from the first `{`.
+fn parse_stats_line(line: &str) -> Option<ContainerStats> {
+    let json = &line[line.find('{')?..];
+    let raw: RawStats = serde_json::from_str(json.trim()).ok()?;
+    let percent = |s: &str| s.trim().trim_end_matches('%').parse().ok();
+    let (mem_usage, mem_limit) = raw
+        .mem_usage
+        .split_once(" / ")
+        .map(|(usage, limit)| (usage.to_string(), limit.to_string()))
+        .unwrap_or((raw.mem_usage.clone(), String::new()));
+    Some(ContainerStats {
+        name: raw.name,
+        cpu_percent: percent(&raw.cpu_percent),
+        mem_usage,
+        mem_limit,
+        mem_percent: percent(&raw.mem_percent),
+        net_io: raw.net_io,
+        block_io: raw.block_io,
+        pids: raw.pids.trim().parse().ok(),
+    })
+}
+
+/// Format samples as a table with `style`
+fn format_stats_table(stats: &[ContainerStats], style: Style) -> String {
+    let percent = |p: Option<f64>| p.map_or("--".to_string(), |p| format!("{:.2}%", p));
+    let cells: Vec<Vec<String>> = stats
+        .iter()
+        .map(|s| {
+            vec![
+                s.name.clone(),
+                percent(s.cpu_percent),
+                format!("{} / {}", s.mem_usage, s.mem_limit),
+                percent(s.mem_percent),
+                s.net_io.clone(),
+                s.block_io.clone(),
+                s.pids.map_or("--".to_string(), |p| p.to_string()),
+            ]
+        })
+        .collect();
+    let rows: Vec<Vec<&str>> = std::iter::once(vec![
+        "NAME",
+        "CPU %",
+        "MEM USAGE / LIMIT",
+        "MEM %",
+        "NET I/O",
+        "BLOCK I/O",
+        "PIDS",
+    ])
+    .chain(
+        cells
+            .iter()
+            .map(|row| row.iter().map(String::as_str).collect()),
+    )
+    .collect();
+    style.table(&rows)
+}
+
+/// Print a session's resource usage once, or every sample until interrupted
+/// with `watch`, as a table or JSON lines
+pub fn show_stats(prefix: &str, container: &str, watch: bool, json: bool) -> anyhow::Result<()> {
+    use std::io::BufRead;
+
+    let runtime = ContainerRuntime::detect()?;
+    let container_name = resolve_container_name(runtime, prefix, container)?;
+
+    let mut child = runtime
+        .new_command()
+        .args(stats_args(&container_name, watch))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let style = output::style();
+    if let Some(stdout) = child.stdout.take() {
+        for line in std::io::BufReader::new(stdout).lines() {
+            let Some(stats) = parse_stats_line(&line?) else {
+                continue;
+            };
+            if json {
+                println!("{}", serde_json::to_string(&stats)?);
+            } else {
+                println!("{}", format_stats_table(&[stats], style));
+                if watch {
+                    print!("{}", style.gap());
+                }
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(DockerError::CommandFailed(format!(
+            "{} stats {} failed",
+            runtime.command(),
+            container_name
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Block until a ccs session exits and return its exit code
+pub fn wait_session(prefix: &str, container: &str) -> anyhow::Result<i32> {
+    let runtime = ContainerRuntime::detect()?;
+
+    // Resolve partial container name
+    let container_name = resolve_container_name(runtime, prefix, container)?;
+
+    println!("Waiting for {}...", container_name);
+    wait_container(runtime, &container_name)
+}
+
+/// Block until all running ccs sessions exit and return the highest exit code
+pub fn wait_all_sessions(prefix: &str) -> anyhow::Result<i32> {
+    let runtime = ContainerRuntime::detect()?;
+    let containers = list_ccs_containers(runtime, prefix);
+
+    if containers.is_empty() {
+        println!("No running ccs sessions.");
+        return Ok(0);
+    }
+
+    println!("Waiting for {} session(s)...", containers.len());
+    let mut max_code = 0;
+    for name in &containers {
+        let code = wait_container(runtime, name)?;
+        println!("{} exited with code {}", name, code);
+        max_code = max_code.max(code);
+    }
+
+    Ok(max_code)
+}
+
+fn wait_container(runtime: ContainerRuntime, name: &str) -> anyhow::Result<i32> {
+    let output = runtime.new_command().args(["wait", name]).output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DockerError::CommandFailed(stderr.to_string()).into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_wait_output(&stdout).ok_or_else(|| {
+        DockerError::CommandFailed(format!("unexpected wait output: {}", stdout.trim())).into()
+    })
+}
+
+/// Parse the exit code printed by `docker wait`
+fn parse_wait_output(s: &str) -> Option<i32> {
+    s.lines().rev().find_map(|line| line.trim().parse().ok())
+}
+
+/// Which session a command acts on
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionTarget {
+    /// A full or partial container name
+    Named(String),
+    /// The most recently created session (`--last`)
+    Last,
+}
+
+/// Resolve a session target to a full container name
+fn resolve_session(
+    runtime: ContainerRuntime,
+    prefix: &str,
+    target: &SessionTarget,
+) -> anyhow::Result<String> {
+    match target {
+        SessionTarget::Named(partial) => resolve_container_name(runtime, prefix, partial),
+        SessionTarget::Last => most_recent_session(runtime, prefix),
+    }
+}
+
+/// Name of the most recently created ccs container, running or not
+pub fn most_recent_session(runtime: ContainerRuntime, prefix: &str) -> anyhow::Result<String> {
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "-a",
+            "--filter",
+            &container_name_filter(prefix),
+            "--filter",
+            SESSION_LABEL_FILTER,
+            "--format",
+            "{{.Names}}\t{{.CreatedAt}}",
+        ])
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DockerError::CommandFailed(stderr.to_string()).into());
+    }
+
+    let sessions = parse_session_created(&String::from_utf8_lossy(&output.stdout));
+    newest_session(sessions).ok_or_else(|| anyhow::anyhow!("No ccs sessions found"))
+}
+
+/// Parse `name<TAB>CreatedAt` lines into (name, Unix seconds), skipping unparsable dates
+fn parse_session_created(stdout: &str) -> Vec<(String, u64)> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let (name, created) = line.split_once('\t')?;
+            Some((name.trim().to_string(), parse_created_at(created)?))
+        })
+        .collect()
+}
+
+/// Parse a `{{.CreatedAt}}` value like "2024-01-15 10:30:00 +0100 CET" to Unix seconds
+fn parse_created_at(s: &str) -> Option<u64> {
+    let mut parts = s.split_whitespace();
+    let date = parts.next()?;
+    let time = parts.next()?;
+    let offset = parts.next()?;
+    if offset.len() != 5 || !offset.is_char_boundary(3) {
+        return None;
+    }
+
+    reaper::parse_timestamp(&format!(
+        "{}T{}{}:{}",
+        date,
+        time,
+        &offset[..3],
+        &offset[3..]
+    ))
+}
+
+/// Pick the session with the latest creation time
+fn newest_session(sessions: Vec<(String, u64)>) -> Option<String> {
+    sessions
+        .into_iter()
+        .max_by_key(|(_, created)| *created)
+        .map(|(name, _)| name)
+}
+
+/// Resolve a partial container name to full name
+fn resolve_container_name(
+    runtime: ContainerRuntime,
+    prefix: &str,
+    partial: &str,
+) -> anyhow::Result<String> {
+    // If it already starts with the prefix, use as-is
+    let search_name = if partial.starts_with(&format!("{}-", prefix)) {
+        partial.to_string()
+    } else {
+        format!("{}-{}", prefix, partial)
+    };
+
+    // Try to find matching container
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "-a",
+            "--filter",
+            &format!("name={}", search_name),
+            "--filter",
+            SESSION_LABEL_FILTER,
+            "--format",
+            "{{.Names}}",
+        ])
+        .output()?;
+
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let names: Vec<String> = stdout
+            .lines()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.trim().to_string())
+            .collect();
+
+        // Fall back to the branch a session was started on
+        if names.is_empty() {
+            if let Some(name) = resolve_container_by_branch(runtime, prefix, partial)? {
+                return Ok(name);
+            }
+        }
+
+        select_container_match(names, partial, &search_name)
+    } else {
+        Ok(search_name)
+    }
+}
+
+/// Find the session named with `prefix` whose `ccs.branch` label matches `branch`
+fn resolve_container_by_branch(
+    runtime: ContainerRuntime,
+    prefix: &str,
+    branch: &str,
+) -> anyhow::Result<Option<String>> {
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "-a",
+            "--filter",
+            &container_name_filter(prefix),
+            "--filter",
+            SESSION_LABEL_FILTER,
+            "--format",
+            &format!("{{{{.Names}}}}\t{{{{.Label \"{}\"}}}}", LABEL_BRANCH),
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let sessions: Vec<(String, String)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, branch)| (name.trim().to_string(), branch.trim().to_string()))
+        .collect();
+    select_branch_match(&sessions, branch)
+}
+
+/// Select the session for `branch` from `(name, branch label)` pairs
+///
+/// `branch` matches a label exactly or as its last `/` components, so
+/// `fix-auth` finds `ccs/fix-auth`. An exact match wins over suffix matches.
+fn select_branch_match(
+    sessions: &[(String, String)],
+    branch: &str,
+) -> anyhow::Result<Option<String>> {
+    let matches: Vec<&(String, String)> = sessions
+        .iter()
+        .filter(|(_, label)| git::branch_matches(label, branch))
+        .collect();
+    let exact: Vec<&(String, String)> = matches
+        .iter()
+        .copied()
+        .filter(|(_, label)| label == branch)
+        .collect();
+
+    match (matches.as_slice(), exact.as_slice()) {
+        ([], _) => Ok(None),
+        ([(name, _)], _) | (_, [(name, _)]) => Ok(Some(name.clone())),
+        _ => Err(anyhow::anyhow!(
+            "Multiple containers match branch '{}': {}",
+            branch,
+            matches
+                .iter()
+                .map(|(name, label)| format!("{} ({})", name, label))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Select the best container match from a list of names
+fn select_container_match(
+    mut names: Vec<String>,
+    partial: &str,
+    search_name: &str,
+) -> anyhow::Result<String> {
+    match names.len() {
+        0 => Err(anyhow::anyhow!("No container found matching '{}'", partial)),
+        1 => Ok(names.pop().unwrap()),
+        _ => {
+            // Check for exact match
+            if let Some(pos) = names.iter().position(|n| n == search_name) {
+                Ok(names.swap_remove(pos))
+            } else {
+                Err(anyhow::anyhow!(
+                    "Multiple containers match '{}': {}",
+                    partial,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// Generate a container name, using `suffix` in place of the timestamp if given
+fn generate_container_name(prefix: &str, repo_name: &str, suffix: Option<&str>) -> String {
+    let repo_name = match sanitize_container_name_component(repo_name) {
+        name if name.is_empty() => "repo".to_string(),
+        name => name,
+    };
+    if let Some(suffix) = suffix {
+        return format!("{}-{}-{}", prefix, repo_name, suffix);
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    // Use last 6 digits for readability
+    let short_ts = timestamp % 1_000_000;
+    format!("{}-{}-{}", prefix, repo_name, short_ts)
+}
+
+/// Make `s` safe to use in a container name
+///
+/// Lowercases, replaces anything other than ASCII letters, digits and `_` with
+/// `-`, collapses runs of `-` and trims them from the ends. May return an empty
+/// string, e.g. for names without any ASCII letters or digits.
+pub fn sanitize_container_name_component(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            out.push(c);
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+
+    out.trim_end_matches('-').to_string()
+}
+
+/// Sanitized `docker.container_prefix`, falling back to "ccs"
+pub fn container_prefix(config: &Config) -> String {
+    match sanitize_container_name_component(&config.docker.container_prefix) {
+        prefix if prefix.is_empty() => "ccs".to_string(),
+        prefix => prefix,
+    }
+}
+
+/// Named volume holding the container user's ~/.claude for a repository
+pub fn claude_state_volume(config: &Config, repo_name: &str) -> String {
+    let repo = match sanitize_container_name_component(repo_name) {
+        repo if repo.is_empty() => "repo".to_string(),
+        repo => repo,
+    };
+    format!("{}-state-{}", container_prefix(config), repo)
+}
+
+/// `--filter` value matching session containers, but not their services,
+/// which share the name prefix
+pub const SESSION_LABEL_FILTER: &str = "label=ccs.workspace";
+
+/// `--filter` value matching containers with the given name prefix
+pub fn container_name_filter(prefix: &str) -> String {
+    format!("name={}-", prefix)
+}
+
+/// Maximum length of a `--name` session suffix
+const MAX_SESSION_NAME_LEN: usize = 40;
+
+/// Validate a `--name` session suffix against the container name charset
+///
+/// Names are lowercased; they must start with a letter or digit and may
+/// contain only letters, digits, `_`, `.` and `-`.
+pub fn parse_session_name(s: &str) -> Result<String, String> {
+    let name = s.to_lowercase();
+
+    if name.is_empty() {
+        return Err("session name must not be empty".to_string());
+    }
+    if name.len() > MAX_SESSION_NAME_LEN {
+        return Err(format!(
+            "session name must be at most {} characters",
+            MAX_SESSION_NAME_LEN
+        ));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err("session name must start with a letter or digit".to_string());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')))
+    {
+        return Err(format!(
+            "invalid character '{}' in session name (allowed: a-z, 0-9, _, ., -)",
+            c
+        ));
+    }
+
+    Ok(name)
+}
+
+/// Check whether a container with this exact name exists (running or stopped)
+fn container_exists(runtime: ContainerRuntime, name: &str) -> bool {
+    runtime
+        .new_command()
+        .args(["container", "inspect", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Inspect format reporting "<running>|<health>"; health is empty without a HEALTHCHECK
+const READINESS_FORMAT: &str =
+    "{{.State.Running}}|{{if .State.Health}}{{.State.Health.Status}}{{end}}";
+
+/// How often to poll a detached session while waiting for it to be ready
+const READINESS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Readiness of a freshly started container
+#[derive(Debug, PartialEq)]
+enum Readiness {
+    Ready,
+    Pending,
+    Failed(String),
+}
+
+/// Parse `inspect` output in [`READINESS_FORMAT`]
+fn parse_readiness(output: &str) -> Readiness {
+    let (running, health) = output.trim().split_once('|').unwrap_or((output.trim(), ""));
+    if running != "true" {
+        return Readiness::Failed("container is not running".to_string());
+    }
+    match health {
+        "" | "healthy" => Readiness::Ready,
+        "unhealthy" => Readiness::Failed("health check reports unhealthy".to_string()),
+        _ => Readiness::Pending,
+    }
+}
+
+/// Poll `inspect` until the container is ready, has failed, or `timeout` elapses
+///
+/// `inspect` returns the container's state in [`READINESS_FORMAT`], or None
+/// if it couldn't be queried (treated as not ready yet).
+fn wait_until_ready(
+    mut inspect: impl FnMut() -> Option<String>,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(output) = inspect() {
+            match parse_readiness(&output) {
+                Readiness::Ready => return Ok(()),
+                Readiness::Failed(reason) => return Err(reason),
+                Readiness::Pending => {}
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("timed out after {}s", timeout.as_secs()));
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Query a container's state in an `inspect` format, None if it can't be inspected
+fn inspect_state(runtime: ContainerRuntime, name: &str, format: &str) -> Option<String> {
+    let output = runtime
+        .new_command()
+        .args(["inspect", "--format", format, name])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Status information about the container runtime environment
+#[derive(Debug)]
+pub struct RuntimeStatus {
+    pub runtime: Option<ContainerRuntime>,
+    pub runtime_version: Option<String>,
+    pub rootless: bool,
+    pub host: DaemonHost,
+    /// Podman connection in use: `docker.connection`, else the default one
+    /// unless `CONTAINER_HOST` overrides it
+    pub podman_connection: Option<PodmanConnection>,
+    pub image_exists: bool,
+    pub running_containers: Vec<String>,
+    pub config_path: Option<PathBuf>,
+    pub config_exists: bool,
+    pub mcp_config_path: Option<PathBuf>,
+    pub mcp_config_exists: bool,
+    pub credentials: ClaudeCredentials,
+    /// Directory new worktrees are created in
+    pub worktree_base: PathBuf,
+    /// Free space available to unprivileged users there, in bytes
+    pub free_space: Option<u64>,
+    /// `docker.persist_claude_state` volume for the current repository
+    pub claude_state_volume: Option<String>,
+    pub claude_state_exists: bool,
+}
+
+impl RuntimeStatus {
+    /// Check the status of the container runtime environment
+    pub fn check(config: &Config, config_path: Option<&Path>) -> Self {
+        let runtime = ContainerRuntime::detect().ok();
+        let runtime_version = runtime.and_then(get_runtime_version);
+        let rootless = runtime.map(|r| r.is_rootless()).unwrap_or(false);
+        let host = runtime
+            .map(DaemonHost::detect)
+            .unwrap_or(DaemonHost::Default);
+        let configured = configured_connection(config);
+        let podman_connection = match runtime {
+            Some(ContainerRuntime::Podman)
+                if configured.is_some() || host == DaemonHost::Default =>
+            {
+                active_podman_connection(&list_podman_connections(), configured)
+            }
+            _ => None,
+        };
+        let image_exists = runtime
+            .map(|r| check_image_exists(r, &config.docker.image))
+            .unwrap_or(false);
+        let prefix = container_prefix(config);
+        let running_containers = runtime
+            .map(|r| list_ccs_containers(r, &prefix))
+            .unwrap_or_default();
+
+        let config_path = config_path
+            .map(Path::to_path_buf)
+            .or_else(|| Config::config_path().ok());
+        let config_exists = config_path.as_ref().map(|p| p.exists()).unwrap_or(false);
+
+        let mcp_config_path = config.mcp_servers_path().ok();
+        let mcp_config_exists = mcp_config_path
+            .as_ref()
+            .map(|p| p.exists())
+            .unwrap_or(false);
+
+        let credentials = auth::discover_credentials_with(&config.auth);
+
+        // Worktree locations depend on the repo; measure the part they share
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let worktree_base = config.resolve_worktree_path("", &cwd);
+        let free_space = available_space(&worktree_base);
+
+        let claude_state_volume = config
+            .docker
+            .persist_claude_state
+            .then(|| GitContext::detect(&cwd).ok())
+            .flatten()
+            .map(|ctx| claude_state_volume(config, &ctx.repo_name));
+        let claude_state_exists = match (runtime, &claude_state_volume) {
+            (Some(r), Some(volume)) => volume_exists(r, volume),
+            _ => false,
+        };
+
+        RuntimeStatus {
+            runtime,
+            runtime_version,
+            rootless,
+            host,
+            podman_connection,
+            image_exists,
+            running_containers,
+            config_path,
+            config_exists,
+            mcp_config_path,
+            mcp_config_exists,
+            credentials,
+            worktree_base,
+            free_space,
+            claude_state_volume,
+            claude_state_exists,
+        }
+    }
+
+    /// Print status in a human-readable format
+    pub fn print(&self, config: &Config) {
+        let style = output::style();
+        println!("{}", style.header("CCS Status"));
+
+        // Runtime
+        match &self.runtime {
+            Some(r) => {
+                let version = self.runtime_version.as_deref().unwrap_or("unknown");
+                println!(
+                    "Container runtime: {} ({}, {})",
+                    r.name(),
+                    version,
+                    if self.rootless { "rootless" } else { "rootful" }
+                );
+                match (configured_connection(config), &self.podman_connection) {
+                    (Some(_), Some(conn)) if *r == ContainerRuntime::Podman => {
+                        println!("Connection: {} (docker.connection)", conn)
+                    }
+                    (Some(name), None) if *r == ContainerRuntime::Podman => println!(
+                        "Connection: '{}' NOT FOUND (see: podman system connection list)",
+                        name
+                    ),
+                    (_, Some(conn)) => println!("Connection: {}", conn),
+                    _ => println!("Connection: {}", self.host),
+                }
+                if self.host.is_remote() {
+                    println!("  Bind mounts won't work against a remote daemon");
+                }
+                if *r == ContainerRuntime::Docker && configured_connection(config).is_some() {
+                    println!("  docker.connection only applies to Podman and is ignored");
+                }
+            }
+            None => {
+                println!("Container runtime: {}", style.warn("NOT FOUND"));
+                println!("  Install Docker or Podman to use ccs");
+            }
+        }
+
+        // Image
+        println!(
+            "Image '{}': {}",
+            config.docker.image,
+            if self.image_exists {
+                "available".to_string()
+            } else {
+                style.warn("NOT FOUND (run: ccs --build)")
+            }
+        );
+        if config.docker.image.contains('/') {
+            println!("  Pull policy: {}", config.docker.pull_policy.as_str());
+        }
+
+        // Running containers
+        if self.running_containers.is_empty() {
+            println!("Running ccs containers: none");
+        } else {
+            println!("Running ccs containers:");
+            for name in &self.running_containers {
+                println!("{}", style.item(name));
+            }
+        }
+
+        print!("{}", style.gap());
+
+        // Credentials
+        match self.credentials.source {
+            CredentialSource::None => {
+                println!("Claude credentials: {}", style.warn("NOT FOUND"));
+                println!("  Run 'claude login' on host, or set ANTHROPIC_API_KEY");
+            }
+            ref source => {
+                println!(
+                    "Claude credentials: {} ({})",
+                    source,
+                    if self.credentials.api_key.is_some() {
+                        "API key"
+                    } else {
+                        "OAuth token"
+                    }
+                );
+            }
+        }
+
+        // Disk space
+        match self.free_space {
+            Some(bytes) => println!(
+                "Free space for worktrees: {} ({})",
+                format_bytes(bytes),
+                self.worktree_base.display()
+            ),
+            None => println!(
+                "Free space for worktrees: unknown ({})",
+                self.worktree_base.display()
+            ),
+        }
+
+        // Persisted Claude state
+        if let Some(ref volume) = self.claude_state_volume {
+            println!(
+                "Claude state volume: {} ({})",
+                volume,
+                if self.claude_state_exists {
+                    "exists"
+                } else {
+                    "not created"
+                }
+            );
+        }
+
+        print!("{}", style.gap());
+
+        // Config files
+        if let Some(ref path) = self.config_path {
+            println!(
+                "Config: {} ({})",
+                path.display(),
+                if self.config_exists {
+                    "exists"
+                } else {
+                    "not created"
+                }
+            );
+        }
+
+        if let Some(ref path) = self.mcp_config_path {
+            println!(
+                "MCP config: {} ({})",
+                path.display(),
+                if self.mcp_config_exists {
+                    "exists"
+                } else {
+                    "not created"
+                }
+            );
+        }
+
+        // Resource limits
+        print!("{}", style.gap());
+        println!("Resource limits:");
+        match &config.docker.memory_limit {
+            Some(mem) => println!("  Memory: {}", mem),
+            None => println!("  Memory: unlimited"),
+        }
+        match config.docker.cpu_limit {
+            Some(cpu) => println!("  CPU: {} cores", cpu),
+            None => println!("  CPU: unlimited"),
+        }
+    }
+}
+
+pub fn get_runtime_version(runtime: ContainerRuntime) -> Option<String> {
+    let output = runtime.new_command().arg("--version").output().ok()?;
+
+    if output.status.success() {
+        let version = String::from_utf8_lossy(&output.stdout);
+        // Extract just the version number
+        Some(version.trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// What to do about the configured image before running
+#[derive(Debug, PartialEq)]
+enum ImageAction {
+    /// Image is present (or pulled by the runtime on demand)
+    Ready,
+    /// Image is missing and should be built first
+    Build,
+    /// Image is missing and auto-building is disabled
+    Missing,
+}
+
+/// Decide whether the image needs building; `exists` is only called for local images
+fn missing_image_action(
+    image: &str,
+    auto_build: bool,
+    exists: impl FnOnce() -> bool,
+) -> ImageAction {
+    // Images without a registry path are only ever built locally
+    if image.contains('/') || exists() {
+        ImageAction::Ready
+    } else if auto_build {
+        ImageAction::Build
+    } else {
+        ImageAction::Missing
+    }
+}
+
+/// Whether to pull `image` before running under `policy`
+///
+/// Like `missing_image_action`, only registry images (with a `/`) are pulled;
+/// `exists` is only called for `missing`.
+fn should_pull(image: &str, policy: PullPolicy, exists: impl FnOnce() -> bool) -> bool {
+    if !image.contains('/') {
+        return false;
+    }
+    match policy {
+        PullPolicy::Never => false,
+        PullPolicy::Missing => !exists(),
+        PullPolicy::Always => true,
+    }
+}
+
+/// Pull `image`, falling back to a local copy if the registry can't be reached
+fn pull_image(runtime: ContainerRuntime, image: &str) -> Result<(), DockerError> {
+    println!("Pulling {}...", image);
+    let status = runtime.new_command().args(["pull", image]).status()?;
+    if status.success() {
+        return Ok(());
+    }
+    if check_image_exists(runtime, image) {
+        eprintln!("Warning: failed to pull {}, using the local copy", image);
+        Ok(())
+    } else {
+        Err(DockerError::CommandFailed(format!(
+            "{} pull {} failed",
+            runtime.command(),
+            image
+        )))
+    }
+}
+
+/// Free space in bytes on the filesystem holding `path`
+///
+/// Uses the nearest existing ancestor, so it works for directories that
+/// haven't been created yet.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    fs2::available_space(existing).ok()
+}
+
+/// Format a byte count as GB/MB for display
+pub fn format_bytes(bytes: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    let bytes = bytes as f64;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes / GB)
+    } else {
+        format!("{:.0} MB", bytes / MB)
+    }
+}
+
+fn check_image_exists(runtime: ContainerRuntime, image: &str) -> bool {
+    let output = runtime
+        .new_command()
+        .args(["image", "inspect", image])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    output.map(|s| s.success()).unwrap_or(false)
+}
+
+/// Shell used when the image lacks `docker.shell`
+const FALLBACK_SHELL: &str = "/bin/sh";
+
+/// File in the ccs data dir caching which image IDs have which shells
+const SHELL_PROBE_CACHE: &str = "shell-probes.json";
+
+/// `configured`, or /bin/sh if `has_shell` reports it missing from the image
+fn shell_with_fallback(configured: &str, has_shell: impl FnOnce(&str) -> bool) -> String {
+    if configured == FALLBACK_SHELL || has_shell(configured) {
+        return configured.to_string();
+    }
+    eprintln!(
+        "Warning: {} not found in the image, using {}",
+        configured, FALLBACK_SHELL
+    );
+    FALLBACK_SHELL.to_string()
+}
+
+/// Whether `shell` is executable in `image`, cached per image ID
+fn image_has_shell(runtime: ContainerRuntime, image: &str, shell: &str) -> bool {
+    let probe = || {
+        runtime
+            .new_command()
+            .args(["run", "--rm", "--entrypoint", "test", image, "-x", shell])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false)
+    };
+
+    // Without an image ID a rebuilt image can't be told apart, so don't cache
+    let Some(image_id) = image_id(runtime, image) else {
+        return probe();
+    };
+    match dirs::data_dir() {
+        Some(dir) => cached_shell_probe(
+            &dir.join("ccs").join(SHELL_PROBE_CACHE),
+            &format!("{} {}", image_id, shell),
+            probe,
+        ),
+        None => probe(),
+    }
+}
+
+/// Look `key` up in the probe cache at `path`, running and recording `probe` on a miss
+///
+/// An unreadable cache is treated as empty; failing to save it only costs a re-probe.
+fn cached_shell_probe(path: &Path, key: &str, probe: impl FnOnce() -> bool) -> bool {
+    let mut cache: HashMap<String, bool> = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    if let Some(&found) = cache.get(key) {
+        return found;
+    }
+
+    let found = probe();
+    cache.insert(key.to_string(), found);
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&cache) {
+        let _ = std::fs::write(path, json);
+    }
+    found
+}
+
+/// Content-addressed ID of a local image
+fn image_id(runtime: ContainerRuntime, image: &str) -> Option<String> {
+    let output = runtime
+        .new_command()
+        .args(["image", "inspect", "--format", "{{.Id}}", image])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !id.is_empty()).then_some(id)
+}
+
+/// An entry of `podman system connection list`
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct PodmanConnection {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "URI")]
+    pub uri: String,
+    #[serde(rename = "Default", default)]
+    pub default: bool,
+}
+
+impl std::fmt::Display for PodmanConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "podman connection '{}' ({})", self.name, self.uri)
+    }
+}
+
+fn list_podman_connections() -> Vec<PodmanConnection> {
+    // Listing doesn't contact a service, so skip the global --connection flag
+    Command::new(ContainerRuntime::Podman.command())
+        .args(["system", "connection", "list", "--format", "json"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| parse_podman_connections(&String::from_utf8_lossy(&o.stdout)))
+        .unwrap_or_default()
+}
+
+/// Parse `podman system connection list --format json` output
+fn parse_podman_connections(json: &str) -> Vec<PodmanConnection> {
+    serde_json::from_str(json.trim()).unwrap_or_default()
+}
+
+/// The named connection, or the default one when no name is configured
+fn active_podman_connection(
+    connections: &[PodmanConnection],
+    configured: Option<&str>,
+) -> Option<PodmanConnection> {
+    connections
+        .iter()
+        .find(|c| match configured {
+            Some(name) => c.name == name,
+            None => c.default,
+        })
+        .cloned()
+}
+
+fn volume_exists(runtime: ContainerRuntime, volume: &str) -> bool {
+    let output = runtime
+        .new_command()
+        .args(["volume", "inspect", volume])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    output.map(|s| s.success()).unwrap_or(false)
+}
+
+fn list_ccs_containers(runtime: ContainerRuntime, prefix: &str) -> Vec<String> {
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "--filter",
+            &container_name_filter(prefix),
+            "--filter",
+            SESSION_LABEL_FILTER,
+            "--format",
+            "{{.Names}}",
+        ])
+        .output();
+
+    match output {
+        Ok(Output { status, stdout, .. }) if status.success() => String::from_utf8_lossy(&stdout)
+            .lines()
+            .map(|s| s.to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Remove generated MCP configs of sessions whose container is gone
+///
+/// Nothing is removed when the containers can't be listed.
+pub fn reap_orphaned_mcp_configs() {
+    let Ok(runtime) = ContainerRuntime::detect() else {
+        return;
+    };
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "-a",
+            "--filter",
+            SESSION_LABEL_FILTER,
+            "--format",
+            "{{.Names}}",
+        ])
+        .output();
+
+    if let Ok(Output { status, stdout, .. }) = output {
+        if status.success() {
+            let sessions: Vec<String> = String::from_utf8_lossy(&stdout)
+                .lines()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect();
+            mcp::reap_orphaned_configs(&sessions);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_runner(config: Config, workspace: &TempDir) -> DockerRunner {
+        DockerRunner {
+            runtime: ContainerRuntime::Docker,
+            config,
+            git_context: GitContext {
+                workspace_path: workspace.path().to_path_buf(),
+                shared_git_dir: None,
+                repo_name: "project".to_string(),
+                is_worktree: false,
+            },
+            mcp_config_path: None,
+            container_name: "ccs-project-123".to_string(),
+            credentials: ClaudeCredentials {
+                source: CredentialSource::None,
+                oauth_token: None,
+                api_key: None,
+            },
+            toolchain: Toolchain::default(),
+            git_identity: GitIdentity::default(),
+            git_credentials: None,
+            rootless: false,
+        }
+    }
+
+    /// Split the commands of a generated script into words, as `sh` would
+    fn script_commands(script: &str) -> Vec<Vec<String>> {
+        let joined = script.replace(" \\\n  ", " ");
+        joined
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with(['#', ':']))
+            .map(|line| {
+                let mut words = Vec::new();
+                let mut word = String::new();
+                let mut quoted = false;
+                for c in line.chars() {
+                    match c {
+                        '\'' => quoted = !quoted,
+                        ' ' if !quoted => {
+                            words.push(std::mem::take(&mut word));
+                        }
+                        _ => word.push(c),
+                    }
+                }
+                words.push(word);
+                words
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_export_script_round_trip() {
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config
+            .docker
+            .extra_env
+            .insert("API_TOKEN".to_string(), "op://Vault/Item/token".to_string());
+        config
+            .docker
+            .extra_env
+            .insert("GREETING".to_string(), "hello world".to_string());
+        let started = RunOptions {
+            mounts: vec![parse_mount("/data:/mnt/data:ro").unwrap()],
+            user: Some("1000:1000".to_string()),
+            docker_args: vec!["--shm-size=1g".to_string()],
+            // op:// can't be resolved here; the recorded options don't depend on it
+            keep_secret_refs: true,
+            ..Default::default()
+        };
+        let runner = test_runner(config, &workspace);
+        let started_args = runner
+            .build_run_args(&["-p".to_string(), "fix it".to_string()], &started)
+            .unwrap();
+
+        // Export from what the session recorded, as --export-command does
+        let labels: HashMap<String, String> = started_args
+            .args
+            .windows(2)
+            .filter(|w| w[0] == "--label")
+            .filter_map(|w| w[1].split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let metadata = SessionMetadata::from_labels(&labels).unwrap();
+        let options = RunOptions {
+            keep_secret_refs: true,
+            ..metadata.run_options()
+        };
+        let run_args = runner
+            .build_run_args(&metadata.claude_args, &options)
+            .unwrap();
+        let script = export_script(ContainerRuntime::Docker, "ccs-project-123", &run_args, true);
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("# MCP servers were not exported"));
+        assert!(!script.contains(".claude.json"));
+        assert!(script.contains(": \"${API_TOKEN:?"));
+        assert!(!script.contains("op://"));
+
+        let commands = script_commands(&script);
+        assert_eq!(commands[0][0], "set");
+        let run = &commands[1];
+        assert_eq!(run[..4], ["docker", "run", "--name", "ccs-project-123"]);
+        // Every argument survives quoting, with secrets passed through by name
+        let expected: Vec<String> = run_args
+            .args
+            .iter()
+            .map(|arg| export_arg(arg, &run_args.secret_env_keys))
+            .collect();
+        assert_eq!(run[1..], expected[..]);
+        let has_pair =
+            |flag: &str, value: &str| run.windows(2).any(|w| w[0] == flag && w[1] == value);
+        assert!(has_pair("-e", "API_TOKEN"));
+        assert!(has_pair("-e", "GREETING=hello world"));
+        assert!(has_pair("-v", "/data:/mnt/data:ro"));
+        assert!(has_pair("--user", "1000:1000"));
+        assert!(run.iter().any(|arg| arg == "--shm-size=1g"));
+        assert_eq!(run[run.len() - 2..], ["-p", "fix it"]);
+    }
+
+    #[test]
+    fn test_extra_env_secrets_resolved() {
+        std::env::set_var("TEST_SECRET_CCS_EXTRA_ENV", "resolved_value");
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.docker.extra_env.insert(
+            "API_TOKEN".to_string(),
+            "env://TEST_SECRET_CCS_EXTRA_ENV".to_string(),
+        );
+        config
+            .docker
+            .extra_env
+            .insert("EDITOR".to_string(), "vim".to_string());
+
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        assert!(run_args
+            .args
+            .contains(&"API_TOKEN=resolved_value".to_string()));
+        assert!(run_args.args.contains(&"EDITOR=vim".to_string()));
+        assert_eq!(run_args.secret_env_keys, vec!["API_TOKEN".to_string()]);
+        assert_eq!(
+            redact_arg("API_TOKEN=resolved_value", &run_args.secret_env_keys),
+            "API_TOKEN=[...]"
+        );
+        std::env::remove_var("TEST_SECRET_CCS_EXTRA_ENV");
+    }
+
+    #[test]
+    fn test_env_file_secrets_resolved() {
+        std::env::set_var("TEST_SECRET_CCS_ENV_FILE", "file_secret");
+        let workspace = TempDir::new().unwrap();
+        let contents = "# comment\nDB_PASSWORD=env://TEST_SECRET_CCS_ENV_FILE\nPLAIN=value\n";
+        std::fs::write(workspace.path().join(".env"), contents).unwrap();
+        let ci_env = workspace.path().join("ci.env");
+        std::fs::write(&ci_env, contents).unwrap();
+
+        // The workspace .env comes with the repository and is passed as written
+        let run_args = test_runner(Config::default(), &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        assert!(run_args.env_file_loaded);
+        assert!(run_args.args.contains(&"--env-file".to_string()));
+        assert!(!run_args
+            .args
+            .iter()
+            .any(|arg| arg.starts_with("DB_PASSWORD=")));
+        assert!(run_args.secret_env_keys.is_empty());
+
+        // An explicit --env-file is the user's own, so its references resolve
+        let options = RunOptions {
+            env_files: vec![ci_env],
+            ..Default::default()
+        };
+        let run_args = test_runner(Config::default(), &workspace)
+            .build_run_args(&[], &options)
+            .unwrap();
+        assert!(run_args
+            .args
+            .contains(&"DB_PASSWORD=file_secret".to_string()));
+        assert!(!run_args.args.contains(&"PLAIN=value".to_string()));
+        std::env::remove_var("TEST_SECRET_CCS_ENV_FILE");
+    }
+
+    #[test]
+    fn test_cli_env_files_dry_run() {
+        let workspace = TempDir::new().unwrap();
+        std::fs::write(workspace.path().join(".env"), "FROM_PROJECT=1\n").unwrap();
+        let ci_env = workspace.path().join("ci.env");
+        std::fs::write(&ci_env, "FROM_CI=1\n").unwrap();
+
+        let config = Config {
+            docker: crate::config::DockerConfig {
+                load_env_file: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let options = RunOptions {
+            env_files: vec![parse_env_file_arg(ci_env.to_str().unwrap()).unwrap()],
+            ..Default::default()
+        };
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &options)
+            .unwrap();
+
+        // The project .env is skipped, the explicit file is still passed
+        assert!(!run_args.env_file_loaded);
+        let env_files: Vec<&String> = run_args
+            .args
+            .windows(2)
+            .filter(|w| w[0] == "--env-file")
+            .map(|w| &w[1])
+            .collect();
+        assert_eq!(
+            env_files,
+            [&ci_env.canonicalize().unwrap().display().to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_env_file_arg_missing() {
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("missing.env");
+        assert!(parse_env_file_arg(missing.to_str().unwrap())
+            .unwrap_err()
+            .contains("not found"));
+        assert!(parse_env_file_arg(dir.path().to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_session_metadata_labels() {
+        let metadata = SessionMetadata {
+            workspace: PathBuf::from("/home/user/project"),
+            detach: true,
+            claude_args: vec!["--model".to_string(), "opus".to_string()],
+            log_file: Some(PathBuf::from(
+                "/home/user/.local/share/ccs/logs/ccs-project-1.log",
+            )),
+            network: Some("ccs-project-1-net".to_string()),
+            options: SessionOptions {
+                mounts: vec![parse_mount("~/data:/data:ro").unwrap()],
+                also: vec![PathBuf::from("/home/user/lib")],
+                user: Some("1000:1000".to_string()),
+                env_files: vec![PathBuf::from("/home/user/ci.env")],
+                docker_args: vec!["--shm-size=1g".to_string()],
+                overrides: RunOverrides {
+                    memory: Some("2g".to_string()),
+                    cpus: Some(1.5),
+                    permission_mode: Some(PermissionMode::Plan),
+                    workdir: Some("packages/app".to_string()),
+                    sparse_paths: vec!["packages/app".to_string()],
+                    name: Some("review".to_string()),
+                },
+            },
+        };
+
+        let mut labels: HashMap<String, String> = metadata
+            .to_labels()
+            .iter()
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(labels[LABEL_ARGS], r#"["--model","opus"]"#);
+        assert_eq!(labels[LABEL_NETWORK], "ccs-project-1-net");
+        assert_eq!(
+            SessionMetadata::from_labels(&labels),
+            Some(metadata.clone())
+        );
+
+        let options = metadata.run_options();
+        assert_eq!(options.mounts, metadata.options.mounts);
+        assert_eq!(options.user.as_deref(), Some("1000:1000"));
+        assert_eq!(options.overrides.cpus, Some(1.5));
+        assert_eq!(options.docker_args, vec!["--shm-size=1g".to_string()]);
+
+        // Sessions from before the options were recorded still restart
+        labels.remove(LABEL_OPTIONS);
+        assert_eq!(
+            SessionMetadata::from_labels(&labels).unwrap().options,
+            SessionOptions::default()
+        );
+    }
+
+    #[test]
+    fn test_run_overrides_apply() {
+        let workspace = TempDir::new().unwrap();
+        std::fs::create_dir(workspace.path().join("app")).unwrap();
+        let mut config = Config::default();
+        RunOverrides {
+            memory: Some("2g".to_string()),
+            cpus: Some(2.0),
+            permission_mode: Some(PermissionMode::AcceptEdits),
+            workdir: Some("app".to_string()),
+            ..Default::default()
+        }
+        .apply(&mut config, workspace.path())
+        .unwrap();
+        assert_eq!(config.docker.memory_limit.as_deref(), Some("2g"));
+        assert_eq!(config.docker.cpu_limit, Some(2.0));
+        assert_eq!(
+            config.claude.permission_mode,
+            Some(PermissionMode::AcceptEdits)
+        );
+        assert_eq!(
+            config.docker.workdir,
+            resolve_workdir("app", workspace.path()).unwrap()
+        );
+
+        let missing = RunOverrides {
+            workdir: Some("missing".to_string()),
+            ..Default::default()
+        };
+        assert!(missing.apply(&mut config, workspace.path()).is_err());
+    }
+
+    #[test]
+    fn test_service_group_args() {
+        let mut services = HashMap::new();
+        services.insert(
+            "db".to_string(),
+            ServiceConfig {
+                image: "postgres:16".to_string(),
+                env: HashMap::from([("POSTGRES_PASSWORD".to_string(), "dev".to_string())]),
+                ports: vec!["5432:5432".to_string()],
+            },
+        );
+        services.insert(
+            "cache".to_string(),
+            ServiceConfig {
+                image: "redis:7".to_string(),
+                ..Default::default()
+            },
+        );
+
+        let group = ServiceGroup::new("ccs-project-1", &services, &SecretsConfig::default(), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(group.network, "ccs-project-1-net");
+        assert_eq!(
+            group.network_create_args(),
+            vec![
+                "network",
+                "create",
+                "--label",
+                "ccs.service_of=ccs-project-1",
+                "ccs-project-1-net"
+            ]
+        );
+
+        // Services are started in name order
+        let names: Vec<&str> = group.services.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["cache", "db"]);
+
+        let db_args = &group.services[1].1;
+        assert_eq!(
+            db_args,
+            &vec![
+                "run",
+                "-d",
+                "--name",
+                "ccs-project-1-db",
+                "--network",
+                "ccs-project-1-net",
+                "--network-alias",
+                "db",
+                "--label",
+                "ccs.service_of=ccs-project-1",
+                "-e",
+                "POSTGRES_PASSWORD=dev",
+                "-p",
+                "5432:5432",
+                "postgres:16",
+            ]
+        );
+        assert!(group.secret_env_keys.is_empty());
+    }
+
+    #[test]
+    fn test_service_group_none_without_services() {
+        let group = ServiceGroup::new(
+            "ccs-project-1",
+            &HashMap::new(),
+            &SecretsConfig::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(group, None);
+    }
+
+    #[test]
+    fn test_build_run_args_persists_claude_state() {
+        let dir = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.docker.persist_claude_state = true;
+        let mut runner = test_runner(config, &dir);
+        runner.mcp_config_path = Some(PathBuf::from("/tmp/ccs-mcp.json"));
+
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(run_args
+            .args
+            .contains(&"ccs-state-project:/home/claude/.claude".to_string()));
+        // The MCP config lives next to, not inside, the state volume
+        assert!(run_args
+            .args
+            .contains(&"/tmp/ccs-mcp.json:/home/claude/.claude.json:ro".to_string()));
+    }
+
+    #[test]
+    fn test_build_run_args_without_claude_state() {
+        let dir = TempDir::new().unwrap();
+        let runner = test_runner(Config::default(), &dir);
+
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(!run_args.args.iter().any(|a| a.contains("-state-")));
+    }
+
+    #[test]
+    fn test_claude_state_volume() {
+        let mut config = Config::default();
+        assert_eq!(claude_state_volume(&config, "My Repo"), "ccs-state-my-repo");
+        config.docker.container_prefix = "team".to_string();
+        assert_eq!(claude_state_volume(&config, "..."), "team-state-repo");
+    }
+
+    #[test]
+    fn test_build_run_args_joins_service_network() {
+        let dir = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.services.insert(
+            "db".to_string(),
+            ServiceConfig {
+                image: "postgres:16".to_string(),
+                ..Default::default()
+            },
+        );
+        let runner = test_runner(config, &dir);
+
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        let pos = run_args.args.iter().position(|a| a == "--network").unwrap();
+        assert_eq!(run_args.args[pos + 1], "ccs-project-123-net");
+        assert!(run_args
+            .args
+            .contains(&format!("{}=ccs-project-123-net", LABEL_NETWORK)));
+        assert!(run_args.services.is_some());
+    }
+
+    #[test]
+    fn test_find_session_log() {
+        let dir = TempDir::new().unwrap();
+        for name in ["ccs-project-review", "ccs-project-123", "ccs-other-123"] {
+            std::fs::write(dir.path().join(format!("{}.log", name)), "").unwrap();
+        }
+
+        assert_eq!(
+            find_session_log(dir.path(), "review"),
+            Some(dir.path().join("ccs-project-review.log"))
+        );
+        assert_eq!(
+            find_session_log(dir.path(), "ccs-project-123"),
+            Some(dir.path().join("ccs-project-123.log"))
+        );
+        // Ambiguous or unknown names don't match
+        assert_eq!(find_session_log(dir.path(), "123"), None);
+        assert_eq!(find_session_log(dir.path(), "missing"), None);
+    }
+
+    fn session_info(name: &str, state: &str, repo: Option<&str>) -> SessionInfo {
+        SessionInfo {
+            name: name.to_string(),
+            state: state.to_string(),
+            status: String::new(),
+            created_at: String::new(),
+            repo: repo.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_session_infos() {
+        let stdout = "ccs-api-1\trunning\tUp 2 hours\t2024-01-15 10:30:00 +0000 UTC\tapi\n\
+                      ccs-web-2\texited\tExited (0) 1 hour ago\t2024-01-15 09:00:00 +0000 UTC\t<no value>\n";
+        let sessions = parse_session_infos(stdout);
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].repo.as_deref(), Some("api"));
+        assert!(sessions[0].is_running());
+        assert_eq!(sessions[1].status, "Exited (0) 1 hour ago");
+        assert_eq!(sessions[1].repo, None);
+        assert!(!sessions[1].is_running());
+    }
+
+    #[test]
+    fn test_session_filter_state() {
+        let running = session_info("ccs-api-1", "running", None);
+        let exited = session_info("ccs-api-2", "exited", None);
+
+        let filter = SessionFilter::default();
+        assert!(filter.matches(&running, "ccs") && filter.matches(&exited, "ccs"));
+
+        let filter = SessionFilter {
+            state: Some(SessionStateFilter::Running),
+            ..Default::default()
+        };
+        assert!(filter.matches(&running, "ccs"));
+        assert!(!filter.matches(&exited, "ccs"));
+
+        let filter = SessionFilter {
+            state: Some(SessionStateFilter::Stopped),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&running, "ccs"));
+        assert!(filter.matches(&exited, "ccs"));
+    }
+
+    #[test]
+    fn test_session_filter_repo() {
+        let filter = SessionFilter {
+            repo: Some("My-API".to_string()),
+            ..Default::default()
+        };
+
+        // The label wins over the container name
+        assert!(filter.matches(&session_info("ccs-x-1", "running", Some("my-api")), "ccs"));
+        assert!(!filter.matches(&session_info("ccs-my-api-1", "running", Some("web")), "ccs"));
+
+        // Older sessions fall back to the name
+        assert!(filter.matches(&session_info("ccs-my-api-123456", "running", None), "ccs"));
+        assert!(!filter.matches(&session_info("ccs-my-api2-1", "running", None), "ccs"));
+        assert!(!filter.matches(&session_info("team-my-api-1", "running", None), "ccs"));
+    }
+
+    #[test]
+    fn test_format_session_table() {
+        let mut session = session_info("ccs-api-1", "running", None);
+        session.status = "Up 2 hours".to_string();
+        session.created_at = "2024-01-15".to_string();
+        assert_eq!(
+            format_session_table(&[session], Style::default()),
+            "NAMES       STATUS       CREATED AT\nccs-api-1   Up 2 hours   2024-01-15"
+        );
+    }
+
+    #[test]
+    fn test_parse_stats_line() {
+        let docker = r#"{"BlockIO":"4.1MB / 0B","CPUPerc":"12.34%","Container":"3f2a","ID":"3f2a","MemPerc":"6.25%","MemUsage":"128MiB / 2GiB","Name":"ccs-api-1","NetIO":"1.2kB / 648B","PIDs":"17"}"#;
+        let stats = parse_stats_line(docker).unwrap();
+        assert_eq!(
+            stats,
+            ContainerStats {
+                name: "ccs-api-1".to_string(),
+                cpu_percent: Some(12.34),
+                mem_usage: "128MiB".to_string(),
+                mem_limit: "2GiB".to_string(),
+                mem_percent: Some(6.25),
+                net_io: "1.2kB / 648B".to_string(),
+                block_io: "4.1MB / 0B".to_string(),
+                pids: Some(17),
+            }
+        );
+
+        // Streaming output clears the screen before each sample
+        let streamed = format!("\x1b[2J\x1b[H{}", docker);
+        assert_eq!(parse_stats_line(&streamed), Some(stats.clone()));
+
+        let podman = r#"{"id":"3f2a","name":"ccs-api-1","cpu_percent":"--","mem_usage":"128MiB / 2GiB","mem_percent":"6.25%","net_io":"1.2kB / 648B","block_io":"4.1MB / 0B","pids":"17"}"#;
+        let stats = parse_stats_line(podman).unwrap();
+        assert_eq!(stats.name, "ccs-api-1");
+        assert_eq!(stats.cpu_percent, None);
+        assert_eq!(stats.pids, Some(17));
+
+        assert_eq!(parse_stats_line("CONTAINER ID   NAME"), None);
+        assert_eq!(
+            stats_args("ccs-api-1", false)[..2],
+            ["stats", "--no-stream"]
+        );
+        assert!(!stats_args("ccs-api-1", true).contains(&"--no-stream".to_string()));
+    }
+
+    #[test]
+    fn test_build_run_args_repo_label() {
+        let dir = TempDir::new().unwrap();
+        let runner = test_runner(Config::default(), &dir);
+
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(run_args.args.contains(&format!("{}=project", LABEL_REPO)));
+    }
+
+    #[test]
+    fn test_parse_session_log_files() {
+        let stdout = "ccs-a-1\t/logs/ccs-a-1.log\nccs-b-2\t\nccs-c-3\t<no value>\n";
+        assert_eq!(
+            parse_session_log_files(stdout),
+            vec![("ccs-a-1".to_string(), PathBuf::from("/logs/ccs-a-1.log"))]
+        );
+    }
+
+    #[test]
+    fn test_session_metadata_missing() {
+        let mut labels = HashMap::new();
+        labels.insert("other".to_string(), "label".to_string());
+        assert_eq!(SessionMetadata::from_labels(&labels), None);
+    }
+
+    #[test]
+    fn test_run_args_include_session_labels() {
+        let workspace = TempDir::new().unwrap();
+        let run_args = test_runner(Config::default(), &workspace)
+            .build_run_args(
+                &["--verbose".to_string()],
+                &RunOptions {
+                    detach: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        assert!(run_args.args.contains(&format!("{}=true", LABEL_DETACH)));
+        assert!(run_args
+            .args
+            .contains(&format!("{}=[\"--verbose\"]", LABEL_ARGS)));
+        // Nothing to record beyond the defaults
+        assert!(!run_args
+            .args
+            .iter()
+            .any(|arg| arg.starts_with(LABEL_OPTIONS)));
+
+        let run_args = test_runner(Config::default(), &workspace)
+            .build_run_args(
+                &[],
+                &RunOptions {
+                    user: Some("1000".to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let recorded = run_args
+            .args
+            .iter()
+            .find_map(|arg| arg.strip_prefix(&format!("{}=", LABEL_OPTIONS)))
+            .unwrap();
+        let recorded: SessionOptions = serde_json::from_str(recorded).unwrap();
+        assert_eq!(recorded.user.as_deref(), Some("1000"));
+    }
+
+    #[test]
+    fn test_resolve_workdir_relative_and_absolute() {
+        let workspace = TempDir::new().unwrap();
+        std::fs::create_dir_all(workspace.path().join("packages/app")).unwrap();
+
+        assert_eq!(
+            resolve_workdir("packages/app", workspace.path()).unwrap(),
+            "/workspace/packages/app"
+        );
+        assert_eq!(
+            resolve_workdir("/workspace/packages/app/", workspace.path()).unwrap(),
+            "/workspace/packages/app"
+        );
+        assert_eq!(
+            resolve_workdir("/workspace", workspace.path()).unwrap(),
+            "/workspace"
+        );
+    }
+
+    #[test]
+    fn test_resolve_workdir_rejects_escapes() {
+        let workspace = TempDir::new().unwrap();
+        assert!(resolve_workdir("/etc", workspace.path()).is_err());
+        assert!(resolve_workdir("../other", workspace.path()).is_err());
+        assert!(resolve_workdir("missing", workspace.path()).is_err());
+    }
+
+    #[test]
+    fn test_workdir_override_dry_run() {
+        let workspace = TempDir::new().unwrap();
+        std::fs::create_dir(workspace.path().join("api")).unwrap();
+        let mut config = Config::default();
+        config.docker.workdir = resolve_workdir("api", workspace.path()).unwrap();
+
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        let pos = run_args.args.iter().position(|a| a == "-w").unwrap();
+        assert_eq!(run_args.args[pos + 1], "/workspace/api");
+    }
+
+    #[test]
+    fn test_default_claude_args_dry_run() {
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.docker.default_claude_args = vec![
+            "--model".to_string(),
+            "sonnet".to_string(),
+            "--dangerously-skip-permissions".to_string(),
+        ];
+        let runner = test_runner(config, &workspace);
+
+        let cli_args = vec!["--model".to_string(), "opus".to_string()];
+        let run_args = runner
+            .build_run_args(&cli_args, &RunOptions::default())
+            .unwrap();
+        let image = run_args
+            .args
+            .iter()
+            .position(|a| a == "ccs:latest")
+            .unwrap();
+        assert_eq!(
+            run_args.args[image + 1..],
+            [
+                "--model",
+                "sonnet",
+                "--dangerously-skip-permissions",
+                "--model",
+                "opus"
+            ]
+        );
+
+        // Restart metadata keeps only the command-line args, so defaults aren't doubled
+        assert!(run_args
+            .args
+            .contains(&format!("{}=[\"--model\",\"opus\"]", LABEL_ARGS)));
+    }
+
+    #[test]
+    fn test_permission_mode_precedes_claude_args() {
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.claude.permission_mode = Some(PermissionMode::AcceptEdits);
+        config.docker.default_claude_args = vec!["--verbose".to_string()];
+        let runner = test_runner(config, &workspace);
+
+        let run_args = runner
+            .build_run_args(
+                &["--model".to_string(), "opus".to_string()],
+                &RunOptions::default(),
+            )
+            .unwrap();
+        let image = run_args
+            .args
+            .iter()
+            .position(|a| a == "ccs:latest")
+            .unwrap();
+        assert_eq!(
+            run_args.args[image + 1..],
+            [
+                "--permission-mode",
+                "acceptEdits",
+                "--verbose",
+                "--model",
+                "opus"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shell_replaces_claude_entrypoint() {
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.docker.shell = "/bin/zsh".to_string();
+        config.docker.default_claude_args = vec!["--verbose".to_string()];
+        let options = RunOptions {
+            shell: true,
+            ..Default::default()
+        };
+
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&["--model".to_string()], &options)
+            .unwrap();
+        let index = run_args.shell_index.unwrap();
+        assert_eq!(run_args.args[index - 1], "--entrypoint");
+        assert_eq!(run_args.args[index], "/bin/zsh");
+        // The image is last: no Claude args follow it
+        assert_eq!(run_args.args[index + 1..], ["ccs:latest"]);
+    }
+
+    #[test]
+    fn test_shell_with_fallback() {
+        assert_eq!(shell_with_fallback("/bin/bash", |_| true), "/bin/bash");
+        assert_eq!(shell_with_fallback("/bin/bash", |_| false), "/bin/sh");
+        // /bin/sh is the fallback anyway, so it isn't probed
+        assert_eq!(
+            shell_with_fallback("/bin/sh", |_| panic!("probed /bin/sh")),
+            "/bin/sh"
+        );
+    }
+
+    #[test]
+    fn test_cached_shell_probe() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("ccs").join(SHELL_PROBE_CACHE);
+
+        assert!(!cached_shell_probe(&path, "sha256:abc /bin/bash", || false));
+        // Cached results are reused without probing again
+        assert!(!cached_shell_probe(&path, "sha256:abc /bin/bash", || {
+            panic!("probed a cached shell")
+        }));
+        // A rebuilt image has a new ID and is probed afresh
+        assert!(cached_shell_probe(&path, "sha256:def /bin/bash", || true));
+    }
+
+    #[test]
+    fn test_stop_each_continues_past_failures() {
+        let names: Vec<String> = ["ccs-a-1", "ccs-b-2", "ccs-c-3"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut attempted = Vec::new();
+
+        let result = stop_each(&names, |name| {
+            attempted.push(name.to_string());
+            if name == "ccs-b-2" {
+                Err("no such container".to_string())
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(attempted, names);
+        assert_eq!(result.stopped, vec!["ccs-a-1", "ccs-c-3"]);
+        assert_eq!(result.errors, vec!["ccs-b-2: no such container"]);
+        assert!(result.had_errors());
+    }
+
+    #[test]
+    fn test_stop_each_empty() {
+        let result = stop_each(&[], |_| panic!("nothing to stop"));
+        assert!(result.stopped.is_empty());
+        assert!(!result.had_errors());
+    }
+
+    #[test]
+    fn test_parse_permission_mode() {
+        assert_eq!(parse_permission_mode("plan"), Ok(PermissionMode::Plan));
+        assert_eq!(
+            parse_permission_mode("bypassPermissions"),
+            Ok(PermissionMode::BypassPermissions)
+        );
+        let err = parse_permission_mode("acceptedits").unwrap_err();
+        assert!(err.contains("unknown permission mode 'acceptedits'"));
+        assert!(err.contains("acceptEdits"));
+    }
+
+    #[test]
+    fn test_extra_run_args_before_image() {
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.docker.extra_run_args = vec!["--cap-add=SYS_PTRACE".to_string()];
+        let options = RunOptions {
+            docker_args: vec!["--shm-size".to_string(), "1g".to_string()],
+            ..Default::default()
+        };
+
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&["--verbose".to_string()], &options)
+            .unwrap();
+        let image = run_args
+            .args
+            .iter()
+            .position(|a| a == "ccs:latest")
+            .unwrap();
+        assert_eq!(
+            run_args.args[image - 3..image],
+            ["--cap-add=SYS_PTRACE", "--shm-size", "1g"]
+        );
+        assert_eq!(run_args.args.last().unwrap(), "--verbose");
+    }
+
+    #[test]
+    fn test_check_credentials() {
+        let sources = [
+            CredentialSource::EnvApiKey,
+            CredentialSource::ClaudeDir,
+            #[cfg(target_os = "macos")]
+            CredentialSource::MacOsKeychain,
+            CredentialSource::ConfigDir,
+        ];
+
+        for source in &sources {
+            assert!(check_credentials(source, false).is_ok());
+            assert!(check_credentials(source, true).is_ok());
+        }
+        assert!(check_credentials(&CredentialSource::None, false).is_ok());
+        assert!(matches!(
+            check_credentials(&CredentialSource::None, true),
+            Err(DockerError::NoCredentials)
+        ));
+    }
+
+    #[test]
+    fn test_use_init_dry_run() {
+        let workspace = TempDir::new().unwrap();
+
+        let mut config = Config::default();
+        let run_args = test_runner(config.clone(), &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        let init = run_args.args.iter().position(|a| a == "--init").unwrap();
+        let image = run_args
+            .args
+            .iter()
+            .position(|a| a == "ccs:latest")
+            .unwrap();
+        assert!(init < image);
+
+        config.docker.use_init = false;
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        assert!(!run_args.args.contains(&"--init".to_string()));
+    }
+
+    #[test]
+    fn test_mirror_host_path_dry_run() {
+        let workspace = TempDir::new().unwrap();
+        std::fs::create_dir(workspace.path().join("api")).unwrap();
+        let host = workspace.path().display().to_string();
+
+        let mut config = Config::default();
+        let run_args = test_runner(config.clone(), &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        assert!(run_args.args.contains(&format!("{}:/workspace", host)));
+
+        config.docker.mirror_host_path = true;
+        config.docker.workdir = resolve_workdir("api", workspace.path()).unwrap();
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        assert!(run_args.args.contains(&format!("{}:{}", host, host)));
+        let pos = run_args.args.iter().position(|a| a == "-w").unwrap();
+        assert_eq!(
+            run_args.args[pos + 1],
+            workspace.path().join("api").display().to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_mount() {
+        assert_eq!(
+            parse_mount("/data:/mnt/data"),
+            Ok(Mount {
+                host: "/data".to_string(),
+                container: "/mnt/data".to_string(),
+                read_only: false,
+            })
+        );
+        assert_eq!(
+            parse_mount("~/.aws:/home/claude/.aws:ro"),
+            Ok(Mount {
+                host: "~/.aws".to_string(),
+                container: "/home/claude/.aws".to_string(),
+                read_only: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_mount_windows_drive() {
+        assert_eq!(
+            parse_mount("C:\\Users\\me\\data:/data:ro"),
+            Ok(Mount {
+                host: "C:\\Users\\me\\data".to_string(),
+                container: "/data".to_string(),
+                read_only: true,
+            })
+        );
+        assert_eq!(
+            parse_mount("D:/projects:/projects").map(|m| m.host),
+            Ok("D:/projects".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_mount_invalid() {
+        assert!(parse_mount("/only-host").is_err());
+        assert!(parse_mount("/a:/b:rx").is_err());
+        assert!(parse_mount("/a:/b:ro:extra").is_err());
+        assert!(parse_mount(":/b").is_err());
+    }
+
+    #[test]
+    fn test_cli_mounts_dry_run() {
+        let workspace = TempDir::new().unwrap();
+        let options = RunOptions {
+            mounts: vec![parse_mount("/tmp:/host-tmp:ro").unwrap()],
+            ..Default::default()
+        };
+
+        let run_args = test_runner(Config::default(), &workspace)
+            .build_run_args(&[], &options)
+            .unwrap();
+        assert!(run_args.args.contains(&"/tmp:/host-tmp:ro".to_string()));
+    }
+
+    #[test]
+    fn test_also_workspaces_dry_run() {
+        let workspace = TempDir::new().unwrap();
+        let extra = TempDir::new().unwrap();
+        let api = extra.path().join("api");
+        let web = extra.path().join("web");
+        std::fs::create_dir_all(&api).unwrap();
+        std::fs::create_dir_all(&web).unwrap();
+        let options = RunOptions {
+            also: vec![api.clone(), web.clone()],
+            ..Default::default()
+        };
+
+        let run_args = test_runner(Config::default(), &workspace)
+            .build_run_args(&[], &options)
+            .unwrap();
+        let volumes: Vec<&String> = run_args
+            .args
+            .windows(2)
+            .filter(|w| w[0] == "-v")
+            .map(|w| &w[1])
+            .collect();
+        assert!(volumes.contains(&&format!("{}:/workspace", workspace.path().display())));
+        assert!(volumes.contains(&&format!("{}:/workspace-api", api.display())));
+        assert!(volumes.contains(&&format!("{}:/workspace-web", web.display())));
+    }
+
+    #[test]
+    fn test_user_dry_run() {
+        let workspace = TempDir::new().unwrap();
+
+        let run_args = test_runner(Config::default(), &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        assert!(!run_args.args.contains(&"--user".to_string()));
+
+        let mut config = Config::default();
+        config.docker.match_host_uid = true;
+        let run_args = test_runner(config.clone(), &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        if cfg!(unix) {
+            let pos = run_args.args.iter().position(|a| a == "--user").unwrap();
+            let (uid, gid) = run_args.args[pos + 1].split_once(':').unwrap();
+            assert!(uid.parse::<u32>().is_ok() && gid.parse::<u32>().is_ok());
+            assert!(run_args.args.contains(&"HOME=/home/claude".to_string()));
+        }
+
+        // An explicit --user wins over match_host_uid
+        let options = RunOptions {
+            user: Some("1234:1234".to_string()),
+            ..Default::default()
+        };
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &options)
+            .unwrap();
+        let pos = run_args.args.iter().position(|a| a == "--user").unwrap();
+        assert_eq!(run_args.args[pos + 1], "1234:1234");
+    }
+
+    #[test]
+    fn test_missing_project_claude_md() {
+        let main = TempDir::new().unwrap();
+        let worktree = TempDir::new().unwrap();
+        let git_dir = main.path().join(".git");
+
+        assert_eq!(
+            missing_project_claude_md(worktree.path(), Some(&git_dir)),
+            None
+        );
+
+        std::fs::write(main.path().join("CLAUDE.md"), "rules").unwrap();
+        assert_eq!(
+            missing_project_claude_md(worktree.path(), Some(&git_dir)),
+            Some(main.path().join("CLAUDE.md"))
+        );
+        assert_eq!(missing_project_claude_md(worktree.path(), None), None);
+
+        // The worktree's own CLAUDE.md is already in the workspace mount
+        std::fs::write(worktree.path().join("CLAUDE.md"), "rules").unwrap();
+        assert_eq!(
+            missing_project_claude_md(worktree.path(), Some(&git_dir)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_claude_md_mounted_for_worktree() {
+        let main = TempDir::new().unwrap();
+        let workspace = TempDir::new().unwrap();
+        std::fs::write(main.path().join("CLAUDE.md"), "rules").unwrap();
+        let expected = format!(
+            "{}:/home/claude/.claude/CLAUDE.md:ro",
+            main.path().join("CLAUDE.md").display()
+        );
+
+        let mut runner = test_runner(Config::default(), &workspace);
+        runner.git_context.shared_git_dir = Some(main.path().join(".git"));
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(!run_args.args.contains(&expected));
+
+        runner.config.docker.mount_claude_settings = true;
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(run_args.args.contains(&expected));
+    }
+
+    #[test]
+    fn test_parse_user_spec() {
+        assert_eq!(parse_user_spec("1000:1000"), Ok("1000:1000".to_string()));
+        assert!(parse_user_spec("claude").is_ok());
+        assert!(parse_user_spec("claude:staff").is_ok());
+
+        assert!(parse_user_spec("").is_err());
+        assert!(parse_user_spec(":1000").is_err());
+        assert!(parse_user_spec("1000:").is_err());
+        assert!(parse_user_spec("a b").is_err());
+    }
+
+    #[test]
+    fn test_parse_daemon_host() {
+        assert_eq!(parse_daemon_host(None), DaemonHost::Default);
+        assert_eq!(parse_daemon_host(Some("  ")), DaemonHost::Default);
+        assert_eq!(
+            parse_daemon_host(Some("unix:///var/run/docker.sock")),
+            DaemonHost::Local("unix:///var/run/docker.sock".to_string())
+        );
+        assert!(!parse_daemon_host(Some("npipe:////./pipe/docker_engine")).is_remote());
+        assert!(!parse_daemon_host(Some("tcp://localhost:2375")).is_remote());
+        assert!(!parse_daemon_host(Some("tcp://127.0.0.1:2376")).is_remote());
+        assert!(!parse_daemon_host(Some("tcp://[::1]:2375")).is_remote());
+
+        assert_eq!(
+            parse_daemon_host(Some("ssh://user@build-box")),
+            DaemonHost::Remote("ssh://user@build-box".to_string())
+        );
+        assert!(parse_daemon_host(Some("ssh://localhost")).is_remote());
+        assert!(parse_daemon_host(Some("tcp://10.0.0.5:2376")).is_remote());
+        assert!(parse_daemon_host(Some("tcp://docker.example.com:2376/")).is_remote());
+    }
+
+    #[test]
+    fn test_parse_rootless_output() {
+        assert!(parse_rootless_output(ContainerRuntime::Podman, "true\n"));
+        assert!(!parse_rootless_output(ContainerRuntime::Podman, "false\n"));
+        assert!(parse_rootless_output(
+            ContainerRuntime::Docker,
+            "[name=seccomp,profile=builtin name=rootless name=cgroupns]"
+        ));
+        assert!(!parse_rootless_output(
+            ContainerRuntime::Docker,
+            "[name=seccomp,profile=builtin]"
+        ));
+    }
+
+    #[test]
+    fn test_connection_args() {
+        assert!(connection_args(ContainerRuntime::Podman, None).is_empty());
+        assert_eq!(
+            connection_args(ContainerRuntime::Podman, Some("builder")),
+            vec!["--connection", "builder"]
+        );
+        // Docker has no --connection flag
+        assert!(connection_args(ContainerRuntime::Docker, Some("builder")).is_empty());
+
+        let mut config = Config::default();
+        config.docker.connection = Some("  ".to_string());
+        assert_eq!(configured_connection(&config), None);
+        config.docker.connection = Some("builder".to_string());
+        assert_eq!(configured_connection(&config), Some("builder"));
+    }
+
+    #[test]
+    fn test_active_podman_connection() {
+        let connections = parse_podman_connections(
+            r#"[
+                {"Name": "podman-machine-default", "URI": "ssh://core@127.0.0.1:53025/run/user/501/podman/podman.sock", "Identity": "", "IsMachine": true, "Default": true},
+                {"Name": "builder", "URI": "ssh://me@build.example.com/run/podman/podman.sock", "Default": false}
+            ]"#,
+        );
+        assert_eq!(connections.len(), 2);
+
+        let default = active_podman_connection(&connections, None).unwrap();
+        assert_eq!(default.name, "podman-machine-default");
+        let named = active_podman_connection(&connections, Some("builder")).unwrap();
+        assert_eq!(
+            named.to_string(),
+            "podman connection 'builder' (ssh://me@build.example.com/run/podman/podman.sock)"
+        );
+        assert_eq!(
+            active_podman_connection(&connections, Some("missing")),
+            None
+        );
+        assert!(parse_podman_connections("").is_empty());
+    }
+
+    #[test]
+    fn test_rootless_podman_userns() {
+        let workspace = TempDir::new().unwrap();
+        let mut runner = test_runner(Config::default(), &workspace);
+        runner.runtime = ContainerRuntime::Podman;
+        runner.rootless = true;
+
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(run_args.args.contains(&"--userns=keep-id".to_string()));
+
+        runner.runtime = ContainerRuntime::Docker;
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(!run_args.args.iter().any(|a| a.starts_with("--userns")));
+    }
+
+    #[test]
+    fn test_git_identity_env() {
+        let workspace = TempDir::new().unwrap();
+        let mut runner = test_runner(Config::default(), &workspace);
+        runner.git_identity = GitIdentity {
+            name: Some("Jane Doe".to_string()),
+            email: Some("jane@example.com".to_string()),
+        };
+
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(run_args
+            .args
+            .contains(&"GIT_AUTHOR_NAME=Jane Doe".to_string()));
+        assert!(run_args
+            .args
+            .contains(&"GIT_COMMITTER_EMAIL=jane@example.com".to_string()));
+    }
+
+    #[test]
+    fn test_forwarded_git_credentials() {
+        let workspace = TempDir::new().unwrap();
+        let mut runner = test_runner(Config::default(), &workspace);
+        runner.git_credentials = Some(GitCredentials::Token("ghp_xyz".to_string()));
+
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(run_args.args.contains(&"CCS_GIT_TOKEN=ghp_xyz".to_string()));
+        // The token is redacted in --dry-run output
+        assert_eq!(
+            redact_arg("CCS_GIT_TOKEN=ghp_xyz", &run_args.secret_env_keys),
+            "CCS_GIT_TOKEN=[...]"
+        );
+
+        runner.git_credentials = Some(GitCredentials::StoreFile(PathBuf::from(
+            "/home/jane/.git-credentials",
+        )));
+        let run_args = runner.build_run_args(&[], &RunOptions::default()).unwrap();
+        assert!(run_args
+            .args
+            .contains(&"/home/jane/.git-credentials:/home/claude/.git-credentials:ro".to_string()));
+        assert!(run_args
+            .args
+            .contains(&"GIT_CONFIG_VALUE_0=store".to_string()));
+    }
+
+    #[test]
+    fn test_ssh_agent_forwarding() {
+        std::env::set_var("SSH_AUTH_SOCK", "/tmp/ssh-test/agent.sock");
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.docker.forward_ssh_agent = true;
+
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        let (host_sock, container_sock) = if cfg!(target_os = "macos") {
+            (DOCKER_DESKTOP_SSH_AUTH_SOCK, DOCKER_DESKTOP_SSH_AUTH_SOCK)
+        } else {
+            ("/tmp/ssh-test/agent.sock", CONTAINER_SSH_AUTH_SOCK)
+        };
+        assert!(run_args
+            .args
+            .contains(&format!("{}:{}", host_sock, container_sock)));
+        assert!(run_args
+            .args
+            .contains(&format!("SSH_AUTH_SOCK={}", container_sock)));
+        std::env::remove_var("SSH_AUTH_SOCK");
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_ssh_agent_socket_unset() {
+        assert_eq!(ssh_agent_socket(ContainerRuntime::Podman, None), None);
+        assert_eq!(ssh_agent_socket(ContainerRuntime::Docker, Some("")), None);
+    }
+
+    #[test]
+    fn test_timezone_fallback_chain() {
+        let link = Path::new("/usr/share/zoneinfo/America/New_York");
+
+        assert_eq!(
+            timezone_from_sources(Some(":Asia/Tokyo"), Some("Europe/Berlin\n"), Some(link)),
+            Some("Asia/Tokyo".to_string())
+        );
+        assert_eq!(
+            timezone_from_sources(Some(""), Some("Europe/Berlin\n"), Some(link)),
+            Some("Europe/Berlin".to_string())
+        );
+        assert_eq!(
+            timezone_from_sources(None, None, Some(link)),
+            Some("America/New_York".to_string())
+        );
+        assert_eq!(
+            timezone_from_sources(None, Some("  \n"), Some(Path::new("/etc/localtime.bak"))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_build_step() {
+        assert_eq!(
+            parse_build_step("Step 2/8 : RUN apt-get update"),
+            Some("[2/8] RUN apt-get update".to_string())
+        );
+        assert_eq!(
+            parse_build_step("STEP 3/8: COPY . /app"),
+            Some("[3/8] COPY . /app".to_string())
+        );
+        assert_eq!(
+            parse_build_step("#6 [2/5] RUN npm install -g @anthropic-ai/claude-code"),
+            Some("[2/5] RUN npm install -g @anthropic-ai/claude-code".to_string())
+        );
+        assert_eq!(
+            parse_build_step("#7 [builder 3/4] RUN cargo build"),
+            Some("[builder 3/4] RUN cargo build".to_string())
+        );
+
+        assert_eq!(
+            parse_build_step("#1 [internal] load build definition"),
+            None
+        );
+        assert_eq!(parse_build_step("#6 DONE 12.3s"), None);
+        assert_eq!(parse_build_step("#6 0.512 Reading package lists..."), None);
+        assert_eq!(parse_build_step("Step back: nothing here"), None);
+        assert_eq!(
+            parse_build_step("Get:1 http://deb.debian.org bookworm"),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_quiet_build_status() {
+        let mut ok = Command::new("sh");
+        ok.args(["-c", "echo 'Step 1/1 : FROM scratch'; echo noise >&2"]);
+        assert!(run_quiet_build(ok).unwrap().success());
+
+        let mut failing = Command::new("sh");
+        failing.args(["-c", "echo 'STEP 1/2: RUN false' >&2; exit 1"]);
+        assert!(!run_quiet_build(failing).unwrap().success());
+    }
+
+    #[test]
+    fn test_build_command_args() {
+        std::env::set_var("TEST_SECRET_CCS_BUILD_ARG", "build_token");
+        let mut config = Config::default();
+        config
+            .docker
+            .build_args
+            .insert("NODE_VERSION".to_string(), "20".to_string());
+        config.docker.build_args.insert(
+            "NPM_TOKEN".to_string(),
+            "env://TEST_SECRET_CCS_BUILD_ARG".to_string(),
+        );
+        let options = BuildOptions {
+            no_cache: true,
+            build_args: vec![("NODE_VERSION".to_string(), "22".to_string())],
+            ..Default::default()
+        };
+
+        let cmd = build_command(
+            ContainerRuntime::Docker,
+            &config,
+            Path::new("docker/Dockerfile"),
+            Path::new("docker"),
+            &options,
+        )
+        .unwrap();
+        let args: Vec<String> = cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect();
+
+        assert_eq!(cmd.get_program(), "docker");
+        assert_eq!(
+            args,
+            vec![
+                "build",
+                "-t",
+                "ccs:latest",
+                "-f",
+                "docker/Dockerfile",
+                "--no-cache",
+                "--build-arg",
+                "NODE_VERSION=22",
+                "--build-arg",
+                "NPM_TOKEN=build_token",
+                "docker",
+            ]
+        );
+        std::env::remove_var("TEST_SECRET_CCS_BUILD_ARG");
+    }
+
+    #[test]
+    fn test_build_command_target() {
+        let target_of = |config: &Config, options: &BuildOptions| {
+            let cmd = build_command(
+                ContainerRuntime::Docker,
+                config,
+                Path::new("docker/Dockerfile"),
+                Path::new("docker"),
+                options,
+            )?;
+            let args: Vec<String> = cmd
+                .get_args()
+                .map(|a| a.to_string_lossy().to_string())
+                .collect();
+            Ok::<_, DockerError>(
+                args.windows(2)
+                    .find(|pair| pair[0] == "--target")
+                    .map(|pair| pair[1].clone()),
+            )
+        };
+
+        let mut config = Config::default();
+        let options = BuildOptions::default();
+        assert_eq!(target_of(&config, &options).unwrap(), None);
+
+        config.docker.build_target = Some("dev".to_string());
+        assert_eq!(
+            target_of(&config, &options).unwrap().as_deref(),
+            Some("dev")
+        );
+
+        // --target overrides the configured stage
+        let options = BuildOptions {
+            target: Some("release".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            target_of(&config, &options).unwrap().as_deref(),
+            Some("release")
+        );
+
+        config.docker.build_target = Some("  ".to_string());
+        assert!(matches!(
+            target_of(&config, &BuildOptions::default()),
+            Err(DockerError::InvalidBuildTarget(_))
+        ));
+        assert!(parse_build_target("").is_err());
+    }
+
+    fn build_arg_values(config: &Config, options: &BuildOptions) -> Vec<String> {
+        let cmd = build_command(
+            ContainerRuntime::Docker,
+            config,
+            Path::new("docker/Dockerfile"),
+            Path::new("docker"),
+            options,
+        )
+        .unwrap();
+        let args: Vec<String> = cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect();
+        args.windows(2)
+            .filter(|pair| pair[0] == "--build-arg")
+            .map(|pair| pair[1].clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_build_context_dir() {
+        let mut config = Config::default();
+        assert_eq!(
+            build_context_dir(&config, Path::new("docker/Dockerfile")),
+            PathBuf::from("docker")
+        );
+        assert_eq!(
+            build_context_dir(&config, Path::new("Dockerfile")),
+            PathBuf::from(".")
+        );
+
+        // build_context is independent of the Dockerfile location
+        config.docker.build_context = Some(PathBuf::from("/srv/context"));
+        assert_eq!(
+            build_context_dir(&config, Path::new("Dockerfile")),
+            PathBuf::from("/srv/context")
+        );
+    }
+
+    #[test]
+    fn test_large_context_detection() {
+        let context = TempDir::new().unwrap();
+        std::fs::create_dir(context.path().join("assets")).unwrap();
+        std::fs::write(context.path().join("Dockerfile"), "FROM scratch\n").unwrap();
+        std::fs::write(
+            context.path().join("assets").join("blob.bin"),
+            vec![0u8; 2 * 1024 * 1024],
+        )
+        .unwrap();
+
+        let size = measure_context(context.path(), u64::MAX);
+        assert_eq!(size.files, 2);
+        assert!(size.bytes > 2 * 1024 * 1024);
+
+        assert!(is_large_unignored_context(context.path(), 1));
+        assert!(!is_large_unignored_context(context.path(), 10));
+        assert!(!is_large_unignored_context(context.path(), 0));
+
+        std::fs::write(context.path().join(".dockerignore"), "assets\n").unwrap();
+        assert!(!is_large_unignored_context(context.path(), 1));
+    }
+
+    #[test]
+    fn test_build_command_claude_version() {
+        let mut config = Config::default();
+        assert!(build_arg_values(&config, &BuildOptions::default()).is_empty());
+
+        config.docker.claude_version = Some("1.0.30".to_string());
+        assert_eq!(
+            build_arg_values(&config, &BuildOptions::default()),
+            vec!["CLAUDE_VERSION=1.0.30"]
+        );
+
+        // An explicit --build-arg wins over claude_version
+        let options = BuildOptions {
+            build_args: vec![("CLAUDE_VERSION".to_string(), "latest".to_string())],
+            ..Default::default()
+        };
+        assert_eq!(
+            build_arg_values(&config, &options),
+            vec!["CLAUDE_VERSION=latest"]
+        );
+    }
+
+    #[test]
+    fn test_build_command_update_claude_busts_cache() {
+        let options = BuildOptions {
+            update_claude: true,
+            ..Default::default()
+        };
+        let values = build_arg_values(&Config::default(), &options);
+        assert_eq!(values.len(), 1);
+        let stamp = values[0].strip_prefix("CLAUDE_CACHE_BUST=").unwrap();
+        assert!(stamp.parse::<u64>().is_ok());
+    }
+
+    #[test]
+    fn test_parse_build_arg() {
+        assert_eq!(
+            parse_build_arg("KEY=a=b"),
+            Ok(("KEY".to_string(), "a=b".to_string()))
+        );
+        assert_eq!(
+            parse_build_arg("EMPTY="),
+            Ok(("EMPTY".to_string(), String::new()))
+        );
+        assert!(parse_build_arg("NOVALUE").is_err());
+        assert!(parse_build_arg("=value").is_err());
+    }
+
+    #[test]
+    fn test_logs_args_since() {
+        assert_eq!(
+            logs_args("ccs-project-1", &LogOptions::default()),
+            vec!["logs", "ccs-project-1"]
+        );
+
+        let options = LogOptions {
+            since: Some(parse_since(" 10m ").unwrap()),
+            follow: true,
+        };
+        assert_eq!(
+            logs_args("ccs-project-1", &options),
+            vec!["logs", "-f", "--since", "10m", "ccs-project-1"]
+        );
+
+        assert!(parse_since("").is_err());
+        assert!(parse_since("   ").is_err());
+        assert_eq!(
+            parse_since("2024-01-15T10:30:00Z"),
+            Ok("2024-01-15T10:30:00Z".to_string())
+        );
+    }
+
+    #[test]
+    fn test_generate_container_name_suffix() {
+        assert_eq!(
+            generate_container_name("ccs", "project", Some("review")),
+            "ccs-project-review"
+        );
+        assert!(generate_container_name("ccs", "project", None).starts_with("ccs-project-"));
+        assert_eq!(
+            generate_container_name("team", "My.Project", Some("review")),
+            "team-my-project-review"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_container_name_component() {
+        assert_eq!(sanitize_container_name_component("my.repo"), "my-repo");
+        assert_eq!(sanitize_container_name_component("MyRepo"), "myrepo");
+        assert_eq!(
+            sanitize_container_name_component("snake_case"),
+            "snake_case"
+        );
+        assert_eq!(sanitize_container_name_component("a..b  c"), "a-b-c");
+        assert_eq!(sanitize_container_name_component(".hidden."), "hidden");
+        assert_eq!(
+            sanitize_container_name_component("café-Ünïcode"),
+            "caf-n-code"
+        );
+        assert_eq!(sanitize_container_name_component("日本語"), "");
+        assert_eq!(
+            generate_container_name("ccs", "日本語", Some("x")),
+            "ccs-repo-x"
+        );
     }
-}
 
-/// Generate a unique container name with timestamp
-fn generate_container_name(repo_name: &str) -> String {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
+    #[test]
+    fn test_container_prefix() {
+        let mut config = Config::default();
+        assert_eq!(container_prefix(&config), "ccs");
+        config.docker.container_prefix = "Team.Sandbox".to_string();
+        assert_eq!(container_prefix(&config), "team-sandbox");
+        config.docker.container_prefix = "  ".to_string();
+        assert_eq!(container_prefix(&config), "ccs");
+    }
 
-    // Use last 6 digits for readability
-    let short_ts = timestamp % 1_000_000;
-    format!("ccs-{}-{}", repo_name, short_ts)
-}
+    #[test]
+    fn test_parse_session_name() {
+        assert_eq!(parse_session_name("Review-2"), Ok("review-2".to_string()));
+        assert_eq!(parse_session_name("a.b_c"), Ok("a.b_c".to_string()));
 
-/// Status information about the container runtime environment
-#[derive(Debug)]
-pub struct RuntimeStatus {
-    pub runtime: Option<ContainerRuntime>,
-    pub runtime_version: Option<String>,
-    pub image_exists: bool,
-    pub running_containers: Vec<String>,
-    pub config_path: Option<PathBuf>,
-    pub config_exists: bool,
-    pub mcp_config_path: Option<PathBuf>,
-    pub mcp_config_exists: bool,
-    pub credentials: ClaudeCredentials,
-}
+        assert!(parse_session_name("").is_err());
+        assert!(parse_session_name("has space").is_err());
+        assert!(parse_session_name("feat/x").is_err());
+        assert!(parse_session_name("-leading").is_err());
+        assert!(parse_session_name(&"x".repeat(MAX_SESSION_NAME_LEN)).is_ok());
+        assert!(parse_session_name(&"x".repeat(MAX_SESSION_NAME_LEN + 1)).is_err());
+    }
 
-impl RuntimeStatus {
-    /// Check the status of the container runtime environment
-    pub fn check(config: &Config) -> Self {
-        let runtime = ContainerRuntime::detect().ok();
-        let runtime_version = runtime.and_then(get_runtime_version);
-        let image_exists = runtime
-            .map(|r| check_image_exists(r, &config.docker.image))
-            .unwrap_or(false);
-        let running_containers = runtime.map(list_ccs_containers).unwrap_or_default();
+    #[test]
+    fn test_multiplex_lines_prefixes_each_stream() {
+        let sources: Vec<(String, Box<dyn std::io::Read + Send>)> = vec![
+            (
+                "ccs-api-1".to_string(),
+                Box::new(std::io::Cursor::new("first\nsecond\n")),
+            ),
+            (
+                "ccs-web-2".to_string(),
+                Box::new(std::io::Cursor::new("only")),
+            ),
+        ];
 
-        let config_path = Config::config_path().ok();
-        let config_exists = config_path.as_ref().map(|p| p.exists()).unwrap_or(false);
+        let mut out = Vec::new();
+        multiplex_lines(sources, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
 
-        let mcp_config_path = Config::mcp_servers_path().ok();
-        let mcp_config_exists = mcp_config_path
-            .as_ref()
-            .map(|p| p.exists())
-            .unwrap_or(false);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.contains(&"[ccs-web-2] only"));
+        let first = lines.iter().position(|l| *l == "[ccs-api-1] first");
+        let second = lines.iter().position(|l| *l == "[ccs-api-1] second");
+        assert!(first.unwrap() < second.unwrap());
+    }
 
-        let credentials = auth::discover_credentials();
+    #[test]
+    fn test_passthrough_for_terminals() {
+        let interactive = Passthrough::for_terminals(true, true);
+        assert!(interactive.stdin && !interactive.strip_ansi);
+        assert_eq!(
+            attach_args("ccs-project-1", interactive),
+            vec!["attach", "ccs-project-1"]
+        );
 
-        RuntimeStatus {
-            runtime,
-            runtime_version,
-            image_exists,
-            running_containers,
-            config_path,
-            config_exists,
-            mcp_config_path,
-            mcp_config_exists,
-            credentials,
-        }
+        // `ccs --logs foo | grep error`
+        assert!(Passthrough::for_terminals(true, false).strip_ansi);
+
+        let scripted = Passthrough::for_terminals(false, false);
+        assert!(!scripted.stdin && scripted.strip_ansi);
+        assert_eq!(
+            attach_args("ccs-project-1", scripted),
+            vec!["attach", "--no-stdin", "ccs-project-1"]
+        );
     }
 
-    /// Print status in a human-readable format
-    pub fn print(&self, config: &Config) {
-        println!("=== CCS Status ===\n");
+    #[test]
+    fn test_ansi_stripper() {
+        let mut stripper = AnsiStripper::default();
+        let mut out = Vec::new();
+        stripper.strip(b"\x1b[1;31merror\x1b[0m: x\x1b]0;title\x07\n", &mut out);
+        assert_eq!(out, b"error: x\n");
 
-        // Runtime
-        match &self.runtime {
-            Some(r) => {
-                let version = self.runtime_version.as_deref().unwrap_or("unknown");
-                println!("Container runtime: {} ({})", r.name(), version);
-            }
-            None => {
-                println!("Container runtime: NOT FOUND");
-                println!("  Install Docker or Podman to use ccs");
-            }
+        // Sequences split across reads are still removed
+        out.clear();
+        for chunk in [
+            &b"a\x1b"[..],
+            b"[2",
+            b"Kb\x1b]8;;",
+            b"url\x1b",
+            b"\\c\x1b(B",
+        ] {
+            stripper.strip(chunk, &mut out);
         }
+        assert_eq!(out, b"abc");
+    }
 
-        // Image
-        println!(
-            "Image '{}': {}",
-            config.docker.image,
-            if self.image_exists {
-                "available"
-            } else {
-                "NOT FOUND (run: ccs --build)"
-            }
+    #[test]
+    fn test_replay_args() {
+        assert_eq!(
+            replay_args("ccs-project-1", 50),
+            vec!["logs", "--tail", "50", "ccs-project-1"]
         );
+    }
 
-        // Running containers
-        if self.running_containers.is_empty() {
-            println!("Running ccs containers: none");
-        } else {
-            println!("Running ccs containers:");
-            for name in &self.running_containers {
-                println!("  - {}", name);
-            }
-        }
-
-        println!();
+    #[test]
+    fn test_parse_memory_limit() {
+        assert_eq!(parse_memory_limit("512m"), Ok("512m".to_string()));
+        assert_eq!(parse_memory_limit("2G"), Ok("2g".to_string()));
+        assert_eq!(parse_memory_limit("1048576"), Ok("1048576".to_string()));
+        assert_eq!(parse_memory_limit("64k"), Ok("64k".to_string()));
 
-        // Credentials
-        match self.credentials.source {
-            CredentialSource::None => {
-                println!("Claude credentials: NOT FOUND");
-                println!("  Run 'claude login' on host, or set ANTHROPIC_API_KEY");
-            }
-            ref source => {
-                println!(
-                    "Claude credentials: {} ({})",
-                    source,
-                    if self.credentials.api_key.is_some() {
-                        "API key"
-                    } else {
-                        "OAuth token"
-                    }
-                );
-            }
-        }
+        assert!(parse_memory_limit("").is_err());
+        assert!(parse_memory_limit("g").is_err());
+        assert!(parse_memory_limit("0m").is_err());
+        assert!(parse_memory_limit("1.5g").is_err());
+        assert!(parse_memory_limit("2gb").is_err());
+        assert!(parse_memory_limit("4t").is_err());
+        assert!(parse_memory_limit("-1m").is_err());
+    }
 
-        println!();
+    #[test]
+    fn test_parse_cpus() {
+        assert_eq!(parse_cpus("2"), Ok(2.0));
+        assert_eq!(parse_cpus("0.5"), Ok(0.5));
 
-        // Config files
-        if let Some(ref path) = self.config_path {
-            println!(
-                "Config: {} ({})",
-                path.display(),
-                if self.config_exists {
-                    "exists"
-                } else {
-                    "not created"
-                }
-            );
-        }
+        assert!(parse_cpus("0").is_err());
+        assert!(parse_cpus("-1").is_err());
+        assert!(parse_cpus("two").is_err());
+        assert!(parse_cpus("inf").is_err());
+    }
 
-        if let Some(ref path) = self.mcp_config_path {
-            println!(
-                "MCP config: {} ({})",
-                path.display(),
-                if self.mcp_config_exists {
-                    "exists"
-                } else {
-                    "not created"
-                }
-            );
-        }
+    #[test]
+    fn test_parse_readiness() {
+        assert_eq!(parse_readiness("true|\n"), Readiness::Ready);
+        assert_eq!(parse_readiness("true|healthy"), Readiness::Ready);
+        assert_eq!(parse_readiness("true|starting"), Readiness::Pending);
+        assert!(matches!(
+            parse_readiness("true|unhealthy"),
+            Readiness::Failed(_)
+        ));
+        assert!(matches!(parse_readiness("false|"), Readiness::Failed(_)));
+    }
 
-        // Resource limits
-        println!();
-        println!("Resource limits:");
-        match &config.docker.memory_limit {
-            Some(mem) => println!("  Memory: {}", mem),
-            None => println!("  Memory: unlimited"),
-        }
-        match config.docker.cpu_limit {
-            Some(cpu) => println!("  CPU: {} cores", cpu),
-            None => println!("  CPU: unlimited"),
-        }
+    #[test]
+    fn test_wait_until_ready_polls_until_healthy() {
+        let mut outputs = vec![None, Some("true|starting"), Some("true|healthy")].into_iter();
+        let mut calls = 0;
+        let result = wait_until_ready(
+            || {
+                calls += 1;
+                outputs.next().flatten().map(str::to_string)
+            },
+            Duration::from_secs(5),
+            Duration::ZERO,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(calls, 3);
     }
-}
 
-fn get_runtime_version(runtime: ContainerRuntime) -> Option<String> {
-    let output = Command::new(runtime.command())
-        .arg("--version")
-        .output()
-        .ok()?;
+    #[test]
+    fn test_wait_until_ready_fails_and_times_out() {
+        let exited = wait_until_ready(
+            || Some("false|".to_string()),
+            Duration::from_secs(5),
+            Duration::ZERO,
+        );
+        assert_eq!(exited, Err("container is not running".to_string()));
 
-    if output.status.success() {
-        let version = String::from_utf8_lossy(&output.stdout);
-        // Extract just the version number
-        Some(version.trim().to_string())
-    } else {
-        None
+        let timed_out = wait_until_ready(
+            || Some("true|starting".to_string()),
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        assert!(timed_out.unwrap_err().starts_with("timed out"));
     }
-}
 
-fn check_image_exists(runtime: ContainerRuntime, image: &str) -> bool {
-    let output = Command::new(runtime.command())
-        .args(["image", "inspect", image])
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status();
+    #[test]
+    fn test_watch_until_stopped() {
+        // Stops once the container is no longer running
+        let mut outputs = vec!["true\n", "true\n", "false\n"].into_iter();
+        let mut polls = 0;
+        let stopped = watch_until_stopped(
+            || {
+                polls += 1;
+                outputs.next().map(str::to_string)
+            },
+            || false,
+            || 0,
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        assert!(stopped);
+        assert_eq!(polls, 3);
 
-    output.map(|s| s.success()).unwrap_or(false)
-}
+        // A container that can't be inspected any more (removed) counts as stopped
+        let mut outputs = vec![Some("true".to_string()), None].into_iter();
+        assert!(watch_until_stopped(
+            || outputs.next().flatten(),
+            || false,
+            || 0,
+            Duration::ZERO,
+            Duration::ZERO
+        ));
 
-fn list_ccs_containers(runtime: ContainerRuntime) -> Vec<String> {
-    let output = Command::new(runtime.command())
-        .args(["ps", "--filter", "name=ccs-", "--format", "{{.Names}}"])
-        .output();
+        // An already stopped container is left to the follower while it
+        // keeps printing its log
+        let mut checks = 0;
+        let mut relayed = 0;
+        let stopped = watch_until_stopped(
+            || Some("false".to_string()),
+            || {
+                checks += 1;
+                checks > 5
+            },
+            || {
+                relayed += 1;
+                relayed
+            },
+            Duration::ZERO,
+            Duration::from_secs(60),
+        );
+        assert!(!stopped);
 
-    match output {
-        Ok(Output { status, stdout, .. }) if status.success() => String::from_utf8_lossy(&stdout)
-            .lines()
-            .map(|s| s.to_string())
-            .filter(|s| !s.is_empty())
-            .collect(),
-        _ => vec![],
-    }
-}
+        // ...and ended once it goes quiet
+        let mut checks = 0;
+        let stopped = watch_until_stopped(
+            || Some("false".to_string()),
+            || {
+                checks += 1;
+                checks > 5
+            },
+            || 0,
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        assert!(stopped);
 
-// Need shellexpand for ~ expansion in volume paths
-mod shellexpand {
-    pub fn tilde(path: &str) -> std::borrow::Cow<'_, str> {
-        if path.starts_with("~/") {
-            if let Some(home) = dirs::home_dir() {
-                return std::borrow::Cow::Owned(format!("{}{}", home.display(), &path[1..]));
-            }
-        }
-        std::borrow::Cow::Borrowed(path)
+        // A follower that ends on its own isn't reported as cut short
+        let mut checks = 0;
+        let stopped = watch_until_stopped(
+            || Some("true".to_string()),
+            || {
+                checks += 1;
+                checks > 2
+            },
+            || 0,
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        assert!(!stopped);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_follow_relayed_ends_when_container_stops() {
+        // A follower that never exits by itself, like a hung `logs -f`
+        let mut follower = Command::new("sleep");
+        follower.arg("30");
+        let passthrough = Passthrough::for_terminals(false, true);
+
+        let mut outputs = vec!["true", "false"].into_iter();
+        let started = Instant::now();
+        let status = follow_relayed(&mut follower, passthrough, || {
+            Some(outputs.next().unwrap_or("false").to_string())
+        })
+        .unwrap();
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
 
     #[test]
     fn test_select_container_match_empty() {
@@ -830,6 +6260,58 @@ mod tests {
         assert!(err_msg.contains("ccs-foo-456"));
     }
 
+    #[test]
+    fn test_select_branch_match() {
+        let sessions = vec![
+            ("ccs-api-1".to_string(), "ccs/fix-auth".to_string()),
+            ("ccs-api-2".to_string(), "main".to_string()),
+            ("ccs-web-3".to_string(), String::new()),
+        ];
+        assert_eq!(
+            select_branch_match(&sessions, "fix-auth").unwrap(),
+            Some("ccs-api-1".to_string())
+        );
+        assert_eq!(
+            select_branch_match(&sessions, "ccs/fix-auth").unwrap(),
+            Some("ccs-api-1".to_string())
+        );
+        assert_eq!(
+            select_branch_match(&sessions, "main").unwrap(),
+            Some("ccs-api-2".to_string())
+        );
+        // Only whole path components match
+        assert_eq!(select_branch_match(&sessions, "auth").unwrap(), None);
+        assert_eq!(select_branch_match(&sessions, "").unwrap(), None);
+    }
+
+    #[test]
+    fn test_select_branch_match_ambiguous() {
+        let sessions = vec![
+            ("ccs-api-1".to_string(), "jane/fix-auth".to_string()),
+            ("ccs-web-2".to_string(), "ccs/fix-auth".to_string()),
+        ];
+        let err = select_branch_match(&sessions, "fix-auth")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Multiple containers match branch 'fix-auth'"));
+        assert!(err.contains("ccs-api-1 (jane/fix-auth)"));
+        assert!(err.contains("ccs-web-2 (ccs/fix-auth)"));
+
+        // A full branch name settles it
+        assert_eq!(
+            select_branch_match(&sessions, "ccs/fix-auth").unwrap(),
+            Some("ccs-web-2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_wait_output() {
+        assert_eq!(parse_wait_output("0\n"), Some(0));
+        assert_eq!(parse_wait_output("137"), Some(137));
+        assert_eq!(parse_wait_output(""), None);
+        assert_eq!(parse_wait_output("not a code"), None);
+    }
+
     #[test]
     fn test_shell_quote_simple() {
         assert_eq!(shell_quote("hello"), "hello");
@@ -893,4 +6375,111 @@ mod tests {
         );
         assert_eq!(redact_credentials("/path/to/file"), "/path/to/file");
     }
+
+    #[test]
+    fn test_should_pull() {
+        let remote = "ghcr.io/org/ccs:latest";
+        assert!(!should_pull(remote, PullPolicy::Never, || false));
+        assert!(should_pull(remote, PullPolicy::Missing, || false));
+        assert!(!should_pull(remote, PullPolicy::Missing, || true));
+        assert!(should_pull(remote, PullPolicy::Always, || true));
+        assert!(should_pull(remote, PullPolicy::Always, || false));
+
+        // Only `missing` needs to know whether the image exists
+        let unchecked = || panic!("image existence checked");
+        assert!(should_pull(remote, PullPolicy::Always, unchecked));
+        assert!(!should_pull(remote, PullPolicy::Never, unchecked));
+
+        // Local images are built, never pulled
+        assert!(!should_pull("ccs:latest", PullPolicy::Always, unchecked));
+        assert!(!should_pull("ccs:latest", PullPolicy::Missing, unchecked));
+    }
+
+    #[test]
+    fn test_pull_never_run_arg() {
+        let workspace = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.docker.image = "ghcr.io/org/ccs:latest".to_string();
+        let run_args = test_runner(config.clone(), &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        assert!(!run_args.args.iter().any(|a| a.starts_with("--pull")));
+
+        config.docker.pull_policy = PullPolicy::Never;
+        let run_args = test_runner(config, &workspace)
+            .build_run_args(&[], &RunOptions::default())
+            .unwrap();
+        let image = run_args
+            .args
+            .iter()
+            .position(|a| a == "ghcr.io/org/ccs:latest")
+            .unwrap();
+        assert_eq!(run_args.args[image - 1], "--pull=never");
+    }
+
+    #[test]
+    fn test_missing_image_action() {
+        assert_eq!(
+            missing_image_action("ccs:latest", true, || true),
+            ImageAction::Ready
+        );
+        assert_eq!(
+            missing_image_action("ccs:latest", true, || false),
+            ImageAction::Build
+        );
+        assert_eq!(
+            missing_image_action("ccs:latest", false, || false),
+            ImageAction::Missing
+        );
+        // Registry images are pulled by the runtime, never inspected or built
+        assert_eq!(
+            missing_image_action("ghcr.io/org/ccs:latest", true, || {
+                panic!("registry images must not be inspected")
+            }),
+            ImageAction::Ready
+        );
+    }
+
+    #[test]
+    fn test_newest_session() {
+        let sessions = vec![
+            ("ccs-api-1".to_string(), 1_700_000_100),
+            ("ccs-web-2".to_string(), 1_700_000_300),
+            ("ccs-api-3".to_string(), 1_700_000_200),
+        ];
+        assert_eq!(newest_session(sessions), Some("ccs-web-2".to_string()));
+        assert_eq!(newest_session(vec![]), None);
+    }
+
+    #[test]
+    fn test_parse_session_created() {
+        let stdout = "ccs-api-1\t2024-01-15 10:30:00 +0000 UTC\n\
+                      ccs-web-2\t2024-01-15 11:00:00.123456789 +0100 CET\n\
+                      ccs-bad-3\tyesterday\n";
+        assert_eq!(
+            parse_session_created(stdout),
+            vec![
+                ("ccs-api-1".to_string(), 1_705_314_600),
+                ("ccs-web-2".to_string(), 1_705_312_800),
+            ]
+        );
+        // 11:00 CET is earlier than 10:30 UTC
+        assert_eq!(
+            newest_session(parse_session_created(stdout)),
+            Some("ccs-api-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_available_space_missing_dir() {
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("not/created/yet");
+        assert!(available_space(&missing).is_some());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+        assert_eq!(format_bytes(300 * 1024 * 1024), "300 MB");
+    }
 }
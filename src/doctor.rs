@@ -0,0 +1,219 @@
+//! Environment diagnostics
+//!
+//! Checks the runtime environment for problems that would stop a sandbox
+//! from starting or working correctly.
+
+use crate::auth::CredentialSource;
+use crate::config::Config;
+use crate::docker::{self, RuntimeStatus};
+use crate::output;
+
+/// Outcome of a single diagnostic check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckLevel {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// A single diagnostic check result
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub level: CheckLevel,
+    pub message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, level: CheckLevel, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+/// Run all checks against a collected runtime status
+pub fn diagnose(status: &RuntimeStatus, config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let Some(runtime) = status.runtime else {
+        checks.push(Check::new(
+            "runtime",
+            CheckLevel::Fail,
+            "Neither Docker nor Podman found; install one to use ccs",
+        ));
+        return checks;
+    };
+    checks.push(Check::new(
+        "runtime",
+        CheckLevel::Ok,
+        format!(
+            "{} {}",
+            runtime.name(),
+            status
+                .runtime_version
+                .as_deref()
+                .unwrap_or("(unknown version)")
+        ),
+    ));
+
+    if status.host.is_remote() {
+        checks.push(Check::new(
+            "daemon",
+            CheckLevel::Fail,
+            format!("{}; workspace bind mounts need a local daemon", status.host),
+        ));
+    } else {
+        checks.push(Check::new(
+            "daemon",
+            CheckLevel::Ok,
+            status.host.to_string(),
+        ));
+    }
+
+    if status.image_exists {
+        checks.push(Check::new(
+            "image",
+            CheckLevel::Ok,
+            format!("'{}' available", config.docker.image),
+        ));
+    } else {
+        checks.push(Check::new(
+            "image",
+            CheckLevel::Warn,
+            format!("'{}' not found (run: ccs --build)", config.docker.image),
+        ));
+    }
+
+    if status.credentials.source == CredentialSource::None {
+        checks.push(Check::new(
+            "credentials",
+            CheckLevel::Warn,
+            "No Claude credentials found (run 'claude login' or set ANTHROPIC_API_KEY)",
+        ));
+    } else {
+        checks.push(Check::new(
+            "credentials",
+            CheckLevel::Ok,
+            status.credentials.source.to_string(),
+        ));
+    }
+
+    if let Some(free) = status.free_space {
+        let min_free = (config.safety.min_free_gb * 1024.0 * 1024.0 * 1024.0) as u64;
+        let message = format!(
+            "{} free in {}",
+            docker::format_bytes(free),
+            status.worktree_base.display()
+        );
+        if free < min_free {
+            checks.push(Check::new(
+                "disk",
+                CheckLevel::Warn,
+                format!(
+                    "{} (below {} GB; worktrees and builds may fail)",
+                    message, config.safety.min_free_gb
+                ),
+            ));
+        } else {
+            checks.push(Check::new("disk", CheckLevel::Ok, message));
+        }
+    }
+
+    checks
+}
+
+/// Print check results, returning true if none failed
+pub fn print_report(checks: &[Check]) -> bool {
+    println!("{}", output::style().header("CCS Doctor"));
+
+    for check in checks {
+        let marker = match check.level {
+            CheckLevel::Ok => "ok",
+            CheckLevel::Warn => "warn",
+            CheckLevel::Fail => "FAIL",
+        };
+        println!("[{:>4}] {}: {}", marker, check.name, check.message);
+    }
+
+    !checks.iter().any(|c| c.level == CheckLevel::Fail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::ClaudeCredentials;
+    use crate::docker::{ContainerRuntime, DaemonHost};
+    use std::path::PathBuf;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    fn status(host: DaemonHost) -> RuntimeStatus {
+        RuntimeStatus {
+            runtime: Some(ContainerRuntime::Docker),
+            runtime_version: Some("24.0.0".to_string()),
+            rootless: false,
+            host,
+            podman_connection: None,
+            image_exists: true,
+            running_containers: vec![],
+            config_path: None,
+            config_exists: false,
+            mcp_config_path: None,
+            mcp_config_exists: false,
+            credentials: ClaudeCredentials {
+                source: CredentialSource::EnvApiKey,
+                oauth_token: None,
+                api_key: Some("key".to_string()),
+            },
+            worktree_base: PathBuf::from("/data/ccs"),
+            free_space: Some(100 * GB),
+            claude_state_volume: None,
+            claude_state_exists: false,
+        }
+    }
+
+    fn level(checks: &[Check], name: &str) -> CheckLevel {
+        checks.iter().find(|c| c.name == name).unwrap().level
+    }
+
+    #[test]
+    fn test_diagnose_local_daemon() {
+        let checks = diagnose(&status(DaemonHost::Default), &Config::default());
+        assert!(checks.iter().all(|c| c.level == CheckLevel::Ok));
+    }
+
+    #[test]
+    fn test_diagnose_flags_remote_daemon() {
+        let remote = DaemonHost::Remote("ssh://build-box".to_string());
+        let checks = diagnose(&status(remote), &Config::default());
+        assert_eq!(level(&checks, "daemon"), CheckLevel::Fail);
+        assert!(!print_report(&checks));
+    }
+
+    #[test]
+    fn test_diagnose_low_disk_space() {
+        let mut status = status(DaemonHost::Default);
+        status.free_space = Some(2 * GB);
+        let checks = diagnose(&status, &Config::default());
+        assert_eq!(level(&checks, "disk"), CheckLevel::Warn);
+        // A warning alone doesn't fail the report
+        assert!(print_report(&checks));
+
+        let mut config = Config::default();
+        config.safety.min_free_gb = 1.0;
+        let checks = diagnose(&status, &config);
+        assert_eq!(level(&checks, "disk"), CheckLevel::Ok);
+    }
+
+    #[test]
+    fn test_diagnose_missing_runtime() {
+        let mut status = status(DaemonHost::Default);
+        status.runtime = None;
+        let checks = diagnose(&status, &Config::default());
+        assert_eq!(checks.len(), 1);
+        assert_eq!(level(&checks, "runtime"), CheckLevel::Fail);
+    }
+}
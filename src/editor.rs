@@ -0,0 +1,109 @@
+//! Opening worktrees in the user's editor
+//!
+//! Resolves the editor from `editor.command`, `$VISUAL` or `$EDITOR` and
+//! launches it without blocking for GUI editors.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+
+/// GUI editors that open a window and return (or should not be waited on)
+const GUI_EDITORS: &[&str] = &[
+    "code", "codium", "cursor", "zed", "subl", "atom", "mate", "idea", "gvim", "mvim",
+];
+
+/// Resolve the editor command line, in order of precedence:
+/// `editor.command`, `$VISUAL`, `$EDITOR`
+fn resolve_editor(
+    configured: Option<&str>,
+    visual: Option<&str>,
+    editor: Option<&str>,
+) -> Option<Vec<String>> {
+    [configured, visual, editor]
+        .into_iter()
+        .flatten()
+        .map(|cmd| {
+            cmd.split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .find(|parts| !parts.is_empty())
+}
+
+/// Check whether a program is a GUI editor that shouldn't block ccs
+fn is_gui_editor(program: &str) -> bool {
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(program);
+    GUI_EDITORS.contains(&name)
+}
+
+/// Open `path` in the configured editor
+///
+/// GUI editors are started in the background; terminal editors run in the
+/// foreground and the sandbox starts once they exit.
+pub fn open_in_editor(config: &Config, path: &Path) -> anyhow::Result<()> {
+    let visual = std::env::var("VISUAL").ok();
+    let editor = std::env::var("EDITOR").ok();
+    let parts = resolve_editor(
+        config.editor.command.as_deref(),
+        visual.as_deref(),
+        editor.as_deref(),
+    )
+    .ok_or_else(|| {
+        anyhow::anyhow!("No editor configured: set editor.command, $VISUAL or $EDITOR")
+    })?;
+
+    let (program, args) = parts.split_first().expect("editor command is non-empty");
+    let mut cmd = Command::new(program);
+    cmd.args(args).arg(path);
+
+    if is_gui_editor(program) {
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start editor '{}': {}", program, e))?;
+    } else {
+        let status = cmd
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to start editor '{}': {}", program, e))?;
+        if !status.success() {
+            eprintln!("Warning: editor '{}' exited with {}", program, status);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_editor_precedence() {
+        assert_eq!(
+            resolve_editor(Some("code -n"), Some("vim"), Some("nano")),
+            Some(vec!["code".to_string(), "-n".to_string()])
+        );
+        assert_eq!(
+            resolve_editor(None, Some("vim"), Some("nano")),
+            Some(vec!["vim".to_string()])
+        );
+        assert_eq!(
+            resolve_editor(Some("  "), None, Some("nano")),
+            Some(vec!["nano".to_string()])
+        );
+        assert_eq!(resolve_editor(None, None, None), None);
+    }
+
+    #[test]
+    fn test_is_gui_editor() {
+        assert!(is_gui_editor("code"));
+        assert!(is_gui_editor("/usr/local/bin/zed"));
+        assert!(!is_gui_editor("vim"));
+        assert!(!is_gui_editor("nano"));
+    }
+}
@@ -0,0 +1,266 @@
+//! Process exit codes
+//!
+//! Maps known error types to stable exit codes so scripts can tell
+//! failures apart without parsing error messages.
+
+use serde::Serialize;
+
+use crate::config::ConfigError;
+use crate::docker::DockerError;
+use crate::git::GitError;
+use crate::mcp::McpError;
+use crate::safety::SafetyError;
+use crate::secrets::SecretsError;
+
+/// Exit codes returned by ccs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Unclassified error
+    Failure = 1,
+    /// Neither Docker nor Podman found in PATH
+    RuntimeNotFound = 10,
+    /// The configured image has not been built
+    ImageNotFound = 11,
+    /// The project is not inside a git repository
+    NotAGitRepo = 12,
+    /// A secret reference could not be resolved
+    SecretResolution = 13,
+    /// The config file could not be read or parsed
+    Config = 14,
+    /// No Claude credentials found while `auth.require_credentials` is set
+    NoCredentials = 15,
+}
+
+impl ExitCode {
+    /// Classify an error by the first known error type in its chain
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(e) = cause.downcast_ref::<DockerError>() {
+                match e {
+                    DockerError::RuntimeNotFound => return ExitCode::RuntimeNotFound,
+                    DockerError::ImageNotFound(_) => return ExitCode::ImageNotFound,
+                    DockerError::NoCredentials => return ExitCode::NoCredentials,
+                    _ => {}
+                }
+            }
+            if let Some(GitError::NotARepo(_) | GitError::NoRepoFound(_)) =
+                cause.downcast_ref::<GitError>()
+            {
+                return ExitCode::NotAGitRepo;
+            }
+            if cause.downcast_ref::<SecretsError>().is_some() {
+                return ExitCode::SecretResolution;
+            }
+            if cause.downcast_ref::<ConfigError>().is_some() {
+                return ExitCode::Config;
+            }
+        }
+
+        ExitCode::Failure
+    }
+
+    /// Integer code passed to `std::process::exit`
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+/// Broad category of a failure, for `--json` error output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Container runtime, image or session problems
+    Docker,
+    /// Repository or worktree problems
+    Git,
+    /// Config or MCP servers file problems
+    Config,
+    /// A secret reference could not be resolved
+    Secrets,
+    /// Blocked by `[safety]` settings
+    Safety,
+    /// No Claude credentials to run with
+    Auth,
+    /// Anything else
+    Other,
+}
+
+/// Machine-readable description of a failed run, printed by `--json`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorReport {
+    pub error_kind: ErrorKind,
+    /// The error and its causes, outermost first
+    pub message: String,
+    /// What to do about it, if known
+    pub hint: Option<String>,
+    pub exit_code: i32,
+}
+
+impl ErrorReport {
+    /// Classify an error by the innermost known error type in its chain
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        let mut error_kind = ErrorKind::Other;
+        let mut hint = None;
+
+        for cause in err.chain() {
+            let (kind, cause_hint) = if let Some(e) = cause.downcast_ref::<DockerError>() {
+                let kind = match e {
+                    DockerError::NoCredentials => ErrorKind::Auth,
+                    _ => ErrorKind::Docker,
+                };
+                (kind, docker_hint(e))
+            } else if let Some(e) = cause.downcast_ref::<GitError>() {
+                (ErrorKind::Git, git_hint(e))
+            } else if cause.downcast_ref::<ConfigError>().is_some()
+                || cause.downcast_ref::<McpError>().is_some()
+            {
+                (
+                    ErrorKind::Config,
+                    Some("Check the file with: ccs --print-config"),
+                )
+            } else if let Some(e) = cause.downcast_ref::<SecretsError>() {
+                (ErrorKind::Secrets, secrets_hint(e))
+            } else if cause.downcast_ref::<SafetyError>().is_some() {
+                (
+                    ErrorKind::Safety,
+                    Some("Adjust [safety] blocked_paths / allowed_roots in config.toml"),
+                )
+            } else {
+                continue;
+            };
+            error_kind = kind;
+            hint = cause_hint.map(str::to_string).or(hint);
+        }
+
+        ErrorReport {
+            error_kind,
+            message: format!("{:#}", err),
+            hint,
+            exit_code: ExitCode::from_error(err).code(),
+        }
+    }
+}
+
+fn docker_hint(e: &DockerError) -> Option<&'static str> {
+    match e {
+        DockerError::RuntimeNotFound => Some("Install Docker or Podman"),
+        DockerError::ImageNotFound(_) => Some("Build the image with: ccs --build"),
+        DockerError::DockerfileNotFound(_) => Some("Set docker.dockerfile_path in config.toml"),
+        DockerError::ContainerExists(_) => {
+            Some("Stop the session with --stop, or pick another --name")
+        }
+        DockerError::RemoteDaemon(..) => Some("Unset DOCKER_HOST (CONTAINER_HOST for Podman)"),
+        DockerError::NotReady(..) => Some("Check the session output with: ccs --logs <session>"),
+        DockerError::NoCredentials => {
+            Some("Run 'claude login' on the host, or set ANTHROPIC_API_KEY")
+        }
+        _ => None,
+    }
+}
+
+fn git_hint(e: &GitError) -> Option<&'static str> {
+    match e {
+        GitError::NotARepo(_) | GitError::NoRepoFound(_) => {
+            Some("Run ccs inside a git repository, or create one with: git init")
+        }
+        GitError::BareRepoUnsupported(_) => {
+            Some("Run ccs in a clone or worktree of the repository")
+        }
+        GitError::CannotCreateFromWorktree => Some("Run --new from the main repository"),
+        GitError::BranchExists(_) => Some("Drop -b/--track to check out the existing branch"),
+        GitError::BranchNotFound(_) => Some("Use -b to create the branch"),
+        GitError::BranchCheckedOut(..) => {
+            Some("Use --reuse to run in the existing worktree, or -b for a new branch")
+        }
+        _ => None,
+    }
+}
+
+fn secrets_hint(e: &SecretsError) -> Option<&'static str> {
+    match e {
+        SecretsError::ResolutionFailed(..) => {
+            Some("Check the secret reference and that you are signed in to the backend")
+        }
+        SecretsError::CustomResolverFailed(..) => {
+            Some("Check [secrets.custom] command in config.toml")
+        }
+        SecretsError::Io(_) => None,
+        _ => Some("Install the backend's CLI (see: ccs --list-secrets-backends)"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_runtime_not_found() {
+        let err = anyhow::Error::from(DockerError::RuntimeNotFound);
+        assert_eq!(ExitCode::from_error(&err), ExitCode::RuntimeNotFound);
+        assert_eq!(ExitCode::from_error(&err).code(), 10);
+    }
+
+    #[test]
+    fn test_wrapped_errors() {
+        let err = anyhow::Error::from(GitError::NoRepoFound(PathBuf::from("/tmp")))
+            .context("--here requires a git repository");
+        assert_eq!(ExitCode::from_error(&err), ExitCode::NotAGitRepo);
+
+        let err = anyhow::Error::from(DockerError::Secrets(SecretsError::PassNotFound));
+        assert_eq!(ExitCode::from_error(&err), ExitCode::SecretResolution);
+    }
+
+    #[test]
+    fn test_unknown_error() {
+        let err = anyhow::anyhow!("something else");
+        assert_eq!(ExitCode::from_error(&err), ExitCode::Failure);
+    }
+
+    #[test]
+    fn test_error_report_classification() {
+        let err = anyhow::Error::from(DockerError::ImageNotFound("ccs:latest".to_string()));
+        let report = ErrorReport::from_error(&err);
+        assert_eq!(report.error_kind, ErrorKind::Docker);
+        assert_eq!(report.exit_code, 11);
+        assert!(report.hint.unwrap().contains("ccs --build"));
+
+        // The innermost known error decides the kind
+        let err = anyhow::Error::from(DockerError::Secrets(SecretsError::PassNotFound));
+        assert_eq!(ErrorReport::from_error(&err).error_kind, ErrorKind::Secrets);
+
+        let err = anyhow::Error::from(GitError::NoRepoFound(PathBuf::from("/tmp")))
+            .context("--here requires a git repository");
+        let report = ErrorReport::from_error(&err);
+        assert_eq!(report.error_kind, ErrorKind::Git);
+        assert!(report
+            .message
+            .starts_with("--here requires a git repository: "));
+
+        let err = anyhow::Error::from(ConfigError::NotFound(PathBuf::from("/x.toml")));
+        assert_eq!(ErrorReport::from_error(&err).error_kind, ErrorKind::Config);
+
+        let err = anyhow::Error::from(SafetyError::Blocked(PathBuf::from("/")));
+        assert_eq!(ErrorReport::from_error(&err).error_kind, ErrorKind::Safety);
+
+        let err = anyhow::Error::from(DockerError::NoCredentials);
+        let report = ErrorReport::from_error(&err);
+        assert_eq!(report.error_kind, ErrorKind::Auth);
+        assert_eq!(report.exit_code, 15);
+        assert!(report.hint.unwrap().contains("claude login"));
+    }
+
+    #[test]
+    fn test_error_report_json() {
+        let report = ErrorReport::from_error(&anyhow::anyhow!("something else"));
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "error_kind": "other",
+                "message": "something else",
+                "hint": null,
+                "exit_code": 1,
+            })
+        );
+    }
+}
@@ -1,15 +1,31 @@
 use git2::Repository;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
-use crate::config::Config;
+use crate::config::{Config, WorktreeOnExists};
 
 #[derive(Error, Debug)]
 pub enum GitError {
     #[error("Not a git repository: {0}")]
     NotARepo(PathBuf),
 
+    #[error(
+        "No git repository found at or above {0}\n  \
+         - run 'git init' to create one here\n  \
+         - run 'ccs --new <branch>' from inside an existing repository\n  \
+         - or cd into a project directory first"
+    )]
+    NoRepoFound(PathBuf),
+
+    #[error(
+        "{0} is a bare repository, which has no working tree to mount\n  \
+         - clone it ('git clone {0} <dir>') and run ccs in the clone\n  \
+         - or add a worktree ('git -C {0} worktree add <dir> <branch>') and run ccs there"
+    )]
+    BareRepoUnsupported(PathBuf),
+
     #[error("Git error: {0}")]
     Git2(#[from] git2::Error),
 
@@ -22,16 +38,64 @@ pub enum GitError {
     #[error("Worktree already exists: {0}")]
     WorktreeExists(PathBuf),
 
-    #[error("Branch '{0}' already exists. Use -b to create from existing branch.")]
+    #[error("Branch '{0}' already exists. Drop -b/--track to check out the existing branch.")]
     BranchExists(String),
 
-    #[error("Branch '{0}' not found. Use -b to create a new branch.")]
+    #[error("Branch '{0}' is already checked out at {1}, and git won't check it out in a second worktree. Use --reuse to run in that worktree, or -b to start a new branch.")]
+    BranchCheckedOut(String, PathBuf),
+
+    #[error("Branch '{0}' not found locally or on a remote. Use -b to create a new branch.")]
     BranchNotFound(String),
 
+    #[error("No remote branch '{0}' to track (run git fetch first?)")]
+    RemoteBranchNotFound(String),
+
+    #[error("Branch '{0}' exists on several remotes ({1}); name one, e.g. {2}")]
+    AmbiguousRemoteBranch(String, String, String),
+
+    #[error("Cannot resolve base ref '{0}': {1}")]
+    RefNotFound(String, String),
+
+    #[error("git sparse-checkout failed: {0}")]
+    SparseCheckout(String),
+
+    #[error("Invalid worktree.branch_template '{0}': {1}")]
+    BranchTemplate(String, String),
+
+    #[error("git {0} failed: {1}")]
+    CommandFailed(String, String),
+
+    #[error("Multiple worktrees match branch '{0}': {1}")]
+    AmbiguousBranch(String, String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+/// Container path the workspace is mounted at
+pub const CONTAINER_WORKSPACE: &str = "/workspace";
+
+/// Which branch a new worktree checks out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorktreeBranch {
+    /// An existing local branch, or a local branch tracking a remote one of
+    /// the same name if there is no local branch yet
+    Existing,
+    /// A new branch (`-b`)
+    New,
+    /// A new local branch tracking a remote branch (`--track`)
+    Track,
+}
+
+/// A remote-tracking branch to create a local branch from
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteBranch {
+    /// Name of the local branch to create (`feature-x`)
+    pub local: String,
+    /// Remote-tracking branch it tracks (`origin/feature-x`)
+    pub remote: String,
+}
+
 /// Git context for mounting in Docker
 #[derive(Debug, Clone)]
 pub struct GitContext {
@@ -52,7 +116,10 @@ pub struct GitContext {
 impl GitContext {
     /// Detect git context from a path
     pub fn detect(path: &PathBuf) -> Result<Self, GitError> {
-        let repo = Repository::discover(path).map_err(|_| GitError::NotARepo(path.clone()))?;
+        let repo = Self::discover(path)?;
+        if repo.is_bare() {
+            return Err(GitError::BareRepoUnsupported(repo.path().to_path_buf()));
+        }
 
         let is_worktree = repo.is_worktree();
         let workdir = repo
@@ -78,6 +145,17 @@ impl GitContext {
         })
     }
 
+    /// Open the repository containing `path`, searching parent directories
+    fn discover(path: &PathBuf) -> Result<Repository, GitError> {
+        Repository::discover(path).map_err(|e| {
+            if e.code() == git2::ErrorCode::NotFound {
+                GitError::NoRepoFound(path.clone())
+            } else {
+                GitError::Git2(e)
+            }
+        })
+    }
+
     /// Find the common git directory for a worktree
     fn find_common_git_dir(repo: &Repository) -> Option<PathBuf> {
         // repo.path() returns the .git directory (or .git/worktrees/<name> for worktrees)
@@ -99,58 +177,146 @@ impl GitContext {
     }
 
     /// Create a new worktree and return its context
+    ///
+    /// When creating a branch, it starts from `base_ref` if given, otherwise HEAD.
+    /// `branch_name` may name a remote branch (`origin/feature-x`) to track.
     pub fn create_worktree(
         repo_path: &PathBuf,
         branch_name: &str,
-        create_branch: bool,
+        branch: WorktreeBranch,
+        base_ref: Option<&str>,
         config: &Config,
     ) -> Result<Self, GitError> {
-        let repo =
-            Repository::discover(repo_path).map_err(|_| GitError::NotARepo(repo_path.clone()))?;
+        let create_branch = branch == WorktreeBranch::New;
+        let repo = Self::discover(repo_path)?;
 
         // Don't allow creating worktrees from within a worktree
         if repo.is_worktree() {
             return Err(GitError::CannotCreateFromWorktree);
         }
+        if repo.is_bare() {
+            return Err(GitError::BareRepoUnsupported(repo.path().to_path_buf()));
+        }
 
         let repo_name = Self::extract_repo_name(&repo)?;
 
+        // Without a local branch, check out a remote one under its local name
+        let tracking = match branch {
+            WorktreeBranch::New => None,
+            WorktreeBranch::Existing if Self::branch_exists(&repo, branch_name) => None,
+            _ => {
+                let found =
+                    Self::find_remote_branch(&repo, branch_name, &config.git.default_remote)?;
+                match (found, branch) {
+                    (Some(found), _) => Some(found),
+                    (None, WorktreeBranch::Track) => {
+                        return Err(GitError::RemoteBranchNotFound(branch_name.to_string()))
+                    }
+                    (None, _) => return Err(GitError::BranchNotFound(branch_name.to_string())),
+                }
+            }
+        };
+        let branch_name = tracking.as_ref().map_or(branch_name, |t| t.local.as_str());
+
         // Determine worktree location
-        let repo_parent = repo
+        let repo_root = repo
             .workdir()
-            .ok_or_else(|| GitError::NotARepo(repo_path.clone()))?
-            .parent()
-            .ok_or(GitError::NoRepoName)?;
+            .ok_or_else(|| GitError::NotARepo(repo_path.clone()))?;
+
+        let worktree_base = config.resolve_worktree_path(&repo_name, repo_root);
+
+        let mut branch_name = branch_name.to_string();
+        let mut worktree_path = worktree_base.join(&branch_name);
+
+        if worktree_path.exists() {
+            match config.worktree.on_exists {
+                WorktreeOnExists::Error => return Err(GitError::WorktreeExists(worktree_path)),
+                WorktreeOnExists::Reuse => {
+                    // Only reuse a worktree of this repository on the same
+                    // branch, not an unrelated directory or checkout
+                    if !is_worktree_on(&repo, &worktree_path, &branch_name) {
+                        return Err(GitError::WorktreeExists(worktree_path));
+                    }
+                    println!("Reusing existing worktree: {}", worktree_path.display());
+                    return Self::detect(&worktree_path);
+                }
+                WorktreeOnExists::Suffix => {
+                    let name = free_suffixed_name(&branch_name, |candidate| {
+                        worktree_base.join(candidate).exists()
+                            || (create_branch && Self::branch_exists(&repo, candidate))
+                    })
+                    .ok_or_else(|| GitError::WorktreeExists(worktree_path.clone()))?;
+                    println!(
+                        "Worktree {} exists, using {}",
+                        worktree_path.display(),
+                        name
+                    );
+                    worktree_path = worktree_base.join(&name);
+                    // An existing branch keeps its name; only the directory moves
+                    if create_branch {
+                        branch_name = name;
+                    }
+                }
+            }
+        }
+        let branch_name = branch_name.as_str();
+
+        // git refuses a branch that another worktree has checked out, so say
+        // where it is rather than passing on git's error
+        if !create_branch && tracking.is_none() {
+            if let Some(path) = checked_out_at(&repo, branch_name) {
+                return Err(GitError::BranchCheckedOut(branch_name.to_string(), path));
+            }
+        }
 
-        let worktree_base = config.resolve_worktree_path(&repo_name, repo_parent);
+        // Fail before fetching or touching the filesystem if -b can't create the branch
+        if (create_branch || tracking.is_some()) && Self::branch_exists(&repo, branch_name) {
+            return Err(GitError::BranchExists(branch_name.to_string()));
+        }
 
         // Create worktree base directory if it doesn't exist
         std::fs::create_dir_all(&worktree_base)?;
 
-        let worktree_path = worktree_base.join(branch_name);
-
-        if worktree_path.exists() {
-            return Err(GitError::WorktreeExists(worktree_path));
+        // Keep worktrees inside the repo (e.g. {repo_root}/.worktrees) out of its status
+        if let Ok(relative) = worktree_base.strip_prefix(repo_root) {
+            if !relative.as_os_str().is_empty() {
+                exclude_path(repo.path(), relative)?;
+            }
         }
 
         // Determine the reference for the worktree
         let reference = if create_branch {
-            // Create new branch from HEAD
-            let head = repo.head()?;
-            let head_commit = head.peel_to_commit()?;
+            // Bring remote-tracking refs up to date; a failed fetch only warns
+            let remotes: Vec<String> = repo
+                .remotes()?
+                .iter()
+                .flatten()
+                .map(str::to_string)
+                .collect();
+            let workdir = repo.workdir().unwrap().to_path_buf();
+            fetch_before_create(
+                config.worktree.fetch_before_create,
+                base_ref,
+                &remotes,
+                |args| run_git(&workdir, args),
+            );
 
-            // Check if branch already exists
-            if repo
-                .find_branch(branch_name, git2::BranchType::Local)
-                .is_ok()
-            {
-                return Err(GitError::BranchExists(branch_name.to_string()));
-            }
+            // Create new branch from the base ref, or HEAD by default
+            let base_commit = match base_ref {
+                Some(rev) => repo
+                    .revparse_single(rev)
+                    .and_then(|obj| obj.peel_to_commit())
+                    .map_err(|e| GitError::RefNotFound(rev.to_string(), e.message().to_string()))?,
+                None => repo.head()?.peel_to_commit()?,
+            };
 
             // Create the branch
-            repo.branch(branch_name, &head_commit, false)?;
+            repo.branch(branch_name, &base_commit, false)?;
 
             format!("refs/heads/{}", branch_name)
+        } else if let Some(ref tracking) = tracking {
+            // `git worktree add --track -b` creates it below
+            format!("refs/heads/{} (tracking {})", branch_name, tracking.remote)
         } else {
             // Use existing branch
             let branch = repo
@@ -165,14 +331,24 @@ impl GitContext {
         };
 
         // Create the worktree using git command (git2's worktree support is limited)
-        let status = std::process::Command::new("git")
-            .arg("-C")
+        // With sparse paths, files are only checked out once the patterns are set
+        let sparse_paths = &config.worktree.sparse_paths;
+        let mut cmd = std::process::Command::new("git");
+        cmd.arg("-C")
             .arg(repo.workdir().unwrap())
             .arg("worktree")
-            .arg("add")
-            .arg(&worktree_path)
-            .arg(branch_name)
-            .status()?;
+            .arg("add");
+        if !sparse_paths.is_empty() {
+            cmd.arg("--no-checkout");
+        }
+        match tracking {
+            Some(ref tracking) => cmd
+                .args(["--track", "-b", branch_name])
+                .arg(&worktree_path)
+                .arg(&tracking.remote),
+            None => cmd.arg(&worktree_path).arg(branch_name),
+        };
+        let status = cmd.status()?;
 
         if !status.success() {
             return Err(GitError::Git2(git2::Error::from_str(
@@ -180,8 +356,28 @@ impl GitContext {
             )));
         }
 
+        if !sparse_paths.is_empty() {
+            Self::sparse_checkout(&worktree_path, sparse_paths)?;
+        }
+
+        // Lets cleanup recognize the worktree wherever base_path puts it
+        mark_ccs_worktree(&worktree_path)?;
+
         println!("Created worktree at: {}", worktree_path.display());
         println!("Branch: {}", reference);
+        if !sparse_paths.is_empty() {
+            println!("Sparse checkout: {}", sparse_paths.join(", "));
+        }
+
+        let copied = copy_into_worktree(
+            repo.workdir().unwrap(),
+            &worktree_path,
+            &config.worktree.copy_patterns,
+        );
+        if !copied.is_empty() {
+            let copied: Vec<String> = copied.iter().map(|p| p.display().to_string()).collect();
+            println!("Copied into worktree: {}", copied.join(", "));
+        }
 
         // Return context for the new worktree
         Ok(GitContext {
@@ -192,13 +388,103 @@ impl GitContext {
         })
     }
 
-    /// Generate a unique branch name for auto-worktree mode
-    pub fn generate_branch_name() -> String {
+    /// Restrict a `--no-checkout` worktree to `paths` (cone mode) and check them out
+    fn sparse_checkout(worktree_path: &Path, paths: &[String]) -> Result<(), GitError> {
+        let set: Vec<&str> = ["sparse-checkout", "set", "--"]
+            .into_iter()
+            .chain(paths.iter().map(String::as_str))
+            .collect();
+        // The index is still empty, so populate it and the sparse working tree from HEAD
+        for args in [set, vec!["read-tree", "-mu", "HEAD"]] {
+            let output = std::process::Command::new("git")
+                .arg("-C")
+                .arg(worktree_path)
+                .args(&args)
+                .output()?;
+
+            if !output.status.success() {
+                return Err(GitError::SparseCheckout(
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a local branch called `name` exists in `repo`
+    pub fn branch_exists(repo: &Repository, name: &str) -> bool {
+        repo.find_branch(name, git2::BranchType::Local).is_ok()
+    }
+
+    /// Find the remote-tracking branch `name` refers to
+    fn find_remote_branch(
+        repo: &Repository,
+        name: &str,
+        default_remote: &str,
+    ) -> Result<Option<RemoteBranch>, GitError> {
+        let remotes: Vec<String> = repo
+            .remotes()?
+            .iter()
+            .flatten()
+            .map(str::to_string)
+            .collect();
+        let mut remote_branches = Vec::new();
+        for entry in repo.branches(Some(git2::BranchType::Remote))? {
+            if let Some(short) = entry?.0.name()? {
+                remote_branches.push(short.to_string());
+            }
+        }
+        match_remote_branch(name, &remotes, &remote_branches, default_remote)
+    }
+
+    /// Generate a branch name from `template` that no local branch uses yet
+    ///
+    /// Regenerates a few times (templates with `{uuid}` or `{timestamp}` vary),
+    /// then falls back to a `-2`, `-3`, ... suffix.
+    pub fn generate_free_branch_name(
+        template: &str,
+        project_path: &Path,
+    ) -> Result<String, GitError> {
+        let repo = Repository::discover(project_path).ok();
+        first_free_name(
+            || Self::generate_branch_name(template, project_path),
+            |name| {
+                repo.as_ref()
+                    .is_some_and(|repo| Self::branch_exists(repo, name))
+            },
+        )
+    }
+
+    /// Generate a branch name for a new worktree from `worktree.branch_template`
+    pub fn generate_branch_name(template: &str, project_path: &Path) -> Result<String, GitError> {
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
-        format!("ccs-{}", timestamp)
+
+        let user = host_identity()
+            .name
+            .or_else(|| std::env::var("USER").ok())
+            .map(|name| slugify(&name))
+            .filter(|s| !s.is_empty());
+        let slug = Repository::discover(project_path)
+            .ok()
+            .and_then(|repo| {
+                let head = repo.head().ok()?;
+                head.is_branch()
+                    .then(|| head.shorthand().map(str::to_string))?
+            })
+            .and_then(|branch| branch.rsplit('/').next().map(slugify))
+            .filter(|s| !s.is_empty());
+
+        let vars = BranchTemplateVars {
+            user,
+            slug,
+            date: format_date(timestamp),
+            timestamp,
+            uuid: random_uuid()?,
+        };
+        render_branch_template(template, &vars)
     }
 
     /// Extract repository name from the repository
@@ -228,22 +514,928 @@ impl GitContext {
             .ok_or(GitError::NoRepoName)
     }
 
+    /// Branch checked out in the workspace, or None if detached
+    pub fn current_branch(&self) -> Option<String> {
+        let repo = Repository::open(&self.workspace_path).ok()?;
+        let head = repo.head().ok()?;
+        head.is_branch()
+            .then(|| head.shorthand().map(str::to_string))?
+    }
+
+    /// Container path the workspace is mounted at: its host path with
+    /// `mirror_host_path`, otherwise `/workspace`
+    pub fn container_workspace(&self, mirror_host_path: bool) -> String {
+        if mirror_host_path {
+            self.workspace_path.display().to_string()
+        } else {
+            CONTAINER_WORKSPACE.to_string()
+        }
+    }
+
     /// Get mount specifications for Docker
-    pub fn docker_mounts(&self) -> Vec<(PathBuf, String)> {
-        let mut mounts = vec![(self.workspace_path.clone(), "/workspace".to_string())];
+    ///
+    /// With `mirror_host_path`, everything is mounted at its host path, so
+    /// absolute paths (including a worktree's link to the shared .git) are the
+    /// same inside and outside the container.
+    pub fn docker_mounts(&self, mirror_host_path: bool) -> Vec<(PathBuf, String)> {
+        let mut mounts = vec![(
+            self.workspace_path.clone(),
+            self.container_workspace(mirror_host_path),
+        )];
+
+        if mirror_host_path {
+            if let Some(ref git_dir) = self.shared_git_dir {
+                mounts.push((git_dir.clone(), git_dir.display().to_string()));
+            }
+            return mounts;
+        }
 
         // For worktrees, also mount the shared .git directory
         if let Some(ref git_dir) = self.shared_git_dir {
             // Mount the parent of the .git directory to preserve the structure
             // The worktree's .git file points to ../../.git/worktrees/<name>
             // So we need to mount the shared .git at a path that matches
-            mounts.push((git_dir.clone(), "/workspace/.git-main".to_string()));
+            mounts.push((
+                git_dir.clone(),
+                format!("{}/.git-main", CONTAINER_WORKSPACE),
+            ));
         }
 
         mounts
     }
 }
 
+/// Validate an `--also` path: it must be an existing directory
+pub fn parse_extra_workspace(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    if !path.is_dir() {
+        return Err(format!("not a directory: {}", s));
+    }
+    path.canonicalize()
+        .map_err(|e| format!("cannot resolve {}: {}", s, e))
+}
+
+/// Mounts for additional project directories at `/workspace-<name>`
+///
+/// The name is the directory's basename; duplicates get a numeric suffix so
+/// every directory has a distinct target.
+pub fn extra_workspace_mounts(paths: &[PathBuf]) -> Vec<(PathBuf, String)> {
+    let mut mounts: Vec<(PathBuf, String)> = Vec::new();
+
+    for path in paths {
+        let name: String = path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let base = format!("{}-{}", CONTAINER_WORKSPACE, name);
+
+        let mut target = base.clone();
+        let mut n = 2;
+        while mounts.iter().any(|(_, existing)| *existing == target) {
+            target = format!("{}-{}", base, n);
+            n += 1;
+        }
+        mounts.push((path.clone(), target));
+    }
+
+    mounts
+}
+
+/// Identity used for commits made inside the container
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GitIdentity {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+impl GitIdentity {
+    /// Environment variables that set author and committer identity
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+
+        if let Some(ref name) = self.name {
+            vars.push(("GIT_AUTHOR_NAME".to_string(), name.clone()));
+            vars.push(("GIT_COMMITTER_NAME".to_string(), name.clone()));
+        }
+
+        if let Some(ref email) = self.email {
+            vars.push(("GIT_AUTHOR_EMAIL".to_string(), email.clone()));
+            vars.push(("GIT_COMMITTER_EMAIL".to_string(), email.clone()));
+        }
+
+        vars
+    }
+}
+
+/// Read the host's git identity from `git config`
+pub fn host_identity() -> GitIdentity {
+    host_identity_with(|key| {
+        let output = std::process::Command::new("git")
+            .args(["config", "--get", key])
+            .output()
+            .ok()?;
+
+        if output.status.success() {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Host whose credentials `git.forward_credentials` forwards
+const CREDENTIAL_HOST: &str = "github.com";
+
+/// Container env var holding a forwarded git token
+pub const GIT_TOKEN_ENV: &str = "CCS_GIT_TOKEN";
+
+/// Host git credentials handed to the container (`git.forward_credentials`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitCredentials {
+    /// Token from a credential manager, served by an inline credential helper
+    Token(String),
+    /// The host's plain-text `~/.git-credentials`, mounted read-only
+    StoreFile(PathBuf),
+}
+
+impl GitCredentials {
+    /// Prefer a short-lived token from the host's credential manager, falling
+    /// back to `~/.git-credentials`
+    pub fn discover() -> Option<Self> {
+        host_git_token().map(GitCredentials::Token).or_else(|| {
+            dirs::home_dir()
+                .map(|home| home.join(".git-credentials"))
+                .filter(|path| path.is_file())
+                .map(GitCredentials::StoreFile)
+        })
+    }
+
+    /// Environment variables configuring git's credential helper in the container
+    ///
+    /// Uses `GIT_CONFIG_COUNT` so nothing is written to the container's git config.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        let (key, helper) = match self {
+            GitCredentials::Token(_) => (
+                format!("credential.https://{}.helper", CREDENTIAL_HOST),
+                format!(
+                    "!f() {{ test \"$1\" = get && echo username=x-access-token && echo \"password=${}\"; }}; f",
+                    GIT_TOKEN_ENV
+                ),
+            ),
+            GitCredentials::StoreFile(_) => ("credential.helper".to_string(), "store".to_string()),
+        };
+
+        let mut vars = vec![
+            ("GIT_CONFIG_COUNT".to_string(), "1".to_string()),
+            ("GIT_CONFIG_KEY_0".to_string(), key),
+            ("GIT_CONFIG_VALUE_0".to_string(), helper),
+        ];
+        if let GitCredentials::Token(token) = self {
+            vars.push((GIT_TOKEN_ENV.to_string(), token.clone()));
+        }
+        vars
+    }
+}
+
+/// Token for pushing to GitHub from the host's credential manager: `gh auth
+/// token`, then `git credential fill`
+pub fn host_git_token() -> Option<String> {
+    host_git_token_with(|program, args, stdin| {
+        use std::io::Write;
+
+        let mut child = std::process::Command::new(program)
+            .args(args)
+            // Never prompt: a missing credential just means nothing to forward
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .ok()?;
+        if let Some(mut pipe) = child.stdin.take() {
+            pipe.write_all(stdin.as_bytes()).ok()?;
+        }
+
+        let output = child.wait_with_output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+    })
+}
+
+/// `host_git_token` using `run(program, args, stdin)`, which returns stdout on success
+fn host_git_token_with<F>(run: F) -> Option<String>
+where
+    F: Fn(&str, &[&str], &str) -> Option<String>,
+{
+    let gh_token = run("gh", &["auth", "token", "--hostname", CREDENTIAL_HOST], "")
+        .map(|out| out.trim().to_string())
+        .filter(|token| !token.is_empty());
+    if gh_token.is_some() {
+        return gh_token;
+    }
+
+    let request = format!("protocol=https\nhost={}\n\n", CREDENTIAL_HOST);
+    run("git", &["credential", "fill"], &request)?
+        .lines()
+        .find_map(|line| line.strip_prefix("password="))
+        .map(str::to_string)
+        .filter(|token| !token.is_empty())
+}
+
+/// Copy paths matching `patterns` (globs relative to `src`) into the same
+/// place under `dst`, returning the matched paths relative to `src`
+///
+/// Meant for untracked and ignored files a fresh worktree lacks, so files
+/// already in `dst` (e.g. tracked ones) are never overwritten and `.git` is
+/// skipped. Directories are copied recursively. Copy errors only warn.
+///
+/// Nothing outside `src` is copied: patterns with `..` are ignored, and so
+/// are matches reached through a symlinked directory pointing elsewhere.
+pub fn copy_into_worktree(src: &Path, dst: &Path, patterns: &[String]) -> Vec<PathBuf> {
+    let options = glob::MatchOptions {
+        require_literal_leading_dot: false,
+        ..Default::default()
+    };
+    let base = glob::Pattern::escape(&src.to_string_lossy());
+    let src_root = src.canonicalize().unwrap_or_else(|_| src.to_path_buf());
+
+    let mut copied = Vec::new();
+    for pattern in patterns {
+        let escapes = Path::new(pattern).components().any(|c| {
+            matches!(
+                c,
+                std::path::Component::ParentDir | std::path::Component::Prefix(_)
+            )
+        });
+        if escapes {
+            eprintln!(
+                "Warning: ignoring copy pattern '{}': it must stay inside the repository",
+                pattern
+            );
+            continue;
+        }
+        let full = format!("{}/{}", base, pattern.trim_start_matches('/'));
+        let matches = match glob::glob_with(&full, options) {
+            Ok(matches) => matches,
+            Err(e) => {
+                eprintln!("Warning: invalid copy pattern '{}': {}", pattern, e);
+                continue;
+            }
+        };
+        for path in matches.flatten() {
+            let Ok(relative) = path.strip_prefix(src) else {
+                continue;
+            };
+            let in_git_dir = relative.components().any(|c| c.as_os_str() == ".git");
+            if in_git_dir || copied.iter().any(|c: &PathBuf| relative.starts_with(c)) {
+                continue;
+            }
+            let inside = path
+                .parent()
+                .and_then(|parent| parent.canonicalize().ok())
+                .is_some_and(|parent| parent.starts_with(&src_root));
+            if !inside {
+                continue;
+            }
+            if let Err(e) = copy_path(&path, &dst.join(relative)) {
+                eprintln!("Warning: failed to copy {}: {}", relative.display(), e);
+                continue;
+            }
+            copied.push(relative.to_path_buf());
+        }
+    }
+    copied
+}
+
+/// Copy a file, symlink or directory tree, skipping anything that already exists
+fn copy_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = from.symlink_metadata()?;
+    if metadata.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+    if to.symlink_metadata().is_ok() {
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Keep symlinks as links (e.g. node_modules/.bin) rather than copying targets
+    #[cfg(unix)]
+    if metadata.file_type().is_symlink() {
+        return std::os::unix::fs::symlink(std::fs::read_link(from)?, to);
+    }
+    std::fs::copy(from, to).map(|_| ())
+}
+
+/// Whether branch `full` is `name`, or ends in `/name` (`fix-auth` matches `ccs/fix-auth`)
+pub fn branch_matches(full: &str, name: &str) -> bool {
+    !name.is_empty() && (full == name || full.ends_with(&format!("/{}", name)))
+}
+
+/// Find the worktree of the repository at `repo_path` that has `branch` checked out
+///
+/// `branch` is matched with [`branch_matches`]; an exact match wins over suffix matches.
+pub fn find_worktree_by_branch(
+    repo_path: &Path,
+    branch: &str,
+) -> Result<Option<PathBuf>, GitError> {
+    let repo = Repository::discover(repo_path)?;
+    // From inside a worktree, list the main repository's worktrees
+    let main = match GitContext::find_common_git_dir(&repo).filter(|_| repo.is_worktree()) {
+        Some(common) => Repository::open(common)?,
+        None => repo,
+    };
+
+    let mut candidates = Vec::new();
+    for name in main.worktrees()?.iter().flatten() {
+        let Ok(worktree) = main.find_worktree(name) else {
+            continue;
+        };
+        let path = worktree.path().to_path_buf();
+        let context = GitContext {
+            workspace_path: path.clone(),
+            shared_git_dir: None,
+            repo_name: String::new(),
+            is_worktree: true,
+        };
+        if let Some(checked_out) = context.current_branch() {
+            if branch_matches(&checked_out, branch) {
+                candidates.push((path, checked_out));
+            }
+        }
+    }
+
+    if let Some(pos) = candidates.iter().position(|(_, b)| b == branch) {
+        return Ok(Some(candidates.swap_remove(pos).0));
+    }
+    match candidates.len() {
+        0 => Ok(None),
+        1 => Ok(candidates.pop().map(|(path, _)| path)),
+        _ => Err(GitError::AmbiguousBranch(
+            branch.to_string(),
+            candidates
+                .iter()
+                .map(|(path, b)| format!("{} ({})", path.display(), b))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )),
+    }
+}
+
+/// A changed path in a worktree
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    /// Two-letter `git status --porcelain` code, e.g. ` M`, `A ` or `??`
+    pub status: String,
+    pub path: String,
+    /// Lines added and removed against HEAD; None for untracked and binary files
+    pub lines: Option<(usize, usize)>,
+}
+
+/// Uncommitted changes (staged and unstaged) in a worktree
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffSummary {
+    pub files: Vec<FileChange>,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffSummary {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Print one line per file, then totals like `git diff --stat`
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("No uncommitted changes.");
+            return;
+        }
+
+        let width = self.files.iter().map(|f| f.path.len()).max().unwrap_or(0);
+        for file in &self.files {
+            let lines = match file.lines {
+                Some((added, removed)) => format!(" | +{} -{}", added, removed),
+                None => String::new(),
+            };
+            println!(
+                "{} {:<width$}{}",
+                file.status,
+                file.path,
+                lines,
+                width = width
+            );
+        }
+        println!(
+            "{} file{} changed, {} insertion{}(+), {} deletion{}(-)",
+            self.files.len(),
+            if self.files.len() == 1 { "" } else { "s" },
+            self.insertions,
+            if self.insertions == 1 { "" } else { "s" },
+            self.deletions,
+            if self.deletions == 1 { "" } else { "s" },
+        );
+    }
+}
+
+/// Summarize uncommitted changes in the worktree at `path`, from
+/// `git status --porcelain` and `git diff HEAD --numstat`
+pub fn worktree_diff(path: &Path) -> Result<DiffSummary, GitError> {
+    let status = git_output(path, &["status", "--porcelain", "--no-renames"])?;
+    // No HEAD yet (a repository without commits) just means no line counts
+    let numstat =
+        git_output(path, &["diff", "HEAD", "--numstat", "--no-renames"]).unwrap_or_default();
+    Ok(parse_diff_summary(&status, &numstat))
+}
+
+/// Combine `git status --porcelain` and `git diff --numstat` output
+fn parse_diff_summary(status: &str, numstat: &str) -> DiffSummary {
+    let counts: HashMap<&str, (usize, usize)> = numstat
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let added = fields.next()?.parse().ok()?;
+            let removed = fields.next()?.parse().ok()?;
+            Some((fields.next()?, (added, removed)))
+        })
+        .collect();
+
+    let files: Vec<FileChange> = status
+        .lines()
+        .filter(|line| line.len() > 3)
+        .map(|line| {
+            let path = line[3..].trim_matches('"').to_string();
+            FileChange {
+                status: line[..2].to_string(),
+                lines: counts.get(path.as_str()).copied(),
+                path,
+            }
+        })
+        .collect();
+
+    DiffSummary {
+        insertions: files.iter().filter_map(|f| f.lines).map(|(a, _)| a).sum(),
+        deletions: files.iter().filter_map(|f| f.lines).map(|(_, r)| r).sum(),
+        files,
+    }
+}
+
+/// File in a worktree's admin dir (`.git/worktrees/<name>/`) marking it as created by ccs
+const WORKTREE_MARKER: &str = "ccs-worktree";
+
+/// Where `branch` is checked out, in the main worktree of `repo` or a linked one
+fn checked_out_at(repo: &Repository, branch: &str) -> Option<PathBuf> {
+    let workdir = repo.workdir()?;
+    let head = repo.head().ok().filter(|head| head.is_branch());
+    if head.as_ref().and_then(|head| head.shorthand()) == Some(branch) {
+        return Some(workdir.to_path_buf());
+    }
+    list_worktrees(workdir)
+        .ok()?
+        .into_iter()
+        .find(|entry| entry.branch.as_deref() == Some(branch))
+        .map(|entry| entry.path)
+}
+
+/// Whether `path` is a linked worktree of `repo` with `branch` checked out
+fn is_worktree_on(repo: &Repository, path: &Path, branch: &str) -> bool {
+    let Ok(worktree) = Repository::open(path) else {
+        return false;
+    };
+    // A linked worktree's admin dir names the repository's git dir in `commondir`
+    let commondir = std::fs::read_to_string(worktree.path().join("commondir"))
+        .ok()
+        .map(|dir| worktree.path().join(dir.trim()));
+    let same_repo = match (
+        commondir.and_then(|dir| dir.canonicalize().ok()),
+        repo.path().canonicalize(),
+    ) {
+        (Some(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    let on_branch = worktree
+        .find_reference("HEAD")
+        .ok()
+        .and_then(|head| head.symbolic_target().map(str::to_string))
+        == Some(format!("refs/heads/{}", branch));
+    worktree.is_worktree() && same_repo && on_branch
+}
+
+/// Mark `worktree` as created by ccs
+pub(crate) fn mark_ccs_worktree(worktree: &Path) -> std::io::Result<()> {
+    match worktree_admin_dir(worktree) {
+        Some(admin_dir) => std::fs::write(admin_dir.join(WORKTREE_MARKER), ""),
+        None => Ok(()),
+    }
+}
+
+/// Admin directory a worktree's `.git` file points to
+fn worktree_admin_dir(worktree: &Path) -> Option<PathBuf> {
+    let content = std::fs::read_to_string(worktree.join(".git")).ok()?;
+    let gitdir = content.strip_prefix("gitdir: ")?.trim();
+    // Relative gitdir paths are relative to the worktree
+    Some(worktree.join(gitdir))
+}
+
+/// A linked worktree from `git worktree list --porcelain`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorktreeEntry {
+    pub path: PathBuf,
+    /// Checked out branch, without `refs/heads/`; None when detached
+    pub branch: Option<String>,
+    pub locked: bool,
+}
+
+impl WorktreeEntry {
+    /// Whether ccs created this worktree, going by ccs's marker alone; the
+    /// branch name says nothing, since users may name their own branches alike
+    pub fn is_ccs_created(&self) -> bool {
+        worktree_admin_dir(&self.path).is_some_and(|dir| dir.join(WORKTREE_MARKER).exists())
+    }
+}
+
+/// Linked worktrees of the repository containing `path`, excluding the main one
+pub fn list_worktrees(path: &Path) -> Result<Vec<WorktreeEntry>, GitError> {
+    Ok(parse_worktree_list(&git_output(
+        path,
+        &["worktree", "list", "--porcelain"],
+    )?))
+}
+
+/// Parse `git worktree list --porcelain`, skipping the main and bare worktrees
+fn parse_worktree_list(output: &str) -> Vec<WorktreeEntry> {
+    // The main worktree is always listed first
+    output
+        .split("\n\n")
+        .skip(1)
+        .filter_map(|record| {
+            let mut lines = record.lines();
+            let path = lines.next()?.strip_prefix("worktree ")?;
+            let mut entry = WorktreeEntry {
+                path: PathBuf::from(path),
+                branch: None,
+                locked: false,
+            };
+            for line in lines {
+                if line == "bare" {
+                    return None;
+                } else if let Some(branch) = line.strip_prefix("branch ") {
+                    let branch = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+                    entry.branch = Some(branch.to_string());
+                } else if line == "locked" || line.starts_with("locked ") {
+                    entry.locked = true;
+                }
+            }
+            Some(entry)
+        })
+        .collect()
+}
+
+/// Add `relative` (a directory inside the repo) to the repo's `info/exclude`
+fn exclude_path(git_dir: &Path, relative: &Path) -> Result<(), GitError> {
+    let pattern = format!("/{}/", relative.to_string_lossy().trim_end_matches('/'));
+    let exclude = git_dir.join("info").join("exclude");
+    let contents = std::fs::read_to_string(&exclude).unwrap_or_default();
+    if contents.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
+
+    if let Some(parent) = exclude.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut updated = contents;
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&pattern);
+    updated.push('\n');
+    std::fs::write(&exclude, updated)?;
+    Ok(())
+}
+
+/// Push `branch` from `worktree` to `remote`, setting it as the upstream
+pub fn push_branch(worktree: &Path, remote: &str, branch: &str) -> Result<(), GitError> {
+    push_branch_with(worktree, remote, branch, |dir, args| {
+        git_output(dir, args).map(|_| ())
+    })
+}
+
+fn push_branch_with<F>(worktree: &Path, remote: &str, branch: &str, run: F) -> Result<(), GitError>
+where
+    F: FnOnce(&Path, &[&str]) -> Result<(), GitError>,
+{
+    run(worktree, &["push", "-u", remote, branch])
+}
+
+/// Run git in `dir`, returning stdout, or a `CommandFailed` error with stderr
+fn git_output(dir: &Path, args: &[&str]) -> Result<String, GitError> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(GitError::CommandFailed(
+            args.first().copied().unwrap_or_default().to_string(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Run git in `dir`, returning stderr on failure
+fn run_git(dir: &Path, args: &[&str]) -> Result<(), String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Match `name` against remote-tracking branches (`<remote>/<branch>`)
+///
+/// `name` is either a remote branch like `origin/feature-x` or a branch name
+/// looked up on every remote; when several remotes have it, `default_remote`
+/// wins.
+fn match_remote_branch(
+    name: &str,
+    remotes: &[String],
+    remote_branches: &[String],
+    default_remote: &str,
+) -> Result<Option<RemoteBranch>, GitError> {
+    let candidates: Vec<(&str, RemoteBranch)> = remote_branches
+        .iter()
+        .filter_map(|short| {
+            // Remote names may contain '/', so take the longest matching one
+            let (remote, branch) = remotes
+                .iter()
+                .filter_map(|remote| {
+                    let branch = short.strip_prefix(remote.as_str())?.strip_prefix('/')?;
+                    Some((remote.as_str(), branch))
+                })
+                .max_by_key(|(remote, _)| remote.len())?;
+            let branch = RemoteBranch {
+                local: branch.to_string(),
+                remote: short.clone(),
+            };
+            (branch.local != "HEAD").then_some((remote, branch))
+        })
+        .collect();
+
+    if let Some((_, exact)) = candidates.iter().find(|(_, b)| b.remote == name) {
+        return Ok(Some(exact.clone()));
+    }
+    let mut matches: Vec<(&str, RemoteBranch)> = candidates
+        .into_iter()
+        .filter(|(_, b)| b.local == name)
+        .collect();
+    if matches.len() > 1 {
+        return match matches
+            .iter()
+            .position(|(remote, _)| *remote == default_remote)
+        {
+            Some(i) => Ok(Some(matches.swap_remove(i).1)),
+            None => Err(GitError::AmbiguousRemoteBranch(
+                name.to_string(),
+                matches
+                    .iter()
+                    .map(|(remote, _)| *remote)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                matches[0].1.remote.clone(),
+            )),
+        };
+    }
+    Ok(matches.pop().map(|(_, b)| b))
+}
+
+/// Remote to fetch for `base_ref`: the remote it names (e.g. `upstream/main`),
+/// otherwise origin, otherwise the only remote
+fn remote_for_ref(base_ref: Option<&str>, remotes: &[String]) -> Option<String> {
+    let named = base_ref
+        .map(|rev| rev.strip_prefix("refs/remotes/").unwrap_or(rev))
+        .and_then(|rev| rev.split_once('/'))
+        .map(|(remote, _)| remote)
+        .filter(|remote| remotes.iter().any(|r| r == remote));
+
+    named
+        .or_else(|| remotes.iter().map(String::as_str).find(|r| *r == "origin"))
+        .or(match remotes {
+            [only] => Some(only.as_str()),
+            _ => None,
+        })
+        .map(str::to_string)
+}
+
+/// Fetch the remote for `base_ref` if `enabled`, using `run_git` for `git <args>`
+///
+/// A failed fetch is reported but not fatal: the ref may still resolve to a
+/// slightly stale commit. Returns the remote that was fetched.
+fn fetch_before_create<F>(
+    enabled: bool,
+    base_ref: Option<&str>,
+    remotes: &[String],
+    run_git: F,
+) -> Option<String>
+where
+    F: FnOnce(&[&str]) -> Result<(), String>,
+{
+    if !enabled {
+        return None;
+    }
+    let remote = remote_for_ref(base_ref, remotes)?;
+
+    println!("Fetching {}...", remote);
+    match run_git(&["fetch", &remote]) {
+        Ok(()) => Some(remote),
+        Err(e) => {
+            eprintln!("Warning: git fetch {} failed: {}", remote, e);
+            None
+        }
+    }
+}
+
+/// Most suffixes `free_suffixed_name` tries before giving up
+const MAX_NAME_SUFFIX: u32 = 100;
+
+/// Names `first_free_name` generates before resorting to a suffix
+const MAX_GENERATE_ATTEMPTS: usize = 3;
+
+/// First name from `generate` that is not `taken`, else the last one with a free suffix
+fn first_free_name<G, F>(mut generate: G, taken: F) -> Result<String, GitError>
+where
+    G: FnMut() -> Result<String, GitError>,
+    F: Fn(&str) -> bool,
+{
+    let mut name = String::new();
+    for _ in 0..MAX_GENERATE_ATTEMPTS {
+        name = generate()?;
+        if !taken(&name) {
+            return Ok(name);
+        }
+    }
+    free_suffixed_name(&name, taken).ok_or(GitError::BranchExists(name))
+}
+
+/// First of `name-2`, `name-3`, ... that is not `taken`
+fn free_suffixed_name<F>(name: &str, taken: F) -> Option<String>
+where
+    F: Fn(&str) -> bool,
+{
+    (2..=MAX_NAME_SUFFIX)
+        .map(|n| format!("{}-{}", name, n))
+        .find(|candidate| !taken(candidate))
+}
+
+/// Placeholders supported in `worktree.branch_template`
+const BRANCH_TEMPLATE_PLACEHOLDERS: &[&str] = &["user", "slug", "date", "timestamp", "uuid"];
+
+/// Values substituted into `worktree.branch_template`
+#[derive(Debug, Clone, Default)]
+struct BranchTemplateVars {
+    /// Slug of the git user name, or `$USER`
+    user: Option<String>,
+    /// Slug of the current branch's last path segment
+    slug: Option<String>,
+    /// Today's date as YYYY-MM-DD (UTC)
+    date: String,
+    /// Unix timestamp in seconds
+    timestamp: u64,
+    /// Random UUID (v4)
+    uuid: String,
+}
+
+/// Substitute `{placeholder}`s in a branch template, validating the result
+fn render_branch_template(template: &str, vars: &BranchTemplateVars) -> Result<String, GitError> {
+    let error = |reason: String| GitError::BranchTemplate(template.to_string(), reason);
+
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| error("unclosed '{'".to_string()))?;
+        let placeholder = &after[..end];
+
+        let value = match placeholder {
+            "user" => vars.user.clone().ok_or_else(|| {
+                error("{user} is unknown; set git user.name or $USER".to_string())
+            })?,
+            "slug" => vars
+                .slug
+                .clone()
+                .ok_or_else(|| error("{slug} needs a checked-out branch".to_string()))?,
+            "date" => vars.date.clone(),
+            "timestamp" => vars.timestamp.to_string(),
+            "uuid" => vars.uuid.clone(),
+            other => {
+                let known: Vec<String> = BRANCH_TEMPLATE_PLACEHOLDERS
+                    .iter()
+                    .map(|p| format!("{{{}}}", p))
+                    .collect();
+                return Err(error(format!(
+                    "unknown placeholder '{{{}}}' (expected one of {})",
+                    other,
+                    known.join(", ")
+                )));
+            }
+        };
+        name.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    name.push_str(rest);
+
+    if !git2::Branch::name_is_valid(&name).unwrap_or(false) {
+        return Err(error(format!("'{}' is not a valid branch name", name)));
+    }
+    Ok(name)
+}
+
+/// Lowercase `s`, replacing runs of anything but ASCII letters and digits with '-'
+fn slugify(s: &str) -> String {
+    let mut slug = String::new();
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Format Unix seconds as a UTC YYYY-MM-DD date
+fn format_date(secs: u64) -> String {
+    // Civil date from days since the Unix epoch (proleptic Gregorian)
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Generate a random (v4) UUID
+fn random_uuid() -> Result<String, GitError> {
+    let mut bytes = [0u8; 16];
+    openssl::rand::rand_bytes(&mut bytes).map_err(std::io::Error::other)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Read the git identity using the given `git config --get` lookup
+fn host_identity_with<F>(get_config: F) -> GitIdentity
+where
+    F: Fn(&str) -> Option<String>,
+{
+    GitIdentity {
+        name: get_config("user.name").filter(|s| !s.is_empty()),
+        email: get_config("user.email").filter(|s| !s.is_empty()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,9 +1449,18 @@ mod tests {
             is_worktree: false,
         };
 
-        let mounts = ctx.docker_mounts();
+        let mounts = ctx.docker_mounts(false);
         assert_eq!(mounts.len(), 1);
         assert_eq!(mounts[0].1, "/workspace");
+
+        let mounts = ctx.docker_mounts(true);
+        assert_eq!(
+            mounts,
+            vec![(
+                PathBuf::from("/home/user/project"),
+                "/home/user/project".to_string()
+            )]
+        );
     }
 
     #[test]
@@ -271,17 +1472,981 @@ mod tests {
             is_worktree: true,
         };
 
-        let mounts = ctx.docker_mounts();
+        let mounts = ctx.docker_mounts(false);
         assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[1].1, "/workspace/.git-main");
+
+        let targets: Vec<String> = ctx.docker_mounts(true).into_iter().map(|m| m.1).collect();
+        assert_eq!(
+            targets,
+            [
+                "/home/user/project-worktrees/feature",
+                "/home/user/project/.git"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extra_workspace_mounts() {
+        let mounts = extra_workspace_mounts(&[
+            PathBuf::from("/home/user/api"),
+            PathBuf::from("/home/user/web app"),
+            PathBuf::from("/other/api"),
+        ]);
+        let targets: Vec<&str> = mounts.iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(
+            targets,
+            ["/workspace-api", "/workspace-web-app", "/workspace-api-2"]
+        );
+    }
+
+    #[test]
+    fn test_parse_extra_workspace() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("file.txt");
+        std::fs::write(&file, "").unwrap();
+
+        assert!(parse_extra_workspace(dir.path().to_str().unwrap()).is_ok());
+        assert!(parse_extra_workspace(file.to_str().unwrap()).is_err());
+        assert!(parse_extra_workspace("/nonexistent/ccs-also").is_err());
+    }
+
+    #[test]
+    fn test_detect_outside_repo() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let result = GitContext::detect(&dir.path().to_path_buf());
+        match result {
+            Err(GitError::NoRepoFound(path)) => assert_eq!(path, dir.path()),
+            other => panic!("expected NoRepoFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_detect_bare_repo() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let bare = dir.path().join("project.git");
+        Repository::init_bare(&bare).unwrap();
+
+        let result = GitContext::detect(&bare);
+        match result {
+            Err(e @ GitError::BareRepoUnsupported(_)) => {
+                assert!(e.to_string().contains("bare repository"));
+            }
+            other => panic!("expected BareRepoUnsupported, got {:?}", other),
+        }
+
+        let result = GitContext::create_worktree(
+            &bare,
+            "feature",
+            WorktreeBranch::New,
+            None,
+            &Config::default(),
+        );
+        assert!(matches!(result, Err(GitError::BareRepoUnsupported(_))));
+    }
+
+    #[test]
+    fn test_fetch_before_create() {
+        let remotes = vec!["origin".to_string(), "upstream".to_string()];
+        let fetch = |enabled, base_ref| {
+            let mut calls = Vec::new();
+            let fetched = fetch_before_create(enabled, base_ref, &remotes, |args| {
+                calls.push(args.join(" "));
+                Ok(())
+            });
+            (fetched, calls)
+        };
+
+        assert_eq!(fetch(false, Some("origin/main")), (None, vec![]));
+        assert_eq!(
+            fetch(true, Some("upstream/main")),
+            (
+                Some("upstream".to_string()),
+                vec!["fetch upstream".to_string()]
+            )
+        );
+        assert_eq!(
+            fetch(true, Some("refs/remotes/upstream/dev")),
+            (
+                Some("upstream".to_string()),
+                vec!["fetch upstream".to_string()]
+            )
+        );
+        // Local refs and HEAD fall back to origin
+        assert_eq!(
+            fetch(true, Some("main")),
+            (Some("origin".to_string()), vec!["fetch origin".to_string()])
+        );
+        assert_eq!(
+            fetch(true, None),
+            (Some("origin".to_string()), vec!["fetch origin".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_fetch_before_create_failure_not_fatal() {
+        let remotes = vec!["origin".to_string()];
+        let fetched = fetch_before_create(true, Some("origin/main"), &remotes, |_| {
+            Err("could not resolve host".to_string())
+        });
+        assert_eq!(fetched, None);
+
+        // Nothing to fetch without remotes
+        let fetched = fetch_before_create(true, Some("origin/main"), &[], |_| {
+            panic!("fetch must not run without remotes")
+        });
+        assert_eq!(fetched, None);
+    }
+
+    #[test]
+    fn test_remote_for_ref() {
+        let remotes = vec!["fork".to_string(), "upstream".to_string()];
+        assert_eq!(
+            remote_for_ref(Some("fork/feature"), &remotes),
+            Some("fork".to_string())
+        );
+        // No origin and several remotes: nothing obvious to fetch
+        assert_eq!(remote_for_ref(Some("main"), &remotes), None);
+        assert_eq!(
+            remote_for_ref(None, &["mirror".to_string()]),
+            Some("mirror".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match_remote_branch() {
+        let remotes = vec!["origin".to_string(), "upstream".to_string()];
+        let branches: Vec<String> = [
+            "origin/HEAD",
+            "origin/feature-x",
+            "origin/shared",
+            "upstream/shared",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let matched = |name: &str, default: &str| {
+            match_remote_branch(name, &remotes, &branches, default).map(|b| b.map(|b| b.remote))
+        };
+
+        let found = match_remote_branch("feature-x", &remotes, &branches, "origin").unwrap();
+        assert_eq!(
+            found,
+            Some(RemoteBranch {
+                local: "feature-x".to_string(),
+                remote: "origin/feature-x".to_string(),
+            })
+        );
+        assert_eq!(
+            matched("upstream/shared", "origin").unwrap().as_deref(),
+            Some("upstream/shared")
+        );
+        // On several remotes, the default one wins
+        assert_eq!(
+            matched("shared", "origin").unwrap().as_deref(),
+            Some("origin/shared")
+        );
+        assert!(matches!(
+            matched("shared", "fork"),
+            Err(GitError::AmbiguousRemoteBranch(..))
+        ));
+        assert_eq!(matched("missing", "origin").unwrap(), None);
+        assert_eq!(matched("HEAD", "origin").unwrap(), None);
+    }
+
+    #[test]
+    fn test_host_identity_with_runner() {
+        let identity = host_identity_with(|key| match key {
+            "user.name" => Some("Jane Doe".to_string()),
+            "user.email" => Some("jane@example.com".to_string()),
+            _ => None,
+        });
+        assert_eq!(identity.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(identity.email.as_deref(), Some("jane@example.com"));
+
+        let vars = identity.env_vars();
+        assert!(vars.contains(&("GIT_AUTHOR_NAME".to_string(), "Jane Doe".to_string())));
+        assert!(vars.contains(&(
+            "GIT_COMMITTER_EMAIL".to_string(),
+            "jane@example.com".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_host_git_token_prefers_gh() {
+        let calls = std::cell::RefCell::new(Vec::new());
+        let token = host_git_token_with(|program, _, _| {
+            calls.borrow_mut().push(program.to_string());
+            Some("gho_abc\n".to_string())
+        });
+        assert_eq!(token.as_deref(), Some("gho_abc"));
+        assert_eq!(*calls.borrow(), ["gh"]);
+    }
+
+    #[test]
+    fn test_host_git_token_from_credential_fill() {
+        let token = host_git_token_with(|program, args, stdin| match program {
+            "gh" => None,
+            _ => {
+                assert_eq!(args, ["credential", "fill"]);
+                assert_eq!(stdin, "protocol=https\nhost=github.com\n\n");
+                Some(
+                    "protocol=https\nhost=github.com\nusername=jane\npassword=ghp_xyz\n"
+                        .to_string(),
+                )
+            }
+        });
+        assert_eq!(token.as_deref(), Some("ghp_xyz"));
+
+        assert_eq!(host_git_token_with(|_, _, _| None), None);
+        // An empty gh token falls through to git
+        assert_eq!(
+            host_git_token_with(|program, _, _| (program == "gh").then(String::new)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_push_branch_invocation() {
+        let worktree = Path::new("/tmp/wt");
+        let mut calls = Vec::new();
+        push_branch_with(worktree, "upstream", "ccs/fix-auth", |dir, args| {
+            calls.push((dir.to_path_buf(), args.join(" ")));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            calls,
+            vec![(
+                worktree.to_path_buf(),
+                "push -u upstream ccs/fix-auth".to_string()
+            )]
+        );
+
+        let err = push_branch_with(worktree, "origin", "main", |_, _| {
+            Err(GitError::CommandFailed(
+                "push".to_string(),
+                "rejected".to_string(),
+            ))
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("rejected"));
+    }
+
+    #[test]
+    fn test_git_credentials_env_vars() {
+        let vars = GitCredentials::Token("ghp_xyz".to_string()).env_vars();
+        assert!(vars.contains(&("GIT_CONFIG_COUNT".to_string(), "1".to_string())));
+        assert!(vars.contains(&(
+            "GIT_CONFIG_KEY_0".to_string(),
+            "credential.https://github.com.helper".to_string()
+        )));
+        assert!(vars.contains(&(GIT_TOKEN_ENV.to_string(), "ghp_xyz".to_string())));
+        // The helper reads the token from the environment rather than embedding it
+        let helper = &vars
+            .iter()
+            .find(|(k, _)| k == "GIT_CONFIG_VALUE_0")
+            .unwrap()
+            .1;
+        assert!(helper.contains("$CCS_GIT_TOKEN"));
+        assert!(!helper.contains("ghp_xyz"));
+
+        let vars =
+            GitCredentials::StoreFile(PathBuf::from("/home/jane/.git-credentials")).env_vars();
+        assert!(vars.contains(&("GIT_CONFIG_VALUE_0".to_string(), "store".to_string())));
+        assert!(!vars.iter().any(|(k, _)| k == GIT_TOKEN_ENV));
+    }
+
+    #[test]
+    fn test_copy_into_worktree() {
+        let src = tempfile::TempDir::new().unwrap();
+        let dst = tempfile::TempDir::new().unwrap();
+        std::fs::write(src.path().join(".env"), "KEY=value\n").unwrap();
+        std::fs::write(src.path().join(".env.local"), "LOCAL=1\n").unwrap();
+        std::fs::write(src.path().join("README.md"), "source\n").unwrap();
+        std::fs::write(dst.path().join("README.md"), "checked out\n").unwrap();
+
+        let patterns = vec![".env*".to_string(), "README.md".to_string()];
+        let mut copied = copy_into_worktree(src.path(), dst.path(), &patterns);
+        copied.sort();
+        assert_eq!(
+            copied,
+            [
+                PathBuf::from(".env"),
+                PathBuf::from(".env.local"),
+                PathBuf::from("README.md")
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst.path().join(".env")).unwrap(),
+            "KEY=value\n"
+        );
+        // Files already in the worktree are left alone
+        assert_eq!(
+            std::fs::read_to_string(dst.path().join("README.md")).unwrap(),
+            "checked out\n"
+        );
+    }
+
+    #[test]
+    fn test_copy_into_worktree_nested() {
+        let src = tempfile::TempDir::new().unwrap();
+        let dst = tempfile::TempDir::new().unwrap();
+        let service = src.path().join("services").join("api");
+        std::fs::create_dir_all(service.join("node_modules").join("left-pad")).unwrap();
+        std::fs::write(service.join(".env"), "PORT=8080\n").unwrap();
+        std::fs::write(
+            service
+                .join("node_modules")
+                .join("left-pad")
+                .join("index.js"),
+            "module.exports = 1;\n",
+        )
+        .unwrap();
+        std::fs::create_dir(src.path().join(".git")).unwrap();
+        std::fs::write(src.path().join(".git").join(".env"), "").unwrap();
+
+        let patterns = vec!["**/.env".to_string(), "services/*/node_modules".to_string()];
+        copy_into_worktree(src.path(), dst.path(), &patterns);
+
+        let copied = dst.path().join("services").join("api");
+        assert!(copied.join(".env").is_file());
+        assert!(copied
+            .join("node_modules")
+            .join("left-pad")
+            .join("index.js")
+            .is_file());
+        assert!(!dst.path().join(".git").exists());
+    }
+
+    #[test]
+    fn test_copy_into_worktree_stays_in_repo() {
+        let outside = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(outside.path().join(".ssh")).unwrap();
+        std::fs::write(outside.path().join(".ssh").join("id_ed25519"), "key\n").unwrap();
+        let src = outside.path().join("repo");
+        std::fs::create_dir(&src).unwrap();
+        let dst = tempfile::TempDir::new().unwrap();
+
+        let patterns = vec!["../.ssh/*".to_string(), "link/*".to_string()];
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(outside.path().join(".ssh"), src.join("link")).unwrap();
+        let copied = copy_into_worktree(&src, dst.path(), &patterns);
+        assert!(copied.is_empty(), "{:?}", copied);
+        assert_eq!(std::fs::read_dir(dst.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_host_identity_unset() {
+        let identity = host_identity_with(|_| None);
+        assert_eq!(identity, GitIdentity::default());
+        assert!(identity.env_vars().is_empty());
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn init_repo_with_commits(dir: &Path, count: usize) {
+        git(dir, &["init", "-q"]);
+        git(dir, &["config", "user.email", "test@test.com"]);
+        git(dir, &["config", "user.name", "Test"]);
+        for i in 0..count {
+            std::fs::write(dir.join("file.txt"), i.to_string()).unwrap();
+            git(dir, &["add", "."]);
+            git(dir, &["commit", "-q", "-m", &format!("commit {}", i)]);
+        }
+    }
+
+    #[test]
+    fn test_create_worktree_from_base_ref() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let repo_path = tmp.path().join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo_with_commits(&repo_path, 2);
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let first_commit = repo.revparse_single("HEAD~1").unwrap().id();
+
+        let mut config = Config::default();
+        config.worktree.base_path = tmp.path().join("worktrees").display().to_string();
+
+        let ctx = GitContext::create_worktree(
+            &repo_path,
+            "feature",
+            WorktreeBranch::New,
+            Some("HEAD~1"),
+            &config,
+        )
+        .unwrap();
+        assert!(ctx.is_worktree);
+
+        let branch = repo
+            .find_branch("feature", git2::BranchType::Local)
+            .unwrap();
+        assert_eq!(branch.get().target(), Some(first_commit));
+    }
+
+    #[test]
+    fn test_create_worktree_tracking_remote_branch() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let repo_path = tmp.path().join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo_with_commits(&repo_path, 2);
+        // Simulate a fetched remote branch with no local counterpart
+        git(
+            &repo_path,
+            &["remote", "add", "origin", "https://example.com/repo.git"],
+        );
+        for branch in ["feature-x", "other"] {
+            let remote_ref = format!("refs/remotes/origin/{}", branch);
+            git(&repo_path, &["update-ref", &remote_ref, "HEAD~1"]);
+        }
+
+        let mut config = Config::default();
+        config.worktree.base_path = tmp.path().join("worktrees").display().to_string();
+
+        let ctx = GitContext::create_worktree(
+            &repo_path,
+            "feature-x",
+            WorktreeBranch::Existing,
+            None,
+            &config,
+        )
+        .unwrap();
+        assert!(ctx.workspace_path.ends_with("feature-x"));
+
+        let repo = Repository::open(&repo_path).unwrap();
+        let branch = repo
+            .find_branch("feature-x", git2::BranchType::Local)
+            .unwrap();
+        assert_eq!(
+            branch.upstream().unwrap().name().unwrap(),
+            Some("origin/feature-x")
+        );
+        assert_eq!(
+            branch.get().target(),
+            Some(repo.revparse_single("HEAD~1").unwrap().id())
+        );
+
+        // --track takes the remote branch's name and needs one to exist
+        let ctx = GitContext::create_worktree(
+            &repo_path,
+            "origin/other",
+            WorktreeBranch::Track,
+            None,
+            &config,
+        )
+        .unwrap();
+        assert!(ctx.workspace_path.ends_with("other"));
+        assert!(GitContext::branch_exists(&repo, "other"));
+        let result =
+            GitContext::create_worktree(&repo_path, "nope", WorktreeBranch::Track, None, &config);
+        assert!(matches!(result, Err(GitError::RemoteBranchNotFound(_))));
+        let result = GitContext::create_worktree(
+            &repo_path,
+            "nope",
+            WorktreeBranch::Existing,
+            None,
+            &config,
+        );
+        assert!(matches!(result, Err(GitError::BranchNotFound(_))));
+    }
+
+    #[test]
+    fn test_create_worktree_under_repo_root() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let repo_path = tmp.path().join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo_with_commits(&repo_path, 1);
+
+        let mut config = Config::default();
+        config.worktree.base_path = "{repo_root}/.worktrees".to_string();
+
+        let ctx =
+            GitContext::create_worktree(&repo_path, "feature", WorktreeBranch::New, None, &config)
+                .unwrap();
+        assert_eq!(
+            ctx.workspace_path.canonicalize().unwrap(),
+            repo_path.join(".worktrees/feature").canonicalize().unwrap()
+        );
+        // Listed from the main repo (or any worktree) and marked as ccs's
+        let listed = list_worktrees(&ctx.workspace_path).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].branch.as_deref(), Some("feature"));
+        assert!(listed[0].is_ccs_created());
+
+        // The main repo's status doesn't pick up its worktrees
+        let exclude = std::fs::read_to_string(repo_path.join(".git/info/exclude")).unwrap();
+        assert_eq!(exclude.matches("/.worktrees/").count(), 1);
+        GitContext::create_worktree(&repo_path, "other", WorktreeBranch::New, None, &config)
+            .unwrap();
+        let exclude = std::fs::read_to_string(repo_path.join(".git/info/exclude")).unwrap();
+        assert_eq!(exclude.matches("/.worktrees/").count(), 1);
+        let status = std::process::Command::new("git")
+            .args(["status", "--porcelain"])
+            .current_dir(&repo_path)
+            .output()
+            .unwrap();
+        assert!(status.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_parse_worktree_list() {
+        let output = "worktree /home/u/repo\nHEAD 1111111111111111111111111111111111111111\nbranch refs/heads/main\n\n\
+                      worktree /home/u/repo/.worktrees/ccs/fix-auth\nHEAD 2222222222222222222222222222222222222222\nbranch refs/heads/ccs/fix-auth\n\n\
+                      worktree /tmp/detached\nHEAD 3333333333333333333333333333333333333333\ndetached\nlocked moving disks\n\n\
+                      worktree /tmp/plain\nHEAD 4444444444444444444444444444444444444444\nbranch refs/heads/topic\nlocked\nprunable gitdir file points to non-existent location\n\n";
+        let entries = parse_worktree_list(output);
+        assert_eq!(
+            entries,
+            vec![
+                WorktreeEntry {
+                    path: PathBuf::from("/home/u/repo/.worktrees/ccs/fix-auth"),
+                    branch: Some("ccs/fix-auth".to_string()),
+                    locked: false,
+                },
+                WorktreeEntry {
+                    path: PathBuf::from("/tmp/detached"),
+                    branch: None,
+                    locked: true,
+                },
+                WorktreeEntry {
+                    path: PathBuf::from("/tmp/plain"),
+                    branch: Some("topic".to_string()),
+                    locked: true,
+                },
+            ]
+        );
+        // Without a marker, not even a ccs/ branch identifies a ccs worktree
+        assert!(!entries[0].is_ccs_created());
+        assert!(!entries[1].is_ccs_created());
+        assert!(!entries[2].is_ccs_created());
+
+        // A bare main repo is skipped along with the main worktree slot
+        let bare = "worktree /srv/repo.git\nbare\n\nworktree /srv/wt\nHEAD 5555555555555555555555555555555555555555\nbranch refs/heads/x\n";
+        assert_eq!(parse_worktree_list(bare).len(), 1);
+        assert!(parse_worktree_list("").is_empty());
+    }
+
+    /// Repo with one commit and a worktree for branch "feature"
+    fn repo_with_feature_worktree(tmp: &Path, on_exists: WorktreeOnExists) -> (PathBuf, Config) {
+        let repo_path = tmp.join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo_with_commits(&repo_path, 1);
+
+        let mut config = Config::default();
+        config.worktree.base_path = tmp.join("worktrees").display().to_string();
+        GitContext::create_worktree(&repo_path, "feature", WorktreeBranch::New, None, &config)
+            .unwrap();
+        config.worktree.on_exists = on_exists;
+        (repo_path, config)
+    }
+
+    #[test]
+    fn test_worktree_diff() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        init_repo_with_commits(tmp.path(), 1);
+        std::fs::write(tmp.path().join("file.txt"), "0\nmore\nlines\n").unwrap();
+        std::fs::write(tmp.path().join("staged.txt"), "new\n").unwrap();
+        git(tmp.path(), &["add", "staged.txt"]);
+        std::fs::write(tmp.path().join("scratch.txt"), "untracked\n").unwrap();
+
+        let summary = worktree_diff(tmp.path()).unwrap();
+        let mut files = summary.files.clone();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(
+            files,
+            [
+                FileChange {
+                    status: " M".to_string(),
+                    path: "file.txt".to_string(),
+                    lines: Some((3, 1)),
+                },
+                FileChange {
+                    status: "??".to_string(),
+                    path: "scratch.txt".to_string(),
+                    lines: None,
+                },
+                FileChange {
+                    status: "A ".to_string(),
+                    path: "staged.txt".to_string(),
+                    lines: Some((1, 0)),
+                },
+            ]
+        );
+        assert_eq!((summary.insertions, summary.deletions), (4, 1));
+
+        git(tmp.path(), &["add", "."]);
+        git(tmp.path(), &["commit", "-q", "-m", "done"]);
+        assert!(worktree_diff(tmp.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_worktree_by_branch() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (repo_path, config) = repo_with_feature_worktree(tmp.path(), WorktreeOnExists::Error);
+        GitContext::create_worktree(
+            &repo_path,
+            "ccs/fix-auth",
+            WorktreeBranch::New,
+            None,
+            &config,
+        )
+        .unwrap();
+
+        let feature = find_worktree_by_branch(&repo_path, "feature")
+            .unwrap()
+            .unwrap();
+        assert!(feature.ends_with("feature"));
+        let fix = find_worktree_by_branch(&repo_path, "fix-auth")
+            .unwrap()
+            .unwrap();
+        assert!(fix.ends_with("ccs/fix-auth"));
+        // Also works from inside a worktree
+        assert_eq!(
+            find_worktree_by_branch(&fix, "feature").unwrap(),
+            Some(feature)
+        );
+        assert_eq!(find_worktree_by_branch(&repo_path, "auth").unwrap(), None);
+    }
+
+    #[test]
+    fn test_branch_matches() {
+        assert!(branch_matches("ccs/fix-auth", "fix-auth"));
+        assert!(branch_matches("fix-auth", "fix-auth"));
+        assert!(!branch_matches("ccs/fix-auth", "auth"));
+        assert!(!branch_matches("ccs/fix-auth", ""));
+    }
+
+    #[test]
+    fn test_create_worktree_on_exists_error() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (repo_path, config) = repo_with_feature_worktree(tmp.path(), WorktreeOnExists::Error);
+
+        let result =
+            GitContext::create_worktree(&repo_path, "feature", WorktreeBranch::New, None, &config);
+        assert!(matches!(result, Err(GitError::WorktreeExists(_))));
+    }
+
+    #[test]
+    fn test_create_worktree_on_exists_reuse() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (repo_path, config) = repo_with_feature_worktree(tmp.path(), WorktreeOnExists::Reuse);
+
+        let ctx =
+            GitContext::create_worktree(&repo_path, "feature", WorktreeBranch::New, None, &config)
+                .unwrap();
+        assert!(ctx.is_worktree);
+        assert_eq!(
+            ctx.workspace_path.canonicalize().unwrap(),
+            tmp.path().join("worktrees/feature").canonicalize().unwrap()
+        );
+
+        // A plain directory in the way is not reused
+        std::fs::create_dir_all(tmp.path().join("worktrees/stray")).unwrap();
+        let result =
+            GitContext::create_worktree(&repo_path, "stray", WorktreeBranch::New, None, &config);
+        assert!(matches!(result, Err(GitError::WorktreeExists(_))));
+
+        // Nor is a worktree on another branch
+        let other = tmp.path().join("worktrees/other");
+        git(
+            &repo_path,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "unrelated",
+                &other.to_string_lossy(),
+            ],
+        );
+        let result =
+            GitContext::create_worktree(&repo_path, "other", WorktreeBranch::New, None, &config);
+        assert!(matches!(result, Err(GitError::WorktreeExists(_))));
+
+        // Nor one of another repository
+        let elsewhere = tmp.path().join("elsewhere");
+        std::fs::create_dir(&elsewhere).unwrap();
+        init_repo_with_commits(&elsewhere, 1);
+        let foreign = tmp.path().join("worktrees/foreign");
+        git(
+            &elsewhere,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "foreign",
+                &foreign.to_string_lossy(),
+            ],
+        );
+        let result =
+            GitContext::create_worktree(&repo_path, "foreign", WorktreeBranch::New, None, &config);
+        assert!(matches!(result, Err(GitError::WorktreeExists(_))));
+    }
+
+    #[test]
+    fn test_create_worktree_on_exists_suffix() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let (repo_path, config) = repo_with_feature_worktree(tmp.path(), WorktreeOnExists::Suffix);
+
+        let ctx =
+            GitContext::create_worktree(&repo_path, "feature", WorktreeBranch::New, None, &config)
+                .unwrap();
+        assert!(ctx.workspace_path.ends_with("feature-2"));
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(repo
+            .find_branch("feature-2", git2::BranchType::Local)
+            .is_ok());
+
+        let ctx =
+            GitContext::create_worktree(&repo_path, "feature", WorktreeBranch::New, None, &config)
+                .unwrap();
+        assert!(ctx.workspace_path.ends_with("feature-3"));
+
+        // The existing branch is checked out in feature/ already, which git
+        // won't allow twice
+        let result = GitContext::create_worktree(
+            &repo_path,
+            "feature",
+            WorktreeBranch::Existing,
+            None,
+            &config,
+        );
+        match result {
+            Err(GitError::BranchCheckedOut(branch, path)) => {
+                assert_eq!(branch, "feature");
+                assert!(path.ends_with("feature"));
+            }
+            other => panic!("expected BranchCheckedOut, got {:?}", other.map(|_| ())),
+        }
+
+        // Likewise for the main worktree's branch
+        let repo = Repository::open(&repo_path).unwrap();
+        let main = repo.head().unwrap().shorthand().unwrap().to_string();
+        let result =
+            GitContext::create_worktree(&repo_path, &main, WorktreeBranch::Existing, None, &config);
+        assert!(matches!(result, Err(GitError::BranchCheckedOut(..))));
+    }
+
+    #[test]
+    fn test_free_suffixed_name() {
+        let taken = ["wip-2", "wip-3"];
+        assert_eq!(
+            free_suffixed_name("wip", |c| taken.contains(&c)),
+            Some("wip-4".to_string())
+        );
+        assert_eq!(free_suffixed_name("wip", |_| true), None);
+    }
+
+    #[test]
+    fn test_branch_exists() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        init_repo_with_commits(tmp.path(), 1);
+        git(tmp.path(), &["branch", "feature"]);
+
+        let repo = Repository::open(tmp.path()).unwrap();
+        assert!(GitContext::branch_exists(&repo, "feature"));
+        assert!(!GitContext::branch_exists(&repo, "missing"));
+    }
+
+    #[test]
+    fn test_first_free_name_retries_until_free() {
+        let names = std::cell::RefCell::new(vec!["ccs-3", "ccs-2", "ccs-1"]);
+        let taken = ["ccs-1", "ccs-2"];
+        let name = first_free_name(
+            || Ok(names.borrow_mut().pop().unwrap().to_string()),
+            |n| taken.contains(&n),
+        );
+        assert_eq!(name.unwrap(), "ccs-3");
+
+        // A template that always renders the same name falls back to a suffix
+        let taken = ["wip", "wip-2"];
+        let name = first_free_name(|| Ok("wip".to_string()), |n| taken.contains(&n));
+        assert_eq!(name.unwrap(), "wip-3");
+
+        assert!(matches!(
+            first_free_name(|| Ok("wip".to_string()), |_| true),
+            Err(GitError::BranchExists(name)) if name == "wip"
+        ));
+    }
+
+    #[test]
+    fn test_create_worktree_existing_branch_fails_early() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let repo_path = tmp.path().join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo_with_commits(&repo_path, 1);
+        git(&repo_path, &["branch", "taken"]);
+
+        let mut config = Config::default();
+        let worktrees = tmp.path().join("worktrees");
+        config.worktree.base_path = worktrees.display().to_string();
+        let result =
+            GitContext::create_worktree(&repo_path, "taken", WorktreeBranch::New, None, &config);
+        assert!(matches!(result, Err(GitError::BranchExists(name)) if name == "taken"));
+        assert!(!worktrees.exists());
+    }
+
+    #[test]
+    fn test_create_worktree_sparse() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let repo_path = tmp.path().join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo_with_commits(&repo_path, 0);
+        for file in [
+            "services/api/main.rs",
+            "services/web/app.js",
+            "docs/guide.md",
+        ] {
+            let path = repo_path.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+        std::fs::write(repo_path.join("README.md"), "top").unwrap();
+        git(&repo_path, &["add", "."]);
+        git(&repo_path, &["commit", "-q", "-m", "layout"]);
+
+        let mut config = Config::default();
+        config.worktree.base_path = tmp.path().join("worktrees").display().to_string();
+        config.worktree.sparse_paths = vec!["services/api".to_string()];
+
+        let ctx =
+            GitContext::create_worktree(&repo_path, "feature", WorktreeBranch::New, None, &config)
+                .unwrap();
+        let ws = &ctx.workspace_path;
+        assert!(ws.join("services/api/main.rs").exists());
+        assert!(ws.join("README.md").exists());
+        assert!(!ws.join("services/web").exists());
+        assert!(!ws.join("docs").exists());
+
+        // The sparse worktree is clean, not showing the other paths as deleted
+        let status = std::process::Command::new("git")
+            .args(["status", "--porcelain"])
+            .current_dir(ws)
+            .output()
+            .unwrap();
+        assert!(status.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_create_worktree_bad_base_ref() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let repo_path = tmp.path().join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo_with_commits(&repo_path, 1);
+
+        let mut config = Config::default();
+        config.worktree.base_path = tmp.path().join("worktrees").display().to_string();
+
+        let result = GitContext::create_worktree(
+            &repo_path,
+            "feature",
+            WorktreeBranch::New,
+            Some("no-such-ref"),
+            &config,
+        );
+        assert!(matches!(result, Err(GitError::RefNotFound(ref r, _)) if r == "no-such-ref"));
     }
 
     #[test]
     fn test_generate_branch_name() {
-        let name1 = GitContext::generate_branch_name();
+        let dir = tempfile::TempDir::new().unwrap();
+        let name1 = GitContext::generate_branch_name("ccs-{timestamp}", dir.path()).unwrap();
         assert!(name1.starts_with("ccs-"));
 
         // Should contain a timestamp (numeric suffix)
         let suffix = name1.strip_prefix("ccs-").unwrap();
         assert!(suffix.parse::<u64>().is_ok());
     }
+
+    fn template_vars() -> BranchTemplateVars {
+        BranchTemplateVars {
+            user: Some("jane-doe".to_string()),
+            slug: Some("proj-42-login".to_string()),
+            date: "2024-01-15".to_string(),
+            timestamp: 1_705_314_600,
+            uuid: "0b7e5f0c-3c1a-4d2e-9f00-1234567890ab".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_branch_template() {
+        let vars = template_vars();
+        let cases = [
+            ("ccs-{timestamp}", "ccs-1705314600"),
+            ("{user}/{slug}", "jane-doe/proj-42-login"),
+            ("team/{slug}-{date}", "team/proj-42-login-2024-01-15"),
+            (
+                "{user}/{uuid}",
+                "jane-doe/0b7e5f0c-3c1a-4d2e-9f00-1234567890ab",
+            ),
+            ("wip", "wip"),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(
+                render_branch_template(template, &vars).unwrap(),
+                expected,
+                "template {}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_branch_template_errors() {
+        let vars = template_vars();
+        let cases = [
+            ("{ticket}-{slug}", "unknown placeholder '{ticket}'"),
+            ("{user", "unclosed '{'"),
+            ("{user}/", "not a valid branch name"),
+            ("my branch", "not a valid branch name"),
+        ];
+        for (template, expected) in cases {
+            let err = render_branch_template(template, &vars).unwrap_err();
+            assert!(
+                err.to_string().contains(expected),
+                "template {}: {}",
+                template,
+                err
+            );
+        }
+
+        let no_user = BranchTemplateVars {
+            user: None,
+            ..template_vars()
+        };
+        let err = render_branch_template("{user}/x", &no_user).unwrap_err();
+        assert!(err.to_string().contains("$USER"));
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Jane Doe"), "jane-doe");
+        assert_eq!(slugify("PROJ-42_Fix login!"), "proj-42-fix-login");
+        assert_eq!(slugify("--"), "");
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_705_314_600), "2024-01-15");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+    }
+
+    #[test]
+    fn test_random_uuid() {
+        let uuid = random_uuid().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(uuid, random_uuid().unwrap());
+    }
 }
@@ -2,65 +2,249 @@ mod auth;
 mod cleanup;
 mod config;
 mod docker;
+mod doctor;
+mod editor;
+mod exit;
 mod git;
 mod mcp;
+mod output;
+mod prompt;
+mod reaper;
+mod safety;
+mod secret_cache;
 mod secrets;
+mod telemetry;
 mod toolchain;
+mod version;
 
-use clap::{CommandFactory, Parser};
+use clap::{ArgGroup, CommandFactory, Parser};
 use clap_complete::{generate, Shell};
 use std::io;
-use std::path::PathBuf;
-
-use config::Config;
-use docker::{DockerRunner, RuntimeStatus};
-use git::GitContext;
+use std::path::{Path, PathBuf};
+
+use config::{Config, PermissionMode, WorktreeOnExists};
+use docker::{
+    AttachOptions, BuildOptions, DockerRunner, LogOptions, RunOptions, RunOverrides, RuntimeStatus,
+    SessionFilter, SessionStateFilter, SessionTarget,
+};
+use exit::{ErrorReport, ExitCode};
+use git::{GitContext, WorktreeBranch};
 use toolchain::Toolchain;
 
 /// Claude Code Sandbox - Run Claude Code safely in Docker containers
 #[derive(Parser, Debug)]
 #[command(name = "ccs", version, about)]
+#[command(group = ArgGroup::new("session_command").multiple(true).args(["attach", "logs", "stop"]))]
+#[command(group = ArgGroup::new("all_command").multiple(true).args(["logs", "stop"]))]
 struct Cli {
     /// Path to the project directory (defaults to current directory)
     #[arg(value_name = "PATH")]
     path: Option<PathBuf>,
 
-    /// Create a new worktree and start sandbox in it
-    #[arg(long = "new", value_name = "BRANCH")]
-    new_worktree: Option<String>,
+    /// Create a new worktree and start sandbox in it; without BRANCH, create a
+    /// new branch named from worktree.branch_template
+    #[arg(long = "new", value_name = "BRANCH", num_args = 0..=1)]
+    new_worktree: Option<Option<String>>,
 
     /// Create a new branch when creating worktree (use with --new)
     #[arg(short = 'b', long = "branch", requires = "new_worktree")]
     create_branch: bool,
 
+    /// Create a local branch tracking the remote branch BRANCH (or REMOTE/BRANCH)
+    /// (use with --new; done automatically when no local branch exists)
+    #[arg(long, requires = "new_worktree", conflicts_with = "create_branch")]
+    track: bool,
+
+    /// Base ref for the new branch (use with --new -b, defaults to HEAD)
+    #[arg(long = "from", value_name = "REF", requires = "create_branch")]
+    base_ref: Option<String>,
+
+    /// Only check out this directory in the new worktree (repeatable; overrides worktree.sparse_paths)
+    #[arg(long, value_name = "PATH", requires = "new_worktree")]
+    sparse: Vec<String>,
+
+    /// Use the existing worktree if the --new directory already exists
+    #[arg(long, requires = "new_worktree")]
+    reuse: bool,
+
+    /// Open the new worktree in your editor (use with --new)
+    #[arg(long, requires = "new_worktree")]
+    open: bool,
+
     /// Run directly in current directory without creating a worktree
     #[arg(long, conflicts_with = "new_worktree")]
     here: bool,
 
+    /// Also mount another project directory at /workspace-<name> (repeatable)
+    #[arg(long, value_name = "PATH", value_parser = git::parse_extra_workspace)]
+    also: Vec<PathBuf>,
+
+    /// Bind-mount a host path into the container (repeatable)
+    #[arg(short = 'm', long = "mount", value_name = "HOST:CONTAINER[:ro]", value_parser = docker::parse_mount)]
+    mounts: Vec<docker::Mount>,
+
+    /// Start Claude in this directory (relative paths resolve against /workspace)
+    #[arg(long, value_name = "PATH")]
+    workdir: Option<String>,
+
+    /// Memory limit for this run, overriding docker.memory_limit (e.g. 512m, 2g)
+    #[arg(long, value_name = "SIZE", value_parser = docker::parse_memory_limit)]
+    memory: Option<String>,
+
+    /// CPU limit for this run, overriding docker.cpu_limit (e.g. 2 or 1.5)
+    #[arg(long, value_name = "N", value_parser = docker::parse_cpus)]
+    cpus: Option<f32>,
+
+    /// Claude Code permission mode for this run, overriding claude.permission_mode
+    /// (default, acceptEdits, plan, bypassPermissions)
+    #[arg(long, value_name = "MODE", value_parser = docker::parse_permission_mode)]
+    permission_mode: Option<PermissionMode>,
+
+    /// Run the container as this user (user, uid, user:group or uid:gid)
+    #[arg(long, value_name = "USER", value_parser = docker::parse_user_spec)]
+    user: Option<String>,
+
+    /// Pass this env file to the container, regardless of docker.load_env_file (repeatable)
+    #[arg(long = "env-file", value_name = "PATH", value_parser = docker::parse_env_file_arg)]
+    env_files: Vec<PathBuf>,
+
+    /// Pass a flag verbatim to `docker run`, before the image (repeatable, not validated)
+    #[arg(long = "docker-arg", value_name = "FLAG", allow_hyphen_values = true)]
+    docker_args: Vec<String>,
+
     /// Run container in detached mode (background)
     #[arg(short = 'd', long)]
     detach: bool,
 
+    /// Copy detached session output to this host file
+    #[arg(long, value_name = "PATH", requires = "detach")]
+    log_file: Option<PathBuf>,
+
+    /// Start docker.shell in the sandbox instead of Claude, for debugging
+    #[arg(long, conflicts_with = "detach")]
+    shell: bool,
+
+    /// Name the session ccs-<repo>-<NAME> instead of using a timestamp
+    #[arg(long, value_name = "NAME", value_parser = docker::parse_session_name)]
+    name: Option<String>,
+
     /// List running ccs sessions
     #[arg(long)]
     list: bool,
 
+    /// Only list sessions for this repository (use with --list)
+    #[arg(long, value_name = "NAME", requires = "list")]
+    repo: Option<String>,
+
+    /// Only list running sessions (use with --list)
+    #[arg(long, requires = "list", conflicts_with = "stopped")]
+    running: bool,
+
+    /// Only list stopped sessions (use with --list)
+    #[arg(long, requires = "list")]
+    stopped: bool,
+
     /// Attach to a running ccs session
     #[arg(long, value_name = "CONTAINER")]
-    attach: Option<String>,
+    attach: Option<Option<String>>,
+
+    /// Act on the most recently created session (use with --attach, --logs or --stop)
+    #[arg(long, requires = "session_command")]
+    last: bool,
+
+    /// Print recent output before attaching (use with --attach)
+    #[arg(long, requires = "attach")]
+    replay: bool,
+
+    /// Number of lines to replay (use with --replay, default: 50)
+    #[arg(long, value_name = "N", requires = "replay")]
+    tail: Option<usize>,
 
     /// Show logs from a running/stopped ccs session
     #[arg(long, value_name = "CONTAINER")]
-    logs: Option<String>,
+    logs: Option<Option<String>>,
+
+    /// Follow the logs of (with --logs) or stop (with --stop) all running sessions
+    #[arg(long, requires = "all_command")]
+    all: bool,
+
+    /// Print the logs so far and exit instead of following them (use with --logs)
+    #[arg(long, requires = "logs")]
+    no_follow: bool,
+
+    /// Only show logs since a duration (e.g. 10m) or timestamp (use with --logs)
+    #[arg(long, value_name = "TIME", requires = "logs", value_parser = docker::parse_since)]
+    since: Option<String>,
 
     /// Stop a running ccs session
     #[arg(long, value_name = "CONTAINER")]
-    stop: Option<String>,
+    stop: Option<Option<String>>,
+
+    /// Remove a git worktree created by ccs
+    #[arg(long, value_name = "PATH")]
+    remove_worktree: Option<PathBuf>,
+
+    /// Remove the worktree even if it has unmerged commits or uncommitted changes
+    #[arg(long, requires = "remove_worktree")]
+    force: bool,
+
+    /// Answer yes to confirmation prompts (required when not on a terminal)
+    #[arg(short = 'y', long)]
+    yes: bool,
+
+    /// Summarize uncommitted changes in a session's worktree (by session or branch name)
+    #[arg(long, value_name = "CONTAINER|BRANCH")]
+    diff: Option<String>,
+
+    /// Print the complete patch after the summary (use with --diff)
+    #[arg(long, requires = "diff")]
+    full: bool,
+
+    /// Recreate a ccs session with the parameters it was started with
+    #[arg(long, value_name = "CONTAINER")]
+    restart: Option<String>,
+
+    /// Print a shell script recreating a session's docker run (secrets are not exported)
+    #[arg(long, value_name = "CONTAINER")]
+    export_command: Option<String>,
+
+    /// Show a session's CPU, memory and network/block I/O usage
+    #[arg(long, value_name = "CONTAINER")]
+    stats: Option<String>,
+
+    /// Keep printing samples until interrupted (use with --stats)
+    #[arg(long, requires = "stats")]
+    watch: bool,
+
+    /// Wait for a detached session to exit (all sessions if no name given)
+    #[arg(long, value_name = "CONTAINER")]
+    wait: Option<Option<String>>,
 
     /// Rebuild the container image before starting
     #[arg(long)]
     build: bool,
 
+    /// Build the image without using the layer cache (use with --build)
+    #[arg(long, requires = "build")]
+    no_cache: bool,
+
+    /// Only print build steps and the result; show the output tail on failure (use with --build)
+    #[arg(long, requires = "build")]
+    quiet: bool,
+
+    /// Reinstall Claude Code without a full rebuild, picking up the newest
+    /// release unless docker.claude_version pins one (use with --build)
+    #[arg(long, requires = "build")]
+    update_claude: bool,
+
+    /// Build only up to this stage of a multi-stage Dockerfile (use with --build)
+    #[arg(long, value_name = "STAGE", requires = "build", value_parser = docker::parse_build_target)]
+    target: Option<String>,
+
+    /// Pass a build argument to the image build (repeatable, use with --build)
+    #[arg(long = "build-arg", value_name = "KEY=VALUE", requires = "build", value_parser = docker::parse_build_arg)]
+    build_args: Vec<(String, String)>,
+
     /// Print the docker/podman command without executing it
     #[arg(long)]
     dry_run: bool,
@@ -69,12 +253,74 @@ struct Cli {
     #[arg(long)]
     config: bool,
 
+    /// Rewrite the config file with all current settings, keeping your values
+    /// (the old file is saved as config.toml.bak)
+    #[arg(long)]
+    reconfigure: bool,
+
+    /// Read the config from this file instead of ~/.config/ccs/config.toml
+    #[arg(long, value_name = "FILE")]
+    config_path: Option<PathBuf>,
+
     /// Show status of container runtime, image, and config
     #[arg(long)]
     status: bool,
 
-    /// Generate shell completions for the specified shell
-    #[arg(long, value_name = "SHELL")]
+    /// Print output without decoration (headers, blank lines, color) for
+    /// scripts and logs; --list prints tab-separated columns
+    #[arg(long)]
+    plain: bool,
+
+    /// Never use color (also set by a non-empty NO_COLOR)
+    #[arg(long)]
+    no_color: bool,
+
+    /// Print a JSON Schema for config.toml (for editor validation and completion)
+    #[arg(long)]
+    config_schema: bool,
+
+    /// Print version with OS, libgit2, container runtime and config path (for bug reports)
+    #[arg(long)]
+    version_full: bool,
+
+    /// Print --version-full and --stats output, and errors on stderr, as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Print the effective config after merging all sources and overrides
+    #[arg(long)]
+    print_config: bool,
+
+    /// Check the environment for problems that would break a sandbox
+    #[arg(long)]
+    doctor: bool,
+
+    /// Use this secrets backend for this run (overrides secrets.backend)
+    #[arg(long, value_name = "NAME")]
+    secrets_backend: Option<String>,
+
+    /// List supported secrets backends and whether their CLIs are installed
+    #[arg(long)]
+    list_secrets_backends: bool,
+
+    /// Check secret references in mcp.toml and docker.extra_env without resolving them
+    #[arg(long)]
+    validate_secrets: bool,
+
+    /// Add an MCP server to mcp.toml, prompting for its command, args and env
+    #[arg(long, value_name = "NAME")]
+    mcp_add: Option<String>,
+
+    /// Remove an MCP server from mcp.toml
+    #[arg(long, value_name = "NAME")]
+    mcp_remove: Option<String>,
+
+    /// List configured MCP servers, including those from .ccs.toml
+    #[arg(long)]
+    mcp_list: bool,
+
+    /// Generate shell completions for the specified shell (bash, zsh, fish, powershell, elvish)
+    #[arg(long, visible_alias = "shell-completion", value_name = "SHELL")]
     completions: Option<Shell>,
 
     /// Extra arguments to pass to Claude Code
@@ -82,87 +328,364 @@ struct Cli {
     claude_args: Vec<String>,
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() {
     let cli = Cli::parse();
+    let json = cli.json;
+
+    let result = try_main(cli);
+    telemetry::flush();
+    if let Err(err) = result {
+        if json {
+            let report = ErrorReport::from_error(&err);
+            match serde_json::to_string(&report) {
+                Ok(line) => eprintln!("{}", line),
+                Err(_) => eprintln!("Error: {:?}", err),
+            }
+        } else {
+            eprintln!("Error: {:?}", err);
+        }
+        std::process::exit(ExitCode::from_error(&err).code());
+    }
+}
+
+fn try_main(cli: Cli) -> anyhow::Result<()> {
+    output::init(cli.plain, cli.no_color);
 
     // Handle --completions flag: generate shell completions
     if let Some(shell) = cli.completions {
         let mut cmd = Cli::command();
         generate(shell, &mut cmd, "ccs", &mut io::stdout());
+        if let Some(helper) = session_completion_helper(shell) {
+            print!("{}", helper);
+        }
+        return Ok(());
+    }
+
+    // Handle --version-full: version plus runtime and platform details
+    if cli.version_full {
+        let info = version::build_info(cli.config_path.as_deref());
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        } else {
+            println!("{}", info);
+        }
+        return Ok(());
+    }
+
+    // Handle --config-schema: emit the JSON Schema for config.toml
+    if cli.config_schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&config::config_schema())?
+        );
         return Ok(());
     }
 
     // Handle --config flag: open config file in editor
     if cli.config {
-        return open_config_in_editor();
+        return open_config_in_editor(cli.config_path.as_deref());
+    }
+
+    // Handle --reconfigure: fill in new settings while keeping the user's
+    if cli.reconfigure {
+        return reconfigure(cli.config_path.as_deref());
+    }
+
+    // Determine project path
+    let project_path = cli
+        .path
+        .clone()
+        .unwrap_or_else(|| std::env::current_dir().expect("Failed to get current directory"));
+
+    // Load configuration, merging any project-local .ccs.toml
+    let mut config = match &cli.config_path {
+        Some(path) => Config::load_from(path, &project_path)?,
+        None => Config::load_with_project(&project_path)?,
+    };
+    telemetry::init(&config.telemetry);
+    docker::init_connection(&config);
+
+    // Apply one-run secrets backend override
+    if let Some(backend) = &cli.secrets_backend {
+        if !secrets::is_supported_backend(backend) {
+            anyhow::bail!("Unknown secrets backend: '{}'", backend);
+        }
+        config.secrets.backend = backend.clone();
+    }
+
+    // Handle --print-config flag: show the merged config
+    if cli.print_config {
+        return print_effective_config(&config, &cli, &project_path);
+    }
+
+    // Handle --list-secrets-backends flag: show backend availability
+    if cli.list_secrets_backends {
+        list_secrets_backends(&config);
+        return Ok(());
     }
 
-    // Load configuration
-    let config = Config::load()?;
+    // Handle --validate-secrets flag: check references without fetching values
+    if cli.validate_secrets {
+        if !validate_secrets(&config, &project_path)? {
+            std::process::exit(ExitCode::Failure.code());
+        }
+        return Ok(());
+    }
+
+    // Handle --mcp-add/--mcp-remove/--mcp-list: edit or show mcp.toml
+    if let Some(name) = &cli.mcp_add {
+        return mcp_add(&config, name);
+    }
+    if let Some(name) = &cli.mcp_remove {
+        let path = config.mcp_servers_path()?;
+        let mut servers = config::McpServersConfig::load(&path)?;
+        servers.remove_server(name)?;
+        servers.save(&path)?;
+        println!("Removed MCP server '{}' from {}", name, path.display());
+        return Ok(());
+    }
+    if cli.mcp_list {
+        match config::McpServersConfig::load_with_project(&config, &project_path)? {
+            Some(servers) if !servers.servers.is_empty() => {
+                println!("{}", mcp::format_server_list(&servers))
+            }
+            _ => println!("No MCP servers configured."),
+        }
+        return Ok(());
+    }
 
     // Lazy cleanup of orphaned worktrees (runs on every invocation)
-    let cleanup_result = cleanup::lazy_cleanup(&config);
+    let cleanup_result = cleanup::lazy_cleanup(&config, &project_path);
     if cleanup_result.had_changes() {
         cleanup_result.print_summary();
     }
 
+    // Stop detached sessions idle past docker.max_idle_secs
+    let reap_result = reaper::reap_idle_sessions(&config);
+    if reap_result.had_changes() {
+        reap_result.print_summary();
+    }
+    docker::reap_orphaned_mcp_configs();
+
     // Handle --status flag: show runtime status
     if cli.status {
-        let status = RuntimeStatus::check(&config);
+        let status = RuntimeStatus::check(&config, cli.config_path.as_deref());
         status.print(&config);
         return Ok(());
     }
 
+    // Handle --doctor flag: diagnose environment problems
+    if cli.doctor {
+        let status = RuntimeStatus::check(&config, cli.config_path.as_deref());
+        if !doctor::print_report(&doctor::diagnose(&status, &config)) {
+            std::process::exit(ExitCode::Failure.code());
+        }
+        return Ok(());
+    }
+
+    // Session commands only match containers named with this prefix
+    let prefix = docker::container_prefix(&config);
+
     // Handle --list flag: list running sessions
     if cli.list {
-        return docker::list_sessions();
+        let state = if cli.running {
+            Some(SessionStateFilter::Running)
+        } else if cli.stopped {
+            Some(SessionStateFilter::Stopped)
+        } else {
+            None
+        };
+        let filter = SessionFilter {
+            repo: cli.repo.clone(),
+            state,
+        };
+        return docker::list_sessions(&prefix, &filter);
     }
 
     // Handle --attach flag: attach to running session
     if let Some(container) = &cli.attach {
-        return docker::attach_session(container);
+        let options = AttachOptions {
+            replay_lines: cli
+                .replay
+                .then(|| cli.tail.unwrap_or(docker::DEFAULT_REPLAY_LINES)),
+        };
+        let target = session_target(container.as_deref(), cli.last, "--attach")?;
+        return docker::attach_session(&prefix, &target, &options);
     }
 
     // Handle --logs flag: show logs from session
     if let Some(container) = &cli.logs {
-        return docker::show_logs(container);
+        let options = LogOptions {
+            since: cli.since.clone(),
+            follow: !cli.no_follow,
+        };
+        if cli.all {
+            if container.is_some() || cli.last {
+                anyhow::bail!("--logs --all doesn't take a session name or --last");
+            }
+            return docker::follow_all_logs(&prefix, &options);
+        }
+        let target = session_target(container.as_deref(), cli.last, "--logs")?;
+        return docker::show_logs(&prefix, &target, &options);
     }
 
     // Handle --stop flag: stop a running session
     if let Some(container) = &cli.stop {
-        return docker::stop_session(container);
+        if cli.all {
+            if container.is_some() || cli.last {
+                anyhow::bail!("--stop --all doesn't take a session name or --last");
+            }
+            if docker::stop_all_sessions(&prefix)?.had_errors() {
+                std::process::exit(ExitCode::Failure.code());
+            }
+            return Ok(());
+        }
+        let target = session_target(container.as_deref(), cli.last, "--stop")?;
+        return docker::stop_session(&prefix, &target);
+    }
+
+    // Handle --remove-worktree flag: remove a single worktree, guarding unmerged work
+    if let Some(path) = &cli.remove_worktree {
+        return cleanup::remove_worktree_checked(path, &config, cli.force, cli.yes);
+    }
+
+    // Handle --diff flag: summarize changes in a session's worktree
+    if let Some(target) = &cli.diff {
+        let path = diff_target_path(&prefix, &project_path, target)?;
+        println!("Changes in {}:", path.display());
+        git::worktree_diff(&path)?.print();
+        if cli.full {
+            println!();
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(&path)
+                .args(["diff", "HEAD"])
+                .status()?;
+            if !status.success() {
+                anyhow::bail!("git diff failed in {}", path.display());
+            }
+        }
+        return Ok(());
+    }
+
+    // Handle --restart flag: recreate a session from its metadata
+    if let Some(container) = &cli.restart {
+        return docker::restart_session(&prefix, container, cli.config_path.as_deref());
+    }
+
+    if let Some(container) = &cli.export_command {
+        return docker::export_session_command(&prefix, container, cli.config_path.as_deref());
+    }
+
+    // Handle --stats flag: show a session's resource usage
+    if let Some(container) = &cli.stats {
+        return docker::show_stats(&prefix, container, cli.watch, cli.json);
+    }
+
+    // Handle --wait flag: block until session(s) exit, propagating the exit code
+    if let Some(container) = &cli.wait {
+        let code = match container {
+            Some(container) => docker::wait_session(&prefix, container)?,
+            None => docker::wait_all_sessions(&prefix)?,
+        };
+        if code != 0 {
+            std::process::exit(code);
+        }
+        return Ok(());
     }
 
     // Handle --build flag: rebuild container image
     if cli.build {
-        return DockerRunner::build_image(&config);
+        let options = BuildOptions {
+            no_cache: cli.no_cache,
+            build_args: cli.build_args.clone(),
+            quiet: cli.quiet,
+            update_claude: cli.update_claude,
+            target: cli.target.clone(),
+        };
+        return DockerRunner::build_image(&config, &options);
     }
 
-    // Determine project path
-    let project_path = cli
-        .path
-        .unwrap_or_else(|| std::env::current_dir().expect("Failed to get current directory"));
+    let project_path = safety::canonicalize_project_path(&project_path)?;
+
+    // Refuse to sandbox blocked or non-allowed directories, going by the
+    // global config alone so a project can't exempt itself
+    let global_config = Config::load_global(cli.config_path.as_deref())?;
+    safety::check_path(&project_path, &global_config)?;
+    // --also directories are mounted too (already canonicalized when parsed)
+    for path in &cli.also {
+        safety::check_path(path, &global_config)?;
+    }
+
+    // --reuse overrides worktree.on_exists for this run
+    if cli.reuse {
+        config.worktree.on_exists = WorktreeOnExists::Reuse;
+    }
 
-    let project_path = project_path.canonicalize().map_err(|e| {
-        anyhow::anyhow!(
-            "Failed to resolve project path '{}': {}",
-            project_path.display(),
-            e
-        )
-    })?;
+    // Per-run overrides, recorded with the session so --restart keeps them
+    let overrides = RunOverrides {
+        memory: cli.memory.clone(),
+        cpus: cli.cpus,
+        permission_mode: cli.permission_mode,
+        workdir: cli.workdir.clone(),
+        sparse_paths: cli.sparse.clone(),
+        name: cli.name.clone(),
+    };
+
+    // Apply per-run sparse checkout paths
+    if !overrides.sparse_paths.is_empty() {
+        config.worktree.sparse_paths = overrides.sparse_paths.clone();
+    }
 
     // Set up git context (detect or create worktree)
     // Default behavior: auto-create worktree unless --here is specified
     let git_context = if let Some(branch_name) = &cli.new_worktree {
-        // Explicit branch name provided with --new
-        GitContext::create_worktree(&project_path, branch_name, cli.create_branch, &config)?
+        // Bare --new always creates a branch, named from the template
+        let (branch_name, branch) = match branch_name {
+            Some(name) if cli.create_branch => (name.clone(), WorktreeBranch::New),
+            Some(name) if cli.track => (name.clone(), WorktreeBranch::Track),
+            Some(name) => (name.clone(), WorktreeBranch::Existing),
+            None if cli.track => anyhow::bail!("--track needs a branch: --new <BRANCH> --track"),
+            None => (
+                GitContext::generate_free_branch_name(
+                    &config.worktree.branch_template,
+                    &project_path,
+                )?,
+                WorktreeBranch::New,
+            ),
+        };
+        let git_context = GitContext::create_worktree(
+            &project_path,
+            &branch_name,
+            branch,
+            cli.base_ref.as_deref(),
+            &config,
+        )?;
+
+        if cli.open {
+            editor::open_in_editor(&config, &git_context.workspace_path)?;
+        }
+
+        git_context
     } else if cli.here {
         // --here: run in current directory without creating worktree
-        GitContext::detect(&project_path)?
+        GitContext::detect(&project_path).map_err(|e| match e {
+            git::GitError::NoRepoFound(_) => {
+                anyhow::Error::new(e).context("--here requires a git repository")
+            }
+            e => e.into(),
+        })?
     } else {
         // Default: auto-create worktree with generated branch name
-        let branch_name = GitContext::generate_branch_name();
-        match GitContext::create_worktree(&project_path, &branch_name, true, &config) {
+        let branch_name =
+            GitContext::generate_free_branch_name(&config.worktree.branch_template, &project_path)?;
+        match GitContext::create_worktree(
+            &project_path,
+            &branch_name,
+            WorktreeBranch::New,
+            None,
+            &config,
+        ) {
             Ok(ctx) => ctx,
             Err(git::GitError::CannotCreateFromWorktree) => {
                 // Already in a worktree, just use it
@@ -172,22 +695,296 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
-    // Detect project toolchain
-    let toolchain = Toolchain::detect(&git_context.workspace_path);
+    // Apply per-run resource limit, permission mode and workdir overrides
+    overrides.apply(&mut config, &git_context.workspace_path)?;
+
+    // Detect project toolchain, including any --also directories
+    let mut toolchain = Toolchain::detect(&git_context.workspace_path);
+    for path in &cli.also {
+        toolchain.merge(Toolchain::detect(path));
+    }
     if !toolchain.is_empty() {
         println!("Detected toolchain: {}", toolchain.tool_names().join(", "));
     }
-
-    // Generate MCP configuration with resolved secrets
-    let mcp_config_path = mcp::generate_mcp_config(&config)?;
+    for note in &toolchain.notes {
+        println!("Note: {}", note);
+    }
 
     // Run the Docker container (or print command if dry-run)
-    let runner = DockerRunner::new(&config, &git_context, mcp_config_path, toolchain)?;
-    runner.run(&cli.claude_args, cli.detach, cli.dry_run)
+    let mut runner =
+        DockerRunner::new(&config, &git_context, toolchain, overrides.name.as_deref())?;
+
+    // Generate the session's MCP configuration with resolved secrets
+    runner.generate_mcp_config()?;
+    let options = RunOptions {
+        detach: cli.detach,
+        dry_run: cli.dry_run,
+        mounts: cli.mounts,
+        log_file: cli.log_file,
+        also: cli.also,
+        env_files: cli.env_files,
+        user: cli.user,
+        shell: cli.shell,
+        docker_args: cli.docker_args,
+        keep_secret_refs: false,
+        overrides,
+    };
+    runner.run(&cli.claude_args, &options)?;
+
+    // run() only returns after an interactive session once it exited cleanly
+    if config.worktree.push_on_exit && !cli.detach && !cli.dry_run && git_context.is_worktree {
+        if let Some(branch) = git_context.current_branch() {
+            push_on_exit(
+                &git_context.workspace_path,
+                &config.git.default_remote,
+                &branch,
+                cli.yes,
+            )?;
+        }
+    }
+    Ok(())
 }
 
-fn open_config_in_editor() -> anyhow::Result<()> {
-    let config_path = Config::config_path()?;
+/// Offer to push the session's branch, or push it straight away with `--yes`
+fn push_on_exit(
+    worktree: &Path,
+    remote: &str,
+    branch: &str,
+    assume_yes: bool,
+) -> anyhow::Result<()> {
+    let is_tty = prompt::stdin_is_tty();
+    if !assume_yes && !is_tty {
+        eprintln!(
+            "Not pushing {} without a terminal; pass --yes to push on exit",
+            branch
+        );
+        return Ok(());
+    }
+
+    let question = format!("Push {} to {}?", branch, remote);
+    if prompt::confirm(&question, assume_yes, is_tty)? {
+        git::push_branch(worktree, remote, branch)?;
+        println!("Pushed {} to {} (upstream set)", branch, remote);
+    }
+    Ok(())
+}
+
+/// Session named on the command line for `flag`, or the newest one with `--last`
+fn session_target(name: Option<&str>, last: bool, flag: &str) -> anyhow::Result<SessionTarget> {
+    match (name, last) {
+        (Some(name), false) => Ok(SessionTarget::Named(name.to_string())),
+        (None, true) => Ok(SessionTarget::Last),
+        (Some(_), true) => anyhow::bail!("{} --last doesn't take a session name", flag),
+        (None, false) => {
+            anyhow::bail!(
+                "{} needs a session name, or --last for the most recent session",
+                flag
+            )
+        }
+    }
+}
+
+/// Options whose value is a session name, completed from `ccs --list`
+const SESSION_NAME_OPTIONS: &str =
+    "--attach|--logs|--stop|--restart|--wait|--diff|--export-command|--stats";
+
+/// Extra completion code that completes session names from `ccs --list`
+///
+/// Appended after clap's static script for shells where it can be layered on
+/// without replacing the generated completion function.
+fn session_completion_helper(shell: Shell) -> Option<String> {
+    // Names are the first column of the table's rows, whatever the prefix;
+    // --plain separates columns with tabs, which the log file lines lack
+    let sessions = "ccs --list --plain 2>/dev/null | awk -F '\\t' 'NR > 1 && NF > 1 {print $1}'";
+    match shell {
+        Shell::Bash => Some(format!(
+            r#"
+_ccs_sessions() {{
+    case "${{COMP_WORDS[COMP_CWORD-1]}}" in
+        {options})
+            COMPREPLY=($(compgen -W "$({sessions})" -- "${{COMP_WORDS[COMP_CWORD]}}"))
+            return 0
+            ;;
+    esac
+    _ccs "$@"
+}}
+complete -F _ccs_sessions -o bashdefault -o default ccs
+"#,
+            options = SESSION_NAME_OPTIONS,
+            sessions = sessions,
+        )),
+        Shell::Fish => Some(
+            std::iter::once(format!(
+                "\nfunction __ccs_sessions\n    {}\nend\n",
+                sessions
+            ))
+            .chain(SESSION_NAME_OPTIONS.split('|').map(|opt| {
+                format!(
+                    "complete -c ccs -l {} -x -a '(__ccs_sessions)'\n",
+                    opt.trim_start_matches("--")
+                )
+            }))
+            .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn list_secrets_backends(config: &Config) {
+    for status in secrets::backend_status() {
+        let availability = match status.cli {
+            Some(cli) if status.available => format!("{} found", cli),
+            Some(cli) => format!("{} NOT found", cli),
+            None => "built-in".to_string(),
+        };
+        let active = if status.name == config.secrets.backend {
+            " (active)"
+        } else {
+            ""
+        };
+        println!(
+            "{} ({}): {}{}",
+            status.name, status.scheme, availability, active
+        );
+    }
+}
+
+/// Print the status of every secret reference in mcp.toml and docker.extra_env,
+/// returning whether all of them can be resolved
+fn validate_secrets(config: &Config, project_path: &Path) -> anyhow::Result<bool> {
+    let mut references: Vec<(String, &String)> = Vec::new();
+
+    let mut extra_env: Vec<_> = config.docker.extra_env.iter().collect();
+    extra_env.sort();
+    for (key, value) in extra_env {
+        references.push((format!("docker.extra_env.{}", key), value));
+    }
+
+    let mcp_servers = config::McpServersConfig::load_with_project(config, project_path)?;
+    let mut servers: Vec<_> = mcp_servers.iter().flat_map(|s| &s.servers).collect();
+    servers.sort_by_key(|(name, _)| *name);
+    for (name, server) in servers {
+        let mut env: Vec<_> = server.env.iter().collect();
+        env.sort();
+        for (key, value) in env {
+            references.push((format!("servers.{}.env.{}", name, key), value));
+        }
+    }
+
+    let mut ok = true;
+    let mut checked = 0;
+    for (location, value) in references {
+        if let Some(check) =
+            secrets::check_reference(value, &config.secrets, &secrets::SystemRunner)
+        {
+            println!("{}: {}", location, check);
+            ok &= !check.is_failure();
+            checked += 1;
+        }
+    }
+
+    if checked == 0 {
+        println!("No secret references found.");
+    }
+    Ok(ok)
+}
+
+/// Worktree for `--diff`: a session's workspace, else a worktree of the
+/// current repository with that branch checked out
+///
+/// Interactive sessions are removed when they exit, so the worktree is often
+/// all that is left to look at.
+fn diff_target_path(prefix: &str, project_path: &Path, target: &str) -> anyhow::Result<PathBuf> {
+    let session_error = match docker::session_workspace(prefix, target) {
+        Ok(path) => return Ok(path),
+        Err(e) => e,
+    };
+    match git::find_worktree_by_branch(project_path, target) {
+        Ok(Some(path)) => Ok(path),
+        Ok(None) => {
+            Err(session_error.context(format!("no worktree has branch '{}' either", target)))
+        }
+        Err(e @ git::GitError::AmbiguousBranch(..)) => Err(e.into()),
+        Err(_) => Err(session_error),
+    }
+}
+
+/// Prompt for a new MCP server and add it to mcp.toml
+fn mcp_add(config: &Config, name: &str) -> anyhow::Result<()> {
+    if name.trim().is_empty() {
+        anyhow::bail!("--mcp-add needs a server name");
+    }
+    let path = config.mcp_servers_path()?;
+    let mut servers = config::McpServersConfig::load(&path)?;
+    // Fail before prompting rather than after
+    if servers.servers.contains_key(name) {
+        return Err(config::ConfigError::ServerExists(name.to_string()).into());
+    }
+
+    println!("Adding MCP server '{}' to {}", name, path.display());
+    let server = mcp::prompt_server(&mut std::io::stdin().lock(), &mut std::io::stdout())?;
+    servers.add_server(name, server)?;
+    servers.save(&path)?;
+    println!("Added MCP server '{}'", name);
+    Ok(())
+}
+
+/// Print the merged config as TOML, headed by the sources that contributed to it
+///
+/// Secret references in `extra_env` are printed as written, never resolved.
+fn print_effective_config(config: &Config, cli: &Cli, project_path: &Path) -> anyhow::Result<()> {
+    let global_path = match &cli.config_path {
+        Some(path) => path.clone(),
+        None => Config::config_path()?,
+    };
+
+    let mut sources = vec!["built-in defaults".to_string()];
+    if global_path.exists() {
+        sources.push(global_path.display().to_string());
+    }
+    if let Some(project_file) = config::find_project_config(project_path) {
+        sources.push(project_file.display().to_string());
+    }
+    if cli.secrets_backend.is_some() {
+        sources.push("--secrets-backend".to_string());
+    }
+
+    println!("# Effective ccs config");
+    println!("# Sources (later override earlier):");
+    for source in &sources {
+        println!("#   {}", source);
+    }
+    println!();
+    print!("{}", config.to_toml()?);
+    Ok(())
+}
+
+fn reconfigure(path: Option<&Path>) -> anyhow::Result<()> {
+    let config_path = match path {
+        Some(path) => path.to_path_buf(),
+        None => Config::config_path()?,
+    };
+    if !config_path.exists() {
+        anyhow::bail!(
+            "No config at {}; run `ccs --config` to create one",
+            config_path.display()
+        );
+    }
+
+    let upgrade = Config::upgrade_on_disk(&config_path)?;
+    for key in &upgrade.dropped_keys {
+        eprintln!("Warning: dropped unknown setting '{}'", key);
+    }
+    println!("Updated config at: {}", config_path.display());
+    println!("Previous version saved to: {}", upgrade.backup.display());
+    Ok(())
+}
+
+fn open_config_in_editor(path: Option<&Path>) -> anyhow::Result<()> {
+    let config_path = match path {
+        Some(path) => path.to_path_buf(),
+        None => Config::config_path()?,
+    };
 
     // Ensure config directory exists
     if let Some(parent) = config_path.parent() {
@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
-use crate::config::{Config, McpServersConfig};
-use crate::secrets::{resolve_secrets, SecretsError};
+use crate::config::{expand_path, repo_root, Config, McpServer, McpServersConfig};
+use crate::git::CONTAINER_WORKSPACE;
+use crate::secrets::{self, resolve_secrets, ParseError, SecretsError};
+use crate::telemetry;
 
 #[derive(Error, Debug)]
 pub enum McpError {
@@ -22,8 +26,14 @@ pub enum McpError {
 
     #[error("Failed to persist temp file: {0}")]
     TempFilePersist(#[from] tempfile::PathPersistError),
+
+    #[error("Failed to parse {0}: {1}")]
+    ProjectConfigParse(PathBuf, serde_json::Error),
 }
 
+/// Claude Code's own project MCP config, merged over the servers from mcp.toml
+pub const PROJECT_MCP_JSON: &str = ".mcp.json";
+
 /// Claude Code MCP configuration format (JSON)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -42,13 +52,47 @@ pub struct ClaudeMcpServer {
     pub env: HashMap<String, String>,
 }
 
-/// Generate MCP configuration file with resolved secrets
+/// Expand the `{workspace}` placeholder to the container workspace path
+fn expand_workspace(path: &str, workspace: &str) -> String {
+    path.replace("{workspace}", workspace)
+}
+
+/// Wrap a command so it runs in `cwd`
+///
+/// The directory and command are passed as positional parameters to
+/// `sh -c`, so they never need shell quoting.
+fn wrap_in_cwd(cwd: &str, command: String, args: Vec<String>) -> (String, Vec<String>) {
+    let mut wrapped = vec![
+        "-c".to_string(),
+        r#"cd "$1" && shift && exec "$@""#.to_string(),
+        "sh".to_string(),
+        cwd.to_string(),
+        command,
+    ];
+    wrapped.extend(args);
+    ("sh".to_string(), wrapped)
+}
+
+/// Generate MCP configuration file with resolved secrets for the session `session`
 /// Returns the path to the generated config file
-pub fn generate_mcp_config(config: &Config) -> Result<Option<PathBuf>, McpError> {
-    // Load MCP servers config
-    let mcp_servers = match McpServersConfig::load()? {
-        Some(servers) => servers,
-        None => return Ok(None),
+pub fn generate_mcp_config(
+    config: &Config,
+    project_path: &Path,
+    session: &str,
+) -> Result<Option<PathBuf>, McpError> {
+    // Load MCP servers config, including servers from a project .ccs.toml
+    let mcp_servers = McpServersConfig::load_with_project(config, project_path)?;
+    let project_servers = load_project_mcp_json(project_path)?;
+    if mcp_servers.is_none() && project_servers.is_none() {
+        return Ok(None);
+    }
+    let mcp_servers = mcp_servers.unwrap_or_default();
+
+    // The workspace is at its host path inside the container when mirrored
+    let workspace = if config.docker.mirror_host_path {
+        project_path.display().to_string()
+    } else {
+        CONTAINER_WORKSPACE.to_string()
     };
 
     // Convert to Claude MCP format and resolve secrets
@@ -72,8 +116,15 @@ pub fn generate_mcp_config(config: &Config) -> Result<Option<PathBuf>, McpError>
         let mut all_args = implicit_args;
         all_args.extend(server.args);
 
+        // Claude's config has no working directory key, so cd in a wrapper shell
+        let (command, all_args) = match server.cwd {
+            Some(ref cwd) => wrap_in_cwd(&expand_workspace(cwd, &workspace), command, all_args),
+            None => (command, all_args),
+        };
+
         // Resolve secrets in environment variables
-        let resolved_env = resolve_secrets(&server.env, &config.secrets.backend)?;
+        let resolved_env =
+            telemetry::timed("secrets", || resolve_secrets(&server.env, &config.secrets))?;
 
         claude_config.mcp_servers.insert(
             name,
@@ -85,24 +136,390 @@ pub fn generate_mcp_config(config: &Config) -> Result<Option<PathBuf>, McpError>
         );
     }
 
-    // Write to temporary file
+    // Servers from the project's .mcp.json win over ccs's own by name. The
+    // file comes with the repository, so its env is passed through as written
+    // rather than resolving the user's secrets for it
+    for (name, server) in project_servers.unwrap_or_default() {
+        let mut references: Vec<&String> = server
+            .env
+            .iter()
+            .filter(|(_, value)| secrets::mentions_secret_reference(value))
+            .map(|(key, _)| key)
+            .collect();
+        if !references.is_empty() {
+            references.sort();
+            eprintln!(
+                "Warning: not resolving secret references in {} for MCP server '{}' ({}); declare the server in mcp.toml instead",
+                PROJECT_MCP_JSON,
+                name,
+                references
+                    .iter()
+                    .map(|key| key.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        claude_config.mcp_servers.insert(name, server);
+    }
+
+    if let Some(ref output_path) = config.mcp.output_path {
+        let output_path = expand_path(&output_path.to_string_lossy());
+        write_config_to(&claude_config, &output_path)?;
+        return Ok(Some(output_path));
+    }
+
+    // Each session mounts a file of its own, kept until the session is gone
+    let path = match session_config_path(session) {
+        Some(path) => {
+            write_config_to(&claude_config, &path)?;
+            path
+        }
+        None => write_temp_config(&claude_config)?,
+    };
+
+    Ok(Some(path))
+}
+
+/// Whether any MCP servers are configured for `project_path`, without
+/// resolving secrets or writing anything
+pub fn has_servers(config: &Config, project_path: &Path) -> Result<bool, McpError> {
+    Ok(
+        McpServersConfig::load_with_project(config, project_path)?.is_some()
+            || load_project_mcp_json(project_path)?.is_some(),
+    )
+}
+
+/// Servers from a `.mcp.json` at the repository root, if there is one
+fn load_project_mcp_json(
+    project_path: &Path,
+) -> Result<Option<HashMap<String, ClaudeMcpServer>>, McpError> {
+    let path = repo_root(project_path).join(PROJECT_MCP_JSON);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&path)?;
+    parse_mcp_json(&contents)
+        .map(Some)
+        .map_err(|e| McpError::ProjectConfigParse(path, e))
+}
+
+/// Parse Claude Code's native `{"mcpServers": {...}}` shape
+///
+/// Only servers started from a `command` are supported; remote (`url`)
+/// servers are skipped with a warning.
+fn parse_mcp_json(contents: &str) -> Result<HashMap<String, ClaudeMcpServer>, serde_json::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct NativeConfig {
+        #[serde(default)]
+        mcp_servers: HashMap<String, serde_json::Value>,
+    }
+
+    let native: NativeConfig = serde_json::from_str(contents)?;
+    let mut servers = HashMap::new();
+    for (name, server) in native.mcp_servers {
+        if server.get("command").is_none() {
+            eprintln!(
+                "Warning: skipping MCP server '{}' in {}: only servers with a command are supported",
+                name, PROJECT_MCP_JSON
+            );
+            continue;
+        }
+        servers.insert(name, serde_json::from_value(server)?);
+    }
+    Ok(servers)
+}
+
+/// Ask for a new server's command, args, env and working directory
+///
+/// Args and env entries are read one per line until an empty line. Env values
+/// that look like secret references are checked, and re-asked if malformed.
+pub fn prompt_server(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> std::io::Result<McpServer> {
+    let command = loop {
+        let command = read_answer(input, output, "Command (e.g. npx): ")?;
+        if !command.is_empty() {
+            break command;
+        }
+        if at_eof(input)? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "no command given",
+            ));
+        }
+    };
+
+    writeln!(output, "Arguments, one per line (empty line to finish):")?;
+    let mut args = Vec::new();
+    loop {
+        let arg = read_answer(input, output, "  arg: ")?;
+        if arg.is_empty() {
+            break;
+        }
+        args.push(arg);
+    }
+
+    writeln!(
+        output,
+        "Environment as KEY=VALUE, one per line (empty line to finish).\n\
+         Values may be secret references, e.g. GITHUB_TOKEN=op://vault/github/token:"
+    )?;
+    let mut env = HashMap::new();
+    loop {
+        let entry = read_answer(input, output, "  env: ")?;
+        if entry.is_empty() {
+            break;
+        }
+        let Some((key, value)) = entry.split_once('=').filter(|(k, _)| !k.trim().is_empty()) else {
+            writeln!(output, "  Expected KEY=VALUE")?;
+            continue;
+        };
+        if let Err(e @ ParseError::Malformed(_)) = secrets::validate_reference(value) {
+            writeln!(output, "  {}: {}", value, e)?;
+            continue;
+        }
+        env.insert(key.trim().to_string(), value.to_string());
+    }
+
+    let cwd = read_answer(
+        input,
+        output,
+        "Working directory ({workspace} for the workspace, empty for default): ",
+    )?;
+
+    Ok(McpServer {
+        command,
+        args,
+        env,
+        cwd: (!cwd.is_empty()).then_some(cwd),
+    })
+}
+
+/// Print `question` and read a trimmed answer; empty at end of input
+fn read_answer(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+) -> std::io::Result<String> {
+    write!(output, "{}", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+fn at_eof(input: &mut impl BufRead) -> std::io::Result<bool> {
+    Ok(input.fill_buf()?.is_empty())
+}
+
+/// One line per server (`name: command args`), then its env and cwd, sorted by name
+///
+/// Secret references are shown only by scheme, e.g. `op://…`.
+pub fn format_server_list(servers: &McpServersConfig) -> String {
+    let mut names: Vec<&String> = servers.servers.keys().collect();
+    names.sort();
+
+    let mut lines = Vec::new();
+    for name in names {
+        let server = &servers.servers[name];
+        let command = std::iter::once(server.command.as_str())
+            .chain(server.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(format!("{}: {}", name, command));
+
+        let mut env: Vec<_> = server.env.iter().collect();
+        env.sort();
+        for (key, value) in env {
+            lines.push(format!("  {}={}", key, mask_reference(value)));
+        }
+        if let Some(ref cwd) = server.cwd {
+            lines.push(format!("  cwd: {}", cwd));
+        }
+    }
+    lines.join("\n")
+}
+
+/// `scheme://…` for a secret reference, otherwise the value itself
+fn mask_reference(value: &str) -> String {
+    match secrets::validate_reference(value) {
+        Err(ParseError::NotAReference) | Err(ParseError::UnknownScheme(_)) => value.to_string(),
+        _ => match value.split_once("://") {
+            Some((scheme, _)) => format!("{}://…", scheme),
+            None => value.to_string(),
+        },
+    }
+}
+
+/// Prefix of generated temp config files
+const TEMP_CONFIG_PREFIX: &str = "ccs-mcp-";
+
+/// How long a session's config may exist before its container does, e.g.
+/// while the image builds
+const ORPHAN_CONFIG_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// Directory of the per-session generated configs
+fn session_config_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("ccs").join("mcp"))
+}
+
+/// Generated config of the session `session`
+fn session_config_path(session: &str) -> Option<PathBuf> {
+    session_config_dir().map(|dir| dir.join(format!("{}.json", session)))
+}
+
+/// Remove the generated config of a session that is gone
+pub fn remove_session_config(session: &str) {
+    if let Some(path) = session_config_path(session) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Remove generated configs whose session is not among `sessions` (every
+/// ccs container, running or not)
+pub fn reap_orphaned_configs(sessions: &[String]) {
+    if let Some(dir) = session_config_dir() {
+        reap_orphaned_configs_in(&dir, sessions, ORPHAN_CONFIG_GRACE, SystemTime::now());
+    }
+}
+
+fn reap_orphaned_configs_in(dir: &Path, sessions: &[String], grace: Duration, now: SystemTime) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(session) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if sessions.iter().any(|s| s == session) {
+            continue;
+        }
+        // A session being started has its config before its container
+        let old_enough = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age >= grace);
+        if old_enough {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// Write the config to a kept temp file readable only by the current user
+fn write_temp_config(claude_config: &ClaudeMcpConfig) -> Result<PathBuf, McpError> {
     let temp_file = tempfile::Builder::new()
-        .prefix("ccs-mcp-")
+        .prefix(TEMP_CONFIG_PREFIX)
         .suffix(".json")
         .tempfile()?;
 
-    let config_json = serde_json::to_string_pretty(&claude_config)?;
+    // The file holds resolved secrets, so don't rely on the umask
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(temp_file.path(), std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    let config_json = serde_json::to_string_pretty(claude_config)?;
     std::fs::write(temp_file.path(), &config_json)?;
 
     // Keep the file (don't delete on drop)
-    let path = temp_file.into_temp_path().keep()?;
+    Ok(temp_file.into_temp_path().keep()?)
+}
 
-    Ok(Some(path))
+/// Write the config to a fixed path readable only by the current user, replacing its contents
+fn write_config_to(claude_config: &ClaudeMcpConfig, path: &Path) -> Result<(), McpError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let config_json = serde_json::to_string_pretty(claude_config)?;
+    options.open(path)?.write_all(config_json.as_bytes())?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::McpConfig;
+
+    #[test]
+    fn test_prompt_server() {
+        let answers = "npx\n-y\n@modelcontextprotocol/server-github\n\n\
+                       NOPE\nGITHUB_TOKEN=op://vault\nGITHUB_TOKEN=op://vault/github/token\nDEBUG=1\n\n\
+                       {workspace}\n";
+        let mut output = Vec::new();
+        let server = prompt_server(&mut answers.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(server.command, "npx");
+        assert_eq!(server.args, ["-y", "@modelcontextprotocol/server-github"]);
+        assert_eq!(server.env.len(), 2);
+        assert_eq!(server.env["GITHUB_TOKEN"], "op://vault/github/token");
+        assert_eq!(server.env["DEBUG"], "1");
+        assert_eq!(server.cwd.as_deref(), Some("{workspace}"));
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Expected KEY=VALUE"));
+        assert!(output.contains("op://vault: malformed reference"));
+    }
+
+    #[test]
+    fn test_prompt_server_requires_command() {
+        let mut output = Vec::new();
+        let err = prompt_server(&mut "\n".as_bytes(), &mut output).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_format_server_list_masks_references() {
+        let servers = McpServersConfig {
+            servers: HashMap::from([
+                (
+                    "github".to_string(),
+                    McpServer {
+                        command: "npx".to_string(),
+                        args: vec!["-y".to_string(), "server-github".to_string()],
+                        env: HashMap::from([
+                            (
+                                "GITHUB_TOKEN".to_string(),
+                                "op://vault/github/token".to_string(),
+                            ),
+                            ("API_URL".to_string(), "https://api.github.com".to_string()),
+                        ]),
+                        cwd: None,
+                    },
+                ),
+                (
+                    "fs".to_string(),
+                    McpServer {
+                        command: "mcp-fs".to_string(),
+                        args: vec![],
+                        env: HashMap::new(),
+                        cwd: Some("{workspace}".to_string()),
+                    },
+                ),
+            ]),
+        };
+
+        assert_eq!(
+            format_server_list(&servers),
+            "fs: mcp-fs\n  cwd: {workspace}\n\
+             github: npx -y server-github\n  API_URL=https://api.github.com\n  GITHUB_TOKEN=op://…"
+        );
+    }
 
     #[test]
     fn test_claude_mcp_config_serialization() {
@@ -124,4 +541,176 @@ mod tests {
         assert!(json.contains("mcpServers"));
         assert!(json.contains("github"));
     }
+
+    #[test]
+    fn test_output_path_written() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let servers_path = dir.path().join("mcp.toml");
+        std::fs::write(
+            &servers_path,
+            "[servers.github]\ncommand = \"npx -y server-github\"\n[servers.github.env]\nTOKEN = \"plain\"\n",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+
+        let output_path = dir.path().join("out").join("mcp.json");
+        let config = Config {
+            mcp_config_path: Some(servers_path),
+            mcp: McpConfig {
+                output_path: Some(output_path.clone()),
+            },
+            ..Default::default()
+        };
+
+        let written = generate_mcp_config(&config, dir.path(), "ccs-test").unwrap();
+        assert_eq!(written, Some(output_path.clone()));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output_path).unwrap()).unwrap();
+        let github = &json["mcpServers"]["github"];
+        assert_eq!(github["command"], "npx");
+        assert_eq!(github["args"], serde_json::json!(["-y", "server-github"]));
+        assert_eq!(github["env"]["TOKEN"], "plain");
+    }
+
+    #[test]
+    fn test_project_mcp_json_merged() {
+        std::env::set_var("TEST_SECRET_CCS_MCP_JSON", "db_secret");
+        let dir = tempfile::TempDir::new().unwrap();
+        let servers_path = dir.path().join("mcp.toml");
+        std::fs::write(
+            &servers_path,
+            "[servers.github]\ncommand = \"npx -y server-github\"\n\n[servers.fs]\ncommand = \"mcp-fs\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join(PROJECT_MCP_JSON),
+            r#"{
+                "mcpServers": {
+                    "db": {
+                        "command": "mcp-postgres",
+                        "args": ["--readonly"],
+                        "env": { "PGPASSWORD": "env://TEST_SECRET_CCS_MCP_JSON" }
+                    },
+                    "fs": { "command": "mcp-fs", "args": ["/workspace/docs"] },
+                    "docs": { "type": "http", "url": "https://example.com/mcp" }
+                }
+            }"#,
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        let subdir = dir.path().join("packages").join("app");
+        std::fs::create_dir_all(&subdir).unwrap();
+
+        let output_path = dir.path().join("out.json");
+        let config = Config {
+            mcp_config_path: Some(servers_path),
+            mcp: McpConfig {
+                output_path: Some(output_path.clone()),
+            },
+            ..Default::default()
+        };
+        // Read from the repository root, not the directory ccs runs in
+        generate_mcp_config(&config, &subdir, "ccs-test").unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output_path).unwrap()).unwrap();
+        let servers = &json["mcpServers"];
+        assert_eq!(servers["github"]["command"], "npx");
+        assert_eq!(servers["db"]["args"], serde_json::json!(["--readonly"]));
+        // The repository's file can't resolve the user's secrets
+        assert_eq!(
+            servers["db"]["env"]["PGPASSWORD"],
+            "env://TEST_SECRET_CCS_MCP_JSON"
+        );
+        // The project's definition replaces mcp.toml's
+        assert_eq!(
+            servers["fs"]["args"],
+            serde_json::json!(["/workspace/docs"])
+        );
+        assert!(servers.get("docs").is_none());
+    }
+
+    #[test]
+    fn test_parse_mcp_json_errors() {
+        assert!(parse_mcp_json("{}").unwrap().is_empty());
+        assert!(parse_mcp_json("{\"mcpServers\": {\"x\": {\"command\": 1}}}").is_err());
+        assert!(parse_mcp_json("not json").is_err());
+    }
+
+    #[test]
+    fn test_cwd_wraps_command() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let servers_path = dir.path().join("mcp.toml");
+        std::fs::write(
+            &servers_path,
+            "[servers.tools]\ncommand = \"node server.js\"\nargs = [\"--port\", \"3000\"]\ncwd = \"{workspace}/tools\"\n",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+
+        let output_path = dir.path().join("mcp.json");
+        let config = Config {
+            mcp_config_path: Some(servers_path),
+            mcp: McpConfig {
+                output_path: Some(output_path.clone()),
+            },
+            ..Default::default()
+        };
+        generate_mcp_config(&config, dir.path(), "ccs-test").unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output_path).unwrap()).unwrap();
+        let tools = &json["mcpServers"]["tools"];
+        assert_eq!(tools["command"], "sh");
+        assert_eq!(
+            tools["args"],
+            serde_json::json!([
+                "-c",
+                r#"cd "$1" && shift && exec "$@""#,
+                "sh",
+                "/workspace/tools",
+                "node",
+                "server.js",
+                "--port",
+                "3000"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_reap_orphaned_configs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for name in ["ccs-a-1.json", "ccs-b-2.json", "notes.txt"] {
+            std::fs::write(dir.path().join(name), "{}").unwrap();
+        }
+        let sessions = vec!["ccs-a-1".to_string()];
+
+        // Fresh files may belong to a session still starting
+        let now = SystemTime::now();
+        reap_orphaned_configs_in(dir.path(), &sessions, Duration::from_secs(60), now);
+        assert!(dir.path().join("ccs-b-2.json").exists());
+
+        // Later, only the config without a container goes
+        let later = now + Duration::from_secs(120);
+        reap_orphaned_configs_in(dir.path(), &sessions, Duration::from_secs(60), later);
+        assert!(dir.path().join("ccs-a-1.json").exists());
+        assert!(!dir.path().join("ccs-b-2.json").exists());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_temp_config_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let config = ClaudeMcpConfig {
+            mcp_servers: HashMap::new(),
+        };
+        let path = write_temp_config(&config).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+    }
 }
@@ -0,0 +1,176 @@
+//! Formatting of human-facing output
+//!
+//! Headers, notes, tables and color go through [`Style`], so `--plain`
+//! (decoration-free, machine-friendly output) and `--no-color`/`NO_COLOR`
+//! apply everywhere at once.
+
+use std::ffi::OsStr;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// Style chosen at startup by [`init`]
+static STYLE: OnceLock<Style> = OnceLock::new();
+
+/// How output is decorated
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    /// No decoration at all (`--plain`)
+    pub plain: bool,
+    /// Color escape sequences are allowed
+    pub color: bool,
+}
+
+/// Pick the output style for this run from the flags and `NO_COLOR`
+pub fn init(plain: bool, no_color: bool) {
+    let no_color = no_color || no_color_env(std::env::var_os("NO_COLOR").as_deref());
+    let _ = STYLE.set(Style::new(plain, no_color, std::io::stdout().is_terminal()));
+}
+
+/// The style set by [`init`], decorated without color before that
+pub fn style() -> Style {
+    STYLE.get().copied().unwrap_or_default()
+}
+
+/// Whether `NO_COLOR` asks for no color: set to anything but an empty string
+fn no_color_env(value: Option<&OsStr>) -> bool {
+    value.is_some_and(|v| !v.is_empty())
+}
+
+impl Style {
+    /// Color only reaches a terminal, and never with `--plain` or `--no-color`
+    pub fn new(plain: bool, no_color: bool, stdout_is_terminal: bool) -> Self {
+        Style {
+            plain,
+            color: !plain && !no_color && stdout_is_terminal,
+        }
+    }
+
+    /// A section header: `=== title ===`, or just the title when plain
+    pub fn header(&self, title: &str) -> String {
+        if self.plain {
+            title.to_string()
+        } else {
+            format!("=== {} ===\n", title)
+        }
+    }
+
+    /// Separator between sections: a blank line, nothing when plain
+    pub fn gap(&self) -> &'static str {
+        if self.plain {
+            ""
+        } else {
+            "\n"
+        }
+    }
+
+    /// An aside in parentheses, without them when plain
+    pub fn note(&self, text: &str) -> String {
+        if self.plain {
+            text.to_string()
+        } else {
+            format!("({})", text)
+        }
+    }
+
+    /// A list item: `  - item`, or indented only when plain
+    pub fn item(&self, text: &str) -> String {
+        if self.plain {
+            format!("  {}", text)
+        } else {
+            format!("  - {}", text)
+        }
+    }
+
+    /// Text flagging a problem, in red when color is on
+    pub fn warn(&self, text: &str) -> String {
+        if self.color {
+            format!("\x1b[31m{}\x1b[0m", text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Rows as space-aligned columns, or tab-separated when plain
+    pub fn table(&self, rows: &[Vec<&str>]) -> String {
+        if self.plain {
+            return rows
+                .iter()
+                .map(|row| row.join("\t"))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| {
+                rows.iter()
+                    .filter_map(|row| row.get(i))
+                    .map(|cell| cell.len())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .collect::<Vec<_>>()
+                    .join("   ")
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_needs_terminal_and_no_opt_out() {
+        assert!(Style::new(false, false, true).color);
+        assert!(!Style::new(false, false, false).color);
+        assert!(!Style::new(false, true, true).color);
+        assert!(!Style::new(true, false, true).color);
+
+        assert!(no_color_env(Some(OsStr::new("1"))));
+        assert!(!no_color_env(Some(OsStr::new(""))));
+        assert!(!no_color_env(None));
+    }
+
+    #[test]
+    fn test_plain_drops_decoration() {
+        let decorated = Style::new(false, false, true);
+        assert_eq!(decorated.header("CCS Status"), "=== CCS Status ===\n");
+        assert_eq!(decorated.gap(), "\n");
+        assert_eq!(decorated.note("in a worktree"), "(in a worktree)");
+        assert_eq!(decorated.item("ccs-api"), "  - ccs-api");
+        assert_eq!(decorated.warn("NOT FOUND"), "\x1b[31mNOT FOUND\x1b[0m");
+
+        let plain = Style::new(true, false, true);
+        assert_eq!(plain.header("CCS Status"), "CCS Status");
+        assert_eq!(plain.gap(), "");
+        assert_eq!(plain.note("in a worktree"), "in a worktree");
+        assert_eq!(plain.item("ccs-api"), "  ccs-api");
+        assert_eq!(plain.warn("NOT FOUND"), "NOT FOUND");
+        for text in [plain.header("x"), plain.note("x"), plain.warn("x")] {
+            assert!(!text.contains(['=', '(', '\x1b']), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        let rows = vec![vec!["NAMES", "STATUS"], vec!["ccs-api-1", "Up"]];
+        assert_eq!(
+            Style::default().table(&rows),
+            "NAMES       STATUS\nccs-api-1   Up"
+        );
+        assert_eq!(
+            Style::new(true, false, false).table(&rows),
+            "NAMES\tSTATUS\nccs-api-1\tUp"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Confirmation prompts
+//!
+//! All interactive questions go through [`confirm`] so `--yes` and
+//! non-interactive runs behave the same everywhere.
+
+use std::io::{IsTerminal, Write};
+
+/// Whether stdin is a terminal we can ask questions on
+pub fn stdin_is_tty() -> bool {
+    std::io::stdin().is_terminal()
+}
+
+/// Ask a yes/no question, defaulting to no
+///
+/// Returns true without asking when `assume_yes` is set, and fails when
+/// there is no terminal to ask on.
+pub fn confirm(question: &str, assume_yes: bool, is_tty: bool) -> anyhow::Result<bool> {
+    confirm_with(question, assume_yes, is_tty, ask)
+}
+
+fn confirm_with<F>(question: &str, assume_yes: bool, is_tty: bool, ask: F) -> anyhow::Result<bool>
+where
+    F: FnOnce(&str) -> std::io::Result<bool>,
+{
+    if assume_yes {
+        return Ok(true);
+    }
+
+    if !is_tty {
+        anyhow::bail!(
+            "{} Not asking without a terminal; pass --yes to confirm",
+            question
+        );
+    }
+
+    Ok(ask(question)?)
+}
+
+/// Ask on the terminal and read a y/N answer
+fn ask(question: &str) -> std::io::Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirm_assume_yes_skips_prompt() {
+        let result = confirm_with("Remove?", true, false, |_| panic!("should not ask"));
+        assert!(result.unwrap());
+    }
+
+    #[test]
+    fn test_confirm_non_tty_errors() {
+        let result = confirm_with("Remove?", false, false, |_| panic!("should not ask"));
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("Remove?"));
+        assert!(err.contains("--yes"));
+    }
+
+    #[test]
+    fn test_confirm_tty_asks() {
+        assert!(confirm_with("Remove?", false, true, |q| {
+            assert_eq!(q, "Remove?");
+            Ok(true)
+        })
+        .unwrap());
+        assert!(!confirm_with("Remove?", false, true, |_| Ok(false)).unwrap());
+    }
+}
@@ -0,0 +1,347 @@
+//! Idle session reaper
+//!
+//! Stops detached ccs sessions that have been idle longer than
+//! `docker.max_idle_secs` on ccs startup.
+
+use std::process::Stdio;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::docker::{self, ContainerRuntime};
+use crate::mcp;
+
+/// CPU usage (percent) above which a session is considered active
+const ACTIVE_CPU_PERCENT: f64 = 1.0;
+
+/// Result of reaper operation
+#[derive(Debug, Default)]
+pub struct ReapResult {
+    /// Sessions that were stopped
+    pub stopped: Vec<String>,
+    /// Errors encountered while stopping sessions
+    pub errors: Vec<String>,
+}
+
+impl ReapResult {
+    /// Print a summary of the reaper operation
+    pub fn print_summary(&self) {
+        if !self.stopped.is_empty() {
+            println!("Stopped {} idle session(s):", self.stopped.len());
+            for name in &self.stopped {
+                println!("  - {}", name);
+            }
+        }
+
+        if !self.errors.is_empty() {
+            eprintln!("Reaper warnings:");
+            for err in &self.errors {
+                eprintln!("  - {}", err);
+            }
+        }
+    }
+
+    /// Check if any sessions were stopped
+    pub fn had_changes(&self) -> bool {
+        !self.stopped.is_empty()
+    }
+}
+
+/// Activity information about a running ccs session
+#[derive(Debug, Clone)]
+struct SessionActivity {
+    /// Whether the session was started detached (not auto-removed)
+    detached: bool,
+    /// Unix timestamp of the last log line, or container start if no output
+    last_activity: Option<u64>,
+    /// Current CPU usage in percent
+    cpu_percent: Option<f64>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ReapDecision {
+    Stop(String),
+    Keep(String),
+}
+
+/// Stop detached sessions that have been idle longer than the configured threshold
+pub fn reap_idle_sessions(config: &Config) -> ReapResult {
+    let mut result = ReapResult::default();
+
+    let max_idle_secs = match config.docker.max_idle_secs {
+        Some(secs) => secs,
+        None => return result,
+    };
+
+    let runtime = match ContainerRuntime::detect() {
+        Ok(r) => r,
+        Err(_) => return result,
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    for name in list_running_sessions(runtime, &docker::container_prefix(config)) {
+        let activity = get_session_activity(runtime, &name);
+
+        match should_reap(&activity, max_idle_secs, now) {
+            ReapDecision::Stop(reason) => match stop_container(runtime, &name) {
+                Ok(()) => result.stopped.push(name),
+                Err(e) => result
+                    .errors
+                    .push(format!("{}: {} (reason: {})", name, e, reason)),
+            },
+            ReapDecision::Keep(reason) => {
+                if std::env::var("CCS_VERBOSE").is_ok() {
+                    result.errors.push(format!("Kept {}: {}", name, reason));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn should_reap(activity: &SessionActivity, max_idle_secs: u64, now: u64) -> ReapDecision {
+    // Interactive sessions have a user attached
+    if !activity.detached {
+        return ReapDecision::Keep("interactive session".to_string());
+    }
+
+    if let Some(cpu) = activity.cpu_percent {
+        if cpu > ACTIVE_CPU_PERCENT {
+            return ReapDecision::Keep("using CPU".to_string());
+        }
+    }
+
+    let last_activity = match activity.last_activity {
+        Some(ts) => ts,
+        None => return ReapDecision::Keep("unknown activity".to_string()),
+    };
+
+    let idle_secs = now.saturating_sub(last_activity);
+    if idle_secs < max_idle_secs {
+        return ReapDecision::Keep("recently active".to_string());
+    }
+
+    ReapDecision::Stop(format!("idle for {}s", idle_secs))
+}
+
+fn list_running_sessions(runtime: ContainerRuntime, prefix: &str) -> Vec<String> {
+    let output = runtime
+        .new_command()
+        .args([
+            "ps",
+            "--filter",
+            &docker::container_name_filter(prefix),
+            "--filter",
+            docker::SESSION_LABEL_FILTER,
+            "--format",
+            "{{.Names}}",
+        ])
+        .output();
+
+    match output {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout)
+            .lines()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn get_session_activity(runtime: ContainerRuntime, name: &str) -> SessionActivity {
+    let inspect = command_stdout(
+        runtime,
+        &[
+            "inspect",
+            "--format",
+            "{{.State.StartedAt}}|{{.HostConfig.AutoRemove}}",
+            name,
+        ],
+    );
+    let (started_at, auto_remove) = inspect
+        .as_deref()
+        .and_then(|s| s.trim().split_once('|'))
+        .map(|(started, auto_remove)| (parse_timestamp(started), auto_remove == "true"))
+        .unwrap_or((None, true));
+
+    // Log lines are prefixed with an RFC 3339 timestamp
+    let last_log = command_stdout(runtime, &["logs", "--timestamps", "--tail", "1", name])
+        .and_then(|s| s.split_whitespace().next().and_then(parse_timestamp));
+
+    let cpu_percent = command_stdout(
+        runtime,
+        &["stats", "--no-stream", "--format", "{{.CPUPerc}}", name],
+    )
+    .and_then(|s| parse_cpu_percent(&s));
+
+    SessionActivity {
+        detached: !auto_remove,
+        last_activity: last_log.or(started_at),
+        cpu_percent,
+    }
+}
+
+fn command_stdout(runtime: ContainerRuntime, args: &[&str]) -> Option<String> {
+    let output = runtime
+        .new_command()
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        None
+    }
+}
+
+fn stop_container(runtime: ContainerRuntime, name: &str) -> Result<(), String> {
+    // Read the service group before the container (and its labels) is removed
+    let network = docker::session_network(runtime, name);
+
+    let status = runtime
+        .new_command()
+        .args(["stop", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("failed to stop container: {}", e))?;
+
+    if !status.success() {
+        return Err("failed to stop container".to_string());
+    }
+
+    let _ = runtime
+        .new_command()
+        .args(["rm", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    if let Some(network) = network {
+        docker::remove_service_group(runtime, name, &network);
+    }
+    mcp::remove_session_config(name);
+
+    Ok(())
+}
+
+/// Parse a CPU percentage like "12.34%"
+fn parse_cpu_percent(s: &str) -> Option<f64> {
+    s.trim().trim_end_matches('%').parse().ok()
+}
+
+/// Parse an RFC 3339 timestamp (e.g. "2024-01-15T10:30:00.123456789Z") to Unix seconds
+pub fn parse_timestamp(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (date, time) = s.split_once('T')?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: i64 = date_parts.next()?.parse().ok()?;
+    let day: i64 = date_parts.next()?.parse().ok()?;
+
+    // Split off the UTC offset ("Z", "+02:00" or "-05:00")
+    let (clock, offset_secs) = if let Some(clock) = time.strip_suffix('Z') {
+        (clock, 0)
+    } else {
+        let pos = time.rfind(['+', '-'])?;
+        let (clock, offset) = time.split_at(pos);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let offset_secs = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        (clock, sign * offset_secs)
+    };
+
+    let clock = clock.split('.').next()?;
+    let mut clock_parts = clock.splitn(3, ':');
+    let hour: i64 = clock_parts.next()?.parse().ok()?;
+    let minute: i64 = clock_parts.next()?.parse().ok()?;
+    let second: i64 = clock_parts.next()?.parse().ok()?;
+
+    // Days since the Unix epoch for a proleptic Gregorian date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset_secs;
+    u64::try_from(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(detached: bool, last_activity: Option<u64>, cpu: Option<f64>) -> SessionActivity {
+        SessionActivity {
+            detached,
+            last_activity,
+            cpu_percent: cpu,
+        }
+    }
+
+    #[test]
+    fn test_reap_result_empty() {
+        let result = ReapResult::default();
+        assert!(!result.had_changes());
+    }
+
+    #[test]
+    fn test_should_reap_idle_detached() {
+        let decision = should_reap(&activity(true, Some(1000), Some(0.0)), 600, 2000);
+        assert_eq!(decision, ReapDecision::Stop("idle for 1000s".to_string()));
+    }
+
+    #[test]
+    fn test_should_reap_keeps_recent() {
+        let decision = should_reap(&activity(true, Some(1900), Some(0.0)), 600, 2000);
+        assert!(matches!(decision, ReapDecision::Keep(_)));
+    }
+
+    #[test]
+    fn test_should_reap_keeps_interactive() {
+        let decision = should_reap(&activity(false, Some(0), None), 600, 2000);
+        assert!(matches!(decision, ReapDecision::Keep(_)));
+    }
+
+    #[test]
+    fn test_should_reap_keeps_busy_cpu() {
+        let decision = should_reap(&activity(true, Some(0), Some(45.2)), 600, 2000);
+        assert_eq!(decision, ReapDecision::Keep("using CPU".to_string()));
+    }
+
+    #[test]
+    fn test_should_reap_keeps_unknown_activity() {
+        let decision = should_reap(&activity(true, None, None), 600, 2000);
+        assert!(matches!(decision, ReapDecision::Keep(_)));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_timestamp("2024-01-15T10:30:00.123456789Z"),
+            Some(1_705_314_600)
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-15T12:30:00+02:00"),
+            Some(1_705_314_600)
+        );
+        assert_eq!(parse_timestamp("not a timestamp"), None);
+    }
+
+    #[test]
+    fn test_parse_cpu_percent() {
+        assert_eq!(parse_cpu_percent("12.34%\n"), Some(12.34));
+        assert_eq!(parse_cpu_percent("--"), None);
+    }
+}
@@ -0,0 +1,178 @@
+//! Guard against sandboxing sensitive directories (`[safety]` config)
+
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::config::{expand_path, Config};
+
+#[derive(Error, Debug)]
+pub enum SafetyError {
+    #[error("Refusing to run in {0}: it is listed in safety.blocked_paths")]
+    Blocked(PathBuf),
+
+    #[error("Refusing to run in {0}: it is outside safety.allowed_roots ({1})")]
+    OutsideAllowedRoots(PathBuf, String),
+}
+
+/// Resolve the project directory, telling a mistyped path apart from missing permissions
+pub fn canonicalize_project_path(path: &Path) -> anyhow::Result<PathBuf> {
+    path.canonicalize()
+        .map_err(|e| anyhow::anyhow!(project_path_error_message(path, &e)))
+}
+
+fn project_path_error_message(path: &Path, error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::NotFound => format!(
+            "Project path '{}' does not exist; check it for typos",
+            path.display()
+        ),
+        io::ErrorKind::PermissionDenied => format!(
+            "Permission denied resolving project path '{}'; check that you can read it and its parent directories",
+            path.display()
+        ),
+        _ => format!(
+            "Failed to resolve project path '{}': {}",
+            path.display(),
+            error
+        ),
+    }
+}
+
+/// Expand a configured path and resolve symlinks, keeping it as-is if it doesn't exist
+fn resolve(configured: &str) -> PathBuf {
+    let expanded = expand_path(configured);
+    expanded.canonicalize().unwrap_or(expanded)
+}
+
+/// Check that ccs may run in `path` (already canonicalized)
+///
+/// `blocked_paths` match exactly, so blocking `~` still allows projects
+/// below it; `allowed_roots`, when non-empty, match the path or any parent.
+pub fn check_path(path: &Path, config: &Config) -> Result<(), SafetyError> {
+    let safety = &config.safety;
+
+    if safety.blocked_paths.iter().any(|b| resolve(b) == path) {
+        return Err(SafetyError::Blocked(path.to_path_buf()));
+    }
+
+    if !safety.allowed_roots.is_empty()
+        && !safety
+            .allowed_roots
+            .iter()
+            .any(|root| path.starts_with(resolve(root)))
+    {
+        return Err(SafetyError::OutsideAllowedRoots(
+            path.to_path_buf(),
+            safety.allowed_roots.join(", "),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SafetyConfig;
+    use tempfile::TempDir;
+
+    fn config(blocked: &[&Path], allowed: &[&Path]) -> Config {
+        let to_strings = |paths: &[&Path]| paths.iter().map(|p| p.display().to_string()).collect();
+        Config {
+            safety: SafetyConfig {
+                blocked_paths: to_strings(blocked),
+                allowed_roots: to_strings(allowed),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_missing_project_path() {
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("no-such-project");
+
+        let err = canonicalize_project_path(&missing).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{}", err);
+        assert_eq!(
+            canonicalize_project_path(dir.path()).unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_project_path_error_message_kinds() {
+        let path = Path::new("/srv/project");
+        let message = |kind| project_path_error_message(path, &io::Error::new(kind, "os error"));
+
+        assert!(message(io::ErrorKind::PermissionDenied).contains("Permission denied"));
+        assert!(message(io::ErrorKind::NotFound).contains("check it for typos"));
+        assert_eq!(
+            message(io::ErrorKind::Other),
+            "Failed to resolve project path '/srv/project': os error"
+        );
+    }
+
+    #[test]
+    fn test_no_rules_allows_everything() {
+        let dir = TempDir::new().unwrap();
+        assert!(check_path(dir.path(), &Config::default()).is_ok());
+    }
+
+    #[test]
+    fn test_blocked_path_is_exact() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = root.join("project");
+        std::fs::create_dir(&project).unwrap();
+
+        let config = config(&[&root], &[]);
+        assert!(matches!(
+            check_path(&root, &config),
+            Err(SafetyError::Blocked(_))
+        ));
+        assert!(check_path(&project, &config).is_ok());
+    }
+
+    #[test]
+    fn test_allowed_roots_prefix_matching() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let code = root.join("code");
+        let project = code.join("project");
+        let sibling = root.join("code-other");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(&sibling).unwrap();
+
+        let config = config(&[], &[&code]);
+        assert!(check_path(&code, &config).is_ok());
+        assert!(check_path(&project, &config).is_ok());
+        // Component-wise, so "code-other" is not under "code"
+        assert!(matches!(
+            check_path(&sibling, &config),
+            Err(SafetyError::OutsideAllowedRoots(_, _))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_config_paths_are_resolved() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let real = root.join("real");
+        let link = root.join("link");
+        std::fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        // Rules written via the symlink apply to the canonical project path
+        assert!(matches!(
+            check_path(&real, &config(&[&link], &[])),
+            Err(SafetyError::Blocked(_))
+        ));
+        let project = real.join("project");
+        std::fs::create_dir(&project).unwrap();
+        assert!(check_path(&project, &config(&[], &[&link])).is_ok());
+    }
+}
@@ -0,0 +1,262 @@
+//! On-disk cache of resolved secrets (`secrets.cache_ttl_secs`)
+//!
+//! Avoids re-running slow or interactive backends (e.g. 1Password biometric
+//! prompts) on every invocation. Entries are keyed by a SHA-256 of the
+//! reference and encrypted with AES-256-GCM under a random machine-local key
+//! kept next to the cache with mode 0600. This keeps values out of plain-text
+//! backups and searches; it does not protect against someone who can read
+//! both files as your user.
+
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CACHE_FILE: &str = "secret-cache.json";
+const KEY_FILE: &str = "secret-cache.key";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Unix time after which the entry is ignored
+    expires_at: u64,
+    nonce: String,
+    tag: String,
+    ciphertext: String,
+}
+
+/// Encrypted cache of resolved secret values
+pub struct SecretCache {
+    path: PathBuf,
+    key: Vec<u8>,
+    ttl_secs: u64,
+    entries: HashMap<String, Entry>,
+    dirty: bool,
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl SecretCache {
+    /// Open the cache in the ccs data dir; None if caching is disabled or the
+    /// cache can't be opened
+    pub fn open(ttl_secs: u64) -> Option<Self> {
+        if ttl_secs == 0 {
+            return None;
+        }
+        let dir = dirs::data_dir()?.join("ccs");
+        Self::open_in(&dir, ttl_secs).ok()
+    }
+
+    /// Open the cache stored in `dir`
+    pub fn open_in(dir: &Path, ttl_secs: u64) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let key = load_or_create_key(&dir.join(KEY_FILE))?;
+
+        let path = dir.join(CACHE_FILE);
+        // A corrupt cache is treated as empty and replaced on save
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<CacheFile>(&s).ok())
+            .map(|f| f.entries)
+            .unwrap_or_default();
+
+        Ok(SecretCache {
+            path,
+            key,
+            ttl_secs,
+            entries,
+            dirty: false,
+        })
+    }
+
+    /// Look up an unexpired value for `reference`
+    pub fn get(&self, reference: &str, now: u64) -> Option<String> {
+        let id = entry_id(reference);
+        let entry = self.entries.get(&id).filter(|e| e.expires_at > now)?;
+
+        let plaintext = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&from_hex(&entry.nonce)?),
+            id.as_bytes(),
+            &from_hex(&entry.ciphertext)?,
+            &from_hex(&entry.tag)?,
+        )
+        .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+
+    /// Store the resolved value for `reference`, valid for the configured TTL
+    pub fn insert(&mut self, reference: &str, value: &str, now: u64) {
+        let id = entry_id(reference);
+        let mut nonce = [0u8; NONCE_LEN];
+        let mut tag = [0u8; TAG_LEN];
+        if openssl::rand::rand_bytes(&mut nonce).is_err() {
+            return;
+        }
+        let Ok(ciphertext) = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            id.as_bytes(),
+            value.as_bytes(),
+            &mut tag,
+        ) else {
+            return;
+        };
+
+        self.entries.insert(
+            id,
+            Entry {
+                expires_at: now.saturating_add(self.ttl_secs),
+                nonce: to_hex(&nonce),
+                tag: to_hex(&tag),
+                ciphertext: to_hex(&ciphertext),
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// Write the cache back if it changed, dropping expired entries
+    pub fn save(&mut self, now: u64) -> std::io::Result<()> {
+        let before = self.entries.len();
+        self.entries.retain(|_, e| e.expires_at > now);
+        if !self.dirty && self.entries.len() == before {
+            return Ok(());
+        }
+
+        let file = CacheFile {
+            entries: std::mem::take(&mut self.entries),
+        };
+        let json = serde_json::to_string(&file)?;
+        self.entries = file.entries;
+        write_private(&self.path, json.as_bytes())?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Read the cache key, creating a random one on first use
+fn load_or_create_key(path: &Path) -> std::io::Result<Vec<u8>> {
+    if let Ok(hex) = std::fs::read_to_string(path) {
+        if let Some(key) = from_hex(hex.trim()).filter(|k| k.len() == KEY_LEN) {
+            return Ok(key);
+        }
+    }
+
+    let mut key = vec![0u8; KEY_LEN];
+    openssl::rand::rand_bytes(&mut key).map_err(std::io::Error::other)?;
+    write_private(path, to_hex(&key).as_bytes())?;
+    Ok(key)
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)
+}
+
+/// Cache key for a reference, so reference names aren't stored in the clear
+fn entry_id(reference: &str) -> String {
+    to_hex(&openssl::sha::sha256(reference.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cache_hit_miss_and_expiry() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = SecretCache::open_in(dir.path(), 60).unwrap();
+
+        assert_eq!(cache.get("op://Vault/Item/token", 1000), None);
+
+        cache.insert("op://Vault/Item/token", "s3cret", 1000);
+        assert_eq!(
+            cache.get("op://Vault/Item/token", 1059),
+            Some("s3cret".to_string())
+        );
+        assert_eq!(cache.get("op://Vault/Item/other", 1059), None);
+        assert_eq!(cache.get("op://Vault/Item/token", 1060), None);
+    }
+
+    #[test]
+    fn test_cache_persists_encrypted() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = SecretCache::open_in(dir.path(), 60).unwrap();
+        cache.insert("op://Vault/Item/token", "s3cret", 1000);
+        cache.save(1000).unwrap();
+
+        let on_disk = std::fs::read_to_string(dir.path().join(CACHE_FILE)).unwrap();
+        assert!(!on_disk.contains("s3cret"));
+        assert!(!on_disk.contains("Vault"));
+
+        let reopened = SecretCache::open_in(dir.path(), 60).unwrap();
+        assert_eq!(
+            reopened.get("op://Vault/Item/token", 1030),
+            Some("s3cret".to_string())
+        );
+
+        // Expired entries are dropped on save
+        let mut reopened = reopened;
+        reopened.save(2000).unwrap();
+        let reopened = SecretCache::open_in(dir.path(), 60).unwrap();
+        assert!(reopened.entries.is_empty());
+    }
+
+    #[test]
+    fn test_cache_rejects_wrong_key() {
+        let dir = TempDir::new().unwrap();
+        let mut cache = SecretCache::open_in(dir.path(), 60).unwrap();
+        cache.insert("op://Vault/Item/token", "s3cret", 1000);
+        cache.save(1000).unwrap();
+
+        std::fs::remove_file(dir.path().join(KEY_FILE)).unwrap();
+        let reopened = SecretCache::open_in(dir.path(), 60).unwrap();
+        assert_eq!(reopened.get("op://Vault/Item/token", 1000), None);
+    }
+
+    #[test]
+    fn test_hex_roundtrip() {
+        assert_eq!(from_hex(&to_hex(&[0, 15, 255])), Some(vec![0, 15, 255]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
@@ -1,7 +1,11 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::Command;
 use thiserror::Error;
 
+use crate::config::SecretsConfig;
+use crate::secret_cache::{self, SecretCache};
+
 #[derive(Error, Debug)]
 pub enum SecretsError {
     #[error("1Password CLI (op) not found. Install it from https://1password.com/downloads/command-line/")]
@@ -13,23 +17,163 @@ pub enum SecretsError {
     #[error("pass not found. Install it from https://www.passwordstore.org/")]
     PassNotFound,
 
+    #[error("Google Cloud CLI (gcloud) not found. Install it from https://cloud.google.com/sdk/docs/install")]
+    GcloudNotFound,
+
+    #[error("Azure CLI (az) not found. Install it from https://learn.microsoft.com/cli/azure/install-azure-cli")]
+    AzureCliNotFound,
+
+    #[error("Doppler CLI (doppler) not found. Install it from https://docs.doppler.com/docs/install-cli")]
+    DopplerNotFound,
+
     #[error("Failed to resolve secret '{0}': {1}")]
     ResolutionFailed(String, String),
 
+    #[error("Custom secret resolver failed for '{0}': {1}")]
+    CustomResolverFailed(String, String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+/// Supported backends as (name, reference scheme, CLI it shells out to)
+const BACKENDS: &[(&str, &str, Option<&str>)] = &[
+    ("1password", "op://", Some("op")),
+    ("bitwarden", "bws://", Some("bws")),
+    ("pass", "pass://", Some("pass")),
+    ("gcp", "gcp://", Some("gcloud")),
+    ("azure", "az://", Some("az")),
+    ("doppler", "doppler://", Some("doppler")),
+    ("custom", "cmd://", None),
+    ("env", "env://", None),
+];
+
+/// Availability of a secrets backend
+#[derive(Debug, Clone)]
+pub struct BackendStatus {
+    /// Backend name as used in `secrets.backend`
+    pub name: &'static str,
+    /// Secret reference scheme (e.g. "op://")
+    pub scheme: &'static str,
+    /// CLI required by the backend, if any
+    pub cli: Option<&'static str>,
+    /// Whether the required CLI is installed
+    pub available: bool,
+}
+
+/// Check which secrets backends have their CLI installed
+pub fn backend_status() -> Vec<BackendStatus> {
+    BACKENDS
+        .iter()
+        .map(|&(name, scheme, cli)| BackendStatus {
+            name,
+            scheme,
+            cli,
+            available: cli.map(|c| which::which(c).is_ok()).unwrap_or(true),
+        })
+        .collect()
+}
+
+/// Names of all supported backends
+pub fn backend_names() -> Vec<&'static str> {
+    BACKENDS.iter().map(|&(name, _, _)| name).collect()
+}
+
+/// Check if a backend name is supported
+pub fn is_supported_backend(name: &str) -> bool {
+    BACKENDS.iter().any(|&(n, _, _)| n == name)
+}
+
+/// Output of a backend CLI invocation
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs backend CLIs; abstracted so resolvers can be tested with canned output
+pub trait CommandRunner {
+    /// Check whether `program` is installed
+    fn is_installed(&self, program: &str) -> bool;
+
+    /// Run `program` with `args` and capture its output
+    fn run(&self, program: &str, args: &[&str]) -> std::io::Result<CommandOutput>;
+}
+
+/// Runs backend CLIs as real processes
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn is_installed(&self, program: &str) -> bool {
+        which::which(program).is_ok()
+    }
+
+    fn run(&self, program: &str, args: &[&str]) -> std::io::Result<CommandOutput> {
+        let output = Command::new(program).args(args).output()?;
+        Ok(CommandOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+}
+
 /// Resolve secrets in a HashMap of environment variables
 /// Secret references are replaced with their actual values
 pub fn resolve_secrets(
     env: &HashMap<String, String>,
-    backend: &str,
+    secrets: &SecretsConfig,
+) -> Result<HashMap<String, String>, SecretsError> {
+    let now = secret_cache::unix_now();
+    let mut cache = SecretCache::open(secrets.cache_ttl_secs);
+    let resolved = resolve_secrets_with(env, secrets, &SystemRunner, cache.as_mut(), now)?;
+
+    if let Some(mut cache) = cache {
+        if let Err(e) = cache.save(now) {
+            eprintln!("Warning: failed to write secret cache: {}", e);
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Resolve secrets using the given command runner, consulting `cache` for
+/// backend references
+///
+/// References are resolved one at a time, so backends that serialize
+/// interactive prompts (1Password's biometric unlock) or rate-limit never
+/// see concurrent calls.
+fn resolve_secrets_with(
+    env: &HashMap<String, String>,
+    secrets: &SecretsConfig,
+    runner: &dyn CommandRunner,
+    mut cache: Option<&mut SecretCache>,
+    now: u64,
 ) -> Result<HashMap<String, String>, SecretsError> {
     let mut resolved = HashMap::new();
 
     for (key, value) in env {
-        let resolved_value = resolve_secret_value(value, backend)?;
+        // env:// is cheap and may change between runs, so it is never cached
+        let cacheable = !value.starts_with("env://");
+        let cached = cache
+            .as_deref()
+            .filter(|_| cacheable)
+            .and_then(|c| c.get(value, now));
+
+        let resolved_value = match cached {
+            Some(cached) => cached,
+            None => {
+                let resolved_value = resolve_secret_value(value, secrets, runner)?;
+                // Only references resolve to something other than themselves
+                if let Some(cache) = cache.as_deref_mut().filter(|_| cacheable) {
+                    if resolved_value != *value {
+                        cache.insert(value, &resolved_value, now);
+                    }
+                }
+                resolved_value
+            }
+        };
         resolved.insert(key.clone(), resolved_value);
     }
 
@@ -37,72 +181,99 @@ pub fn resolve_secrets(
 }
 
 /// Resolve a single secret value
-fn resolve_secret_value(value: &str, backend: &str) -> Result<String, SecretsError> {
+fn resolve_secret_value(
+    value: &str,
+    secrets: &SecretsConfig,
+    runner: &dyn CommandRunner,
+) -> Result<String, SecretsError> {
     // Check if this is a secret reference
     if value.starts_with("op://") {
-        resolve_1password_secret(value)
+        resolve_1password_secret(value, runner)
     } else if value.starts_with("bws://") {
-        resolve_bitwarden_secret(value)
+        resolve_bitwarden_secret(value, runner)
     } else if value.starts_with("pass://") {
-        resolve_pass_secret(value)
+        resolve_pass_secret(value, runner)
+    } else if value.starts_with("gcp://") {
+        resolve_gcp_secret(value, runner)
+    } else if value.starts_with("az://") {
+        resolve_azure_secret(value, runner)
+    } else if value.starts_with("doppler://") {
+        resolve_doppler_secret(value, runner)
     } else if value.starts_with("env://") {
         resolve_env_secret(value)
+    } else if value.starts_with("cmd://") {
+        resolve_custom_secret(value, secrets, runner)
     } else {
         // Not a secret reference, return as-is
         // But if backend is specified, check if it should be resolved
-        match backend {
-            "1password" if value.contains("op://") => resolve_1password_secret(value),
-            "bitwarden" if value.contains("bws://") => resolve_bitwarden_secret(value),
-            "pass" if value.contains("pass://") => resolve_pass_secret(value),
+        match secrets.backend.as_str() {
+            "1password" if value.contains("op://") => resolve_1password_secret(value, runner),
+            "bitwarden" if value.contains("bws://") => resolve_bitwarden_secret(value, runner),
+            "pass" if value.contains("pass://") => resolve_pass_secret(value, runner),
+            "gcp" if value.contains("gcp://") => resolve_gcp_secret(value, runner),
+            "azure" if value.contains("az://") => resolve_azure_secret(value, runner),
+            "doppler" if value.contains("doppler://") => resolve_doppler_secret(value, runner),
+            "custom" if value.contains("cmd://") => resolve_custom_secret(value, secrets, runner),
             _ => Ok(value.to_string()),
         }
     }
 }
 
-/// Resolve a 1Password secret reference
-/// Format: op://Vault/Item/Field
-fn resolve_1password_secret(reference: &str) -> Result<String, SecretsError> {
-    which::which("op").map_err(|_| SecretsError::OnePasswordNotFound)?;
+/// Run a backend CLI, mapping a non-zero exit to a resolution failure
+fn run_backend(
+    runner: &dyn CommandRunner,
+    reference: &str,
+    program: &str,
+    args: &[&str],
+) -> Result<String, SecretsError> {
+    let output = runner.run(program, args)?;
 
-    let output = Command::new("op").arg("read").arg(reference).output()?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.success {
         return Err(SecretsError::ResolutionFailed(
             reference.to_string(),
-            stderr.to_string(),
+            output.stderr,
         ));
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    Ok(output.stdout)
+}
+
+/// Resolve a 1Password secret reference
+/// Format: op://Vault/Item/Field
+fn resolve_1password_secret(
+    reference: &str,
+    runner: &dyn CommandRunner,
+) -> Result<String, SecretsError> {
+    if !runner.is_installed("op") {
+        return Err(SecretsError::OnePasswordNotFound);
+    }
+
+    let stdout = run_backend(runner, reference, "op", &["read", reference])?;
+    Ok(stdout.trim().to_string())
 }
 
 /// Resolve a Bitwarden Secrets Manager secret reference
 /// Format: bws://project-id/secret-name or bws://secret-id
-fn resolve_bitwarden_secret(reference: &str) -> Result<String, SecretsError> {
-    which::which("bws").map_err(|_| SecretsError::BitwardenNotFound)?;
+fn resolve_bitwarden_secret(
+    reference: &str,
+    runner: &dyn CommandRunner,
+) -> Result<String, SecretsError> {
+    if !runner.is_installed("bws") {
+        return Err(SecretsError::BitwardenNotFound);
+    }
 
     // Extract the secret identifier from bws://...
     let secret_id = reference.strip_prefix("bws://").unwrap_or(reference);
 
-    let output = Command::new("bws")
-        .arg("secret")
-        .arg("get")
-        .arg(secret_id)
-        .arg("--output")
-        .arg("json")
-        .output()?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(SecretsError::ResolutionFailed(
-            reference.to_string(),
-            stderr.to_string(),
-        ));
-    }
+    let stdout = run_backend(
+        runner,
+        reference,
+        "bws",
+        &["secret", "get", secret_id, "--output", "json"],
+    )?;
 
     // Parse JSON output to get the value
-    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+    let json: serde_json::Value = serde_json::from_str(&stdout)
         .map_err(|e| SecretsError::ResolutionFailed(reference.to_string(), e.to_string()))?;
 
     json.get("value")
@@ -118,30 +289,237 @@ fn resolve_bitwarden_secret(reference: &str) -> Result<String, SecretsError> {
 
 /// Resolve a pass (password-store) secret reference
 /// Format: pass://path/to/secret
-fn resolve_pass_secret(reference: &str) -> Result<String, SecretsError> {
-    which::which("pass").map_err(|_| SecretsError::PassNotFound)?;
+fn resolve_pass_secret(
+    reference: &str,
+    runner: &dyn CommandRunner,
+) -> Result<String, SecretsError> {
+    if !runner.is_installed("pass") {
+        return Err(SecretsError::PassNotFound);
+    }
 
     // Extract the path from pass://...
     let path = reference.strip_prefix("pass://").unwrap_or(reference);
 
-    let output = Command::new("pass").arg("show").arg(path).output()?;
+    let stdout = run_backend(runner, reference, "pass", &["show", path])?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(SecretsError::ResolutionFailed(
+    // pass outputs the secret on the first line
+    Ok(stdout.lines().next().unwrap_or("").to_string())
+}
+
+/// A parsed GCP Secret Manager reference
+#[derive(Debug, PartialEq)]
+struct GcpSecretRef<'a> {
+    /// Project ID, or None to use the active gcloud project
+    project: Option<&'a str>,
+    secret: &'a str,
+    version: &'a str,
+}
+
+/// Parse a GCP reference
+/// Format: gcp://projects/<project>/secrets/<name>[/versions/<version>] or gcp://<name>
+fn parse_gcp_reference(reference: &str) -> Option<GcpSecretRef<'_>> {
+    let path = reference.strip_prefix("gcp://")?;
+    let parts: Vec<&str> = path.split('/').collect();
+
+    match parts.as_slice() {
+        [secret] if !secret.is_empty() => Some(GcpSecretRef {
+            project: None,
+            secret,
+            version: "latest",
+        }),
+        ["projects", project, "secrets", secret] => Some(GcpSecretRef {
+            project: Some(project),
+            secret,
+            version: "latest",
+        }),
+        ["projects", project, "secrets", secret, "versions", version] => Some(GcpSecretRef {
+            project: Some(project),
+            secret,
+            version,
+        }),
+        _ => None,
+    }
+}
+
+/// Resolve a GCP Secret Manager secret reference
+/// Format: gcp://projects/<project>/secrets/<name>/versions/<version> or gcp://<name>
+fn resolve_gcp_secret(reference: &str, runner: &dyn CommandRunner) -> Result<String, SecretsError> {
+    if !runner.is_installed("gcloud") {
+        return Err(SecretsError::GcloudNotFound);
+    }
+
+    let parsed = parse_gcp_reference(reference).ok_or_else(|| {
+        SecretsError::ResolutionFailed(
             reference.to_string(),
-            stderr.to_string(),
-        ));
+            "Expected gcp://projects/<project>/secrets/<name>/versions/<version> or gcp://<name>"
+                .to_string(),
+        )
+    })?;
+
+    let secret_arg = format!("--secret={}", parsed.secret);
+    let project_arg = parsed.project.map(|p| format!("--project={}", p));
+    let mut args = vec!["secrets", "versions", "access", parsed.version, &secret_arg];
+    if let Some(ref project_arg) = project_arg {
+        args.push(project_arg);
     }
 
-    // pass outputs the secret on the first line
-    let secret = String::from_utf8_lossy(&output.stdout)
-        .lines()
-        .next()
-        .unwrap_or("")
-        .to_string();
+    let stdout = run_backend(runner, reference, "gcloud", &args)?;
+    Ok(stdout.trim().to_string())
+}
+
+/// A parsed Azure Key Vault reference
+#[derive(Debug, PartialEq)]
+struct AzureSecretRef<'a> {
+    vault: &'a str,
+    secret: &'a str,
+    /// Pinned secret version, or None for the current version
+    version: Option<&'a str>,
+}
+
+/// Parse an Azure Key Vault reference
+/// Format: az://<vault-name>/<secret-name>[/versions/<id>]
+fn parse_azure_reference(reference: &str) -> Option<AzureSecretRef<'_>> {
+    let path = reference.strip_prefix("az://")?;
+    let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+
+    match parts.as_slice() {
+        [vault, secret] if !vault.is_empty() && !secret.is_empty() => Some(AzureSecretRef {
+            vault,
+            secret,
+            version: None,
+        }),
+        [vault, secret, "versions", version] if !vault.is_empty() && !secret.is_empty() => {
+            Some(AzureSecretRef {
+                vault,
+                secret,
+                version: Some(version),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Resolve an Azure Key Vault secret reference using the ambient `az login` session
+/// Format: az://<vault-name>/<secret-name>[/versions/<id>]
+fn resolve_azure_secret(
+    reference: &str,
+    runner: &dyn CommandRunner,
+) -> Result<String, SecretsError> {
+    if !runner.is_installed("az") {
+        return Err(SecretsError::AzureCliNotFound);
+    }
+
+    let parsed = parse_azure_reference(reference).ok_or_else(|| {
+        SecretsError::ResolutionFailed(
+            reference.to_string(),
+            "Expected az://<vault-name>/<secret-name>[/versions/<id>]".to_string(),
+        )
+    })?;
+
+    let mut args = vec![
+        "keyvault",
+        "secret",
+        "show",
+        "--vault-name",
+        parsed.vault,
+        "--name",
+        parsed.secret,
+    ];
+    if let Some(version) = parsed.version {
+        args.extend(["--version", version]);
+    }
+    args.extend(["--query", "value", "-o", "tsv"]);
+
+    let stdout = run_backend(runner, reference, "az", &args)?;
+    Ok(stdout.trim().to_string())
+}
 
-    Ok(secret)
+/// A parsed Doppler reference
+#[derive(Debug, PartialEq)]
+struct DopplerSecretRef<'a> {
+    /// Project and config, or None to use DOPPLER_PROJECT/DOPPLER_CONFIG
+    scope: Option<(&'a str, &'a str)>,
+    name: &'a str,
+}
+
+/// Parse a Doppler reference
+/// Format: doppler://<project>/<config>/<name> or doppler://<name>
+fn parse_doppler_reference(reference: &str) -> Option<DopplerSecretRef<'_>> {
+    let path = reference.strip_prefix("doppler://")?;
+    let parts: Vec<&str> = path.split('/').collect();
+
+    match parts.as_slice() {
+        [name] if !name.is_empty() => Some(DopplerSecretRef { scope: None, name }),
+        [project, config, name]
+            if !project.is_empty() && !config.is_empty() && !name.is_empty() =>
+        {
+            Some(DopplerSecretRef {
+                scope: Some((project, config)),
+                name,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Resolve a Doppler secret reference
+/// Format: doppler://<project>/<config>/<name> or doppler://<name>
+fn resolve_doppler_secret(
+    reference: &str,
+    runner: &dyn CommandRunner,
+) -> Result<String, SecretsError> {
+    if !runner.is_installed("doppler") {
+        return Err(SecretsError::DopplerNotFound);
+    }
+
+    let parsed = parse_doppler_reference(reference).ok_or_else(|| {
+        SecretsError::ResolutionFailed(
+            reference.to_string(),
+            "Expected doppler://<project>/<config>/<name> or doppler://<name>".to_string(),
+        )
+    })?;
+
+    let mut args = vec!["secrets", "get", parsed.name];
+    if let Some((project, config)) = parsed.scope {
+        args.extend(["--project", project, "--config", config]);
+    }
+    args.push("--plain");
+
+    let stdout = run_backend(runner, reference, "doppler", &args)?;
+    Ok(stdout.trim().to_string())
+}
+
+/// Resolve a reference with the `[secrets.custom]` command
+/// Format: cmd://anything-the-command-understands
+///
+/// The command runs under `sh -c` with `{ref}` replaced by the quoted
+/// reference, which is passed as a positional argument rather than spliced
+/// into the script. Its stdout, minus trailing newlines, is the value.
+pub fn resolve_custom_secret(
+    reference: &str,
+    secrets: &SecretsConfig,
+    runner: &dyn CommandRunner,
+) -> Result<String, SecretsError> {
+    let command = secrets.custom.command.as_deref().ok_or_else(|| {
+        SecretsError::CustomResolverFailed(
+            reference.to_string(),
+            "no [secrets.custom] command configured".to_string(),
+        )
+    })?;
+    let name = reference.strip_prefix("cmd://").unwrap_or(reference);
+    let script = command.replace("{ref}", "\"$1\"");
+
+    let output = runner
+        .run("sh", &["-c", &script, "sh", name])
+        .map_err(|e| SecretsError::CustomResolverFailed(reference.to_string(), e.to_string()))?;
+    if !output.success {
+        return Err(SecretsError::CustomResolverFailed(
+            reference.to_string(),
+            output.stderr.trim().to_string(),
+        ));
+    }
+
+    Ok(output.stdout.trim_end_matches(['\r', '\n']).to_string())
 }
 
 /// Resolve an environment variable reference
@@ -157,6 +535,293 @@ fn resolve_env_secret(reference: &str) -> Result<String, SecretsError> {
     })
 }
 
+/// Backend a well-formed secret reference belongs to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scheme {
+    OnePassword,
+    Bitwarden,
+    Pass,
+    Gcp,
+    Azure,
+    Doppler,
+    Custom,
+    Env,
+}
+
+impl Scheme {
+    /// Backend name as used in `secrets.backend`
+    pub fn backend(self) -> &'static str {
+        match self {
+            Scheme::OnePassword => "1password",
+            Scheme::Bitwarden => "bitwarden",
+            Scheme::Pass => "pass",
+            Scheme::Gcp => "gcp",
+            Scheme::Azure => "azure",
+            Scheme::Doppler => "doppler",
+            Scheme::Custom => "custom",
+            Scheme::Env => "env",
+        }
+    }
+
+    /// CLI the backend shells out to, if any
+    pub fn cli(self) -> Option<&'static str> {
+        BACKENDS
+            .iter()
+            .find(|&&(name, _, _)| name == self.backend())
+            .and_then(|&(_, _, cli)| cli)
+    }
+}
+
+/// Why a value is not a usable secret reference
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("not a secret reference")]
+    NotAReference,
+
+    #[error("unknown scheme '{0}://'")]
+    UnknownScheme(String),
+
+    #[error("malformed reference, expected {0}")]
+    Malformed(&'static str),
+}
+
+/// Whether `value` contains a secret reference scheme anywhere, including
+/// forms the configured backend resolves inside a longer string
+pub fn mentions_secret_reference(value: &str) -> bool {
+    BACKENDS
+        .iter()
+        .any(|&(_, scheme, _)| value.contains(scheme))
+}
+
+/// Whether `value` uses a secret reference scheme such as `op://`, well-formed or not
+pub fn is_secret_reference(value: &str) -> bool {
+    matches!(
+        validate_reference(value),
+        Ok(_) | Err(ParseError::Malformed(_))
+    )
+}
+
+/// Check that `reference` is a well-formed secret reference, without resolving it
+pub fn validate_reference(reference: &str) -> Result<Scheme, ParseError> {
+    let (scheme, path) = reference
+        .split_once("://")
+        .filter(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        })
+        .ok_or(ParseError::NotAReference)?;
+    let segments: Vec<&str> = path.split('/').collect();
+    let non_empty = !segments.iter().any(|s| s.is_empty());
+
+    let (parsed, expected) = match scheme {
+        "op" => (Scheme::OnePassword, segments.len() >= 3 && non_empty),
+        "bws" => (Scheme::Bitwarden, segments.len() <= 2 && non_empty),
+        "pass" => (Scheme::Pass, non_empty),
+        "gcp" => (Scheme::Gcp, parse_gcp_reference(reference).is_some()),
+        "az" => (Scheme::Azure, parse_azure_reference(reference).is_some()),
+        "doppler" => (
+            Scheme::Doppler,
+            parse_doppler_reference(reference).is_some(),
+        ),
+        "cmd" => (Scheme::Custom, !path.is_empty()),
+        "env" => (
+            Scheme::Env,
+            !path.is_empty() && path.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        ),
+        other => return Err(ParseError::UnknownScheme(other.to_string())),
+    };
+
+    if expected {
+        Ok(parsed)
+    } else {
+        Err(ParseError::Malformed(reference_format(parsed)))
+    }
+}
+
+/// Expected format of references for `scheme`
+fn reference_format(scheme: Scheme) -> &'static str {
+    match scheme {
+        Scheme::OnePassword => "op://<vault>/<item>/[<section>/]<field>",
+        Scheme::Bitwarden => "bws://<secret-id> or bws://<project-id>/<secret-name>",
+        Scheme::Pass => "pass://<path/to/secret>",
+        Scheme::Gcp => {
+            "gcp://projects/<project>/secrets/<name>[/versions/<version>] or gcp://<name>"
+        }
+        Scheme::Azure => "az://<vault-name>/<secret-name>[/versions/<id>]",
+        Scheme::Doppler => "doppler://<project>/<config>/<name> or doppler://<name>",
+        Scheme::Custom => "cmd://<reference>",
+        Scheme::Env => "env://<VARIABLE_NAME>",
+    }
+}
+
+/// Outcome of checking one secret reference with `check_reference`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReferenceCheck {
+    /// Well-formed and the backend is available; existence wasn't probed
+    Ok(Scheme),
+    /// The backend confirmed the secret exists
+    Exists(Scheme),
+    /// The backend reported the secret as missing or inaccessible
+    NotFound(Scheme, String),
+    /// Not a known backend; the value is passed through as-is
+    UnknownScheme(String),
+    Malformed(String),
+    /// The backend's CLI (or `[secrets.custom] command`) is missing
+    BackendMissing(Scheme, String),
+}
+
+impl ReferenceCheck {
+    /// Whether this reference would fail to resolve
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            ReferenceCheck::NotFound(..)
+                | ReferenceCheck::Malformed(_)
+                | ReferenceCheck::BackendMissing(..)
+        )
+    }
+}
+
+impl std::fmt::Display for ReferenceCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReferenceCheck::Ok(scheme) => write!(f, "OK ({})", scheme.backend()),
+            ReferenceCheck::Exists(scheme) => write!(f, "OK, exists ({})", scheme.backend()),
+            ReferenceCheck::NotFound(scheme, reason) => {
+                write!(f, "NOT FOUND ({}): {}", scheme.backend(), reason)
+            }
+            ReferenceCheck::UnknownScheme(scheme) => {
+                write!(
+                    f,
+                    "unknown scheme '{}://', passed through as a plain value",
+                    scheme
+                )
+            }
+            ReferenceCheck::Malformed(reason) => write!(f, "MALFORMED: {}", reason),
+            ReferenceCheck::BackendMissing(scheme, what) => {
+                write!(f, "BACKEND MISSING ({}): {}", scheme.backend(), what)
+            }
+        }
+    }
+}
+
+/// Validate a reference and check its backend, probing existence where that
+/// doesn't fetch the secret's value; `None` for plain values
+pub fn check_reference(
+    reference: &str,
+    secrets: &SecretsConfig,
+    runner: &dyn CommandRunner,
+) -> Option<ReferenceCheck> {
+    let scheme = match validate_reference(reference) {
+        Ok(scheme) => scheme,
+        Err(ParseError::NotAReference) => return None,
+        Err(ParseError::UnknownScheme(scheme)) => {
+            return Some(ReferenceCheck::UnknownScheme(scheme))
+        }
+        Err(e) => return Some(ReferenceCheck::Malformed(e.to_string())),
+    };
+
+    if let Some(cli) = scheme.cli() {
+        if !runner.is_installed(cli) {
+            return Some(ReferenceCheck::BackendMissing(
+                scheme,
+                format!("{} not found", cli),
+            ));
+        }
+    }
+    if scheme == Scheme::Custom && secrets.custom.command.is_none() {
+        return Some(ReferenceCheck::BackendMissing(
+            scheme,
+            "no [secrets.custom] command configured".to_string(),
+        ));
+    }
+
+    Some(probe_reference(reference, scheme, runner))
+}
+
+/// Ask the backend whether a secret exists, for backends with a metadata-only lookup
+fn probe_reference(reference: &str, scheme: Scheme, runner: &dyn CommandRunner) -> ReferenceCheck {
+    let probe = |program: &str, args: &[&str]| match runner.run(program, args) {
+        Ok(output) if output.success => ReferenceCheck::Exists(scheme),
+        Ok(output) => ReferenceCheck::NotFound(scheme, output.stderr.trim().to_string()),
+        Err(e) => ReferenceCheck::NotFound(scheme, e.to_string()),
+    };
+    let path = reference.split_once("://").map(|(_, p)| p).unwrap_or("");
+
+    match scheme {
+        Scheme::OnePassword => {
+            let segments: Vec<&str> = path.split('/').collect();
+            let field = format!("label={}", segments[segments.len() - 1]);
+            probe(
+                "op",
+                &[
+                    "item",
+                    "get",
+                    segments[1],
+                    "--vault",
+                    segments[0],
+                    "--fields",
+                    &field,
+                ],
+            )
+        }
+        Scheme::Gcp => match parse_gcp_reference(reference) {
+            Some(parsed) => {
+                let project_arg = parsed.project.map(|p| format!("--project={}", p));
+                let mut args = vec!["secrets", "describe", parsed.secret];
+                if let Some(ref project_arg) = project_arg {
+                    args.push(project_arg);
+                }
+                probe("gcloud", &args)
+            }
+            None => ReferenceCheck::Ok(scheme),
+        },
+        Scheme::Azure => match parse_azure_reference(reference) {
+            Some(parsed) => probe(
+                "az",
+                &[
+                    "keyvault",
+                    "secret",
+                    "list-versions",
+                    "--vault-name",
+                    parsed.vault,
+                    "--name",
+                    parsed.secret,
+                    "--query",
+                    "length(@)",
+                ],
+            ),
+            None => ReferenceCheck::Ok(scheme),
+        },
+        Scheme::Pass => {
+            let store = std::env::var("PASSWORD_STORE_DIR")
+                .map(PathBuf::from)
+                .ok()
+                .or_else(|| dirs::home_dir().map(|h| h.join(".password-store")));
+            match store {
+                Some(store) if store.join(format!("{}.gpg", path)).is_file() => {
+                    ReferenceCheck::Exists(scheme)
+                }
+                Some(store) => ReferenceCheck::NotFound(
+                    scheme,
+                    format!("no {}.gpg in {}", path, store.display()),
+                ),
+                None => ReferenceCheck::Ok(scheme),
+            }
+        }
+        Scheme::Env => match std::env::var_os(path) {
+            Some(_) => ReferenceCheck::Exists(scheme),
+            None => {
+                ReferenceCheck::NotFound(scheme, format!("environment variable '{}' not set", path))
+            }
+        },
+        // No lookup that skips the value, or (custom) no way to ask
+        Scheme::Bitwarden | Scheme::Doppler | Scheme::Custom => ReferenceCheck::Ok(scheme),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,12 +834,282 @@ mod tests {
         std::env::remove_var("TEST_SECRET_CCS");
     }
 
+    #[test]
+    fn test_backend_status() {
+        let statuses = backend_status();
+        assert_eq!(statuses.len(), BACKENDS.len());
+
+        let env = statuses.iter().find(|s| s.name == "env").unwrap();
+        assert_eq!(env.scheme, "env://");
+        assert!(env.cli.is_none());
+        assert!(env.available);
+    }
+
+    #[test]
+    fn test_is_supported_backend() {
+        assert!(is_supported_backend("1password"));
+        assert!(is_supported_backend("env"));
+        assert!(!is_supported_backend("vault"));
+    }
+
+    /// Secrets config using the named backend
+    fn backend(name: &str) -> SecretsConfig {
+        SecretsConfig {
+            backend: name.to_string(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_plain_value_passthrough() {
-        let result = resolve_secret_value("plain_value", "env").unwrap();
+        let result = resolve_secret_value("plain_value", &backend("env"), &SystemRunner).unwrap();
         assert_eq!(result, "plain_value");
     }
 
+    /// Runner returning canned output and recording invocations
+    struct FakeRunner {
+        installed: bool,
+        output: CommandOutput,
+        calls: std::cell::RefCell<Vec<Vec<String>>>,
+    }
+
+    impl FakeRunner {
+        fn new(stdout: &str) -> Self {
+            FakeRunner {
+                installed: true,
+                output: CommandOutput {
+                    success: true,
+                    stdout: stdout.to_string(),
+                    stderr: String::new(),
+                },
+                calls: std::cell::RefCell::new(Vec::new()),
+            }
+        }
+
+        fn calls(&self) -> Vec<Vec<String>> {
+            self.calls.borrow().clone()
+        }
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn is_installed(&self, _program: &str) -> bool {
+            self.installed
+        }
+
+        fn run(&self, program: &str, args: &[&str]) -> std::io::Result<CommandOutput> {
+            let mut call = vec![program.to_string()];
+            call.extend(args.iter().map(|a| a.to_string()));
+            self.calls.borrow_mut().push(call);
+            Ok(self.output.clone())
+        }
+    }
+
+    #[test]
+    fn test_gcp_full_reference() {
+        let runner = FakeRunner::new("gcp-secret\n");
+        let result = resolve_secret_value(
+            "gcp://projects/my-proj/secrets/api-key/versions/3",
+            &backend("env"),
+            &runner,
+        )
+        .unwrap();
+        assert_eq!(result, "gcp-secret");
+        assert_eq!(
+            runner.calls(),
+            vec![vec![
+                "gcloud",
+                "secrets",
+                "versions",
+                "access",
+                "3",
+                "--secret=api-key",
+                "--project=my-proj"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_gcp_shorthand_reference() {
+        let runner = FakeRunner::new("shorthand-secret");
+        let result = resolve_secret_value("gcp://api-key", &backend("gcp"), &runner).unwrap();
+        assert_eq!(result, "shorthand-secret");
+        assert_eq!(
+            runner.calls(),
+            vec![vec![
+                "gcloud",
+                "secrets",
+                "versions",
+                "access",
+                "latest",
+                "--secret=api-key"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_gcp_errors() {
+        let mut runner = FakeRunner::new("");
+        assert!(matches!(
+            resolve_gcp_secret("gcp://projects/p/bad", &runner),
+            Err(SecretsError::ResolutionFailed(_, _))
+        ));
+
+        runner.installed = false;
+        assert!(matches!(
+            resolve_gcp_secret("gcp://api-key", &runner),
+            Err(SecretsError::GcloudNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_azure_reference() {
+        let runner = FakeRunner::new("azure-secret\n");
+        let result =
+            resolve_secret_value("az://my-vault/db-password", &backend("env"), &runner).unwrap();
+        assert_eq!(result, "azure-secret");
+        assert_eq!(
+            runner.calls(),
+            vec![vec![
+                "az",
+                "keyvault",
+                "secret",
+                "show",
+                "--vault-name",
+                "my-vault",
+                "--name",
+                "db-password",
+                "--query",
+                "value",
+                "-o",
+                "tsv"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_azure_pinned_version() {
+        assert_eq!(
+            parse_azure_reference("az://my-vault/db-password/versions/abc123"),
+            Some(AzureSecretRef {
+                vault: "my-vault",
+                secret: "db-password",
+                version: Some("abc123"),
+            })
+        );
+
+        let runner = FakeRunner::new("pinned");
+        resolve_azure_secret("az://my-vault/db-password/versions/abc123", &runner).unwrap();
+        let call = &runner.calls()[0];
+        let pos = call.iter().position(|a| a == "--version").unwrap();
+        assert_eq!(call[pos + 1], "abc123");
+    }
+
+    #[test]
+    fn test_azure_errors() {
+        let mut runner = FakeRunner::new("");
+        assert!(matches!(
+            resolve_azure_secret("az://vault-only", &runner),
+            Err(SecretsError::ResolutionFailed(_, _))
+        ));
+
+        runner.installed = false;
+        assert!(matches!(
+            resolve_azure_secret("az://vault/secret", &runner),
+            Err(SecretsError::AzureCliNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_doppler_full_reference() {
+        let runner = FakeRunner::new("doppler-secret\n");
+        let result =
+            resolve_secret_value("doppler://backend/prd/DB_URL", &backend("env"), &runner).unwrap();
+        assert_eq!(result, "doppler-secret");
+        assert_eq!(
+            runner.calls(),
+            vec![vec![
+                "doppler",
+                "secrets",
+                "get",
+                "DB_URL",
+                "--project",
+                "backend",
+                "--config",
+                "prd",
+                "--plain"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_doppler_shorthand_reference() {
+        let runner = FakeRunner::new("ambient");
+        let result =
+            resolve_secret_value("doppler://DB_URL", &backend("doppler"), &runner).unwrap();
+        assert_eq!(result, "ambient");
+        assert_eq!(
+            runner.calls(),
+            vec![vec!["doppler", "secrets", "get", "DB_URL", "--plain"]]
+        );
+    }
+
+    #[test]
+    fn test_doppler_errors() {
+        let mut runner = FakeRunner::new("");
+        assert!(matches!(
+            resolve_doppler_secret("doppler://project/DB_URL", &runner),
+            Err(SecretsError::ResolutionFailed(_, _))
+        ));
+
+        runner.installed = false;
+        assert!(matches!(
+            resolve_doppler_secret("doppler://DB_URL", &runner),
+            Err(SecretsError::DopplerNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_backend_failure_reports_stderr() {
+        let mut runner = FakeRunner::new("");
+        runner.output.success = false;
+        runner.output.stderr = "not signed in".to_string();
+
+        let err =
+            resolve_secret_value("op://Vault/Item/field", &backend("env"), &runner).unwrap_err();
+        assert!(err.to_string().contains("not signed in"));
+    }
+
+    #[test]
+    fn test_resolve_secrets_uses_cache() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut cache = SecretCache::open_in(dir.path(), 60).unwrap();
+        let env = HashMap::from([
+            ("TOKEN".to_string(), "op://Vault/Item/token".to_string()),
+            ("PLAIN".to_string(), "plain_value".to_string()),
+        ]);
+
+        // Miss: the backend is called and the value cached
+        let runner = FakeRunner::new("s3cret\n");
+        let resolved =
+            resolve_secrets_with(&env, &backend("env"), &runner, Some(&mut cache), 1000).unwrap();
+        assert_eq!(resolved["TOKEN"], "s3cret");
+        assert_eq!(runner.calls().len(), 1);
+
+        // Hit: served from the cache without calling the backend
+        let runner = FakeRunner::new("changed");
+        let resolved =
+            resolve_secrets_with(&env, &backend("env"), &runner, Some(&mut cache), 1030).unwrap();
+        assert_eq!(resolved["TOKEN"], "s3cret");
+        assert_eq!(resolved["PLAIN"], "plain_value");
+        assert!(runner.calls().is_empty());
+
+        // Expired: resolved again
+        let resolved =
+            resolve_secrets_with(&env, &backend("env"), &runner, Some(&mut cache), 1060).unwrap();
+        assert_eq!(resolved["TOKEN"], "changed");
+        assert_eq!(runner.calls().len(), 1);
+    }
+
     #[test]
     fn test_resolve_secrets_map() {
         std::env::set_var("TEST_SECRET_CCS_2", "secret_value");
@@ -182,9 +1117,180 @@ mod tests {
         env.insert("PLAIN".to_string(), "plain_value".to_string());
         env.insert("SECRET".to_string(), "env://TEST_SECRET_CCS_2".to_string());
 
-        let resolved = resolve_secrets(&env, "env").unwrap();
+        let resolved = resolve_secrets(&env, &SecretsConfig::default()).unwrap();
         assert_eq!(resolved.get("PLAIN").unwrap(), "plain_value");
         assert_eq!(resolved.get("SECRET").unwrap(), "secret_value");
         std::env::remove_var("TEST_SECRET_CCS_2");
     }
+
+    #[test]
+    fn test_custom_resolver_echo() {
+        let mut secrets = backend("env");
+        secrets.custom.command = Some("echo resolved-{ref}".to_string());
+
+        let result = resolve_secret_value("cmd://db password", &secrets, &SystemRunner).unwrap();
+        assert_eq!(result, "resolved-db password");
+    }
+
+    #[test]
+    fn test_custom_resolver_quotes_reference() {
+        let mut secrets = backend("custom");
+        secrets.custom.command = Some("printf '%s' {ref}".to_string());
+
+        // Shell metacharacters in the reference are passed through literally
+        let result = resolve_custom_secret("cmd://$(id); `x`", &secrets, &SystemRunner).unwrap();
+        assert_eq!(result, "$(id); `x`");
+    }
+
+    #[test]
+    fn test_custom_resolver_failed() {
+        let mut secrets = backend("env");
+        let err = resolve_secret_value("cmd://token", &secrets, &SystemRunner).unwrap_err();
+        assert!(matches!(err, SecretsError::CustomResolverFailed(..)));
+
+        secrets.custom.command = Some("echo nope >&2; exit 3".to_string());
+        match resolve_custom_secret("cmd://token", &secrets, &SystemRunner) {
+            Err(SecretsError::CustomResolverFailed(reference, stderr)) => {
+                assert_eq!(reference, "cmd://token");
+                assert_eq!(stderr, "nope");
+            }
+            other => panic!("expected CustomResolverFailed, got {:?}", other),
+        }
+    }
+    #[test]
+    fn test_validate_reference_schemes() {
+        let cases = [
+            ("op://Private/GitHub/token", Scheme::OnePassword),
+            ("op://Private/GitHub/Section/token", Scheme::OnePassword),
+            ("bws://0f6c1c5e-7a1b", Scheme::Bitwarden),
+            ("bws://project/API_KEY", Scheme::Bitwarden),
+            ("pass://work/github", Scheme::Pass),
+            ("gcp://api-key", Scheme::Gcp),
+            ("gcp://projects/p/secrets/api-key/versions/3", Scheme::Gcp),
+            ("az://my-vault/api-key", Scheme::Azure),
+            ("doppler://proj/dev/API_KEY", Scheme::Doppler),
+            ("cmd://vault/api", Scheme::Custom),
+            ("env://GITHUB_TOKEN", Scheme::Env),
+        ];
+        for (reference, scheme) in cases {
+            assert_eq!(validate_reference(reference), Ok(scheme), "{}", reference);
+        }
+    }
+
+    #[test]
+    fn test_validate_reference_malformed() {
+        for reference in [
+            "op://Private/GitHub",
+            "op://Private//token",
+            "bws://",
+            "bws://a/b/c",
+            "pass://",
+            "gcp://projects/p",
+            "az://only-vault",
+            "doppler://proj/API_KEY",
+            "cmd://",
+            "env://",
+            "env://NOT-A-VAR",
+        ] {
+            assert!(
+                matches!(validate_reference(reference), Err(ParseError::Malformed(_))),
+                "{}",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_reference_non_references() {
+        assert_eq!(
+            validate_reference("plain_value"),
+            Err(ParseError::NotAReference)
+        );
+        assert_eq!(
+            validate_reference("vault://secret/x"),
+            Err(ParseError::UnknownScheme("vault".to_string()))
+        );
+        assert_eq!(
+            validate_reference("https://example.com"),
+            Err(ParseError::UnknownScheme("https".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_check_reference_backend_missing() {
+        let mut runner = FakeRunner::new("");
+        runner.installed = false;
+        assert_eq!(
+            check_reference("op://Private/GitHub/token", &backend("env"), &runner),
+            Some(ReferenceCheck::BackendMissing(
+                Scheme::OnePassword,
+                "op not found".to_string()
+            ))
+        );
+
+        let check = check_reference("cmd://api", &backend("custom"), &runner).unwrap();
+        assert!(check.is_failure());
+        assert_eq!(check_reference("plain", &backend("env"), &runner), None);
+    }
+
+    #[test]
+    fn test_check_reference_probes_existence() {
+        let runner = FakeRunner::new("");
+        assert_eq!(
+            check_reference("op://Private/GitHub/token", &backend("env"), &runner),
+            Some(ReferenceCheck::Exists(Scheme::OnePassword))
+        );
+        assert_eq!(
+            check_reference("gcp://projects/p/secrets/key", &backend("env"), &runner),
+            Some(ReferenceCheck::Exists(Scheme::Gcp))
+        );
+        assert_eq!(
+            runner.calls(),
+            vec![
+                vec![
+                    "op",
+                    "item",
+                    "get",
+                    "GitHub",
+                    "--vault",
+                    "Private",
+                    "--fields",
+                    "label=token"
+                ],
+                vec!["gcloud", "secrets", "describe", "key", "--project=p"],
+            ]
+        );
+
+        let mut missing = FakeRunner::new("");
+        missing.output.success = false;
+        missing.output.stderr = "NOT_FOUND\n".to_string();
+        assert_eq!(
+            check_reference("gcp://key", &backend("env"), &missing),
+            Some(ReferenceCheck::NotFound(
+                Scheme::Gcp,
+                "NOT_FOUND".to_string()
+            ))
+        );
+
+        // Bitwarden has no lookup that skips the value, so it isn't called
+        let runner = FakeRunner::new("");
+        assert_eq!(
+            check_reference("bws://key", &backend("env"), &runner),
+            Some(ReferenceCheck::Ok(Scheme::Bitwarden))
+        );
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn test_check_reference_env() {
+        std::env::set_var("TEST_SECRET_CCS_VALIDATE", "x");
+        let runner = FakeRunner::new("");
+        assert_eq!(
+            check_reference("env://TEST_SECRET_CCS_VALIDATE", &backend("env"), &runner),
+            Some(ReferenceCheck::Exists(Scheme::Env))
+        );
+        let check = check_reference("env://TEST_SECRET_CCS_UNSET", &backend("env"), &runner);
+        assert!(check.unwrap().is_failure());
+        std::env::remove_var("TEST_SECRET_CCS_VALIDATE");
+    }
 }
@@ -0,0 +1,298 @@
+//! Opt-in timing spans exported over OTLP (`[telemetry]` config)
+//!
+//! Spans are only recorded when ccs is built with the `telemetry` cargo
+//! feature and `telemetry.endpoint` is set; otherwise `timed` just calls
+//! the closure. Recorded spans are sent as OTLP/HTTP JSON by `flush`.
+
+use crate::config::TelemetryConfig;
+
+/// Run `f`, recording how long it took as span `name`
+#[cfg(feature = "telemetry")]
+pub fn timed<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    otlp::timed(name, f)
+}
+
+/// Run `f` (telemetry is compiled out)
+#[cfg(not(feature = "telemetry"))]
+#[inline(always)]
+pub fn timed<T>(_name: &'static str, f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// Start recording spans if `telemetry.endpoint` is set
+pub fn init(config: &TelemetryConfig) {
+    #[cfg(feature = "telemetry")]
+    otlp::init(config);
+
+    #[cfg(not(feature = "telemetry"))]
+    if config.endpoint.is_some() {
+        eprintln!(
+            "Warning: telemetry.endpoint is set, but this ccs was built without the telemetry feature"
+        );
+    }
+}
+
+/// Export the spans recorded so far, warning if the collector can't be reached
+pub fn flush() {
+    #[cfg(feature = "telemetry")]
+    otlp::flush();
+}
+
+#[cfg(feature = "telemetry")]
+mod otlp {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::sync::{Mutex, OnceLock};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use crate::config::TelemetryConfig;
+
+    /// Seconds to wait for the collector before giving up
+    const EXPORT_TIMEOUT_SECS: u64 = 5;
+
+    /// A finished timing span
+    #[derive(Debug, Clone, PartialEq)]
+    pub(super) struct Span {
+        pub name: &'static str,
+        /// Start time in nanoseconds since the Unix epoch
+        pub start_unix_nanos: u128,
+        pub duration: Duration,
+    }
+
+    /// Spans recorded during this invocation, exported together as one trace
+    #[derive(Debug, Default)]
+    pub(super) struct Recorder {
+        pub spans: Vec<Span>,
+    }
+
+    impl Recorder {
+        /// Run `f`, appending its timing to the recorder
+        ///
+        /// The lock is only taken after `f` returns, so spans may nest.
+        pub fn timed<T>(recorder: &Mutex<Self>, name: &'static str, f: impl FnOnce() -> T) -> T {
+            let start_unix_nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            let started = Instant::now();
+            let result = f();
+            let duration = started.elapsed();
+
+            if let Ok(mut recorder) = recorder.lock() {
+                recorder.spans.push(Span {
+                    name,
+                    start_unix_nanos,
+                    duration,
+                });
+            }
+            result
+        }
+    }
+
+    struct Exporter {
+        endpoint: String,
+        recorder: Mutex<Recorder>,
+    }
+
+    static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+    pub fn init(config: &TelemetryConfig) {
+        if let Some(ref endpoint) = config.endpoint {
+            let _ = EXPORTER.set(Exporter {
+                endpoint: traces_url(endpoint),
+                recorder: Mutex::new(Recorder::default()),
+            });
+        }
+    }
+
+    pub fn timed<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+        match EXPORTER.get() {
+            Some(exporter) => Recorder::timed(&exporter.recorder, name, f),
+            None => f(),
+        }
+    }
+
+    pub fn flush() {
+        let Some(exporter) = EXPORTER.get() else {
+            return;
+        };
+        let spans = match exporter.recorder.lock() {
+            Ok(mut recorder) => std::mem::take(&mut recorder.spans),
+            Err(_) => return,
+        };
+        if spans.is_empty() {
+            return;
+        }
+
+        let trace_id = random_hex(16);
+        let body = traces_json(&spans, &trace_id, random_hex).to_string();
+        if let Err(e) = post_json(&exporter.endpoint, &body) {
+            eprintln!(
+                "Warning: failed to export telemetry to {}: {}",
+                exporter.endpoint, e
+            );
+        }
+    }
+
+    /// OTLP/HTTP traces URL for a collector base URL
+    pub(super) fn traces_url(endpoint: &str) -> String {
+        let endpoint = endpoint.trim_end_matches('/');
+        if endpoint.ends_with("/v1/traces") {
+            endpoint.to_string()
+        } else {
+            format!("{}/v1/traces", endpoint)
+        }
+    }
+
+    /// OTLP JSON `ExportTraceServiceRequest` for `spans`, all in one trace
+    pub(super) fn traces_json(
+        spans: &[Span],
+        trace_id: &str,
+        mut span_id: impl FnMut(usize) -> String,
+    ) -> serde_json::Value {
+        let spans: Vec<serde_json::Value> = spans
+            .iter()
+            .map(|span| {
+                let end = span.start_unix_nanos + span.duration.as_nanos();
+                serde_json::json!({
+                    "traceId": trace_id,
+                    "spanId": span_id(8),
+                    "name": span.name,
+                    "kind": 1,
+                    "startTimeUnixNano": span.start_unix_nanos.to_string(),
+                    "endTimeUnixNano": end.to_string(),
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{
+                        "key": "service.name",
+                        "value": { "stringValue": "ccs" }
+                    }, {
+                        "key": "service.version",
+                        "value": { "stringValue": env!("CARGO_PKG_VERSION") }
+                    }]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "ccs" },
+                    "spans": spans
+                }]
+            }]
+        })
+    }
+
+    /// `len` random bytes as lowercase hex
+    fn random_hex(len: usize) -> String {
+        let mut bytes = vec![0u8; len];
+        if openssl::rand::rand_bytes(&mut bytes).is_err() {
+            // Only used for IDs; fall back to the clock
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = (nanos >> ((i % 16) * 8)) as u8;
+            }
+        }
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// POST `body` as JSON with curl
+    fn post_json(url: &str, body: &str) -> Result<(), String> {
+        let mut child = Command::new("curl")
+            .args([
+                "-sS",
+                "-f",
+                "--max-time",
+                &EXPORT_TIMEOUT_SECS.to_string(),
+                "-X",
+                "POST",
+                "-H",
+                "Content-Type: application/json",
+                "--data-binary",
+                "@-",
+                url,
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("could not run curl: {}", e))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(body.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timed_returns_result() {
+        assert_eq!(timed("test", || 6 * 7), 42);
+    }
+
+    #[cfg(feature = "telemetry")]
+    #[test]
+    fn test_timed_records_duration() {
+        use std::sync::Mutex;
+        use std::time::Duration;
+
+        let recorder = Mutex::new(otlp::Recorder::default());
+        let value = otlp::Recorder::timed(&recorder, "build", || {
+            std::thread::sleep(Duration::from_millis(20));
+            "done"
+        });
+        assert_eq!(value, "done");
+
+        let spans = &recorder.lock().unwrap().spans;
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "build");
+        assert!(spans[0].duration >= Duration::from_millis(20));
+        assert!(spans[0].start_unix_nanos > 0);
+    }
+
+    #[cfg(feature = "telemetry")]
+    #[test]
+    fn test_traces_json() {
+        use std::time::Duration;
+
+        let spans = [otlp::Span {
+            name: "run",
+            start_unix_nanos: 1_000,
+            duration: Duration::from_nanos(500),
+        }];
+        let json = otlp::traces_json(&spans, "ab".repeat(16).as_str(), |_| "cd".repeat(8));
+        let span = &json["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["name"], "run");
+        assert_eq!(span["startTimeUnixNano"], "1000");
+        assert_eq!(span["endTimeUnixNano"], "1500");
+        assert_eq!(span["spanId"], "cdcdcdcdcdcdcdcd");
+    }
+
+    #[cfg(feature = "telemetry")]
+    #[test]
+    fn test_traces_url() {
+        assert_eq!(
+            otlp::traces_url("http://localhost:4318/"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            otlp::traces_url("https://otel.example.com/v1/traces"),
+            "https://otel.example.com/v1/traces"
+        );
+    }
+}
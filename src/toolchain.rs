@@ -9,6 +9,8 @@ use std::path::Path;
 pub struct Toolchain {
     /// Detected tools and their install commands
     pub tools: Vec<Tool>,
+    /// Recommendations for projects that declare their own environment
+    pub notes: Vec<String>,
 }
 
 /// A single tool that should be available in the container
@@ -16,8 +18,8 @@ pub struct Toolchain {
 pub struct Tool {
     /// Tool name (for display)
     pub name: &'static str,
-    /// Install command(s) to run in container
-    pub install_cmd: &'static str,
+    /// Install command(s) to run in container, honoring any pinned version
+    pub install_cmd: String,
     /// Check command to verify installation (reserved for future use)
     #[allow(dead_code)]
     pub check_cmd: &'static str,
@@ -88,12 +90,24 @@ impl Toolchain {
             tools.push(tool);
         }
 
-        Toolchain { tools }
+        // C/C++ detection
+        if let Some(tool) = detect_c_cpp(project_path) {
+            tools.push(tool);
+        }
+
+        // Nix and dev container projects declare their own environment,
+        // which the sandbox can't set up by itself, so they only get a note
+        let notes = [detect_nix(project_path), detect_devcontainer(project_path)]
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Toolchain { tools, notes }
     }
 
     /// Generate shell commands to install all detected tools
-    pub fn install_commands(&self) -> Vec<&'static str> {
-        self.tools.iter().map(|t| t.install_cmd).collect()
+    pub fn install_commands(&self) -> Vec<&str> {
+        self.tools.iter().map(|t| t.install_cmd.as_str()).collect()
     }
 
     /// Check if any tools were detected
@@ -105,6 +119,68 @@ impl Toolchain {
     pub fn tool_names(&self) -> Vec<&'static str> {
         self.tools.iter().map(|t| t.name).collect()
     }
+
+    /// Add tools from another project, keeping the first one detected for each name
+    pub fn merge(&mut self, other: Toolchain) {
+        for tool in other.tools {
+            if !self.tools.iter().any(|t| t.name == tool.name) {
+                self.tools.push(tool);
+            }
+        }
+        for note in other.notes {
+            if !self.notes.contains(&note) {
+                self.notes.push(note);
+            }
+        }
+    }
+}
+
+// === Version pins ===
+
+/// Read a pinned version from a single-value file like `.nvmrc`
+fn read_pin_file(path: &Path, file: &str) -> Option<String> {
+    let content = std::fs::read_to_string(path.join(file)).ok()?;
+    content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .and_then(sanitize_version)
+}
+
+/// Read a tool's pinned version from an asdf/mise `.tool-versions` file
+fn read_tool_versions(path: &Path, tool: &str) -> Option<String> {
+    let content = std::fs::read_to_string(path.join(".tool-versions")).ok()?;
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        (parts.next() == Some(tool))
+            .then(|| parts.next())
+            .flatten()
+            .and_then(sanitize_version)
+    })
+}
+
+/// Read the channel from `rust-toolchain.toml` or a legacy `rust-toolchain` file
+fn read_rust_toolchain(path: &Path) -> Option<String> {
+    if let Ok(content) = std::fs::read_to_string(path.join("rust-toolchain.toml")) {
+        let value: toml::Value = toml::from_str(&content).ok()?;
+        return value
+            .get("toolchain")?
+            .get("channel")?
+            .as_str()
+            .and_then(sanitize_version);
+    }
+
+    read_pin_file(path, "rust-toolchain")
+}
+
+/// Accept only characters that are safe to splice into an install command
+fn sanitize_version(version: &str) -> Option<String> {
+    let version = version.trim().trim_start_matches('v');
+    let valid = !version.is_empty()
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/' | '*' | '+'));
+    valid.then(|| version.to_string())
 }
 
 // === Detection functions ===
@@ -118,9 +194,13 @@ fn detect_rust(path: &Path) -> Option<Tool> {
     ];
 
     if indicators.iter().any(|f| path.join(f).exists()) {
+        let toolchain = read_rust_toolchain(path).unwrap_or_else(|| "stable".to_string());
         Some(Tool {
             name: "Rust",
-            install_cmd: "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y && . $HOME/.cargo/env",
+            install_cmd: format!(
+                "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y --default-toolchain {} && . $HOME/.cargo/env",
+                toolchain
+            ),
             check_cmd: "rustc --version",
         })
     } else {
@@ -134,7 +214,8 @@ fn detect_node(path: &Path) -> Option<Tool> {
         return Some(Tool {
             name: "Bun",
             install_cmd:
-                "curl -fsSL https://bun.sh/install | bash && export PATH=$HOME/.bun/bin:$PATH",
+                "curl -fsSL https://bun.sh/install | bash && export PATH=$HOME/.bun/bin:$PATH"
+                    .to_string(),
             check_cmd: "bun --version",
         });
     }
@@ -142,7 +223,7 @@ fn detect_node(path: &Path) -> Option<Tool> {
     if path.join("pnpm-lock.yaml").exists() {
         return Some(Tool {
             name: "pnpm",
-            install_cmd: "curl -fsSL https://get.pnpm.io/install.sh | sh - && export PNPM_HOME=$HOME/.local/share/pnpm && export PATH=$PNPM_HOME:$PATH",
+            install_cmd: "curl -fsSL https://get.pnpm.io/install.sh | sh - && export PNPM_HOME=$HOME/.local/share/pnpm && export PATH=$PNPM_HOME:$PATH".to_string(),
             check_cmd: "pnpm --version",
         });
     }
@@ -150,7 +231,7 @@ fn detect_node(path: &Path) -> Option<Tool> {
     if path.join("yarn.lock").exists() {
         return Some(Tool {
             name: "Yarn",
-            install_cmd: "corepack enable && corepack prepare yarn@stable --activate",
+            install_cmd: "corepack enable && corepack prepare yarn@stable --activate".to_string(),
             check_cmd: "yarn --version",
         });
     }
@@ -163,9 +244,16 @@ fn detect_node(path: &Path) -> Option<Tool> {
         ".node-version",
     ];
     if indicators.iter().any(|f| path.join(f).exists()) {
+        let install = match node_version(path) {
+            Some(version) => format!("fnm install {0} && fnm default {0}", version),
+            None => "fnm install --lts".to_string(),
+        };
         return Some(Tool {
             name: "Node.js",
-            install_cmd: "curl -fsSL https://fnm.vercel.app/install | bash && export PATH=$HOME/.local/share/fnm:$PATH && eval \"$(fnm env)\" && fnm install --lts",
+            install_cmd: format!(
+                "curl -fsSL https://fnm.vercel.app/install | bash && export PATH=$HOME/.local/share/fnm:$PATH && eval \"$(fnm env)\" && {}",
+                install
+            ),
             check_cmd: "node --version",
         });
     }
@@ -173,12 +261,35 @@ fn detect_node(path: &Path) -> Option<Tool> {
     None
 }
 
+/// Pinned Node.js version from `.nvmrc`, `.node-version` or `.tool-versions`
+fn node_version(path: &Path) -> Option<String> {
+    read_pin_file(path, ".nvmrc")
+        .or_else(|| read_pin_file(path, ".node-version"))
+        .or_else(|| read_tool_versions(path, "nodejs"))
+}
+
+/// Pinned Python version from `.python-version` or `.tool-versions`
+fn python_version(path: &Path) -> Option<String> {
+    read_pin_file(path, ".python-version").or_else(|| read_tool_versions(path, "python"))
+}
+
+/// uv install command, also installing the pinned Python if there is one
+fn uv_install_cmd(path: &Path) -> String {
+    let mut cmd =
+        "curl -LsSf https://astral.sh/uv/install.sh | sh && export PATH=$HOME/.local/bin:$PATH"
+            .to_string();
+    if let Some(version) = python_version(path) {
+        cmd.push_str(&format!(" && uv python install {}", version));
+    }
+    cmd
+}
+
 fn detect_python(path: &Path) -> Option<Tool> {
     // Check for uv first (modern Python package manager)
     if path.join("uv.lock").exists() || path.join("uv.toml").exists() {
         return Some(Tool {
             name: "uv",
-            install_cmd: "curl -LsSf https://astral.sh/uv/install.sh | sh && export PATH=$HOME/.local/bin:$PATH",
+            install_cmd: uv_install_cmd(path),
             check_cmd: "uv --version",
         });
     }
@@ -187,7 +298,7 @@ fn detect_python(path: &Path) -> Option<Tool> {
     if path.join("poetry.lock").exists() || path.join("poetry.toml").exists() {
         return Some(Tool {
             name: "Poetry",
-            install_cmd: "curl -sSL https://install.python-poetry.org | python3 - && export PATH=$HOME/.local/bin:$PATH",
+            install_cmd: "curl -sSL https://install.python-poetry.org | python3 - && export PATH=$HOME/.local/bin:$PATH".to_string(),
             check_cmd: "poetry --version",
         });
     }
@@ -196,7 +307,8 @@ fn detect_python(path: &Path) -> Option<Tool> {
     if path.join("Pipfile").exists() || path.join("Pipfile.lock").exists() {
         return Some(Tool {
             name: "Pipenv",
-            install_cmd: "pip install --user pipenv && export PATH=$HOME/.local/bin:$PATH",
+            install_cmd: "pip install --user pipenv && export PATH=$HOME/.local/bin:$PATH"
+                .to_string(),
             check_cmd: "pipenv --version",
         });
     }
@@ -210,24 +322,14 @@ fn detect_python(path: &Path) -> Option<Tool> {
         "requirements-dev.txt",
         ".python-version",
         "tox.ini",
-        "Makefile", // Many Python projects use Makefiles
     ];
 
-    // Check for .py files in root
-    let has_py_files = path
-        .read_dir()
-        .map(|entries| {
-            entries
-                .filter_map(|e| e.ok())
-                .any(|e| e.path().extension().map(|ext| ext == "py").unwrap_or(false))
-        })
-        .unwrap_or(false);
-
-    if indicators.iter().any(|f| path.join(f).exists()) || has_py_files {
+    // Many Python projects use Makefiles, but a Makefile alone is left to C/C++
+    if indicators.iter().any(|f| path.join(f).exists()) || has_extension(path, &["py"]) {
         // Prefer uv for new projects as it's faster
         return Some(Tool {
             name: "Python (uv)",
-            install_cmd: "curl -LsSf https://astral.sh/uv/install.sh | sh && export PATH=$HOME/.local/bin:$PATH",
+            install_cmd: uv_install_cmd(path),
             check_cmd: "uv --version && python3 --version",
         });
     }
@@ -239,9 +341,15 @@ fn detect_go(path: &Path) -> Option<Tool> {
     let indicators = ["go.mod", "go.sum", "go.work"];
 
     if indicators.iter().any(|f| path.join(f).exists()) {
+        let version = read_pin_file(path, ".go-version")
+            .or_else(|| read_tool_versions(path, "golang"))
+            .unwrap_or_else(|| "1.22.0".to_string());
         Some(Tool {
             name: "Go",
-            install_cmd: "curl -fsSL https://go.dev/dl/go1.22.0.linux-amd64.tar.gz | tar -C /usr/local -xzf - && export PATH=$PATH:/usr/local/go/bin:$HOME/go/bin",
+            install_cmd: format!(
+                "curl -fsSL https://go.dev/dl/go{}.linux-amd64.tar.gz | tar -C /usr/local -xzf - && export PATH=$PATH:/usr/local/go/bin:$HOME/go/bin",
+                version
+            ),
             check_cmd: "go version",
         })
     } else {
@@ -259,7 +367,7 @@ fn detect_moon_proto(path: &Path) -> Option<Tool> {
     if moon_indicators.iter().any(|f| path.join(f).exists()) {
         return Some(Tool {
             name: "moon",
-            install_cmd: "curl -fsSL https://moonrepo.dev/install/moon.sh | bash && export PATH=$HOME/.moon/bin:$PATH",
+            install_cmd: "curl -fsSL https://moonrepo.dev/install/moon.sh | bash && export PATH=$HOME/.moon/bin:$PATH".to_string(),
             check_cmd: "moon --version",
         });
     }
@@ -267,7 +375,7 @@ fn detect_moon_proto(path: &Path) -> Option<Tool> {
     if proto_indicators.iter().any(|f| path.join(f).exists()) {
         return Some(Tool {
             name: "proto",
-            install_cmd: "curl -fsSL https://moonrepo.dev/install/proto.sh | bash && export PATH=$HOME/.proto/bin:$PATH",
+            install_cmd: "curl -fsSL https://moonrepo.dev/install/proto.sh | bash && export PATH=$HOME/.proto/bin:$PATH".to_string(),
             check_cmd: "proto --version",
         });
     }
@@ -282,7 +390,7 @@ fn detect_turbo(path: &Path) -> Option<Tool> {
         Some(Tool {
             name: "Turborepo",
             // Turbo is typically installed via npm, but we can also install globally
-            install_cmd: "npm install -g turbo",
+            install_cmd: "npm install -g turbo".to_string(),
             check_cmd: "turbo --version",
         })
     } else {
@@ -297,7 +405,8 @@ fn detect_deno(path: &Path) -> Option<Tool> {
         Some(Tool {
             name: "Deno",
             install_cmd:
-                "curl -fsSL https://deno.land/install.sh | sh && export PATH=$HOME/.deno/bin:$PATH",
+                "curl -fsSL https://deno.land/install.sh | sh && export PATH=$HOME/.deno/bin:$PATH"
+                    .to_string(),
             check_cmd: "deno --version",
         })
     } else {
@@ -318,9 +427,18 @@ fn detect_java(path: &Path) -> Option<Tool> {
     ];
 
     if indicators.iter().any(|f| path.join(f).exists()) {
+        let install = match read_pin_file(path, ".java-version")
+            .or_else(|| read_tool_versions(path, "java"))
+        {
+            Some(version) => format!("sdk install java {}", version),
+            None => "sdk install java".to_string(),
+        };
         Some(Tool {
             name: "Java (SDKMAN)",
-            install_cmd: "curl -s https://get.sdkman.io | bash && source $HOME/.sdkman/bin/sdkman-init.sh && sdk install java",
+            install_cmd: format!(
+                "curl -s https://get.sdkman.io | bash && source $HOME/.sdkman/bin/sdkman-init.sh && {}",
+                install
+            ),
             check_cmd: "java --version",
         })
     } else {
@@ -352,9 +470,19 @@ fn detect_ruby(path: &Path) -> Option<Tool> {
         .unwrap_or(false);
 
     if indicators[..5].iter().any(|f| path.join(f).exists()) || has_gemspec {
+        let install = match read_pin_file(path, ".ruby-version")
+            .or_else(|| read_tool_versions(path, "ruby"))
+        {
+            Some(version) => format!("rbenv install -s {0} && rbenv global {0}", version),
+            None => "rbenv install -s && rbenv global $(rbenv install -l | grep -v - | tail -1)"
+                .to_string(),
+        };
         Some(Tool {
             name: "Ruby",
-            install_cmd: "curl -fsSL https://github.com/rbenv/rbenv-installer/raw/HEAD/bin/rbenv-installer | bash && export PATH=$HOME/.rbenv/bin:$PATH && eval \"$(rbenv init -)\" && rbenv install -s && rbenv global $(rbenv install -l | grep -v - | tail -1)",
+            install_cmd: format!(
+                "curl -fsSL https://github.com/rbenv/rbenv-installer/raw/HEAD/bin/rbenv-installer | bash && export PATH=$HOME/.rbenv/bin:$PATH && eval \"$(rbenv init -)\" && {}",
+                install
+            ),
             check_cmd: "ruby --version",
         })
     } else {
@@ -368,7 +496,7 @@ fn detect_php(path: &Path) -> Option<Tool> {
     if indicators.iter().any(|f| path.join(f).exists()) {
         Some(Tool {
             name: "PHP",
-            install_cmd: "apt-get update && apt-get install -y php php-cli php-mbstring php-xml php-curl && curl -sS https://getcomposer.org/installer | php -- --install-dir=/usr/local/bin --filename=composer",
+            install_cmd: "apt-get update && apt-get install -y php php-cli php-mbstring php-xml php-curl && curl -sS https://getcomposer.org/installer | php -- --install-dir=/usr/local/bin --filename=composer".to_string(),
             check_cmd: "php --version && composer --version",
         })
     } else {
@@ -388,7 +516,7 @@ fn detect_elixir(path: &Path) -> Option<Tool> {
     if indicators[..2].iter().any(|f| path.join(f).exists()) || has_elixir_in_tool_versions {
         Some(Tool {
             name: "Elixir",
-            install_cmd: "apt-get update && apt-get install -y erlang elixir",
+            install_cmd: "apt-get update && apt-get install -y erlang elixir".to_string(),
             check_cmd: "elixir --version",
         })
     } else {
@@ -402,7 +530,7 @@ fn detect_zig(path: &Path) -> Option<Tool> {
     if indicators.iter().any(|f| path.join(f).exists()) {
         Some(Tool {
             name: "Zig",
-            install_cmd: "curl -fsSL https://ziglang.org/download/0.11.0/zig-linux-x86_64-0.11.0.tar.xz | tar -xJ -C /usr/local && export PATH=$PATH:/usr/local/zig-linux-x86_64-0.11.0",
+            install_cmd: "curl -fsSL https://ziglang.org/download/0.11.0/zig-linux-x86_64-0.11.0.tar.xz | tar -xJ -C /usr/local && export PATH=$PATH:/usr/local/zig-linux-x86_64-0.11.0".to_string(),
             check_cmd: "zig version",
         })
     } else {
@@ -410,6 +538,92 @@ fn detect_zig(path: &Path) -> Option<Tool> {
     }
 }
 
+fn detect_c_cpp(path: &Path) -> Option<Tool> {
+    // Build systems that are unambiguously C/C++
+    let indicators = ["CMakeLists.txt", "meson.build", "configure.ac"];
+
+    // A plain Makefile counts only alongside C/C++ sources and without Python
+    let makefile_project = path.join("Makefile").exists()
+        && (has_extension(path, C_CPP_EXTENSIONS)
+            || has_extension(&path.join("src"), C_CPP_EXTENSIONS))
+        && detect_python(path).is_none();
+
+    if indicators.iter().any(|f| path.join(f).exists()) || makefile_project {
+        Some(Tool {
+            name: "C/C++",
+            install_cmd: "apt-get update && apt-get install -y build-essential clang cmake meson ninja-build pkg-config".to_string(),
+            check_cmd: "cc --version && cmake --version",
+        })
+    } else {
+        None
+    }
+}
+
+/// Nix isn't installed in the sandbox: its installer has to create `/nix`,
+/// which the image's non-root user can't
+fn detect_nix(path: &Path) -> Option<String> {
+    let flake = path.join("flake.nix").exists();
+    let legacy = ["default.nix", "shell.nix"]
+        .iter()
+        .any(|f| path.join(f).exists());
+    let (found, enter) = if flake {
+        ("flake.nix", "nix develop")
+    } else if legacy {
+        ("shell.nix/default.nix", "nix-shell")
+    } else {
+        return None;
+    };
+
+    Some(format!(
+        "{} found: use a docker.image with Nix installed (its installer needs root) and enter the project environment with `{}`",
+        found, enter
+    ))
+}
+
+/// The dev container CLI needs Docker, which the sandbox doesn't have
+fn detect_devcontainer(path: &Path) -> Option<String> {
+    let config = [".devcontainer/devcontainer.json", ".devcontainer.json"]
+        .iter()
+        .map(|f| path.join(f))
+        .find(|p| p.is_file())?;
+
+    // devcontainer.json allows comments, so an image is only reported if it parses as JSON
+    let image = std::fs::read_to_string(&config)
+        .ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        .and_then(|v| v.get("image")?.as_str().map(str::to_string));
+    let note = match image {
+        Some(image) => format!(
+            "{} uses image {}; consider setting docker.image to an image based on it",
+            config.strip_prefix(path).unwrap_or(&config).display(),
+            image
+        ),
+        None => format!(
+            "{} defines the project environment; consider basing docker.image on it",
+            config.strip_prefix(path).unwrap_or(&config).display()
+        ),
+    };
+
+    Some(note)
+}
+
+/// Source file extensions that mark a C/C++ project
+const C_CPP_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx", "h", "hpp"];
+
+/// Check whether a directory directly contains a file with one of `extensions`
+fn has_extension(dir: &Path, extensions: &[&str]) -> bool {
+    dir.read_dir()
+        .map(|entries| {
+            entries.filter_map(|e| e.ok()).any(|e| {
+                e.path()
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| extensions.contains(&ext))
+            })
+        })
+        .unwrap_or(false)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -453,6 +667,108 @@ mod tests {
         assert!(toolchain.tool_names().contains(&"uv"));
     }
 
+    #[test]
+    fn test_node_version_pin() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        fs::write(dir.path().join(".nvmrc"), "v20.11.1\n").unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        let cmd = toolchain.install_commands()[0];
+        assert!(cmd.ends_with("fnm install 20.11.1 && fnm default 20.11.1"));
+        assert!(!cmd.contains("--lts"));
+    }
+
+    #[test]
+    fn test_node_version_unpinned() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        assert!(toolchain.install_commands()[0].ends_with("fnm install --lts"));
+    }
+
+    #[test]
+    fn test_python_version_pin() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("pyproject.toml"), "").unwrap();
+        fs::write(dir.path().join(".python-version"), "3.12.2\n").unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        assert!(toolchain.install_commands()[0].ends_with("&& uv python install 3.12.2"));
+    }
+
+    #[test]
+    fn test_tool_versions_and_rust_toolchain() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join(".tool-versions"),
+            "nodejs 18.19.0\npython 3.11.8\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"1.76.0\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(node_version(dir.path()).as_deref(), Some("18.19.0"));
+        assert_eq!(python_version(dir.path()).as_deref(), Some("3.11.8"));
+        assert_eq!(read_rust_toolchain(dir.path()).as_deref(), Some("1.76.0"));
+    }
+
+    #[test]
+    fn test_sanitize_version_rejects_shell() {
+        assert_eq!(sanitize_version("lts/iron").as_deref(), Some("lts/iron"));
+        assert_eq!(sanitize_version("20; rm -rf /"), None);
+        assert_eq!(sanitize_version("$(whoami)"), None);
+        assert_eq!(sanitize_version("  "), None);
+    }
+
+    #[test]
+    fn test_detect_c_cpp_cmake() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("CMakeLists.txt"), "").unwrap();
+        fs::write(dir.path().join("Makefile"), "").unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        assert!(toolchain.tool_names().contains(&"C/C++"));
+        assert!(!toolchain.tool_names().contains(&"Python (uv)"));
+    }
+
+    #[test]
+    fn test_detect_c_cpp_meson() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("meson.build"), "").unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        assert_eq!(toolchain.tool_names(), vec!["C/C++"]);
+    }
+
+    #[test]
+    fn test_makefile_precedence() {
+        // Makefile with C sources is a C project
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("Makefile"), "").unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/main.c"), "").unwrap();
+        assert_eq!(Toolchain::detect(dir.path()).tool_names(), vec!["C/C++"]);
+
+        // Makefile with Python sources stays with Python
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("Makefile"), "").unwrap();
+        fs::write(dir.path().join("app.py"), "").unwrap();
+        assert_eq!(
+            Toolchain::detect(dir.path()).tool_names(),
+            vec!["Python (uv)"]
+        );
+
+        // A bare Makefile installs nothing
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("Makefile"), "").unwrap();
+        assert!(Toolchain::detect(dir.path()).is_empty());
+    }
+
     #[test]
     fn test_detect_moon() {
         let dir = TempDir::new().unwrap();
@@ -473,6 +789,72 @@ mod tests {
         assert!(toolchain.tools.len() >= 2);
     }
 
+    #[test]
+    fn test_merge_unions_tools() {
+        let rust = TempDir::new().unwrap();
+        fs::write(rust.path().join("Cargo.toml"), "").unwrap();
+        let both = TempDir::new().unwrap();
+        fs::write(both.path().join("Cargo.toml"), "").unwrap();
+        fs::write(both.path().join("package.json"), "{}").unwrap();
+
+        let mut toolchain = Toolchain::detect(rust.path());
+        toolchain.merge(Toolchain::detect(both.path()));
+        let names = toolchain.tool_names();
+        assert_eq!(names.iter().filter(|n| **n == "Rust").count(), 1);
+        assert!(names.contains(&"Node.js"));
+    }
+
+    #[test]
+    fn test_detect_nix_flake() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("flake.nix"), "{}").unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        assert!(toolchain.is_empty());
+        assert!(toolchain.notes[0].contains("nix develop"));
+    }
+
+    #[test]
+    fn test_detect_nix_shell() {
+        for file in ["shell.nix", "default.nix"] {
+            let dir = TempDir::new().unwrap();
+            fs::write(dir.path().join(file), "{}").unwrap();
+
+            let toolchain = Toolchain::detect(dir.path());
+            assert!(toolchain.is_empty(), "{}", file);
+            assert!(toolchain.notes[0].contains("nix-shell"));
+        }
+    }
+
+    #[test]
+    fn test_detect_devcontainer() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".devcontainer")).unwrap();
+        fs::write(
+            dir.path().join(".devcontainer").join("devcontainer.json"),
+            r#"{"image": "mcr.microsoft.com/devcontainers/rust:1"}"#,
+        )
+        .unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        assert!(toolchain.is_empty());
+        assert!(toolchain.notes[0].contains("mcr.microsoft.com/devcontainers/rust:1"));
+    }
+
+    #[test]
+    fn test_detect_devcontainer_root_file_with_comments() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join(".devcontainer.json"),
+            "// JSONC\n{\"build\": {\"dockerfile\": \"Dockerfile\"}}",
+        )
+        .unwrap();
+
+        let toolchain = Toolchain::detect(dir.path());
+        assert!(toolchain.is_empty());
+        assert!(toolchain.notes[0].starts_with(".devcontainer.json defines"));
+    }
+
     #[test]
     fn test_empty_detection() {
         let dir = TempDir::new().unwrap();
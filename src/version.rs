@@ -0,0 +1,102 @@
+//! Detailed version information for bug reports (`--version-full`)
+
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::docker::{self, ContainerRuntime};
+
+/// Build and environment details
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub libgit2: String,
+    /// Detected container runtime (None if neither Docker nor Podman was found)
+    pub runtime: Option<&'static str>,
+    /// The runtime's `--version` output
+    pub runtime_version: Option<String>,
+    pub config_path: Option<PathBuf>,
+}
+
+/// Gather version, platform, libgit2, runtime and config details
+pub fn build_info(config_path: Option<&Path>) -> BuildInfo {
+    let (major, minor, rev) = git2::Version::get().libgit2_version();
+    let runtime = ContainerRuntime::detect().ok();
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        libgit2: format!("{}.{}.{}", major, minor, rev),
+        runtime: runtime.map(|r| r.name()),
+        runtime_version: runtime.and_then(docker::get_runtime_version),
+        config_path: config_path
+            .map(Path::to_path_buf)
+            .or_else(|| Config::config_path().ok()),
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ccs {}", self.version)?;
+        writeln!(f, "OS: {} ({})", self.os, self.arch)?;
+        writeln!(f, "libgit2: {}", self.libgit2)?;
+        match (self.runtime, &self.runtime_version) {
+            (Some(_), Some(version)) => writeln!(f, "Runtime: {}", version)?,
+            (Some(runtime), None) => writeln!(f, "Runtime: {} (version unknown)", runtime)?,
+            (None, _) => writeln!(f, "Runtime: not found")?,
+        }
+        match &self.config_path {
+            Some(path) if path.exists() => write!(f, "Config: {}", path.display()),
+            Some(path) => write!(f, "Config: {} (not found)", path.display()),
+            None => write!(f, "Config: unknown"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> BuildInfo {
+        BuildInfo {
+            version: "1.2.3",
+            os: "linux",
+            arch: "x86_64",
+            libgit2: "1.7.2".to_string(),
+            runtime: Some("Podman"),
+            runtime_version: Some("podman version 4.9.3".to_string()),
+            config_path: Some(PathBuf::from("/nonexistent/ccs/config.toml")),
+        }
+    }
+
+    #[test]
+    fn test_build_info_text() {
+        let text = sample().to_string();
+        assert_eq!(
+            text,
+            "ccs 1.2.3\n\
+             OS: linux (x86_64)\n\
+             libgit2: 1.7.2\n\
+             Runtime: podman version 4.9.3\n\
+             Config: /nonexistent/ccs/config.toml (not found)"
+        );
+    }
+
+    #[test]
+    fn test_build_info_json() {
+        let info = BuildInfo {
+            runtime: None,
+            runtime_version: None,
+            ..sample()
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["version"], "1.2.3");
+        assert_eq!(json["libgit2"], "1.7.2");
+        assert!(json["runtime"].is_null());
+        assert!(info.to_string().contains("Runtime: not found"));
+    }
+}